@@ -0,0 +1,288 @@
+use crate::RequestInterceptor;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    Status,
+};
+
+const AUTHORIZATION_HEADER_KEY: &str = "authorization";
+/// How long to wait before trying again after a failed refresh
+const REFRESH_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A bearer token (ex: a Temporal Cloud API key or an OAuth access token) attached to requests
+#[derive(Clone, Debug)]
+pub struct AuthToken {
+    /// The token itself, sent as `authorization: Bearer <token>`
+    pub token: String,
+    /// When the token stops being valid. If unset, the token is never refreshed.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Supplies the bearer tokens attached to every request made by a client. See
+/// [crate::ConfiguredClient::set_auth_token_provider].
+#[async_trait::async_trait]
+pub trait AuthTokenProvider: Send + Sync {
+    /// Fetch a fresh token
+    async fn fetch_token(&self) -> anyhow::Result<AuthToken>;
+}
+
+/// Attaches the most recently fetched token to requests, and kicks off a background refresh once
+/// that token is close to expiring. Requests never wait on a refresh - until it succeeds they keep
+/// using the old token.
+pub(crate) struct TokenAuthInterceptor {
+    provider: Arc<dyn AuthTokenProvider>,
+    refresh_before_expiry: Duration,
+    state: Arc<Mutex<TokenState>>,
+    clock: Clock,
+}
+
+/// Source of the current time, which tests replace so they need not wait on real tokens expiring
+type Clock = Arc<dyn Fn() -> SystemTime + Send + Sync>;
+
+struct TokenState {
+    /// When the current token should be refreshed. Unset if it never expires.
+    refresh_at: Option<SystemTime>,
+    header: MetadataValue<Ascii>,
+    refreshing: bool,
+    retry_not_before: Option<SystemTime>,
+}
+
+impl TokenAuthInterceptor {
+    /// Fetches the initial token, failing if that is not possible
+    pub(crate) async fn new(
+        provider: Arc<dyn AuthTokenProvider>,
+        refresh_before_expiry: Duration,
+    ) -> anyhow::Result<Self> {
+        Self::with_clock(provider, refresh_before_expiry, Arc::new(SystemTime::now)).await
+    }
+
+    async fn with_clock(
+        provider: Arc<dyn AuthTokenProvider>,
+        refresh_before_expiry: Duration,
+        clock: Clock,
+    ) -> anyhow::Result<Self> {
+        let token = provider.fetch_token().await?;
+        let state = TokenState {
+            refresh_at: refresh_at(token.expires_at, refresh_before_expiry, clock()),
+            header: bearer_header(&token)?,
+            refreshing: false,
+            retry_not_before: None,
+        };
+        Ok(Self {
+            provider,
+            refresh_before_expiry,
+            state: Arc::new(Mutex::new(state)),
+            clock,
+        })
+    }
+
+    fn refresh_if_needed(&self) {
+        {
+            let mut state = self.state.lock();
+            let refresh_at = match state.refresh_at {
+                Some(r) => r,
+                None => return,
+            };
+            let now = (self.clock)();
+            if state.refreshing
+                || matches!(state.retry_not_before, Some(t) if now < t)
+                || now < refresh_at
+            {
+                return;
+            }
+            state.refreshing = true;
+        }
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(h) => h,
+            Err(_) => {
+                warn!("Auth token needs refreshing, but there is no runtime to refresh it on");
+                self.state.lock().refreshing = false;
+                return;
+            }
+        };
+        let provider = self.provider.clone();
+        let state = self.state.clone();
+        let refresh_before_expiry = self.refresh_before_expiry;
+        let clock = self.clock.clone();
+        handle.spawn(async move {
+            let res = provider
+                .fetch_token()
+                .await
+                .and_then(|token| Ok((token.expires_at, bearer_header(&token)?)));
+            let now = clock();
+            let mut state = state.lock();
+            state.refreshing = false;
+            match res {
+                Ok((expires_at, header)) => {
+                    state.refresh_at = refresh_at(expires_at, refresh_before_expiry, now);
+                    state.header = header;
+                    state.retry_not_before = None;
+                }
+                Err(e) => {
+                    warn!(error=?e, "Failed to refresh auth token, will retry");
+                    state.retry_not_before = Some(now + REFRESH_RETRY_DELAY);
+                }
+            }
+        });
+    }
+}
+
+impl RequestInterceptor for TokenAuthInterceptor {
+    fn intercept(&self, request: &mut tonic::Request<()>) -> Result<(), Status> {
+        self.refresh_if_needed();
+        let metadata = request.metadata_mut();
+        // Don't overwrite per-request specified auth
+        if !metadata.contains_key(AUTHORIZATION_HEADER_KEY) {
+            metadata.insert(AUTHORIZATION_HEADER_KEY, self.state.lock().header.clone());
+        }
+        Ok(())
+    }
+}
+
+/// When a token which was just fetched should be refreshed. The refresh window is clamped to half
+/// of the token's remaining lifetime, so tokens which live for less than the window are not
+/// refreshed on every request.
+fn refresh_at(
+    expires_at: Option<SystemTime>,
+    refresh_before_expiry: Duration,
+    now: SystemTime,
+) -> Option<SystemTime> {
+    let expires_at = expires_at?;
+    let lifetime = expires_at.duration_since(now).unwrap_or_default();
+    if lifetime.is_zero() {
+        warn!("Auth token provider returned an already expired token");
+        return Some(now + REFRESH_RETRY_DELAY);
+    }
+    Some(expires_at - refresh_before_expiry.min(lifetime / 2))
+}
+
+fn bearer_header(token: &AuthToken) -> anyhow::Result<MetadataValue<Ascii>> {
+    Ok(format!("Bearer {}", token.token).parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A clock which only moves when told to
+    #[derive(Clone)]
+    struct FakeClock(Arc<Mutex<SystemTime>>);
+    impl FakeClock {
+        fn new() -> Self {
+            Self(Arc::new(Mutex::new(SystemTime::now())))
+        }
+        fn now(&self) -> SystemTime {
+            *self.0.lock()
+        }
+        fn advance(&self, by: Duration) {
+            *self.0.lock() += by;
+        }
+        fn as_clock(&self) -> Clock {
+            let me = self.clone();
+            Arc::new(move || me.now())
+        }
+    }
+
+    struct CountingProvider {
+        fetches: AtomicUsize,
+        lifetime: Duration,
+        clock: FakeClock,
+    }
+    impl CountingProvider {
+        fn new(lifetime: Duration, clock: &FakeClock) -> Arc<Self> {
+            Arc::new(Self {
+                fetches: AtomicUsize::new(0),
+                lifetime,
+                clock: clock.clone(),
+            })
+        }
+    }
+    #[async_trait::async_trait]
+    impl AuthTokenProvider for CountingProvider {
+        async fn fetch_token(&self) -> anyhow::Result<AuthToken> {
+            let n = self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(AuthToken {
+                token: format!("token-{n}"),
+                expires_at: Some(self.clock.now() + self.lifetime),
+            })
+        }
+    }
+
+    fn auth_header(iceptor: &TokenAuthInterceptor) -> String {
+        let mut req = tonic::Request::new(());
+        iceptor.intercept(&mut req).unwrap();
+        req.metadata()
+            .get(AUTHORIZATION_HEADER_KEY)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn token_refreshed_before_expiry() {
+        let clock = FakeClock::new();
+        let provider = CountingProvider::new(Duration::from_secs(100), &clock);
+        let iceptor = TokenAuthInterceptor::with_clock(
+            provider.clone(),
+            Duration::from_secs(30),
+            clock.as_clock(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(auth_header(&iceptor), "Bearer token-0");
+        clock.advance(Duration::from_secs(60));
+        tokio::task::yield_now().await;
+        assert_eq!(auth_header(&iceptor), "Bearer token-0");
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 1);
+        clock.advance(Duration::from_secs(15));
+        // Token is now within the refresh window, so this request triggers a refresh but still
+        // uses the old token
+        assert_eq!(auth_header(&iceptor), "Bearer token-0");
+        tokio::task::yield_now().await;
+        assert_eq!(auth_header(&iceptor), "Bearer token-1");
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn refresh_window_clamped_to_token_lifetime() {
+        let clock = FakeClock::new();
+        let provider = CountingProvider::new(Duration::from_secs(60 * 60), &clock);
+        // The window is longer than the token lives, which must not cause a refresh per request
+        let iceptor = TokenAuthInterceptor::with_clock(
+            provider.clone(),
+            Duration::from_secs(2 * 60 * 60),
+            clock.as_clock(),
+        )
+        .await
+        .unwrap();
+        for _ in 0..10 {
+            assert_eq!(auth_header(&iceptor), "Bearer token-0");
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn token_not_refreshed_when_fresh() {
+        let clock = FakeClock::new();
+        let provider = CountingProvider::new(Duration::from_secs(60 * 60), &clock);
+        let iceptor = TokenAuthInterceptor::with_clock(
+            provider.clone(),
+            Duration::from_secs(10),
+            clock.as_clock(),
+        )
+        .await
+        .unwrap();
+        for _ in 0..10 {
+            assert_eq!(auth_header(&iceptor), "Bearer token-0");
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 1);
+    }
+}
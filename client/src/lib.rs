@@ -7,12 +7,14 @@
 #[macro_use]
 extern crate tracing;
 
+mod auth;
 mod metrics;
 mod raw;
 mod retry;
 mod workflow_handle;
 
 pub use crate::retry::{CallType, RetryClient, RETRYABLE_ERROR_CODES};
+pub use auth::{AuthToken, AuthTokenProvider};
 pub use metrics::ClientMetricProvider;
 pub use raw::{HealthService, OperatorService, TestService, WorkflowService};
 pub use temporal_sdk_core_protos::temporal::api::{
@@ -27,6 +29,7 @@ pub use tonic;
 pub use workflow_handle::{WorkflowExecutionInfo, WorkflowExecutionResult};
 
 use crate::{
+    auth::TokenAuthInterceptor,
     metrics::{GrpcMetricSvc, MetricsContext},
     raw::{sealed::RawClientLike, AttachMetricLabels},
     sealed::WfHandleClient,
@@ -37,6 +40,7 @@ use http::{uri::InvalidUri, Uri};
 use once_cell::sync::OnceCell;
 use parking_lot::RwLock;
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fmt::{Debug, Formatter},
     ops::{Deref, DerefMut},
@@ -46,7 +50,9 @@ use std::{
 };
 use temporal_sdk_core_protos::{
     coresdk::{workflow_commands::QueryResult, IntoPayloadsExt},
-    grpc::health::v1::health_client::HealthClient,
+    grpc::health::v1::{
+        health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+    },
     temporal::api::{
         common::v1::{Header, Payload, Payloads, WorkflowExecution, WorkflowType},
         enums::v1::{TaskQueueKind, WorkflowIdReusePolicy, WorkflowTaskFailedCause},
@@ -120,6 +126,17 @@ pub struct ClientOptions {
     #[builder(default)]
     pub retry_config: RetryConfig,
 
+    /// Retry configuration used instead of [Self::retry_config] for long-poll calls (polling for
+    /// workflow or activity tasks). Default is [RetryConfig::poll_retry_policy]
+    #[builder(default = "RetryConfig::poll_retry_policy()")]
+    pub long_poll_retry_config: RetryConfig,
+
+    /// Retry configurations for specific calls, which take precedence over both
+    /// [Self::retry_config] and [Self::long_poll_retry_config]. Keyed by the name of the
+    /// [WorkflowService] or [WorkflowClientTrait] method, ex: `start_workflow_execution`.
+    #[builder(default)]
+    pub call_retry_overrides: HashMap<String, RetryConfig>,
+
     /// If set, override the origin used when connecting. May be useful in rare situations where tls
     /// verification needs to use a different name from what should be set as the `:authority`
     /// header. If [TlsConfig::domain] is set, and this is not, this will be set to
@@ -169,6 +186,9 @@ pub struct RetryConfig {
     pub max_elapsed_time: Option<Duration>,
     /// maximum number of retry attempts.
     pub max_retries: usize,
+    /// gRPC status codes which are retried. Any other code is returned to the caller immediately
+    /// (long polls are more lenient, see [RetryClient]).
+    pub retryable_codes: Cow<'static, [Code]>,
 }
 
 impl Default for RetryConfig {
@@ -180,12 +200,14 @@ impl Default for RetryConfig {
             max_interval: Duration::from_secs(5), // until it reaches 5 seconds.
             max_elapsed_time: Some(Duration::from_secs(10)), // 10 seconds total allocated time for all retries.
             max_retries: 10,
+            retryable_codes: Cow::Borrowed(&RETRYABLE_ERROR_CODES),
         }
     }
 }
 
 impl RetryConfig {
-    pub(crate) const fn poll_retry_policy() -> Self {
+    /// The default retry policy for long-poll calls, which retries forever
+    pub const fn poll_retry_policy() -> Self {
         Self {
             initial_interval: Duration::from_millis(200),
             randomization_factor: 0.2,
@@ -193,6 +215,7 @@ impl RetryConfig {
             max_interval: Duration::from_secs(10),
             max_elapsed_time: None,
             max_retries: 0,
+            retryable_codes: Cow::Borrowed(&RETRYABLE_ERROR_CODES),
         }
     }
 
@@ -204,9 +227,17 @@ impl RetryConfig {
             max_interval: Duration::from_secs(10),
             max_elapsed_time: None,
             max_retries: 0,
+            retryable_codes: Cow::Borrowed(&RETRYABLE_ERROR_CODES),
         }
     }
 
+    /// Replace the set of gRPC status codes which are retried, keeping the rest of this config.
+    /// Prefer this over a struct literal, which must list every field.
+    pub fn with_retryable_codes(mut self, codes: impl Into<Cow<'static, [Code]>>) -> Self {
+        self.retryable_codes = codes.into();
+        self
+    }
+
     pub(crate) fn into_exp_backoff<C>(self, clock: C) -> exponential::ExponentialBackoff<C> {
         exponential::ExponentialBackoff {
             current_interval: self.initial_interval,
@@ -256,8 +287,11 @@ pub struct ConfiguredClient<C> {
     client: C,
     options: Arc<ClientOptions>,
     headers: Arc<RwLock<HashMap<String, String>>>,
-    /// Capabilities as read from the `get_system_info` RPC call made on client connection
-    capabilities: Option<get_system_info_response::Capabilities>,
+    request_interceptor: SharedRequestInterceptor,
+    auth_interceptor: SharedRequestInterceptor,
+    /// Capabilities as read from the `get_system_info` RPC call, made on client connection, or on
+    /// first use for lazily connected clients
+    capabilities: Arc<OnceCell<get_system_info_response::Capabilities>>,
 }
 
 impl<C> ConfiguredClient<C> {
@@ -267,15 +301,75 @@ impl<C> ConfiguredClient<C> {
         *guard = headers;
     }
 
+    /// Set an interceptor which is run on every outgoing request, replacing any previous one.
+    /// Passing `None` removes the current interceptor. It runs after any auth token set with
+    /// [Self::set_auth_token_provider] has been attached.
+    pub fn set_request_interceptor(&self, interceptor: Option<Arc<dyn RequestInterceptor>>) {
+        *self.request_interceptor.write() = interceptor;
+    }
+
+    /// Attach a bearer token from the provider to every request, fetching a new one in the
+    /// background once the current token is within `refresh_before_expiry` of expiring. For
+    /// tokens which live for less than twice that, the window shrinks to half their lifetime.
+    /// Replaces any previously set provider, but not an interceptor set with
+    /// [Self::set_request_interceptor]. Fails if the initial token cannot be fetched.
+    pub async fn set_auth_token_provider(
+        &self,
+        provider: Arc<dyn AuthTokenProvider>,
+        refresh_before_expiry: Duration,
+    ) -> anyhow::Result<()> {
+        let iceptor = TokenAuthInterceptor::new(provider, refresh_before_expiry).await?;
+        *self.auth_interceptor.write() = Some(Arc::new(iceptor));
+        Ok(())
+    }
+
     /// Returns the options the client is configured with
     pub fn options(&self) -> &ClientOptions {
         &self.options
     }
 
-    /// Returns the server capabilities we (may have) learned about when establishing an initial
-    /// connection
+    /// Returns the server capabilities we have learned about, if they have been fetched yet. They
+    /// are fetched when establishing an initial connection, except for lazily connected clients,
+    /// which must use [ConfiguredClient::fetch_capabilities]. Servers too old to report their
+    /// capabilities are treated as having none of them.
     pub fn capabilities(&self) -> Option<&get_system_info_response::Capabilities> {
-        self.capabilities.as_ref()
+        self.capabilities.get()
+    }
+}
+
+impl ConfiguredClient<TemporalServiceClientWithMetrics> {
+    /// Ask the server's gRPC health service whether the workflow service is serving. Returns an
+    /// error if the server could not be reached. Not retried.
+    pub async fn check_health(&self) -> Result<bool> {
+        let resp = self
+            .health_svc()
+            .clone()
+            .check(HealthCheckRequest {
+                service: "temporal.api.workflowservice.v1.WorkflowService".to_string(),
+            })
+            .await?;
+        Ok(resp.into_inner().status() == ServingStatus::Serving)
+    }
+
+    /// Returns the server's capabilities, asking the server for them if they haven't been fetched
+    /// yet. Once fetched, they are shared by every clone of this client.
+    pub async fn fetch_capabilities(
+        &self,
+    ) -> Result<&get_system_info_response::Capabilities, tonic::Status> {
+        if let Some(caps) = self.capabilities.get() {
+            return Ok(caps);
+        }
+        let mut client = self.clone();
+        let caps = match client
+            .get_system_info(GetSystemInfoRequest::default())
+            .await
+        {
+            Ok(sysinfo) => sysinfo.into_inner().capabilities.unwrap_or_default(),
+            // Servers which predate the call support none of the capabilities it reports
+            Err(status) if status.code() == Code::Unimplemented => Default::default(),
+            Err(status) => return Err(status),
+        };
+        Ok(self.capabilities.get_or_init(|| caps))
     }
 }
 
@@ -307,7 +401,7 @@ impl ClientOptions {
             .await?
             .into_inner();
         let client = Client::new(client, namespace.into());
-        let retry_client = RetryClient::new(client, self.retry_config.clone());
+        let retry_client = RetryClient::from_options(client, self);
         Ok(retry_client)
     }
 
@@ -321,14 +415,49 @@ impl ClientOptions {
         headers: Option<Arc<RwLock<HashMap<String, String>>>>,
     ) -> Result<RetryClient<ConfiguredClient<TemporalServiceClientWithMetrics>>, ClientInitError>
     {
+        let channel = self.endpoint().await?.connect().await?;
+        let client = self.configured_client(channel, metrics_meter, headers);
+        client
+            .fetch_capabilities()
+            .await
+            .map_err(ClientInitError::SystemInfoCallError)?;
+        Ok(RetryClient::from_options(client, self))
+    }
+
+    /// Like [Self::connect_no_namespace], but does not connect to the server until the first call
+    /// is made, so the client (and workers using it) may be created while the server is
+    /// unreachable. Use [ConfiguredClient::check_health] to probe connectivity.
+    ///
+    /// Since no call is made up front, [ConfiguredClient::capabilities] will be unset until
+    /// [ConfiguredClient::fetch_capabilities] is called. Workers using such a client do so before
+    /// their first workflow task poll.
+    pub async fn connect_no_namespace_lazy(
+        &self,
+        metrics_meter: Option<&dyn ClientMetricProvider>,
+        headers: Option<Arc<RwLock<HashMap<String, String>>>>,
+    ) -> Result<RetryClient<ConfiguredClient<TemporalServiceClientWithMetrics>>, ClientInitError>
+    {
+        let channel = self.endpoint().await?.connect_lazy();
+        let client = self.configured_client(channel, metrics_meter, headers);
+        Ok(RetryClient::from_options(client, self))
+    }
+
+    async fn endpoint(&self) -> Result<Endpoint, ClientInitError> {
         let channel = Channel::from_shared(self.target_url.to_string())?;
         let channel = self.add_tls_to_channel(channel).await?;
-        let channel = if let Some(origin) = self.override_origin.clone() {
+        Ok(if let Some(origin) = self.override_origin.clone() {
             channel.origin(origin)
         } else {
             channel
-        };
-        let channel = channel.connect().await?;
+        })
+    }
+
+    fn configured_client(
+        &self,
+        channel: Channel,
+        metrics_meter: Option<&dyn ClientMetricProvider>,
+        headers: Option<Arc<RwLock<HashMap<String, String>>>>,
+    ) -> ConfiguredClient<TemporalServiceClientWithMetrics> {
         let service = ServiceBuilder::new()
             .layer_fn(|channel| GrpcMetricSvc {
                 inner: channel,
@@ -336,31 +465,24 @@ impl ClientOptions {
             })
             .service(channel);
         let headers = headers.unwrap_or_default();
+        let request_interceptor = SharedRequestInterceptor::default();
+        let auth_interceptor = SharedRequestInterceptor::default();
         let interceptor = ServiceCallInterceptor {
             opts: self.clone(),
             headers: headers.clone(),
+            request_interceptor: request_interceptor.clone(),
+            auth_interceptor: auth_interceptor.clone(),
         };
         let svc = InterceptedService::new(service, interceptor);
 
-        let mut client = ConfiguredClient {
+        ConfiguredClient {
             headers,
+            request_interceptor,
+            auth_interceptor,
             client: TemporalServiceClient::new(svc),
             options: Arc::new(self.clone()),
-            capabilities: None,
-        };
-        match client
-            .get_system_info(GetSystemInfoRequest::default())
-            .await
-        {
-            Ok(sysinfo) => {
-                client.capabilities = sysinfo.into_inner().capabilities;
-            }
-            Err(status) => match status.code() {
-                Code::Unimplemented => {}
-                _ => return Err(ClientInitError::SystemInfoCallError(status)),
-            },
-        };
-        Ok(RetryClient::new(client, self.retry_config.clone()))
+            capabilities: Default::default(),
+        }
     }
 
     /// If TLS is configured, set the appropriate options on the provided channel and return it.
@@ -397,12 +519,47 @@ impl ClientOptions {
     }
 }
 
+/// User-provided logic run on every outgoing request, after the static headers have been attached.
+/// May mutate the request (ex: to add a per-request auth token or tenant ID) or reject it, in which
+/// case the returned status is handed to the caller as if the server had returned it.
+///
+/// Interceptors are synchronous: they are run from tonic's [Interceptor], which cannot await, and
+/// supporting async ones would mean replacing it with a custom tower layer around every channel.
+/// They therefore must not block. Anything slow (like refreshing a token) should happen in the
+/// background, with the interceptor only reading the latest result, as the one installed by
+/// [ConfiguredClient::set_auth_token_provider] does.
+pub trait RequestInterceptor: Send + Sync {
+    /// Inspect and possibly modify an outgoing request
+    fn intercept(&self, request: &mut tonic::Request<()>) -> Result<(), Status>;
+}
+
+impl<F> RequestInterceptor for F
+where
+    F: Fn(&mut tonic::Request<()>) -> Result<(), Status> + Send + Sync,
+{
+    fn intercept(&self, request: &mut tonic::Request<()>) -> Result<(), Status> {
+        self(request)
+    }
+}
+
+impl Debug for dyn RequestInterceptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RequestInterceptor(..)")
+    }
+}
+
+type SharedRequestInterceptor = Arc<RwLock<Option<Arc<dyn RequestInterceptor>>>>;
+
 /// Interceptor which attaches common metadata (like "client-name") to every outgoing call
 #[derive(Clone)]
 pub struct ServiceCallInterceptor {
     opts: ClientOptions,
     /// Only accessed as a reader
     headers: Arc<RwLock<HashMap<String, String>>>,
+    /// Only accessed as a reader
+    request_interceptor: SharedRequestInterceptor,
+    /// Only accessed as a reader
+    auth_interceptor: SharedRequestInterceptor,
 }
 
 impl Interceptor for ServiceCallInterceptor {
@@ -441,6 +598,14 @@ impl Interceptor for ServiceCallInterceptor {
         if !metadata.contains_key("grpc-timeout") {
             request.set_timeout(OTHER_CALL_TIMEOUT);
         }
+        let auth_interceptor = self.auth_interceptor.read().clone();
+        if let Some(auth_interceptor) = auth_interceptor {
+            auth_interceptor.intercept(&mut request)?;
+        }
+        let user_interceptor = self.request_interceptor.read().clone();
+        if let Some(user_interceptor) = user_interceptor {
+            user_interceptor.intercept(&mut request)?;
+        }
 
         Ok(request)
     }
@@ -553,10 +718,7 @@ impl Client {
     /// Note that it is reasonably cheap to clone the returned type if you need to own it. Such
     /// clones will keep re-using the same channel.
     pub fn raw_retry_client(&self) -> RetryClient<WorkflowServiceClientWithMetrics> {
-        RetryClient::new(
-            self.raw_client().clone(),
-            self.inner.options.retry_config.clone(),
-        )
+        RetryClient::from_options(self.raw_client().clone(), &self.inner.options)
     }
 
     /// Access the underling grpc client. This raw client is not bound to a specific namespace.
@@ -990,6 +1152,11 @@ pub struct WorkflowOptions {
 
     /// Optionally associate extra search attributes with a workflow
     pub search_attributes: Option<HashMap<String, Payload>>,
+
+    /// Optionally delay the start of the workflow. The execution is created right away, but its
+    /// first workflow task is not dispatched until the delay has elapsed. Only applies to
+    /// workflows started through this client; the server has no start delay for child workflows.
+    pub start_delay: Option<Duration>,
 }
 
 /// Optional fields supplied at start of creating a schedule
@@ -1033,6 +1200,81 @@ pub struct ScheduleOptions {
     pub policies: Option<SchedulePolicies>,
 }
 
+fn start_workflow_request(
+    namespace: String,
+    input: Vec<Payload>,
+    task_queue: String,
+    workflow_id: String,
+    workflow_type: String,
+    request_id: Option<String>,
+    options: WorkflowOptions,
+) -> StartWorkflowExecutionRequest {
+    StartWorkflowExecutionRequest {
+        namespace,
+        input: input.into_payloads(),
+        workflow_id,
+        workflow_type: Some(WorkflowType {
+            name: workflow_type,
+        }),
+        task_queue: Some(TaskQueue {
+            name: task_queue,
+            kind: TaskQueueKind::Unspecified as i32,
+        }),
+        request_id: request_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+        workflow_id_reuse_policy: options.id_reuse_policy as i32,
+        workflow_execution_timeout: options.execution_timeout.and_then(|d| d.try_into().ok()),
+        workflow_run_timeout: options.execution_timeout.and_then(|d| d.try_into().ok()),
+        workflow_task_timeout: options.task_timeout.and_then(|d| d.try_into().ok()),
+        search_attributes: options.search_attributes.and_then(|d| d.try_into().ok()),
+        cron_schedule: options.cron_schedule.unwrap_or_default(),
+        workflow_start_delay: options.start_delay.and_then(|d| d.try_into().ok()),
+        ..Default::default()
+    }
+}
+
+fn signal_with_start_request(
+    namespace: String,
+    identity: String,
+    options: SignalWithStartOptions,
+    workflow_options: WorkflowOptions,
+) -> SignalWithStartWorkflowExecutionRequest {
+    SignalWithStartWorkflowExecutionRequest {
+        namespace,
+        workflow_id: options.workflow_id,
+        workflow_type: Some(WorkflowType {
+            name: options.workflow_type,
+        }),
+        task_queue: Some(TaskQueue {
+            name: options.task_queue,
+            kind: TaskQueueKind::Normal as i32,
+        }),
+        input: options.input,
+        signal_name: options.signal_name,
+        signal_input: options.signal_input,
+        identity,
+        request_id: options
+            .request_id
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        workflow_id_reuse_policy: workflow_options.id_reuse_policy as i32,
+        workflow_execution_timeout: workflow_options
+            .execution_timeout
+            .and_then(|d| d.try_into().ok()),
+        workflow_run_timeout: workflow_options
+            .execution_timeout
+            .and_then(|d| d.try_into().ok()),
+        workflow_task_timeout: workflow_options
+            .task_timeout
+            .and_then(|d| d.try_into().ok()),
+        search_attributes: workflow_options
+            .search_attributes
+            .and_then(|d| d.try_into().ok()),
+        cron_schedule: workflow_options.cron_schedule.unwrap_or_default(),
+        workflow_start_delay: workflow_options.start_delay.and_then(|d| d.try_into().ok()),
+        header: options.signal_header,
+        ..Default::default()
+    }
+}
+
 #[async_trait::async_trait]
 impl WorkflowClientTrait for Client {
     async fn start_workflow(
@@ -1046,28 +1288,15 @@ impl WorkflowClientTrait for Client {
     ) -> Result<StartWorkflowExecutionResponse> {
         Ok(self
             .wf_svc()
-            .start_workflow_execution(StartWorkflowExecutionRequest {
-                namespace: self.namespace.clone(),
-                input: input.into_payloads(),
+            .start_workflow_execution(start_workflow_request(
+                self.namespace.clone(),
+                input,
+                task_queue,
                 workflow_id,
-                workflow_type: Some(WorkflowType {
-                    name: workflow_type,
-                }),
-                task_queue: Some(TaskQueue {
-                    name: task_queue,
-                    kind: TaskQueueKind::Unspecified as i32,
-                }),
-                request_id: request_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
-                workflow_id_reuse_policy: options.id_reuse_policy as i32,
-                workflow_execution_timeout: options
-                    .execution_timeout
-                    .and_then(|d| d.try_into().ok()),
-                workflow_run_timeout: options.execution_timeout.and_then(|d| d.try_into().ok()),
-                workflow_task_timeout: options.task_timeout.and_then(|d| d.try_into().ok()),
-                search_attributes: options.search_attributes.and_then(|d| d.try_into().ok()),
-                cron_schedule: options.cron_schedule.unwrap_or_default(),
-                ..Default::default()
-            })
+                workflow_type,
+                request_id,
+                options,
+            ))
             .await?
             .into_inner())
     }
@@ -1216,40 +1445,12 @@ impl WorkflowClientTrait for Client {
     ) -> Result<SignalWithStartWorkflowExecutionResponse> {
         Ok(self
             .wf_svc()
-            .signal_with_start_workflow_execution(SignalWithStartWorkflowExecutionRequest {
-                namespace: self.namespace.clone(),
-                workflow_id: options.workflow_id,
-                workflow_type: Some(WorkflowType {
-                    name: options.workflow_type,
-                }),
-                task_queue: Some(TaskQueue {
-                    name: options.task_queue,
-                    kind: TaskQueueKind::Normal as i32,
-                }),
-                input: options.input,
-                signal_name: options.signal_name,
-                signal_input: options.signal_input,
-                identity: self.inner.options.identity.clone(),
-                request_id: options
-                    .request_id
-                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
-                workflow_id_reuse_policy: workflow_options.id_reuse_policy as i32,
-                workflow_execution_timeout: workflow_options
-                    .execution_timeout
-                    .and_then(|d| d.try_into().ok()),
-                workflow_run_timeout: workflow_options
-                    .execution_timeout
-                    .and_then(|d| d.try_into().ok()),
-                workflow_task_timeout: workflow_options
-                    .task_timeout
-                    .and_then(|d| d.try_into().ok()),
-                search_attributes: workflow_options
-                    .search_attributes
-                    .and_then(|d| d.try_into().ok()),
-                cron_schedule: workflow_options.cron_schedule.unwrap_or_default(),
-                header: options.signal_header,
-                ..Default::default()
-            })
+            .signal_with_start_workflow_execution(signal_with_start_request(
+                self.namespace.clone(),
+                self.inner.options.identity.clone(),
+                options,
+                workflow_options,
+            ))
             .await?
             .into_inner())
     }
@@ -1683,10 +1884,140 @@ mod tests {
         let mut iceptor = ServiceCallInterceptor {
             opts,
             headers: Arc::new(RwLock::new(static_headers)),
+            request_interceptor: Default::default(),
+            auth_interceptor: Default::default(),
         };
         let mut req = tonic::Request::new(());
         req.metadata_mut().insert("enchi", "cat".parse().unwrap());
         let next_req = iceptor.call(req).unwrap();
         assert_eq!(next_req.metadata().get("enchi").unwrap(), "cat");
     }
+
+    #[test]
+    fn user_request_interceptor_runs_after_static_headers() {
+        let opts = ClientOptionsBuilder::default()
+            .target_url(Url::parse("https://smolkitty").unwrap())
+            .client_name("cute-kitty".to_string())
+            .client_version("0.1.0".to_string())
+            .build()
+            .unwrap();
+
+        let mut static_headers = HashMap::new();
+        static_headers.insert("tenant".to_string(), "default".to_string());
+        let user_iceptor: Arc<dyn RequestInterceptor> = Arc::new(|req: &mut tonic::Request<()>| {
+            if !req.metadata().contains_key("tenant") {
+                return Err(Status::unauthenticated("no tenant"));
+            }
+            req.metadata_mut()
+                .insert("authorization", "Bearer token".parse().unwrap());
+            Ok(())
+        });
+        let mut iceptor = ServiceCallInterceptor {
+            opts,
+            headers: Arc::new(RwLock::new(static_headers)),
+            request_interceptor: Arc::new(RwLock::new(Some(user_iceptor))),
+            auth_interceptor: Default::default(),
+        };
+        let next_req = iceptor.call(tonic::Request::new(())).unwrap();
+        assert_eq!(
+            next_req.metadata().get("authorization").unwrap(),
+            "Bearer token"
+        );
+
+        iceptor.headers.write().clear();
+        let err = iceptor.call(tonic::Request::new(())).unwrap_err();
+        assert_eq!(err.code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn auth_and_user_interceptors_both_run() {
+        let opts = ClientOptionsBuilder::default()
+            .target_url(Url::parse("https://smolkitty").unwrap())
+            .client_name("cute-kitty".to_string())
+            .client_version("0.1.0".to_string())
+            .build()
+            .unwrap();
+
+        let auth_iceptor: Arc<dyn RequestInterceptor> = Arc::new(|req: &mut tonic::Request<()>| {
+            req.metadata_mut()
+                .insert("authorization", "Bearer token".parse().unwrap());
+            Ok(())
+        });
+        let user_iceptor: Arc<dyn RequestInterceptor> = Arc::new(|req: &mut tonic::Request<()>| {
+            if !req.metadata().contains_key("authorization") {
+                return Err(Status::unauthenticated("no token"));
+            }
+            req.metadata_mut()
+                .insert("tenant", "default".parse().unwrap());
+            Ok(())
+        });
+        let mut iceptor = ServiceCallInterceptor {
+            opts,
+            headers: Default::default(),
+            request_interceptor: Arc::new(RwLock::new(Some(user_iceptor))),
+            auth_interceptor: Arc::new(RwLock::new(Some(auth_iceptor))),
+        };
+        let next_req = iceptor.call(tonic::Request::new(())).unwrap();
+        assert_eq!(
+            next_req.metadata().get("authorization").unwrap(),
+            "Bearer token"
+        );
+        assert_eq!(next_req.metadata().get("tenant").unwrap(), "default");
+    }
+
+    #[test]
+    fn start_delay_sent_on_start_request() {
+        let req = start_workflow_request(
+            "ns".to_string(),
+            vec![],
+            "tq".to_string(),
+            "wfid".to_string(),
+            "wftype".to_string(),
+            None,
+            WorkflowOptions {
+                start_delay: Some(Duration::from_secs(30)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            req.workflow_start_delay,
+            Some(Duration::from_secs(30).try_into().unwrap())
+        );
+
+        let req = start_workflow_request(
+            "ns".to_string(),
+            vec![],
+            "tq".to_string(),
+            "wfid".to_string(),
+            "wftype".to_string(),
+            None,
+            Default::default(),
+        );
+        assert_eq!(req.workflow_start_delay, None);
+    }
+
+    #[test]
+    fn start_delay_sent_on_signal_with_start_request() {
+        let opts = SignalWithStartOptions::builder()
+            .task_queue("tq")
+            .workflow_id("wfid")
+            .workflow_type("wftype")
+            .signal_name("sig")
+            .build()
+            .unwrap();
+        let req = signal_with_start_request(
+            "ns".to_string(),
+            "ident".to_string(),
+            opts,
+            WorkflowOptions {
+                start_delay: Some(Duration::from_secs(30)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            req.workflow_start_delay,
+            Some(Duration::from_secs(30).try_into().unwrap())
+        );
+        assert_eq!(req.signal_name, "sig");
+    }
 }
@@ -5,11 +5,18 @@ use opentelemetry::{
     KeyValue,
 };
 use std::{
+    future::Future,
+    pin::Pin,
     sync::Arc,
-    task::{Context, Poll},
+    task::{ready, Context, Poll},
     time::{Duration, Instant},
 };
-use tonic::{body::BoxBody, transport::Channel};
+use tonic::{
+    body::BoxBody,
+    codegen::{Body, Bytes},
+    transport::{self, Channel},
+    Code,
+};
 use tower::Service;
 
 /// Used to track context associated with metrics, and record/update them
@@ -23,8 +30,10 @@ pub struct MetricsContext {
 
     svc_request: Counter<u64>,
     svc_request_failed: Counter<u64>,
+    svc_request_retried: Counter<u64>,
     long_svc_request: Counter<u64>,
     long_svc_request_failed: Counter<u64>,
+    long_svc_request_retried: Counter<u64>,
 
     svc_request_latency: Histogram<u64>,
     long_svc_request_latency: Histogram<u64>,
@@ -47,8 +56,10 @@ impl MetricsContext {
             poll_is_long: false,
             svc_request: metric_provider.counter("request"),
             svc_request_failed: metric_provider.counter("request_failure"),
+            svc_request_retried: metric_provider.counter("request_retry"),
             long_svc_request: metric_provider.counter("long_request"),
             long_svc_request_failed: metric_provider.counter("long_request_failure"),
+            long_svc_request_retried: metric_provider.counter("long_request_retry"),
             svc_request_latency: metric_provider.histogram("request_latency"),
             long_svc_request_latency: metric_provider.histogram("long_request_latency"),
         }
@@ -79,12 +90,24 @@ impl MetricsContext {
         }
     }
 
-    /// A request to the temporal service failed
-    pub(crate) fn svc_request_failed(&self) {
+    /// A request to the temporal service failed with the given status code, or without any
+    /// response from the server at all if `None` (ex: the connection failed)
+    pub(crate) fn svc_request_failed(&self, code: Option<Code>) {
+        let mut kvs = (*self.kvs).clone();
+        kvs.push(status_code_kv(code));
         if self.poll_is_long {
-            self.long_svc_request_failed.add(&self.ctx, 1, &self.kvs);
+            self.long_svc_request_failed.add(&self.ctx, 1, &kvs);
         } else {
-            self.svc_request_failed.add(&self.ctx, 1, &self.kvs);
+            self.svc_request_failed.add(&self.ctx, 1, &kvs);
+        }
+    }
+
+    /// A request to the temporal service was a retry of an earlier, failed, attempt
+    pub(crate) fn svc_request_retried(&self) {
+        if self.poll_is_long {
+            self.long_svc_request_retried.add(&self.ctx, 1, &self.kvs);
+        } else {
+            self.svc_request_retried.add(&self.ctx, 1, &self.kvs);
         }
     }
 
@@ -103,6 +126,9 @@ impl MetricsContext {
 const KEY_NAMESPACE: &str = "namespace";
 const KEY_SVC_METHOD: &str = "operation";
 const KEY_TASK_QUEUE: &str = "task_queue";
+const KEY_STATUS_CODE: &str = "status_code";
+/// Status code label used for requests which failed without any response from the server
+const TRANSPORT_ERROR_STATUS: &str = "TransportError";
 
 pub(crate) fn namespace_kv(ns: String) -> KeyValue {
     KeyValue::new(KEY_NAMESPACE, ns)
@@ -116,6 +142,33 @@ pub(crate) fn svc_operation(op: String) -> KeyValue {
     KeyValue::new(KEY_SVC_METHOD, op)
 }
 
+fn status_code_kv(code: Option<Code>) -> KeyValue {
+    let code = match code {
+        Some(c) => format!("{c:?}"),
+        None => TRANSPORT_ERROR_STATUS.to_string(),
+    };
+    KeyValue::new(KEY_STATUS_CODE, code)
+}
+
+tokio::task_local! {
+    /// Whether requests made by the current call attempt are retries of an earlier attempt
+    static IS_RETRY_ATTEMPT: bool;
+}
+
+/// Runs one attempt of a call. Requests made by retry attempts are counted as retries, with the
+/// same labels as any other request.
+pub(crate) async fn as_call_attempt<F: Future>(is_retry: bool, attempt: F) -> F::Output {
+    IS_RETRY_ATTEMPT.scope(is_retry, attempt).await
+}
+
+/// Returns the status code of a failed request, if the given headers or trailers contain one
+fn failure_code(headers: &http::HeaderMap) -> Option<Code> {
+    headers
+        .get("grpc-status")
+        .map(|v| Code::from_bytes(v.as_bytes()))
+        .filter(|c| *c != Code::Ok)
+}
+
 /// Implements metrics functionality for gRPC (really, any http) calls
 #[derive(Debug, Clone)]
 pub struct GrpcMetricSvc {
@@ -125,7 +178,7 @@ pub struct GrpcMetricSvc {
 }
 
 impl Service<http::Request<BoxBody>> for GrpcMetricSvc {
-    type Response = http::Response<tonic::transport::Body>;
+    type Response = http::Response<MetricsBody>;
     type Error = tonic::transport::Error;
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -154,6 +207,9 @@ impl Service<http::Request<BoxBody>> for GrpcMetricSvc {
                         metrics.set_is_long_poll();
                     }
                     metrics.svc_request();
+                    if IS_RETRY_ATTEMPT.try_with(|r| *r).unwrap_or_default() {
+                        metrics.svc_request_retried();
+                    }
                     metrics
                 })
             });
@@ -161,14 +217,60 @@ impl Service<http::Request<BoxBody>> for GrpcMetricSvc {
         async move {
             let started = Instant::now();
             let res = callfut.await;
-            if let Some(metrics) = metrics {
-                metrics.record_svc_req_latency(started.elapsed());
-                if res.is_err() {
-                    metrics.svc_request_failed();
+            let mut metrics = metrics;
+            if let Some(m) = metrics.as_ref() {
+                m.record_svc_req_latency(started.elapsed());
+                // The server usually reports errors with a trailers-only response, so the status
+                // shows up in the headers. Otherwise, it's in the trailers, which the body checks.
+                let failure_code = match &res {
+                    Ok(resp) => failure_code(resp.headers()).map(Some),
+                    Err(_) => Some(None),
+                };
+                if let Some(code) = failure_code {
+                    m.svc_request_failed(code);
+                    metrics = None;
                 }
             }
-            res
+            res.map(|resp| resp.map(|inner| MetricsBody { inner, metrics }))
         }
         .boxed()
     }
 }
+
+/// Response body which records a failure if the server reports one in the trailers
+#[derive(Debug)]
+pub struct MetricsBody {
+    inner: transport::Body,
+    // Taken once the trailers have been checked
+    metrics: Option<MetricsContext>,
+}
+
+impl Body for MetricsBody {
+    type Data = Bytes;
+    type Error = <transport::Body as Body>::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.get_mut().inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        let res = ready!(Pin::new(&mut this.inner).poll_trailers(cx));
+        if let (Ok(Some(trailers)), Some(metrics)) = (&res, this.metrics.take()) {
+            if let Some(code) = failure_code(trailers) {
+                metrics.svc_request_failed(Some(code));
+            }
+        }
+        Poll::Ready(res)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
@@ -1,11 +1,13 @@
 use crate::{
-    ClientOptions, ListClosedFilters, ListOpenFilters, Namespace, RegisterNamespaceOptions, Result,
-    RetryConfig, ScheduleOptions, SignalWithStartOptions, StartTimeFilter, WorkflowClientTrait,
-    WorkflowOptions,
+    metrics::as_call_attempt, ClientOptions, ListClosedFilters, ListOpenFilters, Namespace,
+    RegisterNamespaceOptions, Result, RetryConfig, ScheduleOptions, SignalWithStartOptions,
+    StartTimeFilter, WorkflowClientTrait, WorkflowOptions,
 };
 use backoff::{backoff::Backoff, exponential::ExponentialBackoff, Clock, SystemClock};
 use futures_retry::{ErrorHandler, FutureRetry, RetryPolicy};
-use std::{fmt::Debug, future::Future, sync::Arc, time::Duration};
+use std::{
+    borrow::Cow, collections::HashMap, fmt::Debug, future::Future, sync::Arc, time::Duration,
+};
 use temporal_sdk_core_protos::{
     coresdk::workflow_commands::QueryResult,
     temporal::api::{
@@ -38,20 +40,55 @@ const POLL_ACTIVITY_METH_NAME: &str = "poll_activity_task_queue";
 
 /// A wrapper for a [WorkflowClientTrait] or [crate::WorkflowService] implementor which performs
 /// auto-retries
+///
+/// Long polls are retried according to their own config, and additionally tolerate cancellation,
+/// timeouts, and (for a short while) otherwise non-retryable errors.
 #[derive(Debug, Clone)]
 pub struct RetryClient<SG> {
     client: SG,
     retry_config: Arc<RetryConfig>,
+    long_poll_retry_config: Arc<RetryConfig>,
+    call_overrides: Arc<HashMap<String, RetryConfig>>,
 }
 
 impl<SG> RetryClient<SG> {
-    /// Use the provided retry config with the provided client
+    /// Use the provided retry config with the provided client. Long polls use
+    /// [RetryConfig::poll_retry_policy].
     pub fn new(client: SG, retry_config: RetryConfig) -> Self {
         Self {
             client,
             retry_config: Arc::new(retry_config),
+            long_poll_retry_config: Arc::new(RetryConfig::poll_retry_policy()),
+            call_overrides: Default::default(),
         }
     }
+
+    /// Use all the retry configs set in the provided options with the provided client
+    pub fn from_options(client: SG, options: &ClientOptions) -> Self {
+        Self {
+            client,
+            retry_config: Arc::new(options.retry_config.clone()),
+            long_poll_retry_config: Arc::new(options.long_poll_retry_config.clone()),
+            call_overrides: Arc::new(options.call_retry_overrides.clone()),
+        }
+    }
+
+    /// Use the provided retry config for long-poll calls
+    pub fn with_long_poll_retry_config(mut self, retry_config: RetryConfig) -> Self {
+        self.long_poll_retry_config = Arc::new(retry_config);
+        self
+    }
+
+    /// Use the provided retry config for the call with the given name, instead of whichever one
+    /// it would otherwise use. See [ClientOptions::call_retry_overrides].
+    pub fn with_call_retry_config(
+        mut self,
+        call_name: impl Into<String>,
+        retry_config: RetryConfig,
+    ) -> Self {
+        Arc::make_mut(&mut self.call_overrides).insert(call_name.into(), retry_config);
+        self
+    }
 }
 
 impl<SG> RetryClient<SG> {
@@ -88,21 +125,31 @@ impl<SG> RetryClient<SG> {
     }
 
     pub(crate) fn get_retry_config(&self, call_name: &'static str) -> RetryConfig {
+        if let Some(cfg) = self.call_overrides.get(call_name) {
+            return cfg.clone();
+        }
         match CallType::from_call_name(call_name) {
             CallType::Normal => (*self.retry_config).clone(),
-            CallType::LongPoll => RetryConfig::poll_retry_policy(),
+            CallType::LongPoll => (*self.long_poll_retry_config).clone(),
         }
     }
 
+    /// Every attempt after the first is marked as a retry, so that the metrics layer can count it
+    /// with the same labels as the request itself.
     pub(crate) fn make_future_retry<R, F, Fut>(
         rtc: RetryConfig,
-        factory: F,
+        mut factory: F,
         call_name: &'static str,
-    ) -> FutureRetry<F, TonicErrorHandler<SystemClock>>
+    ) -> impl Future<Output = Result<(R, usize), (tonic::Status, usize)>>
     where
         F: FnMut() -> Fut + Unpin,
         Fut: Future<Output = Result<R>>,
     {
+        let mut attempt = 0;
+        let factory = move || {
+            attempt += 1;
+            as_call_attempt(attempt > 1, factory())
+        };
         FutureRetry::new(
             factory,
             TonicErrorHandler::new(rtc, RetryConfig::throttle_retry_policy(), call_name),
@@ -115,6 +162,7 @@ pub(crate) struct TonicErrorHandler<C: Clock> {
     backoff: ExponentialBackoff<C>,
     throttle_backoff: ExponentialBackoff<C>,
     max_retries: usize,
+    retryable_codes: Cow<'static, [Code]>,
     call_type: CallType,
     call_name: &'static str,
 }
@@ -142,6 +190,7 @@ where
     ) -> Self {
         Self {
             max_retries: cfg.max_retries,
+            retryable_codes: cfg.retryable_codes.clone(),
             call_type: CallType::from_call_name(call_name),
             call_name,
             backoff: cfg.into_exp_backoff(clock),
@@ -193,7 +242,7 @@ where
         let long_poll_allowed =
             is_long_poll && [Code::Cancelled, Code::DeadlineExceeded].contains(&e.code());
 
-        if RETRYABLE_ERROR_CODES.contains(&e.code()) || long_poll_allowed {
+        if self.retryable_codes.contains(&e.code()) || long_poll_allowed {
             if current_attempt == 1 {
                 debug!(error=?e, "gRPC call {} failed on first attempt", self.call_name);
             } else if self.should_log_retry_warning(current_attempt) {
@@ -631,6 +680,7 @@ mod tests {
         max_interval: Duration::from_millis(2),
         max_elapsed_time: None,
         max_retries: 10,
+        retryable_codes: Cow::Borrowed(&RETRYABLE_ERROR_CODES),
     };
 
     #[tokio::test]
@@ -681,6 +731,7 @@ mod tests {
             for call_name in [POLL_WORKFLOW_METH_NAME, POLL_ACTIVITY_METH_NAME] {
                 let mut err_handler = TonicErrorHandler {
                     max_retries: TEST_RETRY_CONFIG.max_retries,
+                    retryable_codes: TEST_RETRY_CONFIG.retryable_codes,
                     call_type: CallType::LongPoll,
                     call_name,
                     backoff: TEST_RETRY_CONFIG.into_exp_backoff(FixedClock(Instant::now())),
@@ -706,6 +757,7 @@ mod tests {
             for call_name in [POLL_WORKFLOW_METH_NAME, POLL_ACTIVITY_METH_NAME] {
                 let mut err_handler = TonicErrorHandler {
                     max_retries: TEST_RETRY_CONFIG.max_retries,
+                    retryable_codes: TEST_RETRY_CONFIG.retryable_codes,
                     call_type: CallType::LongPoll,
                     call_name,
                     backoff: TEST_RETRY_CONFIG.into_exp_backoff(FixedClock(Instant::now())),
@@ -756,6 +808,7 @@ mod tests {
     async fn retry_resource_exhausted() {
         let mut err_handler = TonicErrorHandler {
             max_retries: TEST_RETRY_CONFIG.max_retries,
+            retryable_codes: TEST_RETRY_CONFIG.retryable_codes,
             call_type: CallType::Normal,
             call_name: POLL_WORKFLOW_METH_NAME,
             backoff: TEST_RETRY_CONFIG.into_exp_backoff(FixedClock(Instant::now())),
@@ -766,6 +819,7 @@ mod tests {
                 max_interval: Duration::from_millis(10),
                 max_elapsed_time: None,
                 max_retries: 10,
+                retryable_codes: Cow::Borrowed(&RETRYABLE_ERROR_CODES),
             }
             .into_exp_backoff(FixedClock(Instant::now())),
         };
@@ -787,6 +841,55 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn only_configured_codes_are_retried() {
+        let mut mock_client = MockWorkflowClientTrait::new();
+        mock_client
+            .expect_cancel_activity_task()
+            .returning(|_, _| Err(Status::new(Code::Unavailable, "normally retryable")))
+            .times(1);
+        let retry_client = RetryClient::new(
+            mock_client,
+            TEST_RETRY_CONFIG.with_retryable_codes(&[Code::Internal][..]),
+        );
+        let result = retry_client
+            .cancel_activity_task(vec![1].into(), None)
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn call_overrides_take_precedence() {
+        let fake_retry = RetryClient::new((), TEST_RETRY_CONFIG)
+            .with_long_poll_retry_config(RetryConfig {
+                max_retries: 5,
+                ..TEST_RETRY_CONFIG
+            })
+            .with_call_retry_config(
+                POLL_WORKFLOW_METH_NAME,
+                RetryConfig {
+                    max_retries: 3,
+                    ..TEST_RETRY_CONFIG
+                },
+            );
+        assert_eq!(
+            fake_retry
+                .get_retry_config(POLL_WORKFLOW_METH_NAME)
+                .max_retries,
+            3
+        );
+        assert_eq!(
+            fake_retry
+                .get_retry_config(POLL_ACTIVITY_METH_NAME)
+                .max_retries,
+            5
+        );
+        assert_eq!(
+            fake_retry.get_retry_config("start_workflow").max_retries,
+            10
+        );
+    }
+
     #[tokio::test]
     async fn long_poll_retries_forever() {
         // A bit odd, but we don't need a real client to test the retry client passes through the
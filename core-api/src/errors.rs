@@ -1,7 +1,120 @@
 //! Error types exposed by public APIs
 
+use crate::worker::WorkerConfigBuilderError;
+use temporal_client::ClientInitError;
 use temporal_sdk_core_protos::coresdk::activity_result::ActivityExecutionResult;
 
+/// The top-level error type for fallible public core APIs. Each variant identifies a broad
+/// category and wraps a more specific, matchable error type. The underlying cause (if any) is
+/// always available via [std::error::Error::source], so lang bridges should not need to parse
+/// error strings to map these into idiomatic exceptions.
+///
+/// Methods on [crate::Worker] return their own, more specific errors (ex: [PollWfError]), as do
+/// building a [crate::worker::WorkerConfig] and connecting a client. All of them convert into this
+/// type, so a bridge may use `?` to funnel every failure into a single error type.
+#[derive(thiserror::Error, Debug)]
+pub enum CoreError {
+    /// A failure while interacting with a worker
+    #[error("Worker error")]
+    Worker(#[from] WorkerError),
+    /// A failure establishing or using a client connection to the server
+    #[error("Client error")]
+    Client(#[from] ClientError),
+    /// A failure while replaying histories
+    #[error("Replay error")]
+    Replay(#[from] ReplayError),
+    /// Invalid configuration, or a failure initializing something based on configuration
+    #[error("Configuration error")]
+    Config(#[from] ConfigError),
+}
+
+/// Any of the errors which can be returned by the methods on [crate::Worker]
+#[derive(thiserror::Error, Debug)]
+pub enum WorkerError {
+    /// See [PollWfError]
+    #[error("Workflow poll failed")]
+    PollWorkflow(#[from] PollWfError),
+    /// See [PollActivityError]
+    #[error("Activity poll failed")]
+    PollActivity(#[from] PollActivityError),
+    /// See [CompleteWfError]
+    #[error("Workflow activation completion failed")]
+    CompleteWorkflow(#[from] CompleteWfError),
+    /// See [CompleteActivityError]
+    #[error("Activity task completion failed")]
+    CompleteActivity(#[from] CompleteActivityError),
+    /// See [HistoryExportError]
+    #[error("History export failed")]
+    HistoryExport(#[from] HistoryExportError),
+}
+
+macro_rules! into_core_error {
+    ($($err:ty => $category:ident),* $(,)?) => {
+        $(impl From<$err> for CoreError {
+            fn from(e: $err) -> Self {
+                CoreError::$category(e.into())
+            }
+        })*
+    };
+}
+into_core_error!(
+    PollWfError => Worker,
+    PollActivityError => Worker,
+    CompleteWfError => Worker,
+    CompleteActivityError => Worker,
+    HistoryExportError => Worker,
+    ClientInitError => Client,
+    WorkerConfigBuilderError => Config,
+);
+
+/// Errors related to the client used to communicate with the server
+#[derive(thiserror::Error, Debug)]
+pub enum ClientError {
+    /// The client could not connect to the server
+    #[error("Client failed to initialize")]
+    Init(#[from] ClientInitError),
+    /// The client passed to a worker is bound to a different namespace than the worker itself
+    #[error(
+        "Client is bound to namespace {client_namespace} but the worker is configured for \
+         namespace {worker_namespace}"
+    )]
+    NamespaceMismatch {
+        /// The namespace in the worker's configuration
+        worker_namespace: String,
+        /// The namespace the client is bound to
+        client_namespace: String,
+    },
+}
+
+/// Errors encountered while replaying workflow histories
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+    /// Histories were fed to a replay worker which has already been dropped or shut down
+    #[error("The replay worker is no longer accepting histories")]
+    FeederClosed,
+    /// Unhandled error when fetching histories to replay from the server
+    #[error("Unhandled grpc error when fetching history")]
+    TonicError(#[from] tonic::Status),
+    /// A fetched history could not be used for replay
+    #[error("Invalid history for replay: {0}")]
+    InvalidHistory(String),
+}
+
+/// Errors caused by invalid configuration, or by failing to initialize components of core using
+/// the provided configuration
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    /// The worker configuration failed validation
+    #[error("Invalid worker configuration")]
+    InvalidWorkerConfig(#[from] WorkerConfigBuilderError),
+    /// Telemetry (tracing, logging, or metrics export) could not be initialized
+    #[error("Telemetry failed to initialize")]
+    Telemetry(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The tokio runtime could not be constructed
+    #[error("Runtime failed to initialize")]
+    Runtime(#[from] std::io::Error),
+}
+
 /// Errors thrown by [crate::Worker::poll_workflow_activation]
 #[derive(thiserror::Error, Debug)]
 pub enum PollWfError {
@@ -46,6 +159,44 @@ pub enum CompleteWfError {
         /// The run associated with the completion
         run_id: String,
     },
+    /// The worker's workflow force eviction timeout elapsed before this completion could be
+    /// processed, and the run it belongs to was forcibly evicted. No eviction is sent for the run,
+    /// so lang should drop it now.
+    #[error("Worker shut down before the completion for run {run_id} could be processed")]
+    ShutDown {
+        /// The run associated with the completion
+        run_id: String,
+    },
+    /// Lang did not complete the activation within
+    /// [crate::worker::WorkerConfig::workflow_activation_deadline], so the run it belongs to was
+    /// considered deadlocked and evicted. Lang will also have been sent an eviction for the run.
+    #[error("Run {run_id} was evicted because its activation was not completed in time")]
+    ActivationDeadlineExceeded {
+        /// The run associated with the completion
+        run_id: String,
+    },
+    /// No activation of the run was awaiting completion. EX: The activation was already
+    /// completed, the run was evicted, or no activation was ever issued for it. The completion was
+    /// ignored.
+    #[error("Run {run_id} has no activation awaiting completion")]
+    NoOutstandingActivation {
+        /// The run associated with the completion
+        run_id: String,
+    },
+}
+
+/// Errors thrown when exporting the history of a workflow run cached by a worker
+#[derive(thiserror::Error, Debug)]
+pub enum HistoryExportError {
+    /// The run is not in the worker's cache, so there is no applied history to export
+    #[error("Run {run_id} is not cached by this worker")]
+    RunNotCached {
+        /// The run which was requested
+        run_id: String,
+    },
+    /// Unhandled error when fetching history from the temporal server
+    #[error("Unhandled grpc error when fetching history")]
+    TonicError(#[from] tonic::Status),
 }
 
 /// Errors thrown by [crate::Worker::complete_activity_task]
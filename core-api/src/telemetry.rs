@@ -21,6 +21,7 @@ pub trait CoreTelemetry {
 
 /// Telemetry configuration options. Construct with [TelemetryOptionsBuilder]
 #[derive(Debug, Clone, derive_builder::Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 #[non_exhaustive]
 pub struct TelemetryOptions {
     /// Optional trace exporter - set as None to disable.
@@ -38,6 +39,22 @@ pub struct TelemetryOptions {
     #[builder(default)]
     pub no_temporal_prefix_for_metrics: bool,
 
+    /// If set, every metric name is prefixed with this instead of `temporal_`. Takes precedence
+    /// over [TelemetryOptions::no_temporal_prefix_for_metrics].
+    #[builder(setter(into, strip_option), default)]
+    pub metric_prefix: Option<String>,
+
+    /// Overrides the histogram bucket boundaries used for specific metrics. Keys are metric names
+    /// without any prefix (ex: `workflow_endtoend_latency`), values are the bucket boundaries, and
+    /// must be non-empty and strictly increasing. Metrics not present here keep their default
+    /// boundaries.
+    ///
+    /// Boundaries are in the unit the metric records. For latency metrics that is milliseconds,
+    /// except for `workflow_machine_handle_latency_us`, which is in microseconds.
+    /// `workflow_command_queue_depth` is a count of commands.
+    #[builder(default)]
+    pub histogram_bucket_overrides: HashMap<String, Vec<f64>>,
+
     /// Specifies the aggregation temporality for metric export. Defaults to cumulative.
     #[builder(default = "MetricTemporality::Cumulative")]
     pub metric_temporality: MetricTemporality,
@@ -115,6 +132,22 @@ impl Default for TelemetryOptions {
     }
 }
 
+impl TelemetryOptionsBuilder {
+    fn validate(&self) -> Result<(), String> {
+        for (name, buckets) in self.histogram_bucket_overrides.iter().flatten() {
+            if buckets.is_empty() {
+                return Err(format!("Histogram buckets for `{name}` must not be empty"));
+            }
+            if buckets.iter().any(|b| b.is_nan()) || buckets.windows(2).any(|w| w[0] >= w[1]) {
+                return Err(format!(
+                    "Histogram buckets for `{name}` must be strictly increasing"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// A log line (which ultimately came from a tracing event) exported from Core->Lang
 #[derive(Debug)]
 pub struct CoreLog {
@@ -143,3 +176,24 @@ impl CoreLog {
             .as_millis()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_histogram_buckets_are_rejected() {
+        for buckets in [vec![], vec![10., 5.], vec![5., 5.], vec![f64::NAN]] {
+            let overrides = HashMap::from([("request_latency".to_string(), buckets)]);
+            assert!(TelemetryOptionsBuilder::default()
+                .histogram_bucket_overrides(overrides)
+                .build()
+                .is_err());
+        }
+        let overrides = HashMap::from([("request_latency".to_string(), vec![5., 10.])]);
+        assert!(TelemetryOptionsBuilder::default()
+            .histogram_bucket_overrides(overrides)
+            .build()
+            .is_ok());
+    }
+}
@@ -1,5 +1,19 @@
-use std::time::Duration;
-use tokio::sync::mpsc::UnboundedSender;
+use std::{
+    collections::HashSet,
+    fmt::{Debug, Formatter},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use temporal_sdk_core_protos::{
+    coresdk::{
+        activity_task::ActivityTask, workflow_activation::WorkflowActivation,
+        workflow_completion::WorkflowActivationCompletion, ActivityTaskCompletion,
+        AsJsonPayloadExt, FromJsonPayloadExt,
+    },
+    temporal::api::{failure::v1::Failure, history::v1::History},
+};
+use tokio::sync::{mpsc::UnboundedSender, Semaphore};
 
 const MAX_OUTSTANDING_WFT_DEFAULT: usize = 100;
 const MAX_CONCURRENT_WFT_POLLS_DEFAULT: usize = 5;
@@ -43,6 +57,21 @@ pub struct WorkerConfig {
     /// concurrently
     #[builder(default = "100")]
     pub max_outstanding_local_activities: usize,
+    /// If set, decides when workflow task slots are available in place of the fixed limit set by
+    /// [WorkerConfig::max_outstanding_workflow_tasks]
+    #[builder(default)]
+    #[serde(skip)]
+    pub workflow_task_slot_supplier: Option<Arc<dyn SlotSupplier>>,
+    /// If set, decides when activity task slots are available in place of the fixed limit set by
+    /// [WorkerConfig::max_outstanding_activities]
+    #[builder(default)]
+    #[serde(skip)]
+    pub activity_slot_supplier: Option<Arc<dyn SlotSupplier>>,
+    /// If set, decides when local activity slots are available in place of the fixed limit set by
+    /// [WorkerConfig::max_outstanding_local_activities]
+    #[builder(default)]
+    #[serde(skip)]
+    pub local_activity_slot_supplier: Option<Arc<dyn SlotSupplier>>,
     /// Maximum number of concurrent poll workflow task requests we will perform at a time on this
     /// worker's task queue. See also [WorkerConfig::nonsticky_to_sticky_poll_ratio]. Must be at
     /// least 1.
@@ -52,21 +81,50 @@ pub struct WorkerConfig {
     /// be allowed for the nonsticky queue when sticky tasks are enabled. If both defaults are used,
     /// the sticky queue will allow 4 max pollers while the nonsticky queue will allow one. The
     /// minimum for either poller is 1, so if `max_concurrent_wft_polls` is 1 and sticky queues are
-    /// enabled, there will be 2 concurrent polls.
+    /// enabled, there will be 2 concurrent polls. Lower values favor cache hits by dedicating more
+    /// pollers to the sticky queue, while higher values pick up new workflows faster. Must be
+    /// greater than 0 and at most 1.
     #[builder(default = "0.2")]
     pub nonsticky_to_sticky_poll_ratio: f32,
     /// Maximum number of concurrent poll activity task requests we will perform at a time on this
     /// worker's task queue
     #[builder(default = "5")]
     pub max_concurrent_at_polls: usize,
+    /// If set, overrides [WorkerConfig::max_concurrent_wft_polls], determining how many
+    /// concurrent workflow task polls are performed. When sticky queues are enabled, the poll
+    /// counts are split between the sticky and nonsticky queues in the same way
+    /// `max_concurrent_wft_polls` is.
+    #[builder(default)]
+    pub workflow_task_poller_behavior: Option<PollerBehavior>,
+    /// If set, overrides [WorkerConfig::max_concurrent_at_polls], determining how many concurrent
+    /// activity task polls are performed.
+    #[builder(default)]
+    pub activity_task_poller_behavior: Option<PollerBehavior>,
     /// If set to true this worker will only handle workflow tasks and local activities, it will not
-    /// poll for activity tasks.
+    /// poll for activity tasks. Optional settings which only affect activity polling (ex:
+    /// [WorkerConfig::activity_task_poller_behavior]) are ignored, and a warning is logged if any
+    /// are set.
     #[builder(default = "false")]
     pub no_remote_activities: bool,
+    /// If set to true this worker will only poll for activity tasks. No workflow pollers or
+    /// workflow cache are created, so it is suited to dedicated activity fleets. Optional workflow
+    /// specific settings (ex: [WorkerConfig::workflow_task_poller_behavior]) must not be set,
+    /// [WorkerConfig::max_cached_workflows] must be zero, and
+    /// [WorkerConfig::no_remote_activities] must be false. Other workflow settings are ignored.
+    #[builder(default = "false")]
+    pub no_workflows: bool,
     /// How long a workflow task is allowed to sit on the sticky queue before it is timed out
-    /// and moved to the non-sticky queue where it may be picked up by any worker.
+    /// and moved to the non-sticky queue where it may be picked up by any worker. Lower values
+    /// make tasks fall back to other workers sooner when this one dies. Must be nonzero: building
+    /// the config fails if it is zero, which earlier versions accepted.
     #[builder(default = "Duration::from_secs(10)")]
     pub sticky_queue_schedule_to_start_timeout: Duration,
+    /// If set, the name of this worker's sticky queue, in place of one generated from the client
+    /// identity, task queue, and a random id. Must be unique to this worker, and differ from
+    /// [WorkerConfig::task_queue]. Ignored if [WorkerConfig::max_cached_workflows] is zero, since
+    /// sticky queues are then not used.
+    #[builder(default)]
+    pub sticky_queue_name_override: Option<String>,
 
     /// Longest interval for throttling activity heartbeats
     #[builder(default = "Duration::from_secs(60)")]
@@ -82,14 +140,15 @@ pub struct WorkerConfig {
     /// Sets the maximum number of activities per second the task queue will dispatch, controlled
     /// server-side. Note that this only takes effect upon an activity poll request. If multiple
     /// workers on the same queue have different values set, they will thrash with the last poller
-    /// winning.
+    /// winning. Values which are not positive normal numbers (ex: zero, negative, infinite, NaN, or
+    /// subnormal values) will cause building the options to fail.
     #[builder(default)]
     pub max_task_queue_activities_per_second: Option<f64>,
 
     /// Limits the number of activities per second that this worker will process. The worker will
     /// not poll for new activities if by doing so it might receive and execute an activity which
-    /// would cause it to exceed this limit. Negative, zero, or NaN values will cause building
-    /// the options to fail.
+    /// would cause it to exceed this limit. Values which are not positive normal numbers (ex: zero,
+    /// negative, infinite, NaN, or subnormal values) will cause building the options to fail.
     #[builder(default)]
     pub max_worker_activities_per_second: Option<f64>,
 
@@ -115,6 +174,13 @@ pub struct WorkerConfig {
     #[builder(default = "5")]
     pub fetching_concurrency: usize,
 
+    /// If set, the maximum number of events requested per page when fetching workflow history.
+    /// History is only fetched a page at a time as replay needs it, so smaller pages reduce how
+    /// many events are held in memory at once for very large histories, at the cost of more
+    /// requests. If unset, pages of at most 1000 events are requested.
+    #[builder(default)]
+    pub history_page_size: Option<u32>,
+
     /// If set, and the `save_wf_inputs` feature is enabled in core, will be sent a serialized
     /// instance of every input to workflow state in order. This is for testing purposes, SDK
     /// implementations never need to care about it.
@@ -126,28 +192,469 @@ pub struct WorkerConfig {
     /// initiated and this amount of time has elapsed.
     #[builder(default)]
     pub graceful_shutdown_period: Option<Duration>,
+
+    /// If set, and workflow processing has not finished this long after [crate::Worker::shutdown]
+    /// was called, every run still cached is forcibly evicted without waiting on lang, and the
+    /// workflow tasks they held are failed so that the server can hand them to another worker.
+    /// Completions for those runs then fail with [crate::errors::CompleteWfError::ShutDown].
+    ///
+    /// Lang is not sent a `RemoveFromCache` job for runs evicted this way, since it may be stuck
+    /// and core does not wait on it any longer. Once [crate::Worker::poll_workflow_activation]
+    /// returns a `ShutDown` error, lang must drop every workflow instance it still holds for this
+    /// worker as if each had been evicted.
+    #[builder(default)]
+    pub workflow_force_eviction_timeout: Option<Duration>,
+
+    /// If set, every workflow activation will be labeled with a shard number in the range
+    /// `[0, activation_shard_count)`, derived from a consistent hash of the workflow id. Lang
+    /// runtimes can use this to route activations to one of several executors.
+    #[builder(default)]
+    pub activation_shard_count: Option<u32>,
+
+    /// Name of the lang SDK using this worker, ex: `temporal-typescript`. Along with
+    /// [WorkerConfig::sdk_version], this is recorded in workflow task completion metadata so that
+    /// it is visible which SDK produced each workflow task. Must be set if and only if
+    /// `sdk_version` is. If neither is set, core reports its own name and version.
+    #[builder(default)]
+    pub sdk_name: Option<String>,
+    /// Version of the lang SDK using this worker, ex: `1.7.0`. Core's own version is appended to
+    /// it as semver build metadata when reported, ex: `1.7.0+core.0.1.0`.
+    #[builder(default)]
+    pub sdk_version: Option<String>,
+
+    /// If set, the names of every workflow type lang has registered on this worker. Workflow
+    /// tasks which would start a workflow of any other type are immediately failed by core with a
+    /// "not registered on this worker" error, rather than being delivered to lang. This avoids
+    /// waiting out timeouts when lang would only fail to find the type later.
+    #[builder(default)]
+    pub registered_workflow_types: Option<HashSet<String>>,
+    /// If set, the names of every activity type lang has registered on this worker. Activity
+    /// tasks of any other type are immediately failed by core, as with
+    /// [WorkerConfig::registered_workflow_types]. Local activities are not checked, since they are
+    /// only ever scheduled by workflows running on this worker.
+    #[builder(default)]
+    pub registered_activity_types: Option<HashSet<String>>,
+
+    /// If set, whenever a run fails with a fatal, nondeterminism, malformed payload, or unsupported
+    /// feature error in core's state machines, a [RunStateDump] of it is sent here so the failure can be investigated, and reproduced
+    /// offline with a replay worker. Enabling this makes core retain every history event it
+    /// applies to cached runs, so it increases memory usage.
+    #[builder(default)]
+    #[serde(skip)]
+    pub run_state_dump: Option<RunStateDumpTarget>,
+
+    /// If set, core logs a warning whenever a single field of a command produced by a workflow
+    /// (ex: an activity's input, or the workflow's result), or an activity's result or failure,
+    /// holds more than this many bytes of payloads. Applies to activity-only workers too.
+    #[builder(default)]
+    pub payload_size_warning_threshold: Option<usize>,
+    /// If set, workflow tasks whose commands have a field holding more than this many bytes of
+    /// payloads are failed by core, with a message naming the command and field, rather than being
+    /// sent to the server only to be rejected there. Commands replayed from history are not
+    /// checked, since the server already accepted them. Likewise, activity completions whose
+    /// result or failure exceed this are reported to the server as a non-retryable failure saying
+    /// so, including on activity-only workers.
+    #[builder(default)]
+    pub payload_size_limit: Option<usize>,
+
+    /// If set, transforms every failure core sends to the server on this worker's behalf (ex:
+    /// failures in workflow commands, or of workflow and activity tasks), and every failure in
+    /// history core reads from it, before core or lang sees it. See [FailureConverter].
+    #[builder(default)]
+    #[serde(skip)]
+    pub failure_converter: Option<Arc<dyn FailureConverter>>,
+
+    /// If set, lang must complete every workflow activation within this long of receiving it.
+    /// Otherwise the run is considered deadlocked (ex: workflow code is stuck in an infinite loop
+    /// or blocking call) and core evicts it without waiting any longer, failing its workflow task
+    /// so the server can retry it elsewhere. Lang is sent an eviction with the `DEADLOCK_DETECTED`
+    /// reason, and its eventual completion of the late activation is rejected with
+    /// [crate::errors::CompleteWfError::ActivationDeadlineExceeded].
+    #[builder(default)]
+    pub workflow_activation_deadline: Option<Duration>,
+
+    /// If set, sees (and may modify) every workflow activation and activity task this worker hands
+    /// to lang, and every completion lang sends back. See [TaskInterceptor].
+    #[builder(default)]
+    #[serde(skip)]
+    pub task_interceptor: Option<Arc<dyn TaskInterceptor>>,
+}
+
+/// A snapshot of a run's state, taken when it failed with an error core's state machines could not
+/// recover from. See [WorkerConfig::run_state_dump].
+#[derive(Debug, Clone)]
+pub struct RunStateDump {
+    /// The id of the workflow the run belongs to
+    pub workflow_id: String,
+    /// The id of the run which failed
+    pub run_id: String,
+    /// The run id the workflow's first execution started with, which is preserved across resets
+    pub original_run_id: String,
+    /// If history shows the workflow was reset, the run id produced by the most recent reset
+    pub reset_run_id: Option<String>,
+    /// The run's workflow type
+    pub workflow_type: String,
+    /// The error the run failed with
+    pub error: String,
+    /// Every event the run had applied, up to and including the workflow task it failed while
+    /// applying. Replaying it reproduces the failure.
+    pub history: History,
+    /// Commands the workflow issued which had not yet been matched with events in history
+    pub pending_commands: Vec<String>,
+    /// The type and current state of each of the run's state machines
+    pub machine_states: Vec<String>,
+}
+
+/// A snapshot of the state machines of a cached run, for debugging runs which are stuck or
+/// behaving nondeterministically without waiting for them to fail. Unlike [RunStateDump], it can be
+/// taken at any time, and does not include history.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MachinesDump {
+    /// The workflow id of the run
+    pub workflow_id: String,
+    /// The run id of the run
+    pub run_id: String,
+    /// The name of the run's workflow type
+    pub workflow_type: String,
+    /// The id of the last history event applied to the run
+    pub last_processed_event: i64,
+    /// True if the run is currently replaying history
+    pub replaying: bool,
+    /// Every state machine the run currently has
+    pub machines: Vec<MachineDump>,
+}
+
+/// The state of one of a run's state machines. See [MachinesDump].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MachineDump {
+    /// The type of machine, ex: `TimerMachine`
+    pub kind: String,
+    /// The name of the state the machine is currently in
+    pub state: String,
+    /// True if core created the machine itself, rather than in response to a workflow command
+    pub core_created: bool,
+    /// Commands this machine produced which have not yet been matched with events in history
+    pub pending_commands: Vec<String>,
+    /// The ids of the history events which were routed to this machine, in ascending order
+    pub event_ids: Vec<i64>,
+}
+
+/// Transforms the failures core exchanges with the server on a worker's behalf, ex: to keep
+/// sensitive messages and stack traces out of plaintext history.
+///
+/// Core does not implement the workflow update protocol, so it never sends update rejections or
+/// outcomes. Failures in update events read from history are still decoded.
+pub trait FailureConverter: Debug + Send + Sync {
+    /// Transform a failure which is about to be sent to the server
+    fn encode(&self, failure: &mut Failure);
+    /// Transform a failure read from history, before core uses it or hands it to lang. Should
+    /// undo [FailureConverter::encode].
+    fn decode(&self, failure: &mut Failure);
+}
+
+/// Intercepts the tasks a worker exchanges with lang, allowing cross-cutting concerns (ex:
+/// auditing, custom metrics, or propagating context through headers) to be implemented once in
+/// core rather than in every lang SDK. Hooks are called inline while polling or completing, so
+/// they should be quick. Every hook defaults to doing nothing.
+///
+/// Hooks may modify the contents of what they are given (ex: headers), but not which task it is.
+/// Core reverts any change to run ids or task tokens, and logs a warning. Adding or removing
+/// activation jobs is also unsupported.
+pub trait TaskInterceptor: Debug + Send + Sync {
+    /// Called with each workflow activation just before it is returned to lang
+    fn on_workflow_activation(&self, _activation: &mut WorkflowActivation) {}
+    /// Called with each workflow activation completion lang sends, before core processes it
+    fn on_workflow_activation_completion(&self, _completion: &mut WorkflowActivationCompletion) {}
+    /// Called with each activity task (including local activities, and cancellations) just
+    /// before it is returned to lang
+    fn on_activity_task(&self, _task: &mut ActivityTask) {}
+    /// Called with each activity task completion lang sends, before core processes it
+    fn on_activity_task_completion(&self, _completion: &mut ActivityTaskCompletion) {}
+}
+
+/// A [FailureConverter] which moves the message and stack trace of a failure, and of all its
+/// causes, into a JSON payload in its `encoded_attributes`, as other Temporal SDKs do when encoding
+/// common failure attributes. The message left behind is just "Encoded failure". Wrap it to
+/// additionally encrypt the payload.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EncodedAttributesFailureConverter;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EncodedFailureAttributes {
+    message: String,
+    stack_trace: String,
+}
+
+impl FailureConverter for EncodedAttributesFailureConverter {
+    fn encode(&self, failure: &mut Failure) {
+        if failure.encoded_attributes.is_none() {
+            let attrs = EncodedFailureAttributes {
+                message: std::mem::replace(&mut failure.message, "Encoded failure".to_string()),
+                stack_trace: std::mem::take(&mut failure.stack_trace),
+            };
+            failure.encoded_attributes = attrs.as_json_payload().ok();
+        }
+        if let Some(cause) = failure.cause.as_mut() {
+            self.encode(cause);
+        }
+    }
+
+    fn decode(&self, failure: &mut Failure) {
+        if let Some(attrs) = failure
+            .encoded_attributes
+            .as_ref()
+            .and_then(|p| EncodedFailureAttributes::from_json_payload(p).ok())
+        {
+            failure.message = attrs.message;
+            failure.stack_trace = attrs.stack_trace;
+            failure.encoded_attributes = None;
+        }
+        if let Some(cause) = failure.cause.as_mut() {
+            self.decode(cause);
+        }
+    }
+}
+
+/// Where [RunStateDump]s are sent. See [WorkerConfig::run_state_dump]. Dumps are delivered on a
+/// blocking thread, so neither writing them nor the callback holds up workflow processing, but
+/// they may arrive shortly after the run's failure is reported.
+#[derive(Clone)]
+pub enum RunStateDumpTarget {
+    /// Each dump is written into this directory as two files: `<run_id>.history.bin`, containing
+    /// the protobuf-encoded history, and `<run_id>.state.txt`, containing everything else.
+    Directory(PathBuf),
+    /// Each dump is passed to this callback
+    Callback(Arc<dyn Fn(RunStateDump) + Send + Sync>),
+}
+
+impl Debug for RunStateDumpTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunStateDumpTarget::Directory(dir) => f.debug_tuple("Directory").field(dir).finish(),
+            RunStateDumpTarget::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+/// Decides when a worker has capacity to take on another task of some kind (workflow tasks,
+/// activities, or local activities), allowing for custom admission control. A slot is reserved
+/// before core polls for (or otherwise accepts) a task, and released once that task is finished.
+/// Slots may also be released without ever being used, ex: when a poll returns no task.
+#[async_trait::async_trait]
+pub trait SlotSupplier: Debug + Send + Sync {
+    /// Wait until a slot is available, and reserve it.
+    ///
+    /// Must be cancel-safe. Core races this future against other events (ex: shutdown) and may
+    /// drop it before it resolves, in which case no slot may be left reserved.
+    async fn reserve_slot(&self);
+    /// Reserve a slot if one is available right now, returning whether one was reserved
+    fn try_reserve_slot(&self) -> bool;
+    /// Called once a reserved slot is actually being used for a task
+    fn mark_slot_used(&self);
+    /// Called whenever a reserved slot is given back, whether or not it was ever used
+    fn release_slot(&self);
+    /// The number of slots currently available, if the supplier knows it. Used for metrics.
+    fn available_slots(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// A [SlotSupplier] which allows at most a fixed number of slots to be reserved at once. This is
+/// what core uses for any kind of slot no custom supplier was provided for.
+#[derive(Debug)]
+pub struct FixedSizeSlotSupplier {
+    sem: Semaphore,
+}
+
+impl FixedSizeSlotSupplier {
+    /// Create a supplier which allows at most `num_slots` slots to be reserved at once
+    pub fn new(num_slots: usize) -> Self {
+        Self {
+            sem: Semaphore::new(num_slots),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SlotSupplier for FixedSizeSlotSupplier {
+    async fn reserve_slot(&self) {
+        self.sem
+            .acquire()
+            .await
+            .expect("Fixed size slot supplier semaphore is never closed")
+            .forget();
+    }
+
+    fn try_reserve_slot(&self) -> bool {
+        self.sem.try_acquire().map(|p| p.forget()).is_ok()
+    }
+
+    fn mark_slot_used(&self) {}
+
+    fn release_slot(&self) {
+        self.sem.add_permits(1);
+    }
+
+    fn available_slots(&self) -> Option<usize> {
+        Some(self.sem.available_permits())
+    }
+}
+
+/// Determines how many long polls a poller keeps outstanding at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PollerBehavior {
+    /// Always perform up to this many concurrent polls
+    SimpleMaximum(usize),
+    /// Start out performing `initial` concurrent polls, and scale between `minimum` and `maximum`
+    /// based on how polls are faring. Polls are added quickly while nearly every poll comes back
+    /// with a task, and removed one at a time while many polls come back empty (or fail).
+    Autoscaling {
+        minimum: usize,
+        maximum: usize,
+        initial: usize,
+    },
+}
+
+impl PollerBehavior {
+    /// The most concurrent polls this behavior will ever perform
+    pub fn maximum(&self) -> usize {
+        match self {
+            PollerBehavior::SimpleMaximum(max) => *max,
+            PollerBehavior::Autoscaling { maximum, .. } => *maximum,
+        }
+    }
+
+    fn map_counts(self, f: impl Fn(usize) -> usize) -> Self {
+        match self {
+            PollerBehavior::SimpleMaximum(max) => PollerBehavior::SimpleMaximum(f(max)),
+            PollerBehavior::Autoscaling {
+                minimum,
+                maximum,
+                initial,
+            } => PollerBehavior::Autoscaling {
+                minimum: f(minimum),
+                maximum: f(maximum),
+                initial: f(initial),
+            },
+        }
+    }
+
+    fn validate(&self, field: &str) -> Result<(), String> {
+        match *self {
+            PollerBehavior::SimpleMaximum(0) => {
+                Err(format!("`{field}` maximum must be at least 1"))
+            }
+            PollerBehavior::Autoscaling {
+                minimum,
+                maximum,
+                initial,
+            } if minimum == 0 || minimum > initial || initial > maximum => Err(format!(
+                "`{field}` must satisfy 1 <= minimum <= initial <= maximum"
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl WorkerConfig {
+    /// The supplier deciding when workflow task slots are available
+    pub fn workflow_task_slots(&self) -> Arc<dyn SlotSupplier> {
+        self.workflow_task_slot_supplier.clone().unwrap_or_else(|| {
+            Arc::new(FixedSizeSlotSupplier::new(
+                self.max_outstanding_workflow_tasks,
+            ))
+        })
+    }
+    /// The supplier deciding when activity task slots are available
+    pub fn activity_slots(&self) -> Arc<dyn SlotSupplier> {
+        self.activity_slot_supplier.clone().unwrap_or_else(|| {
+            Arc::new(FixedSizeSlotSupplier::new(self.max_outstanding_activities))
+        })
+    }
+    /// The supplier deciding when local activity slots are available
+    pub fn local_activity_slots(&self) -> Arc<dyn SlotSupplier> {
+        self.local_activity_slot_supplier
+            .clone()
+            .unwrap_or_else(|| {
+                Arc::new(FixedSizeSlotSupplier::new(
+                    self.max_outstanding_local_activities,
+                ))
+            })
+    }
+
     pub fn max_nonsticky_polls(&self) -> usize {
-        ((self.max_concurrent_wft_polls as f32 * self.nonsticky_to_sticky_poll_ratio) as usize)
-            .max(1)
+        self.nonsticky_share(self.max_concurrent_wft_polls)
     }
     pub fn max_sticky_polls(&self) -> usize {
-        self.max_concurrent_wft_polls
-            .saturating_sub(self.max_nonsticky_polls())
-            .max(1)
+        self.sticky_share(self.max_concurrent_wft_polls)
+    }
+
+    /// How workflow task polling behaves on the normal task queue if sticky queues are not in use
+    pub fn wft_poller_behavior(&self) -> PollerBehavior {
+        self.workflow_task_poller_behavior
+            .unwrap_or(PollerBehavior::SimpleMaximum(self.max_concurrent_wft_polls))
+    }
+    /// How workflow task polling behaves on the normal task queue when sticky queues are in use
+    pub fn nonsticky_wft_poller_behavior(&self) -> PollerBehavior {
+        self.wft_poller_behavior()
+            .map_counts(|n| self.nonsticky_share(n))
+    }
+    /// How workflow task polling behaves on the sticky queue
+    pub fn sticky_wft_poller_behavior(&self) -> PollerBehavior {
+        self.wft_poller_behavior()
+            .map_counts(|n| self.sticky_share(n))
+    }
+    /// How activity task polling behaves
+    pub fn activity_poller_behavior(&self) -> PollerBehavior {
+        self.activity_task_poller_behavior
+            .unwrap_or(PollerBehavior::SimpleMaximum(self.max_concurrent_at_polls))
+    }
+
+    fn nonsticky_share(&self, polls: usize) -> usize {
+        ((polls as f32 * self.nonsticky_to_sticky_poll_ratio) as usize).max(1)
+    }
+    fn sticky_share(&self, polls: usize) -> usize {
+        polls.saturating_sub(self.nonsticky_share(polls)).max(1)
     }
 }
 
 impl WorkerConfigBuilder {
     fn validate(&self) -> Result<(), String> {
+        if self.no_workflows == Some(true) {
+            self.validate_activity_only()?;
+        }
+        if self.no_remote_activities == Some(true) {
+            self.warn_workflow_only();
+        }
         if self.max_concurrent_wft_polls == Some(0) {
             return Err("`max_concurrent_wft_polls` must be at least 1".to_owned());
         }
         if self.max_concurrent_at_polls == Some(0) {
             return Err("`max_concurrent_at_polls` must be at least 1".to_owned());
         }
+        if let Some(ratio) = self.nonsticky_to_sticky_poll_ratio {
+            if ratio.is_nan() || ratio <= 0.0 || ratio > 1.0 {
+                return Err(
+                    "`nonsticky_to_sticky_poll_ratio` must be greater than 0 and at most 1"
+                        .to_owned(),
+                );
+            }
+        }
+        if let Some(Some(ref b)) = self.workflow_task_poller_behavior {
+            b.validate("workflow_task_poller_behavior")?;
+            if b.maximum()
+                > self
+                    .max_outstanding_workflow_tasks
+                    .unwrap_or(MAX_OUTSTANDING_WFT_DEFAULT)
+            {
+                return Err("`workflow_task_poller_behavior` maximum cannot exceed \
+                     `max_outstanding_workflow_tasks`"
+                    .to_owned());
+            }
+        }
+        if let Some(Some(ref b)) = self.activity_task_poller_behavior {
+            b.validate("activity_task_poller_behavior")?;
+        }
         if self.max_cached_workflows > Some(0)
             && self.max_outstanding_workflow_tasks > self.max_cached_workflows
         {
@@ -157,6 +664,49 @@ impl WorkerConfigBuilder {
                     .to_owned(),
             );
         }
+        let sdk_name_set = matches!(self.sdk_name, Some(Some(_)));
+        let sdk_version_set = matches!(self.sdk_version, Some(Some(_)));
+        if sdk_name_set != sdk_version_set {
+            return Err("`sdk_name` and `sdk_version` must be set together".to_owned());
+        }
+        if self.history_page_size == Some(Some(0)) {
+            return Err("`history_page_size` must be at least 1 if set".to_owned());
+        }
+        if self.activation_shard_count == Some(Some(0)) {
+            return Err("`activation_shard_count` must be at least 1 if set".to_owned());
+        }
+        if self.sticky_queue_schedule_to_start_timeout == Some(Duration::ZERO) {
+            return Err("`sticky_queue_schedule_to_start_timeout` must be nonzero".to_owned());
+        }
+        if let Some(Some(ref name)) = self.sticky_queue_name_override {
+            if name.is_empty() || Some(name) == self.task_queue.as_ref() {
+                return Err(
+                    "`sticky_queue_name_override` must be nonempty and differ from `task_queue`"
+                        .to_owned(),
+                );
+            }
+        }
+        if self.workflow_activation_deadline == Some(Some(Duration::ZERO)) {
+            return Err("`workflow_activation_deadline` must be nonzero if set".to_owned());
+        }
+        if let (Some(Some(warn)), Some(Some(limit))) =
+            (self.payload_size_warning_threshold, self.payload_size_limit)
+        {
+            if warn > limit {
+                return Err(
+                    "`payload_size_warning_threshold` cannot exceed `payload_size_limit`"
+                        .to_owned(),
+                );
+            }
+        }
+        if let Some(Some(ref x)) = self.max_task_queue_activities_per_second {
+            if !x.is_normal() || x.is_sign_negative() {
+                return Err(
+                    "`max_task_queue_activities_per_second` must be positive and nonzero"
+                        .to_owned(),
+                );
+            }
+        }
         if let Some(Some(ref x)) = self.max_worker_activities_per_second {
             if !x.is_normal() || x.is_sign_negative() {
                 return Err(
@@ -178,4 +728,82 @@ impl WorkerConfigBuilder {
         }
         Ok(())
     }
+
+    fn validate_activity_only(&self) -> Result<(), String> {
+        if self.no_remote_activities == Some(true) {
+            return Err(
+                "`no_workflows` and `no_remote_activities` cannot both be set, the worker would \
+                 have nothing to do"
+                    .to_owned(),
+            );
+        }
+        if self.max_cached_workflows > Some(0) {
+            return Err(
+                "`max_cached_workflows` must be zero when `no_workflows` is true".to_owned(),
+            );
+        }
+        let workflow_options_set = [
+            (
+                "workflow_task_slot_supplier",
+                matches!(self.workflow_task_slot_supplier, Some(Some(_))),
+            ),
+            (
+                "local_activity_slot_supplier",
+                matches!(self.local_activity_slot_supplier, Some(Some(_))),
+            ),
+            (
+                "workflow_task_poller_behavior",
+                matches!(self.workflow_task_poller_behavior, Some(Some(_))),
+            ),
+            (
+                "activation_shard_count",
+                matches!(self.activation_shard_count, Some(Some(_))),
+            ),
+            (
+                "registered_workflow_types",
+                matches!(self.registered_workflow_types, Some(Some(_))),
+            ),
+            (
+                "workflow_activation_deadline",
+                matches!(self.workflow_activation_deadline, Some(Some(_))),
+            ),
+            (
+                "sticky_queue_name_override",
+                matches!(self.sticky_queue_name_override, Some(Some(_))),
+            ),
+        ];
+        if let Some((name, _)) = workflow_options_set.iter().find(|(_, set)| *set) {
+            return Err(format!(
+                "`{name}` is a workflow option and cannot be set when `no_workflows` is true"
+            ));
+        }
+        Ok(())
+    }
+
+    fn warn_workflow_only(&self) {
+        let activity_options_set = [
+            (
+                "activity_slot_supplier",
+                matches!(self.activity_slot_supplier, Some(Some(_))),
+            ),
+            (
+                "activity_task_poller_behavior",
+                matches!(self.activity_task_poller_behavior, Some(Some(_))),
+            ),
+            (
+                "max_task_queue_activities_per_second",
+                matches!(self.max_task_queue_activities_per_second, Some(Some(_))),
+            ),
+            (
+                "max_worker_activities_per_second",
+                matches!(self.max_worker_activities_per_second, Some(Some(_))),
+            ),
+        ];
+        for (name, _) in activity_options_set.iter().filter(|(_, set)| *set) {
+            tracing::warn!(
+                "`{name}` is an activity polling option, and is ignored since \
+                 `no_remote_activities` is true"
+            );
+        }
+    }
 }
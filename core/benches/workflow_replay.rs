@@ -31,6 +31,39 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
+    // The same workflow replayed with and without events it has no use for. With those events
+    // skipped before full handling, the two should be close.
+    let mut group = c.benchmark_group("Skippable events replay");
+    for (name, t) in [
+        (
+            "without skippable events",
+            canned_histories::long_sequential_timers(num_timers as usize),
+        ),
+        (
+            "with deprecated patches",
+            canned_histories::long_sequential_timers_with_deprecated_patches(
+                num_timers as usize,
+                50,
+            ),
+        ),
+    ] {
+        let hist = HistoryForReplay::new(
+            t.get_full_history_info().unwrap().into(),
+            "whatever".to_string(),
+        );
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                tokio_runtime.block_on(async {
+                    let func = timers_wf(num_timers);
+                    let mut worker = replay_sdk_worker([hist.clone()]);
+                    worker.register_wf(DEFAULT_WORKFLOW_TYPE, func);
+                    worker.run().await.unwrap();
+                })
+            })
+        });
+    }
+    group.finish();
+
     let num_tasks = 50;
     let t = canned_histories::lots_of_big_signals(num_tasks);
     let hist = HistoryForReplay::new(
@@ -11,87 +11,106 @@ use std::{
         Arc,
     },
 };
-use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore, TryAcquireError};
+use temporal_sdk_core_api::worker::SlotSupplier;
+use tokio::sync::TryAcquireError;
 use tokio_util::sync::CancellationToken;
 
-/// Wraps a [Semaphore] with a function call that is fed the available permits any time a permit is
-/// acquired or restored through the provided methods
+/// Hands out permits for task slots from a [SlotSupplier], calling a function fed the available
+/// permits any time a permit is acquired or restored through the provided methods. The number of
+/// permits in use is recorded at the same times.
 #[derive(Clone)]
 pub(crate) struct MeteredSemaphore {
-    sem: Arc<Semaphore>,
+    supplier: Arc<dyn SlotSupplier>,
     /// The number of permit owners who have acquired a permit from the semaphore, but are not yet
     /// meaningfully using that permit. This is useful for giving a more semantically accurate count
     /// of used task slots, since we typically wait for a permit first before polling, but that slot
     /// isn't used in the sense the user expects until we actually also get the corresponding task.
     unused_claimants: Arc<AtomicUsize>,
+    /// The number of permits which have been marked used and not yet released. Unlike available
+    /// permits, this is known regardless of the supplier.
+    used_permits: Arc<AtomicUsize>,
     metrics_ctx: MetricsContext,
     record_fn: fn(&MetricsContext, usize),
 }
 
 impl MeteredSemaphore {
+    /// Create a semaphore handing out at most `inital_permits` permits at once
+    #[cfg(test)]
     pub fn new(
         inital_permits: usize,
         metrics_ctx: MetricsContext,
         record_fn: fn(&MetricsContext, usize),
+    ) -> Self {
+        Self::from_supplier(
+            Arc::new(temporal_sdk_core_api::worker::FixedSizeSlotSupplier::new(
+                inital_permits,
+            )),
+            metrics_ctx,
+            record_fn,
+        )
+    }
+
+    pub fn from_supplier(
+        supplier: Arc<dyn SlotSupplier>,
+        metrics_ctx: MetricsContext,
+        record_fn: fn(&MetricsContext, usize),
     ) -> Self {
         Self {
-            sem: Arc::new(Semaphore::new(inital_permits)),
+            supplier,
             unused_claimants: Arc::new(AtomicUsize::new(0)),
+            used_permits: Arc::new(AtomicUsize::new(0)),
             metrics_ctx,
             record_fn,
         }
     }
 
-    pub fn available_permits(&self) -> usize {
-        self.sem.available_permits()
+    /// The number of available permits, if the underlying supplier knows it
+    pub fn available_permits(&self) -> Option<usize> {
+        self.supplier.available_slots()
     }
 
     #[cfg(test)]
     pub fn unused_permits(&self) -> usize {
-        self.sem.available_permits() + self.unused_claimants.load(Ordering::Acquire)
+        self.available_permits().unwrap_or_default() + self.unused_claimants.load(Ordering::Acquire)
     }
 
-    pub async fn acquire_owned(&self) -> Result<OwnedMeteredSemPermit, AcquireError> {
-        let res = self.sem.clone().acquire_owned().await?;
-        Ok(self.build_owned(res))
+    pub async fn acquire_owned(&self) -> OwnedMeteredSemPermit {
+        self.supplier.reserve_slot().await;
+        self.build_owned()
     }
 
     pub fn try_acquire_owned(&self) -> Result<OwnedMeteredSemPermit, TryAcquireError> {
-        let res = self.sem.clone().try_acquire_owned()?;
-        Ok(self.build_owned(res))
+        if !self.supplier.try_reserve_slot() {
+            return Err(TryAcquireError::NoPermits);
+        }
+        Ok(self.build_owned())
     }
 
-    fn build_owned(&self, res: OwnedSemaphorePermit) -> OwnedMeteredSemPermit {
+    fn build_owned(&self) -> OwnedMeteredSemPermit {
         self.unused_claimants.fetch_add(1, Ordering::Release);
         self.record();
         OwnedMeteredSemPermit {
-            inner: res,
+            supplier: self.supplier.clone(),
             unused_claimants: Some(self.unused_claimants.clone()),
+            used_permits: self.used_permits.clone(),
             record_fn: self.record_owned(),
         }
     }
 
     fn record(&self) {
-        (self.record_fn)(
-            &self.metrics_ctx,
-            self.sem.available_permits() + self.unused_claimants.load(Ordering::Acquire),
-        );
+        if let Some(avail) = self.available_permits() {
+            (self.record_fn)(
+                &self.metrics_ctx,
+                avail + self.unused_claimants.load(Ordering::Acquire),
+            );
+        }
+        self.metrics_ctx
+            .used_task_slots(self.used_permits.load(Ordering::Acquire));
     }
 
-    fn record_owned(&self) -> Box<dyn Fn(bool) + Send + Sync> {
-        let rcf = self.record_fn;
-        let mets = self.metrics_ctx.clone();
-        let sem = self.sem.clone();
-        let uc = self.unused_claimants.clone();
-        // When being called from the drop impl, the semaphore permit isn't actually dropped yet,
-        // so account for that.
-        Box::new(move |add_one: bool| {
-            let extra = usize::from(add_one);
-            rcf(
-                &mets,
-                sem.available_permits() + uc.load(Ordering::Acquire) + extra,
-            )
-        })
+    fn record_owned(&self) -> Box<dyn Fn() + Send + Sync> {
+        let this = self.clone();
+        Box::new(move || this.record())
     }
 }
 
@@ -187,25 +206,34 @@ impl Drop for TrackedOwnedMeteredSemPermit {
     }
 }
 
-/// Wraps an [OwnedSemaphorePermit] to update metrics when it's dropped
+/// A slot reserved from a [SlotSupplier], which releases the slot and updates metrics when
+/// dropped
 pub(crate) struct OwnedMeteredSemPermit {
-    inner: OwnedSemaphorePermit,
+    supplier: Arc<dyn SlotSupplier>,
     /// See [MeteredSemaphore::unused_claimants]. If present when dropping, used to decrement the
     /// count.
     unused_claimants: Option<Arc<AtomicUsize>>,
-    record_fn: Box<dyn Fn(bool) + Send + Sync>,
+    /// See [MeteredSemaphore::used_permits]. Decremented when dropping if this permit was used.
+    used_permits: Arc<AtomicUsize>,
+    record_fn: Box<dyn Fn() + Send + Sync>,
 }
 impl Drop for OwnedMeteredSemPermit {
     fn drop(&mut self) {
         if let Some(uc) = self.unused_claimants.take() {
             uc.fetch_sub(1, Ordering::Release);
+        } else {
+            self.used_permits.fetch_sub(1, Ordering::Release);
         }
-        (self.record_fn)(true)
+        self.supplier.release_slot();
+        (self.record_fn)()
     }
 }
 impl Debug for OwnedMeteredSemPermit {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.inner.fmt(f)
+        f.debug_struct("OwnedMeteredSemPermit")
+            .field("supplier", &self.supplier)
+            .field("used", &self.unused_claimants.is_none())
+            .finish()
     }
 }
 impl OwnedMeteredSemPermit {
@@ -214,7 +242,9 @@ impl OwnedMeteredSemPermit {
     pub(crate) fn into_used(mut self) -> UsedMeteredSemPermit {
         if let Some(uc) = self.unused_claimants.take() {
             uc.fetch_sub(1, Ordering::Release);
-            (self.record_fn)(false)
+            self.used_permits.fetch_add(1, Ordering::Release);
+            self.supplier.mark_slot_used();
+            (self.record_fn)()
         }
         UsedMeteredSemPermit(self)
     }
@@ -225,12 +255,13 @@ pub(crate) struct UsedMeteredSemPermit(OwnedMeteredSemPermit);
 impl UsedMeteredSemPermit {
     #[cfg(feature = "save_wf_inputs")]
     pub(crate) fn fake_deserialized() -> Self {
-        let sem = Arc::new(Semaphore::new(1));
-        let inner = sem.try_acquire_owned().unwrap();
+        let supplier = Arc::new(temporal_sdk_core_api::worker::FixedSizeSlotSupplier::new(1));
+        supplier.try_reserve_slot();
         Self(OwnedMeteredSemPermit {
-            inner,
+            supplier,
             unused_claimants: None,
-            record_fn: Box::new(|_| {}),
+            used_permits: Arc::new(AtomicUsize::new(1)),
+            record_fn: Box::new(|| {}),
         })
     }
 }
@@ -285,4 +316,18 @@ mod tests {
         let perm = sem.try_acquire_owned().unwrap_err();
         assert_matches!(perm, TryAcquireError::Closed);
     }
+
+    #[test]
+    fn used_permits_only_counted_once_used() {
+        let sem = MeteredSemaphore::new(2, MetricsContext::no_op(), |_, _| {});
+        let perm = sem.try_acquire_owned().unwrap();
+        assert_eq!(sem.used_permits.load(Ordering::Acquire), 0);
+        let used = perm.into_used();
+        assert_eq!(sem.used_permits.load(Ordering::Acquire), 1);
+        let unused = sem.try_acquire_owned().unwrap();
+        drop(unused);
+        assert_eq!(sem.used_permits.load(Ordering::Acquire), 1);
+        drop(used);
+        assert_eq!(sem.used_permits.load(Ordering::Acquire), 0);
+    }
 }
@@ -6,11 +6,15 @@ use crate::{
         single_hist_mock_sg, test_worker_cfg, MockPollCfg, MockWorkerInputs, MocksHolder,
         QueueResponse, ResponseType, WorkerExt, WorkflowCachingPolicy, TEST_Q,
     },
-    worker::client::mocks::{mock_manual_workflow_client, mock_workflow_client},
+    worker::{
+        client::mocks::{mock_manual_workflow_client, mock_workflow_client},
+        ManagedWFFunc,
+    },
     ActivityHeartbeat, Worker, WorkerConfigBuilder,
 };
 use futures::FutureExt;
 use itertools::Itertools;
+use parking_lot::Mutex;
 use std::{
     cell::RefCell,
     collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
@@ -23,7 +27,7 @@ use std::{
     time::Duration,
 };
 use temporal_client::WorkflowOptions;
-use temporal_sdk::{ActivityOptions, WfContext};
+use temporal_sdk::{ActContext, ActivityOptions, SessionOptions, WfContext, WorkflowFunction};
 use temporal_sdk_core_api::{
     errors::{CompleteActivityError, PollActivityError},
     Worker as WorkerTrait,
@@ -41,11 +45,13 @@ use temporal_sdk_core_protos::{
             ScheduleActivity,
         },
         workflow_completion::WorkflowActivationCompletion,
-        ActivityTaskCompletion,
+        ActivityTaskCompletion, AsJsonPayloadExt, FromJsonPayloadExt,
     },
     temporal::api::{
         command::v1::{command::Attributes, ScheduleActivityTaskCommandAttributes},
+        common::v1::{ActivityType, RetryPolicy},
         enums::v1::EventType,
+        failure::v1::failure::FailureInfo,
         history::v1::{
             history_event::Attributes as EventAttributes, ActivityTaskScheduledEventAttributes,
         },
@@ -58,7 +64,14 @@ use temporal_sdk_core_protos::{
     TestHistoryBuilder, DEFAULT_WORKFLOW_TYPE,
 };
 use temporal_sdk_core_test_utils::{fanout_tasks, start_timer_cmd, TestWorker};
-use tokio::{join, sync::Barrier, time::sleep};
+use tokio::{
+    join,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        Barrier,
+    },
+    time::sleep,
+};
 use tokio_util::sync::CancellationToken;
 
 fn three_tasks() -> VecDeque<PollActivityTaskQueueResponse> {
@@ -136,6 +149,51 @@ async fn activity_not_found_returns_ok() {
     core.drain_activity_poller_and_shutdown().await;
 }
 
+#[tokio::test]
+async fn heartbeat_not_found_cancels_activity_without_reporting_completion() {
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_record_activity_heartbeat()
+        .times(1)
+        .returning(|_, _| Err(tonic::Status::not_found("workflow is gone")));
+
+    let core = mock_worker(MocksHolder::from_client_with_activities(
+        mock_client,
+        [PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_id: "act1".to_string(),
+            heartbeat_timeout: Some(prost_dur!(from_millis(1))),
+            ..Default::default()
+        }
+        .into()],
+    ));
+
+    let act = core.poll_activity_task().await.unwrap();
+    core.record_activity_heartbeat(ActivityHeartbeat {
+        task_token: act.task_token.clone(),
+        details: vec![vec![1_u8, 2, 3].into()],
+    });
+    let act = core.poll_activity_task().await.unwrap();
+    assert_matches!(
+        &act,
+        ActivityTask {
+            task_token,
+            variant: Some(activity_task::Variant::Cancel(Cancel { reason })),
+        } => {
+            assert_eq!(task_token, &vec![1]);
+            assert_eq!(*reason, ActivityCancelReason::NotFound as i32);
+        }
+    );
+    // The server has no use for the result, so it is not reported
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: act.task_token,
+        result: Some(ActivityExecutionResult::cancel_from_details(None)),
+    })
+    .await
+    .unwrap();
+    core.drain_activity_poller_and_shutdown().await;
+}
+
 #[tokio::test]
 async fn heartbeats_report_cancels_only_once() {
     let mut mock_client = mock_workflow_client();
@@ -708,6 +766,79 @@ async fn no_eager_activities_requested_when_worker_options_disable_remote_activi
     assert_eq!(num_eager_requested.load(Ordering::Relaxed), 0);
 }
 
+#[tokio::test]
+async fn no_eager_activities_requested_for_unregistered_activity_types() {
+    let wfid = "fake_wf_id";
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_full_wf_task();
+    t.add_workflow_execution_completed();
+    let eager_requested = Arc::new(Mutex::new(vec![]));
+    let eager_requested_clone = eager_requested.clone();
+
+    let mut mock = mock_workflow_client();
+    mock.expect_complete_workflow_task()
+        .times(1)
+        .returning(move |req| {
+            *eager_requested_clone.lock() = req
+                .commands
+                .into_iter()
+                .filter_map(|c| match c.attributes {
+                    Some(Attributes::ScheduleActivityTaskCommandAttributes(
+                        ScheduleActivityTaskCommandAttributes {
+                            request_eager_execution: true,
+                            activity_id,
+                            ..
+                        },
+                    )) => Some(activity_id),
+                    _ => None,
+                })
+                .collect();
+            Ok(RespondWorkflowTaskCompletedResponse {
+                workflow_task: None,
+                activity_tasks: vec![],
+                reset_history_event_id: 0,
+            })
+        });
+    let mut mock = single_hist_mock_sg(wfid, t, [1], mock, true);
+    let mut mock_poller = mock_manual_poller();
+    mock_poller
+        .expect_poll()
+        .returning(|| futures::future::pending().boxed());
+    mock.set_act_poller(Box::new(mock_poller));
+    mock.worker_cfg(|wc| {
+        wc.max_cached_workflows = 2;
+        wc.registered_activity_types = Some(HashSet::from(["registered".to_string()]));
+    });
+    let core = mock_worker(mock);
+
+    let wf_task = core.poll_workflow_activation().await.unwrap();
+    let cmds = ["registered", "unregistered"]
+        .into_iter()
+        .enumerate()
+        .map(|(i, act_type)| {
+            ScheduleActivity {
+                seq: i as u32 + 1,
+                activity_id: act_type.to_string(),
+                activity_type: act_type.to_string(),
+                task_queue: TEST_Q.to_string(),
+                cancellation_type: ActivityCancellationType::TryCancel as i32,
+                ..Default::default()
+            }
+            .into()
+        })
+        .collect();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        wf_task.run_id,
+        cmds,
+    ))
+    .await
+    .unwrap();
+
+    core.drain_pollers_and_shutdown().await;
+    assert_eq!(*eager_requested.lock(), vec!["registered".to_string()]);
+}
+
 /// This test verifies that activity tasks which come as replies to completing a WFT are properly
 /// delivered via polling.
 #[tokio::test]
@@ -1120,3 +1251,278 @@ async fn graceful_shutdown(#[values(true, false)] at_max_outstanding: bool) {
     }
     worker.drain_pollers_and_shutdown().await;
 }
+
+#[tokio::test]
+async fn pending_activities_report_retry_backoff() {
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_activity_task()
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskCompletedResponse::default()));
+    let core = mock_worker(MocksHolder::from_client_with_activities(
+        mock_client,
+        [PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_id: "act1".to_string(),
+            attempt: 3,
+            retry_policy: Some(RetryPolicy {
+                initial_interval: Some(prost_dur!(from_secs(1))),
+                backoff_coefficient: 2.0,
+                maximum_interval: Some(prost_dur!(from_secs(60))),
+                maximum_attempts: 5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .into()],
+    ));
+
+    let act = core.poll_activity_task().await.unwrap();
+    let pending = core.pending_activities();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].attempt, 3);
+    assert_eq!(pending[0].next_retry_backoff, Some(Duration::from_secs(4)));
+
+    core.complete_activity_task(ActivityTaskCompletion {
+        task_token: act.task_token,
+        result: Some(ActivityExecutionResult::ok(vec![1].into())),
+    })
+    .await
+    .unwrap();
+    assert!(core.pending_activities().is_empty());
+    core.drain_activity_poller_and_shutdown().await;
+}
+
+#[tokio::test]
+async fn unregistered_activity_types_failed_immediately() {
+    let mut tasks = VecDeque::from(vec![
+        PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            activity_id: "act1".to_string(),
+            activity_type: Some(ActivityType {
+                name: "unregistered".to_string(),
+            }),
+            ..Default::default()
+        },
+        PollActivityTaskQueueResponse {
+            task_token: vec![2],
+            activity_id: "act2".to_string(),
+            activity_type: Some(ActivityType {
+                name: "registered".to_string(),
+            }),
+            ..Default::default()
+        },
+    ]);
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_poll_activity_task()
+        .returning(move |_, _| Ok(tasks.pop_front().unwrap_or_default()));
+    mock_client
+        .expect_fail_activity_task()
+        .withf(|tt, f| {
+            tt.0 == vec![1]
+                && f.as_ref()
+                    .map(|f| {
+                        f.message.contains("not registered on this worker")
+                            && matches!(
+                                &f.failure_info,
+                                Some(FailureInfo::ApplicationFailureInfo(info))
+                                    if !info.non_retryable
+                            )
+                    })
+                    .unwrap_or_default()
+        })
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskFailedResponse::default()));
+
+    let worker = Worker::new_test(
+        test_worker_cfg()
+            .registered_activity_types(HashSet::from(["registered".to_string()]))
+            .build()
+            .unwrap(),
+        mock_client,
+    );
+
+    let act = worker.poll_activity_task().await.unwrap();
+    assert_eq!(act.task_token, vec![2]);
+    assert_matches!(
+        act.variant,
+        Some(activity_task::Variant::Start(s)) if s.activity_type == "registered"
+    );
+}
+
+#[tokio::test]
+async fn oversized_activity_result_is_reported_as_failure() {
+    let mut tasks = three_tasks();
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_poll_activity_task()
+        .returning(move |_, _| Ok(tasks.pop_front().unwrap_or_default()));
+    mock_client.expect_complete_activity_task().times(0);
+    mock_client
+        .expect_fail_activity_task()
+        .withf(|tt, f| {
+            tt.0 == vec![1]
+                && f.as_ref()
+                    .map(|f| {
+                        f.message.contains("Field `result` of activity completion")
+                            && f.maybe_application_failure()
+                                .map_or(false, |a| a.non_retryable)
+                    })
+                    .unwrap_or_default()
+        })
+        .times(1)
+        .returning(|_, _| Ok(RespondActivityTaskFailedResponse::default()));
+
+    let worker = Worker::new_test(
+        test_worker_cfg()
+            .payload_size_limit(Some(100))
+            .build()
+            .unwrap(),
+        mock_client,
+    );
+
+    let act = worker.poll_activity_task().await.unwrap();
+    worker
+        .complete_activity_task(ActivityTaskCompletion {
+            task_token: act.task_token,
+            result: Some(ActivityExecutionResult::ok(vec![0; 1000].into())),
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn session_activities_are_routed_to_session_host() {
+    let func = WorkflowFunction::new(|ctx: WfContext| async move {
+        let session = ctx.create_session(SessionOptions::default()).await?;
+        session
+            .activity(
+                &ctx,
+                ActivityOptions {
+                    activity_type: "in_session".to_string(),
+                    start_to_close_timeout: Some(Duration::from_secs(5)),
+                    ..Default::default()
+                },
+            )
+            .await;
+        session.complete(&ctx).await;
+        Ok(().into())
+    });
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_full_wf_task();
+    let scheduled_event_id = t.add(ActivityTaskScheduledEventAttributes {
+        activity_id: "1".to_string(),
+        activity_type: Some(ActivityType {
+            name: "__temporal_session_creation".to_string(),
+        }),
+        ..Default::default()
+    });
+    let started_event_id = t.add_activity_task_started(scheduled_event_id);
+    t.add_activity_task_completed(
+        scheduled_event_id,
+        started_event_id,
+        "host-q".as_json_payload().unwrap(),
+    );
+    t.add_workflow_task_scheduled_and_started();
+    let mut wfm = ManagedWFFunc::new(t, func, vec![]);
+
+    wfm.get_next_activation().await.unwrap();
+    let commands = wfm.get_server_commands().commands;
+    assert_eq!(commands.len(), 1);
+    assert_matches!(
+        commands[0].attributes.clone().unwrap(),
+        Attributes::ScheduleActivityTaskCommandAttributes(attrs) => {
+            assert_eq!(attrs.activity_type.unwrap().name, "__temporal_session_creation");
+            assert_eq!(attrs.task_queue.unwrap().name, TEST_Q);
+        }
+    );
+
+    // Once the host responds, the session heartbeat and the session's activity both go to the
+    // host's queue
+    wfm.get_next_activation().await.unwrap();
+    let commands = wfm.get_server_commands().commands;
+    let scheduled = commands
+        .into_iter()
+        .filter_map(|c| match c.attributes {
+            Some(Attributes::ScheduleActivityTaskCommandAttributes(attrs)) => Some((
+                attrs.activity_type.unwrap().name,
+                attrs.task_queue.unwrap().name,
+            )),
+            _ => None,
+        })
+        .collect_vec();
+    assert_eq!(
+        scheduled,
+        vec![
+            (
+                "__temporal_session_heartbeat".to_string(),
+                "host-q".to_string()
+            ),
+            ("in_session".to_string(), "host-q".to_string()),
+        ]
+    );
+    wfm.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn session_host_runs_activities_from_both_task_queues() {
+    let act_task =
+        |token: u8, activity_type: &str| -> QueueResponse<PollActivityTaskQueueResponse> {
+            PollActivityTaskQueueResponse {
+                task_token: vec![token],
+                activity_id: format!("act{token}"),
+                activity_type: Some(ActivityType {
+                    name: activity_type.to_string(),
+                }),
+                input: Some(().as_json_payload().unwrap().into()),
+                ..Default::default()
+            }
+            .into()
+        };
+    let (done_tx, mut done_rx) = unbounded_channel();
+    let mock_client_reporting_to = |tx: UnboundedSender<_>| {
+        let mut mock_client = mock_workflow_client();
+        mock_client
+            .expect_complete_activity_task()
+            .times(1)
+            .returning(move |tt, res| {
+                tx.send((tt, res)).unwrap();
+                Ok(RespondActivityTaskCompletedResponse::default())
+            });
+        mock_client
+    };
+    let core = mock_worker(MocksHolder::from_client_with_activities(
+        mock_client_reporting_to(done_tx.clone()),
+        [act_task(1, "__temporal_session_creation")],
+    ));
+    let mut session_mocks = MocksHolder::from_client_with_activities(
+        mock_client_reporting_to(done_tx),
+        [act_task(2, "in_session")],
+    );
+    session_mocks.worker_cfg(|cfg| cfg.task_queue = "host-q".to_string());
+    let session_core = mock_worker(session_mocks);
+
+    let mut worker = temporal_sdk::Worker::new_from_core(Arc::new(core), TEST_Q);
+    worker.enable_sessions(Arc::new(session_core));
+    worker.register_activity("in_session", |_ctx: ActContext, _: ()| async { Ok(()) });
+    let shutdown = worker.shutdown_handle();
+    let completions = async {
+        let mut completed = vec![done_rx.recv().await.unwrap(), done_rx.recv().await.unwrap()];
+        shutdown();
+        completed.sort_by_key(|(tt, _)| tt.0.clone());
+        completed
+    };
+    let (run_res, completed) = join!(worker.run(), completions);
+    run_res.unwrap();
+
+    // The creation activity is polled from the shared queue and hands out the host's queue, and
+    // the session's activity is polled from the host's queue by the session worker
+    let creation_result = completed[0].1.as_ref().unwrap();
+    assert_eq!(
+        String::from_json_payload(&creation_result.payloads[0]).unwrap(),
+        "host-q"
+    );
+    assert_eq!(completed[1].0 .0, vec![2]);
+}
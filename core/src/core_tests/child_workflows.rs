@@ -5,16 +5,26 @@ use crate::{
     },
     worker::{client::mocks::mock_workflow_client, ManagedWFFunc},
 };
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use temporal_client::WorkflowOptions;
 use temporal_sdk::{ChildWorkflowOptions, Signal, WfContext, WorkflowFunction, WorkflowResult};
 use temporal_sdk_core_api::Worker;
-use temporal_sdk_core_protos::coresdk::{
-    child_workflow::{child_workflow_result, ChildWorkflowCancellationType},
-    workflow_activation::{workflow_activation_job, WorkflowActivationJob},
-    workflow_commands::{
-        CancelChildWorkflowExecution, CompleteWorkflowExecution, StartChildWorkflowExecution,
+use temporal_sdk_core_protos::{
+    coresdk::{
+        child_workflow::{child_workflow_result, ChildWorkflowCancellationType},
+        workflow_activation::{workflow_activation_job, WorkflowActivationJob},
+        workflow_commands::{
+            CancelChildWorkflowExecution, CompleteWorkflowExecution, StartChildWorkflowExecution,
+        },
+        workflow_completion::WorkflowActivationCompletion,
+    },
+    temporal::api::{
+        command::v1::command::Attributes,
+        enums::v1::{CommandType, ParentClosePolicy},
     },
-    workflow_completion::WorkflowActivationCompletion,
 };
 use tokio::join;
 
@@ -98,6 +108,66 @@ async fn parent_cancels_child_wf(ctx: WfContext) -> WorkflowResult<()> {
     Ok(().into())
 }
 
+#[tokio::test]
+async fn child_start_and_signal_carry_headers() {
+    let wf_id = "fakeid";
+    let wf_type = DEFAULT_WORKFLOW_TYPE;
+    let t = canned_histories::single_child_workflow_signaled("child-id-1", SIGNAME);
+    let mut mh = MockPollCfg::from_resp_batches(wf_id, t, [1, 2, 3], mock_workflow_client());
+    mh.completion_asserts = Some(Box::new(|wftc| {
+        for cmd in &wftc.commands {
+            match cmd.attributes.as_ref().unwrap() {
+                Attributes::StartChildWorkflowExecutionCommandAttributes(attrs) => {
+                    let fields = &attrs.header.as_ref().unwrap().fields;
+                    assert_eq!(fields["start-key"].data, b"start-val");
+                }
+                Attributes::SignalExternalWorkflowExecutionCommandAttributes(attrs) => {
+                    let fields = &attrs.header.as_ref().unwrap().fields;
+                    assert_eq!(fields["sig-key"].data, b"sig-val");
+                }
+                _ => {}
+            }
+        }
+    }));
+    let mut worker = mock_sdk(mh);
+
+    worker.register_wf(wf_type.to_owned(), |ctx: WfContext| async move {
+        let child = ctx.child_workflow(ChildWorkflowOptions {
+            workflow_id: "child-id-1".to_string(),
+            workflow_type: "child".to_string(),
+            headers: [("start-key".to_string(), b"start-val".into())].into(),
+            ..Default::default()
+        });
+        let start_res = child
+            .start(&ctx)
+            .await
+            .into_started()
+            .expect("Child should get started");
+        let mut sig = Signal::new(SIGNAME, [b"Hi!"]);
+        sig.data.with_header("sig-key", b"sig-val");
+        start_res
+            .signal(&ctx, sig)
+            .await
+            .expect("signal result is ok");
+        start_res
+            .result()
+            .await
+            .status
+            .expect("child wf result is ok");
+        Ok(().into())
+    });
+    worker
+        .submit_wf(
+            wf_id.to_owned(),
+            wf_type.to_owned(),
+            vec![],
+            WorkflowOptions::default(),
+        )
+        .await
+        .unwrap();
+    worker.run_until_done().await.unwrap();
+}
+
 #[tokio::test]
 async fn cancel_child_workflow() {
     let func = WorkflowFunction::new(parent_cancels_child_wf);
@@ -220,3 +290,56 @@ async fn cancel_already_complete_child_ignored() {
     .await
     .unwrap();
 }
+
+#[rstest::rstest]
+#[case::terminate(ParentClosePolicy::Terminate)]
+#[case::request_cancel(ParentClosePolicy::RequestCancel)]
+#[case::abandon(ParentClosePolicy::Abandon)]
+#[tokio::test]
+async fn child_outlives_parent_with_close_policy(#[case] policy: ParentClosePolicy) {
+    let wf_id = "fakeid";
+    let wf_type = DEFAULT_WORKFLOW_TYPE;
+    let t = canned_histories::single_child_workflow_outlives_parent("child-id-1", policy);
+    let mut mh = MockPollCfg::from_resp_batches(wf_id, t, [1, 2], mock_workflow_client());
+    let saw_start_child = Arc::new(AtomicBool::new(false));
+    let saw_start_child_clone = saw_start_child.clone();
+    mh.completion_asserts = Some(Box::new(move |wftc| {
+        if wftc.commands[0].command_type() == CommandType::StartChildWorkflowExecution {
+            assert_matches!(
+                wftc.commands[0].attributes.as_ref().unwrap(),
+                Attributes::StartChildWorkflowExecutionCommandAttributes(attrs)
+                    if attrs.parent_close_policy == policy as i32
+            );
+            saw_start_child_clone.store(true, Ordering::Relaxed);
+        }
+    }));
+    let mut worker = mock_sdk(mh);
+
+    worker.register_wf(wf_type.to_owned(), move |ctx: WfContext| async move {
+        let child = ctx.child_workflow(ChildWorkflowOptions {
+            workflow_id: "child-id-1".to_string(),
+            workflow_type: "child".to_string(),
+            parent_close_policy: policy,
+            ..Default::default()
+        });
+        // Never wait on the child's result. The parent completes while the child is still running,
+        // leaving the child's fate up to the server according to the close policy.
+        child
+            .start(&ctx)
+            .await
+            .into_started()
+            .expect("Child should get started");
+        Ok(().into())
+    });
+    worker
+        .submit_wf(
+            wf_id.to_owned(),
+            wf_type.to_owned(),
+            vec![],
+            WorkflowOptions::default(),
+        )
+        .await
+        .unwrap();
+    worker.run_until_done().await.unwrap();
+    assert!(saw_start_child.load(Ordering::Relaxed));
+}
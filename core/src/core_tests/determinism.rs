@@ -5,13 +5,17 @@ use crate::{
     worker::client::mocks::mock_workflow_client,
 };
 use std::{
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 use temporal_client::WorkflowOptions;
 use temporal_sdk::{
     ActivityOptions, ChildWorkflowOptions, LocalActivityOptions, WfContext, WorkflowResult,
 };
+use temporal_sdk_core_api::worker::RunStateDumpTarget;
 use temporal_sdk_core_protos::{
     temporal::api::{
         enums::v1::{EventType, WorkflowTaskFailedCause},
@@ -19,6 +23,7 @@ use temporal_sdk_core_protos::{
     },
     TestHistoryBuilder, DEFAULT_ACTIVITY_TYPE,
 };
+use tokio::{sync::mpsc::unbounded_channel, time::timeout};
 
 static DID_FAIL: AtomicBool = AtomicBool::new(false);
 pub async fn timer_wf_fails_once(ctx: WfContext) -> WorkflowResult<()> {
@@ -115,6 +120,62 @@ async fn test_wf_task_rejected_properly_due_to_nondeterminism(#[case] use_cache:
     assert_eq!(2, started_count.load(Ordering::Relaxed));
 }
 
+#[tokio::test]
+async fn nondeterminism_dumps_run_state() {
+    let wf_id = "fakeid";
+    let wf_type = DEFAULT_WORKFLOW_TYPE;
+    let t = canned_histories::single_timer_wf_completes("1");
+    let mock = mock_workflow_client();
+    let mut mh = MockPollCfg::from_resp_batches(
+        wf_id,
+        t,
+        [ResponseType::AllHistory, ResponseType::AllHistory],
+        mock,
+    );
+    mh.num_expected_fails = 1;
+    let (dump_tx, mut dump_rx) = unbounded_channel();
+    let mut worker = mock_sdk_cfg(mh, move |cfg| {
+        cfg.run_state_dump = Some(RunStateDumpTarget::Callback(Arc::new(move |d| {
+            let _ = dump_tx.send(d);
+        })));
+    });
+
+    let started_count: &'static _ = Box::leak(Box::new(AtomicUsize::new(0)));
+    worker.register_wf(wf_type.to_owned(), move |ctx: WfContext| async move {
+        if started_count.fetch_add(1, Ordering::Relaxed) == 0 {
+            ctx.timer(Duration::from_secs(1)).await;
+        }
+        ctx.timer(Duration::from_secs(1)).await;
+        Ok(().into())
+    });
+    worker
+        .submit_wf(
+            wf_id.to_owned(),
+            wf_type.to_owned(),
+            vec![],
+            WorkflowOptions::default(),
+        )
+        .await
+        .unwrap();
+    worker.run_until_done().await.unwrap();
+
+    // Dumps are delivered in the background
+    let dump = timeout(Duration::from_secs(5), dump_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(dump_rx.try_recv().is_err());
+    assert_eq!(dump.workflow_id, wf_id);
+    assert!(dump.error.contains("Nondeterminism"));
+    // The dumped history must include the events the run failed while applying
+    assert!(dump
+        .history
+        .events
+        .iter()
+        .any(|e| e.event_type() == EventType::TimerStarted));
+    assert!(!dump.machine_states.is_empty());
+}
+
 #[rstest::rstest]
 #[tokio::test]
 async fn activity_id_or_type_change_is_nondeterministic(
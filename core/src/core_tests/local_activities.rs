@@ -3,13 +3,13 @@ use crate::{
     replay::{default_wes_attribs, TestHistoryBuilder, DEFAULT_WORKFLOW_TYPE},
     test_help::{
         hist_to_poll_resp, mock_sdk, mock_sdk_cfg, mock_worker, single_hist_mock_sg, MockPollCfg,
-        ResponseType,
+        MocksHolder, ResponseType,
     },
     worker::{client::mocks::mock_workflow_client, LEGACY_QUERY_ID},
 };
 use anyhow::anyhow;
 use crossbeam::queue::SegQueue;
-use futures::{future::join_all, FutureExt};
+use futures::{future::join_all, stream, FutureExt, StreamExt};
 use std::{
     collections::HashMap,
     ops::Sub,
@@ -42,6 +42,7 @@ use temporal_sdk_core_protos::{
         common::v1::RetryPolicy,
         enums::v1::{EventType, TimeoutType, WorkflowTaskFailedCause},
         failure::v1::{failure::FailureInfo, Failure},
+        history::v1::History,
         query::v1::WorkflowQuery,
     },
     DEFAULT_ACTIVITY_TYPE,
@@ -49,7 +50,10 @@ use temporal_sdk_core_protos::{
 use temporal_sdk_core_test_utils::{
     schedule_local_activity_cmd, start_timer_cmd, WorkerTestHelpers,
 };
-use tokio::{join, sync::Barrier};
+use tokio::{
+    join,
+    sync::{oneshot, Barrier},
+};
 
 async fn echo(_ctx: ActContext, e: String) -> anyhow::Result<String> {
     Ok(e)
@@ -1125,3 +1129,102 @@ async fn local_activities_can_be_delivered_during_shutdown() {
     assert_matches!(wf_r.unwrap_err(), PollWfError::ShutDown);
     assert_matches!(act_r.unwrap_err(), PollActivityError::ShutDown);
 }
+
+#[tokio::test]
+async fn legacy_query_answered_while_waiting_on_local_activity() {
+    let wfid = "fake_wf_id";
+    let mut t = TestHistoryBuilder::default();
+    t.add(default_wes_attribs());
+    t.add_full_wf_task();
+    t.add_local_activity_result_marker(1, "1", "done".into());
+    t.add_workflow_execution_completed();
+
+    let first_task = hist_to_poll_resp(&t, wfid.to_owned(), 1.into()).resp;
+    let mut query_task = hist_to_poll_resp(&t, wfid.to_owned(), 1.into()).resp;
+    query_task.task_token = b"query-task".to_vec();
+    query_task.history = Some(History { events: vec![] });
+    query_task.query = Some(WorkflowQuery {
+        query_type: "query-type".to_string(),
+        query_args: Some(b"hi".into()),
+        header: None,
+    });
+    // Only deliver the query task once the first WFT is waiting on the LA
+    let (la_running_tx, la_running_rx) = oneshot::channel::<()>();
+    let tasks = stream::iter([first_task]).chain(stream::once(async move {
+        la_running_rx.await.unwrap();
+        query_task
+    }));
+
+    let mut mock = mock_workflow_client();
+    mock.expect_respond_legacy_query()
+        .withf(|tt, _| tt.0 == b"query-task")
+        .times(1)
+        .returning(|_, _| Ok(Default::default()));
+    mock.expect_complete_workflow_task()
+        .times(1)
+        .returning(|_| Ok(Default::default()));
+    let mut mock = MocksHolder::from_wft_stream(mock, tasks);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mock);
+
+    let task = core.poll_workflow_activation().await.unwrap();
+    let run_id = task.run_id;
+    let complete_fut = core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        run_id.clone(),
+        schedule_local_activity_cmd(
+            1,
+            "1",
+            ActivityCancellationType::TryCancel,
+            Duration::from_secs(60),
+        ),
+    ));
+    let query_fut = async {
+        let act_task = core.poll_activity_task().await.unwrap();
+        la_running_tx.send(()).unwrap();
+        // The query must be delivered while the LA (and hence the WFT) is still outstanding
+        let query_act =
+            tokio::time::timeout(Duration::from_secs(5), core.poll_workflow_activation())
+                .await
+                .expect("Query should not wait for the in-flight workflow task")
+                .unwrap();
+        assert_matches!(
+            query_act.jobs.as_slice(),
+            [WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::QueryWorkflow(q)),
+            }] if q.query_id == LEGACY_QUERY_ID
+        );
+        core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            query_act.run_id,
+            QueryResult {
+                query_id: LEGACY_QUERY_ID.to_string(),
+                variant: Some(
+                    QuerySuccess {
+                        response: Some("answer".into()),
+                    }
+                    .into(),
+                ),
+            }
+            .into(),
+        ))
+        .await
+        .unwrap();
+        core.complete_activity_task(ActivityTaskCompletion {
+            task_token: act_task.task_token,
+            result: Some(ActivityExecutionResult::ok("done".into())),
+        })
+        .await
+        .unwrap();
+    };
+    let (complete_res, _) = join!(complete_fut, query_fut);
+    complete_res.unwrap();
+
+    let task = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        task.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::ResolveActivity(_)),
+        }]
+    );
+    core.complete_execution(&run_id).await;
+    core.shutdown().await;
+}
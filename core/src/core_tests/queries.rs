@@ -11,6 +11,7 @@ use std::{
 };
 use temporal_sdk_core_api::Worker as WorkerTrait;
 use temporal_sdk_core_protos::{
+    constants::{ENHANCED_STACK_TRACE_QUERY_TYPE, LIST_PATCHES_QUERY_TYPE, STACK_TRACE_QUERY_TYPE},
     coresdk::{
         workflow_activation::{
             remove_from_cache::EvictionReason, workflow_activation_job, WorkflowActivationJob,
@@ -18,8 +19,10 @@ use temporal_sdk_core_protos::{
         workflow_commands::{
             query_result, ActivityCancellationType, CompleteWorkflowExecution,
             ContinueAsNewWorkflowExecution, QueryResult, QuerySuccess, RequestCancelActivity,
+            SetPatchMarker,
         },
         workflow_completion::WorkflowActivationCompletion,
+        FromJsonPayloadExt,
     },
     temporal::api::{
         common::v1::Payload,
@@ -778,6 +781,91 @@ async fn new_query_fail() {
     .unwrap();
 }
 
+#[tokio::test]
+async fn unanswered_queries_failed_and_unknown_responses_dropped() {
+    let wfid = "fake_wf_id";
+    let t = canned_histories::single_timer("1");
+    let tasks = VecDeque::from(vec![{
+        let mut pr = hist_to_poll_resp(&t, wfid.to_owned(), 1.into());
+        pr.queries = ["q1", "q2"]
+            .into_iter()
+            .map(|qid| {
+                (
+                    qid.to_string(),
+                    WorkflowQuery {
+                        query_type: "query-type".to_string(),
+                        query_args: Some(b"hi".into()),
+                        header: Default::default(),
+                    },
+                )
+            })
+            .collect();
+        pr
+    }]);
+    let mut mock_client = mock_workflow_client();
+    mock_client
+        .expect_complete_workflow_task()
+        .times(1)
+        .returning(|resp| {
+            let mut responses = resp.query_responses;
+            responses.sort_by(|a, b| a.query_id.cmp(&b.query_id));
+            assert_matches!(
+                responses.as_slice(),
+                [QueryResult {
+                    query_id: q1,
+                    variant: Some(query_result::Variant::Succeeded(_)),
+                }, QueryResult {
+                    query_id: q2,
+                    variant: Some(query_result::Variant::Failed(_)),
+                }] if q1 == "q1" && q2 == "q2"
+            );
+            Ok(RespondWorkflowTaskCompletedResponse::default())
+        });
+
+    let mut mock = single_hist_mock_sg(wfid, t, tasks, mock_client, true);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 10);
+    let core = mock_worker(mock);
+
+    let task = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        task.run_id,
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    // Both queries are delivered together in one activation
+    let task = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        task.jobs.as_slice(),
+        [
+            WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::QueryWorkflow(_)),
+            },
+            WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::QueryWorkflow(_)),
+            }
+        ]
+    );
+    // Lang answers only one of them, and also responds to a query it was never given
+    let answer = |qid: &str| -> QueryResult {
+        QueryResult {
+            query_id: qid.to_string(),
+            variant: Some(
+                QuerySuccess {
+                    response: Some("resp".into()),
+                }
+                .into(),
+            ),
+        }
+    };
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        task.run_id,
+        vec![answer("q1").into(), answer("bogus").into()],
+    ))
+    .await
+    .unwrap();
+}
+
 /// This test verifies that if we get a task with a legacy query in it while in the middle of
 /// processing some local-only work (in this case, resolving an activity as soon as it was
 /// cancelled) that we do not combine the legacy query with the resolve job.
@@ -910,3 +998,121 @@ async fn legacy_query_combined_with_timer_fire_repro() {
     .unwrap();
     core.shutdown().await;
 }
+
+#[tokio::test]
+async fn list_patches_query_answered_by_core() {
+    let wfid = "fake_wf_id";
+    let t = canned_histories::single_timer("1");
+    let tasks = [{
+        let mut pr = hist_to_poll_resp(&t, wfid.to_owned(), 1.into());
+        pr.query = Some(WorkflowQuery {
+            query_type: LIST_PATCHES_QUERY_TYPE.to_string(),
+            query_args: None,
+            header: None,
+        });
+        pr
+    }];
+    let mut mock = mock_workflow_client();
+    mock.expect_respond_legacy_query()
+        .times(1)
+        .withf(|_, qr| {
+            let patches = assert_matches!(
+                &qr.variant,
+                Some(query_result::Variant::Succeeded(QuerySuccess { response: Some(p) })) => p
+            );
+            Vec::<String>::from_json_payload(patches).unwrap() == vec!["my-patch".to_string()]
+        })
+        .returning(|_, _| Ok(Default::default()));
+    let mock = MockPollCfg::from_resp_batches(wfid, t, tasks, mock);
+    let mut mock = build_mock_pollers(mock);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 10);
+    let core = mock_worker(mock);
+
+    let task = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        task.run_id,
+        vec![
+            SetPatchMarker {
+                patch_id: "my-patch".to_string(),
+                deprecated: false,
+            }
+            .into(),
+            start_timer_cmd(1, Duration::from_secs(1)),
+        ],
+    ))
+    .await
+    .unwrap();
+
+    // The query never reaches lang. Core answers it and there is nothing left to poll.
+    core.shutdown().await;
+}
+
+#[rstest::rstest]
+#[tokio::test]
+async fn stack_trace_queries_get_dedicated_job(
+    #[values(STACK_TRACE_QUERY_TYPE, ENHANCED_STACK_TRACE_QUERY_TYPE)] query_type: &'static str,
+) {
+    let wfid = "fake_wf_id";
+    let t = canned_histories::single_timer("1");
+    let tasks = [{
+        let mut pr = hist_to_poll_resp(&t, wfid.to_owned(), 1.into());
+        pr.query = Some(WorkflowQuery {
+            query_type: query_type.to_string(),
+            query_args: None,
+            header: None,
+        });
+        pr
+    }];
+    let mut mock = mock_workflow_client();
+    mock.expect_respond_legacy_query()
+        .times(1)
+        .withf(|_, qr| {
+            matches!(
+                &qr.variant,
+                Some(query_result::Variant::Succeeded(QuerySuccess { response: Some(p) }))
+                    if p.data == b"stack"
+            )
+        })
+        .returning(|_, _| Ok(Default::default()));
+    let mock = MockPollCfg::from_resp_batches(wfid, t, tasks, mock);
+    let mut mock = build_mock_pollers(mock);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 10);
+    let core = mock_worker(mock);
+
+    let task = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        task.run_id,
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+
+    let task = core.poll_workflow_activation().await.unwrap();
+    let query = assert_matches!(
+        task.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::QueryStackTrace(q)),
+        }] => q
+    );
+    assert_eq!(
+        query.enhanced,
+        query_type == ENHANCED_STACK_TRACE_QUERY_TYPE
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        task.run_id,
+        QueryResult {
+            query_id: query.query_id.clone(),
+            variant: Some(
+                QuerySuccess {
+                    response: Some("stack".into()),
+                }
+                .into(),
+            ),
+        }
+        .into(),
+    ))
+    .await
+    .unwrap();
+
+    core.shutdown().await;
+}
@@ -1,15 +1,26 @@
 use crate::{
     prost_dur,
     test_help::{
-        build_fake_worker, build_mock_pollers, canned_histories, mock_worker, MockPollCfg,
-        MockWorkerInputs, MocksHolder, ResponseType, WorkerExt,
+        build_fake_worker, build_mock_pollers, canned_histories, mock_worker, test_worker_cfg,
+        MockPollCfg, MockWorkerInputs, MocksHolder, ResponseType, WorkerExt,
     },
     worker::client::mocks::mock_workflow_client,
     PollActivityError, PollWfError,
 };
 use futures_util::{stream, stream::StreamExt};
-use std::{cell::RefCell, time::Duration};
-use temporal_sdk_core_api::Worker;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use temporal_sdk_core_api::{
+    errors::CompleteWfError,
+    worker::{FixedSizeSlotSupplier, SlotSupplier},
+    Worker,
+};
 use temporal_sdk_core_protos::{
     coresdk::{
         workflow_activation::workflow_activation_job,
@@ -17,7 +28,8 @@ use temporal_sdk_core_protos::{
         workflow_completion::WorkflowActivationCompletion,
     },
     temporal::api::workflowservice::v1::{
-        PollWorkflowTaskQueueResponse, RespondWorkflowTaskCompletedResponse,
+        PollActivityTaskQueueResponse, PollWorkflowTaskQueueResponse,
+        RespondWorkflowTaskCompletedResponse,
     },
 };
 use temporal_sdk_core_test_utils::start_timer_cmd;
@@ -229,6 +241,113 @@ async fn complete_eviction_after_shutdown_doesnt_panic() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn shutdown_force_eviction_timeout_evicts_runs() {
+    let t = canned_histories::single_timer("1");
+    let mut mock = mock_workflow_client();
+    mock.expect_fail_workflow_task()
+        .times(1)
+        .returning(|_, _, _| Ok(Default::default()));
+    let mut mh = build_mock_pollers(MockPollCfg::from_resp_batches("fakeid", t, [1], mock));
+    mh.make_wft_stream_interminable();
+    mh.worker_cfg(|wc| wc.workflow_force_eviction_timeout = Some(Duration::from_millis(100)));
+    let core = mock_worker(mh);
+
+    let res = core.poll_workflow_activation().await.unwrap();
+    assert_eq!(res.jobs.len(), 1);
+    // Lang never completes the activation, but shutdown finishes anyway once the timeout
+    // has elapsed
+    core.shutdown().await;
+    assert_matches!(
+        core.poll_workflow_activation().await.unwrap_err(),
+        PollWfError::ShutDown
+    );
+    assert_matches!(
+        core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+            res.run_id,
+            vec![start_timer_cmd(1, Duration::from_secs(1))],
+        ))
+        .await
+        .unwrap_err(),
+        CompleteWfError::ShutDown { .. }
+    );
+}
+
+#[derive(Debug)]
+struct CountingSlotSupplier {
+    inner: FixedSizeSlotSupplier,
+    reserved: AtomicUsize,
+    used: AtomicUsize,
+    released: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl SlotSupplier for CountingSlotSupplier {
+    async fn reserve_slot(&self) {
+        self.inner.reserve_slot().await;
+        self.reserved.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn try_reserve_slot(&self) -> bool {
+        let reserved = self.inner.try_reserve_slot();
+        if reserved {
+            self.reserved.fetch_add(1, Ordering::SeqCst);
+        }
+        reserved
+    }
+
+    fn mark_slot_used(&self) {
+        self.used.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn release_slot(&self) {
+        self.inner.release_slot();
+        self.released.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn custom_workflow_task_slot_supplier_is_used() {
+    let t = canned_histories::single_timer("1");
+    let mut mh = build_mock_pollers(MockPollCfg::from_resp_batches(
+        "fakeid",
+        t,
+        [1, 2],
+        mock_workflow_client(),
+    ));
+    let slots = Arc::new(CountingSlotSupplier {
+        inner: FixedSizeSlotSupplier::new(1),
+        reserved: AtomicUsize::new(0),
+        used: AtomicUsize::new(0),
+        released: AtomicUsize::new(0),
+    });
+    let slots_clone = slots.clone();
+    mh.worker_cfg(|wc| wc.workflow_task_slot_supplier = Some(slots_clone));
+    let core = mock_worker(mh);
+
+    let res = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        res.run_id,
+        vec![start_timer_cmd(1, Duration::from_secs(1))],
+    ))
+    .await
+    .unwrap();
+    let res = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmds(
+        res.run_id,
+        vec![CompleteWorkflowExecution { result: None }.into()],
+    ))
+    .await
+    .unwrap();
+    core.drain_pollers_and_shutdown().await;
+
+    assert_eq!(slots.used.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        slots.released.load(Ordering::SeqCst),
+        slots.reserved.load(Ordering::SeqCst)
+    );
+}
+
 #[tokio::test]
 async fn worker_does_not_panic_on_retry_exhaustion_of_nonfatal_net_err() {
     let t = canned_histories::single_timer("1");
@@ -260,3 +379,30 @@ async fn worker_does_not_panic_on_retry_exhaustion_of_nonfatal_net_err() {
         Some(workflow_activation_job::Variant::RemoveFromCache(_))
     );
 }
+
+#[tokio::test]
+async fn activity_only_worker_does_not_poll_for_workflow_tasks() {
+    // The mock client panics if the worker ever polls for workflow tasks
+    let mut mock_client = mock_workflow_client();
+    mock_client.expect_poll_activity_task().returning(|_, _| {
+        Ok(PollActivityTaskQueueResponse {
+            task_token: vec![1],
+            ..Default::default()
+        })
+    });
+    let cfg = test_worker_cfg()
+        .no_workflows(true)
+        .max_concurrent_at_polls(1_usize)
+        .build()
+        .unwrap();
+    let worker = crate::worker::Worker::new_test(cfg, mock_client);
+
+    let act = worker.poll_activity_task().await.unwrap();
+    assert_eq!(act.task_token, vec![1]);
+    // Workflow polls have nothing to return until the worker shuts down
+    let (wf_poll, _) = tokio::join!(worker.poll_workflow_activation(), async {
+        worker.initiate_shutdown()
+    });
+    assert_matches!(wf_poll.unwrap_err(), PollWfError::ShutDown);
+    assert_eq!(worker.cached_workflows().await, 0);
+}
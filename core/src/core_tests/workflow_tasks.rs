@@ -10,7 +10,10 @@ use crate::{
         test_worker_cfg, FakeWfResponses, MockPollCfg, MocksHolder, ResponseType, WorkerExt,
         WorkflowCachingPolicy::{self, AfterEveryReply, NonSticky},
     },
-    worker::client::mocks::{mock_manual_workflow_client, mock_workflow_client},
+    worker::client::{
+        mocks::{mock_manual_workflow_client, mock_workflow_client, DEFAULT_TEST_CAPABILITIES},
+        MockWorkerClient,
+    },
     Worker,
 };
 use futures::{stream, FutureExt};
@@ -26,13 +29,17 @@ use std::{
 };
 use temporal_client::WorkflowOptions;
 use temporal_sdk::{ActivityOptions, CancellableFuture, WfContext};
-use temporal_sdk_core_api::{errors::PollWfError, Worker as WorkerTrait};
+use temporal_sdk_core_api::{
+    errors::{CompleteWfError, HistoryExportError, PollWfError},
+    worker::TaskInterceptor,
+    Worker as WorkerTrait,
+};
 use temporal_sdk_core_protos::{
     coresdk::{
         activity_result::{self as ar, activity_resolution, ActivityResolution},
         workflow_activation::{
             remove_from_cache::EvictionReason, workflow_activation_job, FireTimer, ResolveActivity,
-            StartWorkflow, UpdateRandomSeed, WorkflowActivationJob,
+            StartWorkflow, UpdateRandomSeed, WorkflowActivation, WorkflowActivationJob,
         },
         workflow_commands::{
             ActivityCancellationType, CancelTimer, CompleteWorkflowExecution,
@@ -60,7 +67,7 @@ use temporal_sdk_core_protos::{
 use temporal_sdk_core_test_utils::{fanout_tasks, start_timer_cmd, WorkerTestHelpers};
 use tokio::{
     join,
-    sync::{Barrier, Semaphore},
+    sync::{Barrier, Notify, Semaphore},
     time,
 };
 
@@ -1181,6 +1188,159 @@ async fn sends_appropriate_sticky_task_queue_responses() {
     core.shutdown().await;
 }
 
+#[tokio::test]
+async fn evicting_unfinished_run_resets_sticky_queue() {
+    let wfid = "fake_wf_id";
+    let t = canned_histories::single_timer("1");
+    let mut mock = MockWorkerClient::new();
+    mock.expect_capabilities()
+        .returning(|| Some(DEFAULT_TEST_CAPABILITIES));
+    mock.expect_complete_workflow_task()
+        .times(1)
+        .returning(|_| Ok(Default::default()));
+    let reset = Arc::new(Notify::new());
+    let reset_c = reset.clone();
+    mock.expect_reset_sticky_task_queue()
+        .withf(move |wid, _| wid == wfid)
+        .times(1)
+        .returning(move |_, _| {
+            reset_c.notify_one();
+            Ok(Default::default())
+        });
+    let mut mock = single_hist_mock_sg(wfid, t, [1], mock, false);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 10);
+    let core = mock_worker(mock);
+
+    let activation = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id.clone(),
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    core.request_workflow_eviction(&activation.run_id);
+    let evict_act = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        evict_act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(evict_act.run_id))
+        .await
+        .unwrap();
+    // The reset happens in the background
+    reset.notified().await;
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn cache_pressure_evictions_reset_sticky_queue() {
+    let hists = ["wf-1", "wf-2"].map(|wf_id| FakeWfResponses {
+        wf_id: wf_id.to_string(),
+        hist: canned_histories::single_timer("1"),
+        response_batches: vec![1.into()],
+    });
+    let mut mh = MockPollCfg::new(hists.into_iter().collect(), false, 0);
+    let mut mock = MockWorkerClient::new();
+    mock.expect_capabilities()
+        .returning(|| Some(DEFAULT_TEST_CAPABILITIES));
+    let reset = Arc::new(Notify::new());
+    let reset_c = reset.clone();
+    mock.expect_reset_sticky_task_queue()
+        .withf(|wid, _| wid == "wf-1")
+        .times(1)
+        .returning(move |_, _| {
+            reset_c.notify_one();
+            Ok(Default::default())
+        });
+    mh.mock_client = mock;
+    let mut mock = build_mock_pollers(mh);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mock);
+
+    let activation = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id.clone(),
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    // The second workflow's task pushes the first out of the cache
+    let evict_act = core.poll_workflow_activation().await.unwrap();
+    assert_eq!(evict_act.run_id, activation.run_id);
+    assert_matches!(
+        evict_act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(evict_act.run_id))
+        .await
+        .unwrap();
+    reset.notified().await;
+    let activation = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id,
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn server_ended_run_is_evicted() {
+    let wfid = "fake_wf_id";
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_full_wf_task();
+    let timer_started_event_id = t.add_by_type(EventType::TimerStarted);
+    t.add_timer_fired(timer_started_event_id, "1".to_string());
+    t.add_full_wf_task();
+    t.add_workflow_execution_terminated();
+    let mock = single_hist_mock_sg(
+        wfid,
+        t,
+        [ResponseType::AllHistory],
+        mock_workflow_client(),
+        true,
+    );
+    let core = mock_worker(mock);
+
+    let activation = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        activation.run_id,
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    // Lang still gets the jobs from the last workflow task before the run ended
+    let timer_fired = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        timer_fired.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::FireTimer(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(timer_fired.run_id))
+        .await
+        .unwrap();
+    // Then the run is evicted, since nothing else would tell lang it has ended
+    let evict_act = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        evict_act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(rfc)),
+        }] => assert_eq!(rfc.reason(), EvictionReason::ServerEndedRun)
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(evict_act.run_id))
+        .await
+        .unwrap();
+    assert_eq!(core.cached_workflows().await, 0);
+    core.shutdown().await;
+}
+
 #[tokio::test]
 async fn new_server_work_while_eviction_outstanding_doesnt_overwrite_activation() {
     let wfid = "fake_wf_id";
@@ -1350,6 +1510,213 @@ async fn fail_wft_then_recover() {
     core.shutdown().await;
 }
 
+#[tokio::test]
+async fn oversized_command_payload_fails_wft() {
+    let t = canned_histories::single_activity("fake_activity");
+    let mut mh = MockPollCfg::from_resp_batches(
+        "fake_wf_id",
+        t,
+        [ResponseType::ToTaskNum(1)],
+        mock_workflow_client(),
+    );
+    mh.num_expected_fails = 1;
+    mh.expect_fail_wft_matcher = Box::new(|_, _, f| {
+        matches!(f, Some(Failure { message, .. })
+            if message.contains("Field `input` of ScheduleActivityTask command"))
+    });
+    let mut mock = build_mock_pollers(mh);
+    mock.worker_cfg(|wc| {
+        wc.max_cached_workflows = 2;
+        wc.payload_size_limit = Some(100);
+    });
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        act.run_id,
+        ScheduleActivity {
+            activity_id: "fake_activity".to_string(),
+            arguments: vec![vec![0; 1000].into()],
+            ..default_act_sched()
+        }
+        .into(),
+    ))
+    .await
+    .unwrap();
+    let evict_act = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        evict_act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(evict_act.run_id))
+        .await
+        .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn activation_not_completed_before_deadline_evicts_run() {
+    let t = canned_histories::single_timer("1");
+    let mut mh = MockPollCfg::from_resp_batches(
+        "fake_wf_id",
+        t,
+        [
+            ResponseType::ToTaskNum(1),
+            // The server hands the task to us again after we fail it
+            ResponseType::UntilResolved(tokio::time::sleep(Duration::from_millis(300)).boxed(), 1),
+        ],
+        mock_workflow_client(),
+    );
+    mh.num_expected_fails = 1;
+    mh.expect_fail_wft_matcher =
+        Box::new(|_, _, f| matches!(f, Some(fail) if fail.message.contains("may be deadlocked")));
+    let mut mock = build_mock_pollers(mh);
+    mock.worker_cfg(|wc| {
+        wc.max_cached_workflows = 2;
+        wc.workflow_activation_deadline = Some(Duration::from_millis(100));
+        // Puts the workflow on a non-zero shard, which the eviction must be labeled with too
+        wc.activation_shard_count = Some(8);
+    });
+    let core = mock_worker(mock);
+
+    let stuck_act = core.poll_workflow_activation().await.unwrap();
+    assert_ne!(stuck_act.shard, 0);
+    // Lang is "stuck" in workflow code for longer than the deadline
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let evict_act = core.poll_workflow_activation().await.unwrap();
+    assert_eq!(evict_act.run_id, stuck_act.run_id);
+    assert_eq!(
+        evict_act.eviction_reason(),
+        Some(EvictionReason::DeadlockDetected)
+    );
+    assert_eq!(evict_act.shard, stuck_act.shard);
+    let new_act = core.poll_workflow_activation().await.unwrap();
+    assert_eq!(new_act.run_id, stuck_act.run_id);
+    assert_matches!(
+        new_act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::StartWorkflow(_)),
+        }]
+    );
+
+    // Lang gets unstuck and completes its activations in order. Only the one which missed the
+    // deadline is rejected, even though the run id has since been reused.
+    assert_matches!(
+        core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+            stuck_act.run_id.clone(),
+            start_timer_cmd(1, Duration::from_secs(1)),
+        ))
+        .await,
+        Err(CompleteWfError::ActivationDeadlineExceeded { run_id }) if run_id == stuck_act.run_id
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(evict_act.run_id))
+        .await
+        .unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        new_act.run_id,
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn completions_without_outstanding_activation_rejected() {
+    let t = canned_histories::single_timer("1");
+    let mh = MockPollCfg::from_resp_batches(
+        "fake_wf_id",
+        t,
+        [ResponseType::ToTaskNum(1)],
+        mock_workflow_client(),
+    );
+    let mut mock = build_mock_pollers(mh);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 2);
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    let completion = || {
+        WorkflowActivationCompletion::from_cmd(
+            act.run_id.clone(),
+            start_timer_cmd(1, Duration::from_secs(1)),
+        )
+    };
+    core.complete_workflow_activation(completion())
+        .await
+        .unwrap();
+    // Completing the same activation again must not touch the run
+    assert_matches!(
+        core.complete_workflow_activation(completion()).await,
+        Err(CompleteWfError::NoOutstandingActivation { run_id }) if run_id == act.run_id
+    );
+    // Nor can a run which was never issued an activation be completed
+    assert_matches!(
+        core.complete_workflow_activation(WorkflowActivationCompletion::empty("not_a_run"))
+            .await,
+        Err(CompleteWfError::NoOutstandingActivation { run_id }) if run_id == "not_a_run"
+    );
+    assert!(core.machines_dump(&act.run_id).await.is_some());
+    core.shutdown().await;
+}
+
+#[derive(Debug, Default)]
+struct RecordingInterceptor {
+    completions: parking_lot::Mutex<Vec<String>>,
+}
+impl TaskInterceptor for RecordingInterceptor {
+    fn on_workflow_activation(&self, activation: &mut WorkflowActivation) {
+        // Not allowed, and reverted by core
+        activation.run_id = "hijacked".to_string();
+        for job in activation.jobs.iter_mut() {
+            if let Some(workflow_activation_job::Variant::StartWorkflow(sw)) = job.variant.as_mut()
+            {
+                sw.headers
+                    .insert("intercepted".to_string(), Payload::default());
+            }
+        }
+    }
+    fn on_workflow_activation_completion(&self, completion: &mut WorkflowActivationCompletion) {
+        self.completions.lock().push(completion.run_id.clone());
+    }
+}
+
+#[tokio::test]
+async fn task_interceptor_sees_activations_and_completions() {
+    let t = canned_histories::single_timer("1");
+    let mut mock = build_mock_pollers(MockPollCfg::from_resp_batches(
+        "fake_wf_id",
+        t,
+        [1],
+        mock_workflow_client(),
+    ));
+    let interceptor = Arc::new(RecordingInterceptor::default());
+    let iceptor = interceptor.clone();
+    mock.worker_cfg(move |wc| {
+        wc.max_cached_workflows = 1;
+        wc.task_interceptor = Some(iceptor.clone());
+    });
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    assert_ne!(act.run_id, "hijacked");
+    assert_matches!(
+        act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::StartWorkflow(sw)),
+        }] if sw.headers.contains_key("intercepted")
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        act.run_id.clone(),
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    assert_eq!(interceptor.completions.lock().as_slice(), [act.run_id]);
+    core.shutdown().await;
+}
+
 #[tokio::test]
 async fn poll_response_triggers_wf_error() {
     let mut t = TestHistoryBuilder::default();
@@ -1382,6 +1749,149 @@ async fn poll_response_triggers_wf_error() {
     );
 }
 
+#[tokio::test]
+async fn unregistered_workflow_types_failed_without_reaching_lang() {
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_workflow_task_scheduled_and_started();
+
+    let mut mock = mock_workflow_client();
+    mock.expect_fail_workflow_task()
+        .withf(|_, _, f| {
+            f.as_ref()
+                .map(|f| f.message.contains("is not registered on this worker"))
+                .unwrap_or_default()
+        })
+        .times(1)
+        .returning(|_, _, _| Ok(Default::default()));
+    let mut mock = single_hist_mock_sg("fake_wf_id", t, [1], mock, true);
+    mock.worker_cfg(|wc| {
+        wc.max_cached_workflows = 1;
+        wc.registered_workflow_types = Some(HashSet::from(["other_wf_type".to_string()]));
+    });
+    let core = mock_worker(mock);
+
+    // Lang never sees the workflow start, only the eviction after the task was failed
+    let act = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(act.run_id))
+        .await
+        .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn invalid_completion_fails_wft_and_evicts() {
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_workflow_task_scheduled_and_started();
+
+    let mut mock = mock_workflow_client();
+    mock.expect_complete_workflow_task()
+        .times(1)
+        .returning(|_| Err(tonic::Status::invalid_argument("Bad result payload")));
+    mock.expect_fail_workflow_task()
+        .withf(|_, cause, f| {
+            *cause == WorkflowTaskFailedCause::WorkflowWorkerUnhandledFailure
+                && f.as_ref()
+                    .map(|f| f.message.contains("Bad result payload"))
+                    .unwrap_or_default()
+        })
+        .times(1)
+        .returning(|_, _, _| Ok(Default::default()));
+    let mut mock = single_hist_mock_sg("fake_wf_id", t, [1], mock, true);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        act.run_id,
+        CompleteWorkflowExecution { result: None }.into(),
+    ))
+    .await
+    .unwrap();
+    let act = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(_)),
+        }]
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(act.run_id))
+        .await
+        .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn completion_retried_when_server_overloaded() {
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_workflow_task_scheduled_and_started();
+
+    let mut mock = mock_workflow_client();
+    mock.expect_complete_workflow_task()
+        .times(1)
+        .returning(|_| Err(tonic::Status::resource_exhausted("Slow down")));
+    mock.expect_complete_workflow_task()
+        .times(1)
+        .returning(|_| Ok(Default::default()));
+    mock.expect_fail_workflow_task().never();
+    let mut mock = single_hist_mock_sg("fake_wf_id", t, [1], mock, true);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        act.run_id,
+        CompleteWorkflowExecution { result: None }.into(),
+    ))
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn completion_evicts_once_overload_retries_run_out() {
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_workflow_task_scheduled_and_started();
+
+    let mut mock = mock_workflow_client();
+    // The first attempt plus every retry
+    mock.expect_complete_workflow_task()
+        .times(4)
+        .returning(|_| Err(tonic::Status::resource_exhausted("Slow down")));
+    mock.expect_fail_workflow_task().never();
+    let mut mock = single_hist_mock_sg("fake_wf_id", t, [1], mock, true);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        act.run_id,
+        CompleteWorkflowExecution { result: None }.into(),
+    ))
+    .await
+    .unwrap();
+    let act = core.poll_workflow_activation().await.unwrap();
+    assert_matches!(
+        act.jobs.as_slice(),
+        [WorkflowActivationJob {
+            variant: Some(workflow_activation_job::Variant::RemoveFromCache(rfc)),
+        }] => assert_eq!(rfc.reason(), EvictionReason::Fatal)
+    );
+    core.complete_workflow_activation(WorkflowActivationCompletion::empty(act.run_id))
+        .await
+        .unwrap();
+    core.shutdown().await;
+}
+
 // Verifies we can handle multiple wft timeouts in a row if lang is being very slow in responding
 #[tokio::test]
 async fn lang_slower_than_wft_timeouts() {
@@ -2401,6 +2911,35 @@ async fn core_internal_flags() {
     core.shutdown().await;
 }
 
+#[tokio::test]
+async fn sdk_name_and_version_sent_in_completion_metadata() {
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_workflow_task_scheduled_and_started();
+
+    let mut mh = MockPollCfg::from_resp_batches(
+        "fake_wf_id",
+        t,
+        [ResponseType::ToTaskNum(1)],
+        mock_workflow_client(),
+    );
+    mh.completion_asserts = Some(Box::new(|c| {
+        assert_eq!(c.sdk_metadata.sdk_name, "temporal-lang");
+        assert!(c.sdk_metadata.sdk_version.starts_with("1.2.3+core."));
+    }));
+    let mut mock = build_mock_pollers(mh);
+    mock.worker_cfg(|wc| {
+        wc.max_cached_workflows = 1;
+        wc.sdk_name = Some("temporal-lang".to_string());
+        wc.sdk_version = Some("1.2.3".to_string());
+    });
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_execution(&act.run_id).await;
+    core.shutdown().await;
+}
+
 #[tokio::test]
 async fn post_terminal_commands_are_discarded() {
     let mut t = TestHistoryBuilder::default();
@@ -2687,3 +3226,88 @@ async fn poller_wont_poll_until_lang_polls() {
     // Nothing should've appeared here or we did poll
     assert!(rx.recv().is_err());
 }
+
+#[tokio::test]
+async fn export_history_of_cached_run() {
+    let t = canned_histories::single_timer("1");
+    let full_hist: GetWorkflowExecutionHistoryResponse = t.get_full_history_info().unwrap().into();
+    let mut mh = MockPollCfg::from_resp_batches("fake_wf_id", t, [1], mock_workflow_client());
+    mh.mock_client
+        .expect_get_workflow_execution_history()
+        .times(1)
+        .returning(move |wid, rid, _| {
+            assert_eq!(wid, "fake_wf_id");
+            assert!(rid.is_some());
+            Ok(full_hist.clone())
+        });
+    let mut mock = build_mock_pollers(mh);
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    // Only the events core has actually applied are exported
+    let exported = core.export_history(&act.run_id).await.unwrap();
+    assert_eq!(
+        exported
+            .events
+            .iter()
+            .map(|e| e.event_id)
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_matches!(
+        core.export_history("not-a-run").await,
+        Err(HistoryExportError::RunNotCached { run_id }) if run_id == "not-a-run"
+    );
+
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        act.run_id,
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+    core.shutdown().await;
+}
+
+#[tokio::test]
+async fn machines_dump_of_cached_run() {
+    let t = canned_histories::single_timer("1");
+    let mut mock = build_mock_pollers(MockPollCfg::from_resp_batches(
+        "fake_wf_id",
+        t,
+        [1],
+        mock_workflow_client(),
+    ));
+    mock.worker_cfg(|wc| wc.max_cached_workflows = 1);
+    let core = mock_worker(mock);
+
+    let act = core.poll_workflow_activation().await.unwrap();
+    core.complete_workflow_activation(WorkflowActivationCompletion::from_cmd(
+        act.run_id.clone(),
+        start_timer_cmd(1, Duration::from_secs(1)),
+    ))
+    .await
+    .unwrap();
+
+    let dump = core.machines_dump(&act.run_id).await.unwrap();
+    assert_eq!(dump.workflow_id, "fake_wf_id");
+    assert_eq!(dump.last_processed_event, 3);
+    let wft_machine = dump
+        .machines
+        .iter()
+        .find(|m| m.kind == "WorkflowTaskMachine")
+        .unwrap();
+    assert!(!wft_machine.event_ids.is_empty());
+    // The timer hasn't been recorded in history yet, so its command is still pending
+    let timer_machine = dump
+        .machines
+        .iter()
+        .find(|m| m.kind == "TimerMachine")
+        .unwrap();
+    assert_eq!(timer_machine.pending_commands.len(), 1);
+    assert!(timer_machine.event_ids.is_empty());
+    // Dumps are meant to be shipped elsewhere for inspection
+    assert!(serde_json::to_string(&dump).is_ok());
+    assert!(core.machines_dump("not-a-run").await.is_none());
+    core.shutdown().await;
+}
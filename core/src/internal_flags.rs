@@ -38,6 +38,10 @@ pub(crate) enum InternalFlags {
         lang: BTreeSet<u32>,
         core_since_last_complete: HashSet<CoreInternalFlags>,
         lang_since_last_complete: HashSet<u32>,
+        /// The SDK name and version most recently recorded in history, which only need to be sent
+        /// again when they change
+        recorded_sdk_name: String,
+        recorded_sdk_version: String,
     },
     Disabled,
 }
@@ -50,13 +54,22 @@ impl InternalFlags {
                 lang: Default::default(),
                 core_since_last_complete: Default::default(),
                 lang_since_last_complete: Default::default(),
+                recorded_sdk_name: Default::default(),
+                recorded_sdk_version: Default::default(),
             },
             false => Self::Disabled,
         }
     }
 
     pub fn add_from_complete(&mut self, e: &WorkflowTaskCompletedEventAttributes) {
-        if let Self::Enabled { core, lang, .. } = self {
+        if let Self::Enabled {
+            core,
+            lang,
+            recorded_sdk_name,
+            recorded_sdk_version,
+            ..
+        } = self
+        {
             if let Some(metadata) = e.sdk_metadata.as_ref() {
                 core.extend(
                     metadata
@@ -65,6 +78,12 @@ impl InternalFlags {
                         .map(|u| CoreInternalFlags::from_u32(*u)),
                 );
                 lang.extend(metadata.lang_used_flags.iter());
+                if !metadata.sdk_name.is_empty() {
+                    recorded_sdk_name.clone_from(&metadata.sdk_name);
+                }
+                if !metadata.sdk_version.is_empty() {
+                    recorded_sdk_version.clone_from(&metadata.sdk_version);
+                }
             }
         }
     }
@@ -116,14 +135,21 @@ impl InternalFlags {
 
     /// Wipes the recorded flags used during the current WFT and returns a partially filled
     /// sdk metadata message that can be combined with any existing data before sending the WFT
-    /// complete
-    pub fn gather_for_wft_complete(&mut self) -> WorkflowTaskCompletedMetadata {
+    /// complete. The provided SDK name and version are only included if they differ from what was
+    /// last recorded.
+    pub fn gather_for_wft_complete(
+        &mut self,
+        sdk_name: &str,
+        sdk_version: &str,
+    ) -> WorkflowTaskCompletedMetadata {
         match self {
             Self::Enabled {
                 core_since_last_complete,
                 lang_since_last_complete,
                 core,
                 lang,
+                recorded_sdk_name,
+                recorded_sdk_version,
             } => {
                 let core_newly_used: Vec<_> = core_since_last_complete
                     .iter()
@@ -137,9 +163,19 @@ impl InternalFlags {
                     .collect();
                 core.extend(core_since_last_complete.iter());
                 lang.extend(lang_since_last_complete.iter());
+                let changed = |recorded: &mut String, current: &str| {
+                    if recorded == current {
+                        String::new()
+                    } else {
+                        *recorded = current.to_string();
+                        current.to_string()
+                    }
+                };
                 WorkflowTaskCompletedMetadata {
                     core_used_flags: core_newly_used,
                     lang_used_flags: lang_newly_used,
+                    sdk_name: changed(recorded_sdk_name, sdk_name),
+                    sdk_version: changed(recorded_sdk_version, sdk_version),
                 }
             }
             Self::Disabled => WorkflowTaskCompletedMetadata::default(),
@@ -182,10 +218,11 @@ mod tests {
             sdk_metadata: Some(WorkflowTaskCompletedMetadata {
                 core_used_flags: vec![1],
                 lang_used_flags: vec![],
+                ..Default::default()
             }),
             ..Default::default()
         });
-        let gathered = f.gather_for_wft_complete();
+        let gathered = f.gather_for_wft_complete("lang", "1.0");
         assert_matches!(gathered.core_used_flags.as_slice(), &[]);
         assert_matches!(gathered.lang_used_flags.as_slice(), &[]);
     }
@@ -207,7 +244,7 @@ mod tests {
         });
         f.add_lang_used([1]);
         f.try_use(CoreInternalFlags::IdAndTypeDeterminismChecks, true);
-        let gathered = f.gather_for_wft_complete();
+        let gathered = f.gather_for_wft_complete("lang", "1.0");
         assert_matches!(gathered.core_used_flags.as_slice(), &[1]);
         assert_matches!(gathered.lang_used_flags.as_slice(), &[1]);
 
@@ -215,13 +252,35 @@ mod tests {
             sdk_metadata: Some(WorkflowTaskCompletedMetadata {
                 core_used_flags: vec![2],
                 lang_used_flags: vec![2],
+                ..Default::default()
             }),
             ..Default::default()
         });
         f.add_lang_used([2]);
         f.try_use(CoreInternalFlags::UpsertSearchAttributeOnPatch, true);
-        let gathered = f.gather_for_wft_complete();
+        let gathered = f.gather_for_wft_complete("lang", "1.0");
         assert_matches!(gathered.core_used_flags.as_slice(), &[]);
         assert_matches!(gathered.lang_used_flags.as_slice(), &[]);
     }
+
+    #[test]
+    fn only_writes_changed_sdk_name_and_version() {
+        let mut f = InternalFlags::new(&Capabilities {
+            sdk_metadata: true,
+            ..Default::default()
+        });
+        f.add_from_complete(&WorkflowTaskCompletedEventAttributes {
+            sdk_metadata: Some(WorkflowTaskCompletedMetadata {
+                sdk_name: "lang".to_string(),
+                sdk_version: "1.0".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let gathered = f.gather_for_wft_complete("lang", "1.1");
+        assert_eq!(gathered.sdk_name, "");
+        assert_eq!(gathered.sdk_version, "1.1");
+        let gathered = f.gather_for_wft_complete("lang", "1.1");
+        assert_eq!(gathered.sdk_version, "");
+    }
 }
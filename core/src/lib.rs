@@ -39,10 +39,15 @@ pub use temporal_sdk_core_protos::TaskToken;
 pub use url::Url;
 #[cfg(feature = "save_wf_inputs")]
 pub use worker::replay_wf_state_inputs;
-pub use worker::{Worker, WorkerConfig, WorkerConfigBuilder};
+#[cfg(feature = "machine_diagrams")]
+pub use worker::{render_machine_diagrams, DiagramFormat, MachineTransitions, TransitionRecording};
+pub use worker::{
+    PendingActivityInfo, ProcResourceInfo, ResourceBasedSlotSupplier, ResourceBasedTargets,
+    ResourceSlotOptions, SystemResourceInfo, Worker, WorkerConfig, WorkerConfigBuilder,
+};
 
 use crate::{
-    replay::{mock_client_from_histories, Historator, HistoryForReplay},
+    replay::{mock_client_from_histories, Historator, HistoryForReplay, ReplayResults},
     telemetry::{
         metrics::{MetricsContext, TemporalMeter},
         remove_trace_subscriber_for_current_thread, set_trace_subscriber_for_current_thread,
@@ -50,11 +55,13 @@ use crate::{
     },
     worker::client::WorkerClientBag,
 };
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use std::sync::Arc;
 use temporal_client::{ConfiguredClient, TemporalServiceClientWithMetrics};
 use temporal_sdk_core_api::{
-    errors::{CompleteActivityError, PollActivityError, PollWfError},
+    errors::{
+        ClientError, CompleteActivityError, ConfigError, CoreError, PollActivityError, PollWfError,
+    },
     telemetry::TelemetryOptions,
     Worker as WorkerTrait,
 };
@@ -68,14 +75,23 @@ use temporal_sdk_core_protos::coresdk::ActivityHeartbeat;
 ///
 /// Lang implementations may pass in a [ConfiguredClient] directly (or a
 /// [RetryClient] wrapping one, or a handful of other variants of the same idea). When they do so,
-/// this function will always overwrite the client retry configuration, force the client to use the
-/// namespace defined in the worker config, and set the client identity appropriately. IE: Use
+/// this function will always replace any retry wrapper with one using the retry configuration from
+/// the client's [ClientOptions], force the client to use the namespace defined in the worker
+/// config, and set the client identity appropriately. IE: Use
 /// [ClientOptions::connect_no_namespace], not [ClientOptions::connect].
+///
+/// Note that this means [ClientOptions::retry_config] applies to worker calls. Older versions
+/// always used [RetryConfig::default] here, ignoring the client's configuration.
+///
+/// Because of that, one connection may back any number of workers: pass a clone of the same client
+/// to each. The workers may poll different task queues in different namespaces, and each may set
+/// its own [WorkerConfig::client_identity_override]. The namespace and task queue of each request
+/// are attached to the client's metrics, so they remain distinguishable per worker.
 pub fn init_worker<CT>(
     runtime: &CoreRuntime,
     worker_config: WorkerConfig,
     client: CT,
-) -> Result<Worker, anyhow::Error>
+) -> Result<Worker, CoreError>
 where
     CT: Into<sealed::AnyClient>,
 {
@@ -86,10 +102,15 @@ where
         if let Some(ref id_override) = worker_config.client_identity_override {
             client.options_mut().identity = id_override.clone();
         }
-        RetryClient::new(client, RetryConfig::default())
+        let options = client.options().clone();
+        RetryClient::from_options(client, &options)
     };
     if client.namespace() != worker_config.namespace {
-        panic!("Passed in client is not bound to the same namespace as the worker");
+        return Err(ClientError::NamespaceMismatch {
+            worker_namespace: worker_config.namespace,
+            client_namespace: client.namespace().to_owned(),
+        }
+        .into());
     }
     let client_ident = client.get_options().identity.clone();
     let sticky_q = sticky_q_name_for_worker(&client_ident, &worker_config);
@@ -99,6 +120,8 @@ where
         client_ident,
         worker_config.worker_build_id.clone(),
         worker_config.use_worker_versioning,
+        worker_config.history_page_size,
+        worker_config.failure_converter.clone(),
     ));
 
     Ok(Worker::new(
@@ -115,45 +138,72 @@ where
 /// You do not necessarily need a [CoreRuntime] for replay workers, but it's advisable to create
 /// one and use it to run the replay worker's async functions the same way you would for a normal
 /// worker.
-pub fn init_replay_worker<I>(
+pub fn init_replay_worker<I>(config: WorkerConfig, histories: I) -> Result<Worker, CoreError>
+where
+    I: Stream<Item = HistoryForReplay> + Send + 'static,
+{
+    init_batch_replay_worker(config, histories, 1).map(|(worker, _)| worker)
+}
+
+/// Create a worker which replays up to `concurrency` of the provided histories at once, for
+/// checking many histories (ex: in CI, against the histories of deployed workflows) in one go. It
+/// will auto-shutdown once every history has finished being replayed.
+///
+/// Alongside the worker, returns a [ReplayResults] which records whether each history replayed
+/// successfully, hit a nondeterminism error, or failed for another reason (ex: workflow code
+/// panicked). Lang should inspect it once the worker has shut down.
+pub fn init_batch_replay_worker<I>(
     mut config: WorkerConfig,
     histories: I,
-) -> Result<Worker, anyhow::Error>
+    concurrency: usize,
+) -> Result<(Worker, ReplayResults), CoreError>
 where
     I: Stream<Item = HistoryForReplay> + Send + 'static,
 {
     info!(
         task_queue = config.task_queue.as_str(),
-        "Registering replay worker"
+        concurrency, "Registering replay worker"
     );
-    config.max_cached_workflows = 1;
+    let concurrency = concurrency.max(1);
+    config.max_cached_workflows = concurrency;
     config.max_concurrent_wft_polls = 1;
     config.no_remote_activities = true;
-    let historator = Historator::new(histories);
+    config.no_workflows = false;
+    let failure_converter = config.failure_converter.clone();
+    let histories = histories.map(move |mut h| {
+        if let Some(conv) = failure_converter.as_ref() {
+            h.decode_failures(conv.as_ref());
+        }
+        h
+    });
+    let historator = Historator::new(histories, concurrency);
+    let results = historator.results();
     let post_activate = historator.get_post_activate_hook();
     let shutdown_tok = historator.get_shutdown_setter();
     let client = mock_client_from_histories(historator);
     let mut worker = Worker::new(config, None, Arc::new(client), None);
     worker.set_post_activate_hook(post_activate);
     shutdown_tok(worker.shutdown_token());
-    Ok(worker)
+    Ok((worker, results))
 }
 
-/// Creates a unique sticky queue name for a worker, iff the config allows for 1 or more cached
-/// workflows.
+/// Creates a unique sticky queue name for a worker (or uses the configured one), iff the config
+/// allows for 1 or more cached workflows.
 pub(crate) fn sticky_q_name_for_worker(
     process_identity: &str,
     config: &WorkerConfig,
 ) -> Option<String> {
-    if config.max_cached_workflows > 0 {
+    if config.max_cached_workflows == 0 {
+        None
+    } else if let Some(name) = config.sticky_queue_name_override.as_ref() {
+        Some(name.clone())
+    } else {
         Some(format!(
             "{}-{}-{}",
             &process_identity,
             &config.task_queue,
             uuid::Uuid::new_v4().simple()
         ))
-    } else {
-        None
     }
 }
 
@@ -186,6 +236,11 @@ mod sealed {
             Self(Box::new(c.get_client().inner().clone()))
         }
     }
+    impl From<Client> for AnyClient {
+        fn from(c: Client) -> Self {
+            Self(Box::new(c.into_inner()))
+        }
+    }
     impl From<ConfiguredClient<TemporalServiceClientWithMetrics>> for AnyClient {
         fn from(c: ConfiguredClient<TemporalServiceClientWithMetrics>) -> Self {
             Self(Box::new(c))
@@ -219,15 +274,17 @@ impl CoreRuntime {
     pub fn new(
         telemetry_options: TelemetryOptions,
         mut tokio_builder: tokio::runtime::Builder,
-    ) -> Result<Self, anyhow::Error> {
-        let telemetry = telemetry_init(telemetry_options)?;
+    ) -> Result<Self, CoreError> {
+        let telemetry =
+            telemetry_init(telemetry_options).map_err(|e| ConfigError::Telemetry(e.into()))?;
         let subscriber = telemetry.trace_subscriber();
         let runtime = tokio_builder
             .enable_all()
             .on_thread_start(move || {
                 set_trace_subscriber_for_current_thread(subscriber.clone());
             })
-            .build()?;
+            .build()
+            .map_err(ConfigError::from)?;
         let _rg = runtime.enter();
         let mut me = Self::new_assume_tokio_initialized_telem(telemetry);
         me.runtime = Some(runtime);
@@ -239,8 +296,9 @@ impl CoreRuntime {
     ///
     /// # Panics
     /// If there is no currently active Tokio runtime
-    pub fn new_assume_tokio(telemetry_options: TelemetryOptions) -> Result<Self, anyhow::Error> {
-        let telemetry = telemetry_init(telemetry_options)?;
+    pub fn new_assume_tokio(telemetry_options: TelemetryOptions) -> Result<Self, CoreError> {
+        let telemetry =
+            telemetry_init(telemetry_options).map_err(|e| ConfigError::Telemetry(e.into()))?;
         Ok(Self::new_assume_tokio_initialized_telem(telemetry))
     }
 
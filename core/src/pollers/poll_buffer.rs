@@ -1,6 +1,7 @@
 use crate::{
     abstractions::{dbg_panic, MeteredSemaphore, OwnedMeteredSemPermit},
     pollers::{self, Poller},
+    telemetry::metrics::MetricsContext,
     worker::client::WorkerClient,
 };
 use futures::{prelude::stream::FuturesUnordered, StreamExt};
@@ -11,7 +12,9 @@ use std::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
+    time::Instant,
 };
+use temporal_sdk_core_api::worker::PollerBehavior;
 use temporal_sdk_core_protos::temporal::api::workflowservice::v1::{
     PollActivityTaskQueueResponse, PollWorkflowTaskQueueResponse,
 };
@@ -19,7 +22,7 @@ use tokio::{
     sync::{
         broadcast,
         mpsc::{unbounded_channel, UnboundedReceiver},
-        Mutex,
+        watch, Mutex,
     },
     task::JoinHandle,
 };
@@ -63,12 +66,15 @@ impl<T> LongPollBuffer<T>
 where
     T: Send + Debug + 'static,
 {
+    /// `is_empty` must return true for responses to polls which ended without a task (ex: because
+    /// the long poll timed out). It informs autoscaling.
     pub(crate) fn new<FT>(
         poll_fn: impl Fn() -> FT + Send + Sync + 'static,
+        is_empty: fn(&T) -> bool,
         poll_semaphore: Arc<MeteredSemaphore>,
-        max_pollers: usize,
+        behavior: PollerBehavior,
         shutdown: CancellationToken,
-        num_pollers_handler: Option<impl Fn(usize) + Send + Sync + 'static>,
+        metrics: Option<MetricsContext>,
     ) -> Self
     where
         FT: Future<Output = pollers::Result<T>> + Send,
@@ -78,14 +84,18 @@ where
         let active_pollers = Arc::new(AtomicUsize::new(0));
         let join_handles = FuturesUnordered::new();
         let pf = Arc::new(poll_fn);
-        let nph = num_pollers_handler.map(Arc::new);
-        for _ in 0..max_pollers {
+        let (scaler, target_rx) = PollScaler::new(behavior, metrics.clone());
+        let scaler = scaler.map(Arc::new);
+        let nph = metrics.map(|m| Arc::new(move |np: usize| m.record_num_pollers(np)));
+        for poller_ix in 0..behavior.maximum() {
             let tx = tx.clone();
             let pf = pf.clone();
             let shutdown = shutdown.clone();
             let ap = active_pollers.clone();
             let poll_semaphore = poll_semaphore.clone();
             let nph = nph.clone();
+            let scaler = scaler.clone();
+            let mut target_rx = target_rx.clone();
             let mut wait_for_start = wait_for_start.resubscribe();
             let jh = tokio::spawn(async move {
                 tokio::select! {
@@ -99,20 +109,25 @@ where
                     if shutdown.is_cancelled() {
                         break;
                     }
+                    // Pollers beyond the current target sit idle until the target grows
+                    while *target_rx.borrow() <= poller_ix {
+                        tokio::select! {
+                            r = target_rx.changed() => if r.is_err() { return },
+                            _ = shutdown.cancelled() => return,
+                        }
+                    }
                     let permit = tokio::select! {
                         p = poll_semaphore.acquire_owned() => p,
                         _ = shutdown.cancelled() => break,
                     };
-                    let permit = if let Ok(p) = permit {
-                        p
-                    } else {
-                        break;
-                    };
                     let _active_guard = ActiveCounter::new(ap.as_ref(), nph);
                     let r = tokio::select! {
                         r = pf() => r,
                         _ = shutdown.cancelled() => break,
                     };
+                    if let Some(scaler) = scaler.as_ref() {
+                        scaler.record_poll(matches!(&r, Ok(resp) if !is_empty(resp)));
+                    }
                     let _ = tx.send(r.map(|r| (r, permit)));
                 }
             });
@@ -167,6 +182,143 @@ where
     }
 }
 
+/// Number of completed polls considered by each autoscaling decision
+const SCALING_WINDOW_POLLS: usize = 10;
+/// If no more than this fraction of the polls in a window came back without a task, pollers are
+/// added
+const SCALE_UP_MAX_EMPTY_RATIO: f64 = 0.1;
+/// If at least this fraction of the polls in a window came back without a task, a poller is
+/// removed
+const SCALE_DOWN_MIN_EMPTY_RATIO: f64 = 0.5;
+/// After pollers are added, they are only added again once the rate at which tasks are received
+/// has grown by at least this fraction. Otherwise something other than polling (ex: available
+/// task slots) is what limits throughput, and more pollers would only add load on the server.
+const SCALE_UP_MIN_RATE_GAIN: f64 = 0.1;
+
+/// Decides how many of a [LongPollBuffer]'s pollers should be actively polling when it is
+/// configured with [PollerBehavior::Autoscaling]
+struct PollScaler {
+    minimum: usize,
+    maximum: usize,
+    target: watch::Sender<usize>,
+    window: parking_lot::Mutex<ScalingWindow>,
+    metrics: Option<MetricsContext>,
+}
+
+#[derive(Default)]
+struct ScalingWindow {
+    polls: usize,
+    empty: usize,
+    /// When the current window began. Unset until the first poll completes.
+    started: Option<Instant>,
+    /// Tasks received per second during the window which last caused pollers to be added, unless
+    /// pollers have been removed since
+    rate_at_last_scale_up: Option<f64>,
+}
+
+impl PollScaler {
+    /// Returns the scaler, if the behavior calls for one, along with a receiver for the number of
+    /// pollers which should currently be polling
+    fn new(
+        behavior: PollerBehavior,
+        metrics: Option<MetricsContext>,
+    ) -> (Option<Self>, watch::Receiver<usize>) {
+        match behavior {
+            PollerBehavior::SimpleMaximum(max) => {
+                // The sender is dropped immediately, but every poller is always within the target
+                // so none will ever wait on it changing
+                let (_, target_rx) = watch::channel(max);
+                (None, target_rx)
+            }
+            PollerBehavior::Autoscaling {
+                minimum,
+                maximum,
+                initial,
+            } => {
+                let (target, target_rx) = watch::channel(initial);
+                if let Some(m) = metrics.as_ref() {
+                    m.record_target_num_pollers(initial);
+                }
+                let scaler = Self {
+                    minimum,
+                    maximum,
+                    target,
+                    window: Default::default(),
+                    metrics,
+                };
+                (Some(scaler), target_rx)
+            }
+        }
+    }
+
+    /// Record the outcome of a completed poll, adjusting the target number of pollers once enough
+    /// polls have completed to make a decision. Polls which failed count as empty.
+    fn record_poll(&self, got_task: bool) {
+        self.record_poll_at(got_task, Instant::now())
+    }
+
+    fn record_poll_at(&self, got_task: bool, now: Instant) {
+        let (empty_ratio, rate, scale_up) = {
+            let mut window = self.window.lock();
+            let started = *window.started.get_or_insert(now);
+            window.polls += 1;
+            if !got_task {
+                window.empty += 1;
+            }
+            if window.polls < SCALING_WINDOW_POLLS {
+                return;
+            }
+            let empty_ratio = window.empty as f64 / window.polls as f64;
+            let elapsed = now.duration_since(started).as_secs_f64();
+            let rate = (window.polls - window.empty) as f64 / elapsed.max(f64::EPSILON);
+            // Tasks are arriving about as fast as we can poll for them. Add pollers, unless adding
+            // them last time did not let us receive tasks any faster.
+            let scale_up = empty_ratio <= SCALE_UP_MAX_EMPTY_RATIO
+                && window
+                    .rate_at_last_scale_up
+                    .map_or(true, |prev| rate >= prev * (1.0 + SCALE_UP_MIN_RATE_GAIN));
+            if scale_up {
+                window.rate_at_last_scale_up = Some(rate);
+            } else if empty_ratio >= SCALE_DOWN_MIN_EMPTY_RATIO {
+                window.rate_at_last_scale_up = None;
+            }
+            window.polls = 0;
+            window.empty = 0;
+            window.started = Some(now);
+            (empty_ratio, rate, scale_up)
+        };
+        let mut new_target = None;
+        self.target.send_modify(|target| {
+            let cur = *target;
+            if scale_up {
+                // Ramp up quickly
+                *target = (cur + (cur + 1) / 2).min(self.maximum);
+            } else if empty_ratio >= SCALE_DOWN_MIN_EMPTY_RATIO {
+                *target = cur.saturating_sub(1).max(self.minimum);
+            }
+            if *target != cur {
+                new_target = Some(*target);
+            }
+        });
+        if let Some(new_target) = new_target {
+            debug!(
+                new_target,
+                empty_ratio,
+                tasks_per_sec = rate,
+                "Adjusted number of pollers"
+            );
+            if let Some(m) = self.metrics.as_ref() {
+                m.record_target_num_pollers(new_target);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn current_target(&self) -> usize {
+        *self.target.borrow()
+    }
+}
+
 /// A poller capable of polling on a sticky and a nonsticky queue simultaneously for workflow tasks.
 #[derive(derive_more::Constructor)]
 pub struct WorkflowTaskPoller {
@@ -214,10 +366,10 @@ pub(crate) fn new_workflow_task_buffer(
     client: Arc<dyn WorkerClient>,
     task_queue: String,
     is_sticky: bool,
-    concurrent_pollers: usize,
+    behavior: PollerBehavior,
     semaphore: Arc<MeteredSemaphore>,
     shutdown: CancellationToken,
-    num_pollers_handler: Option<impl Fn(usize) + Send + Sync + 'static>,
+    metrics: Option<MetricsContext>,
 ) -> PollWorkflowTaskBuffer {
     LongPollBuffer::new(
         move || {
@@ -225,10 +377,11 @@ pub(crate) fn new_workflow_task_buffer(
             let task_queue = task_queue.clone();
             async move { client.poll_workflow_task(task_queue, is_sticky).await }
         },
+        |resp| resp.task_token.is_empty(),
         semaphore,
-        concurrent_pollers,
+        behavior,
         shutdown,
-        num_pollers_handler,
+        metrics,
     )
 }
 
@@ -236,11 +389,11 @@ pub type PollActivityTaskBuffer = LongPollBuffer<PollActivityTaskQueueResponse>;
 pub(crate) fn new_activity_task_buffer(
     client: Arc<dyn WorkerClient>,
     task_queue: String,
-    concurrent_pollers: usize,
+    behavior: PollerBehavior,
     semaphore: Arc<MeteredSemaphore>,
     max_tps: Option<f64>,
     shutdown: CancellationToken,
-    num_pollers_handler: Option<impl Fn(usize) + Send + Sync + 'static>,
+    metrics: Option<MetricsContext>,
 ) -> PollActivityTaskBuffer {
     LongPollBuffer::new(
         move || {
@@ -248,10 +401,11 @@ pub(crate) fn new_activity_task_buffer(
             let task_queue = task_queue.clone();
             async move { client.poll_activity_task(task_queue, max_tps).await }
         },
+        |resp| resp.task_token.is_empty(),
         semaphore,
-        concurrent_pollers,
+        behavior,
         shutdown,
-        num_pollers_handler,
+        metrics,
     )
 }
 
@@ -270,11 +424,7 @@ where
     PT: Poller<T> + Send + Sync + 'static,
 {
     async fn poll(&self) -> Option<pollers::Result<(T, OwnedMeteredSemPermit)>> {
-        let p = self
-            .sem
-            .acquire_owned()
-            .await
-            .expect("Semaphore in poller not closed!");
+        let p = self.sem.acquire_owned().await;
         self.inner.poll().await.map(|r| r.map(|r| (r, p)))
     }
 
@@ -319,14 +469,14 @@ mod tests {
             Arc::new(mock_client),
             "someq".to_string(),
             false,
-            1,
+            PollerBehavior::SimpleMaximum(1),
             Arc::new(MeteredSemaphore::new(
                 10,
                 MetricsContext::no_op(),
                 |_, _| {},
             )),
             CancellationToken::new(),
-            None::<fn(usize)>,
+            None,
         );
 
         // Poll a bunch of times, "interrupting" it each time, we should only actually have polled
@@ -353,4 +503,39 @@ mod tests {
         pb.poll().await.unwrap().unwrap();
         pb.shutdown().await;
     }
+
+    #[test]
+    fn scaler_grows_when_busy_and_shrinks_when_idle() {
+        let (scaler, target_rx) = PollScaler::new(
+            PollerBehavior::Autoscaling {
+                minimum: 1,
+                maximum: 8,
+                initial: 2,
+            },
+            None,
+        );
+        let scaler = scaler.unwrap();
+        let mut now = Instant::now();
+        // Completes a window's worth of polls, one every `interval`
+        let mut window = |got_task: bool, interval: Duration| {
+            for _ in 0..SCALING_WINDOW_POLLS {
+                now += interval;
+                scaler.record_poll_at(got_task, now);
+            }
+            scaler.current_target()
+        };
+        let interval = Duration::from_millis(100);
+        assert_eq!(window(true, interval), 3);
+        // The extra poller didn't bring in tasks any faster, so don't add more
+        assert_eq!(window(true, interval), 3);
+        assert_eq!(window(true, interval / 2), 5);
+        assert_eq!(window(true, interval / 4), 8);
+        assert_eq!(window(true, interval / 8), 8);
+        assert_eq!(*target_rx.borrow(), 8);
+        assert_eq!(window(false, interval), 7);
+        for _ in 0..10 {
+            window(false, interval);
+        }
+        assert_eq!(scaler.current_target(), 1);
+    }
 }
@@ -0,0 +1,763 @@
+//! Parses histories in the JSON format produced by `tctl workflow show --output json` (and the
+//! `temporal` CLI), so that histories exported from production can be replayed. That format is the
+//! standard protobuf JSON mapping, except that tctl writes enum values without their type prefix
+//! (ex: `WorkflowExecutionStarted` rather than `EVENT_TYPE_WORKFLOW_EXECUTION_STARTED`).
+//!
+//! The generated API types have no JSON (de)serializers, so rather than hand-writing one for every
+//! event type, the JSON is transcoded into the protobuf binary encoding using the descriptors
+//! embedded in the protos crate, and then decoded as usual.
+
+use base64::{engine::general_purpose, Engine};
+use once_cell::sync::Lazy;
+use prost::{
+    encoding::{encode_key, encode_varint, WireType},
+    Message,
+};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use temporal_sdk_core_protos::{
+    temporal::api::history::v1::History, FILE_DESCRIPTOR_SET, STAND_IN_FILE_DESCRIPTOR_SET,
+};
+
+const HISTORY_TYPE_NAME: &str = ".temporal.api.history.v1.History";
+const TIMESTAMP_TYPE_NAME: &str = ".google.protobuf.Timestamp";
+const DURATION_TYPE_NAME: &str = ".google.protobuf.Duration";
+/// Well-known types whose JSON mapping differs from their message definition in ways the
+/// transcoder does not handle. None of them currently appear in history, but should one start to,
+/// failing loudly is better than silently dropping its contents.
+const UNSUPPORTED_TYPE_NAMES: &[&str] = &[
+    ".google.protobuf.Any",
+    ".google.protobuf.Struct",
+    ".google.protobuf.Value",
+    ".google.protobuf.ListValue",
+    ".google.protobuf.FieldMask",
+];
+
+/// Upstream messages which the protos crate replaces with a local definition carrying fields newer
+/// API versions have, paired with the name of that definition. Without substituting them, those
+/// fields would be silently dropped from parsed histories.
+const STAND_IN_TYPE_NAMES: &[(&str, &str)] = &[(
+    ".temporal.api.sdk.v1.WorkflowTaskCompletedMetadata",
+    ".coresdk.sdk_metadata.WorkflowTaskCompletedMetadata",
+)];
+
+static DESCRIPTORS: Lazy<Descriptors> = Lazy::new(|| {
+    let decode = |bytes: &'static [u8]| {
+        FileDescriptorSet::decode(bytes)
+            .expect("Descriptors embedded in the protos crate must be valid")
+    };
+    let mut descriptors = Descriptors::new(decode(FILE_DESCRIPTOR_SET));
+    descriptors.substitute_stand_ins(Descriptors::new(decode(STAND_IN_FILE_DESCRIPTOR_SET)));
+    descriptors
+});
+
+/// Returned when a JSON history could not be parsed
+#[derive(thiserror::Error, Debug)]
+pub enum HistoryJsonError {
+    /// The input was not valid JSON at all
+    #[error("History is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The input was valid JSON, but some value in it did not fit the history protos
+    #[error("Invalid value at `{path}`: {message}")]
+    InvalidValue {
+        /// Where in the document the problem was found, ex: `events[3].eventTime`
+        path: String,
+        /// What was wrong with the value
+        message: String,
+    },
+}
+
+/// Parse a history from its JSON representation. Accepts either a `History` object, or the bare
+/// list of its events. Fields which are unknown to the protos core was built with are ignored, the
+/// same as they would be when decoding the binary format.
+pub(crate) fn history_from_json(json: &str) -> Result<History, HistoryJsonError> {
+    let val = match serde_json::from_str(json)? {
+        Value::Array(events) => Value::Object(Map::from_iter([(
+            "events".to_string(),
+            Value::Array(events),
+        )])),
+        other => other,
+    };
+    let mut buf = vec![];
+    DESCRIPTORS.encode_message(HISTORY_TYPE_NAME, &val, "", &mut buf)?;
+    History::decode(buf.as_slice()).map_err(|e| invalid("", e.to_string()))
+}
+
+fn invalid(path: &str, message: impl Into<String>) -> HistoryJsonError {
+    HistoryJsonError::InvalidValue {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+/// Field types, as numbered in `google.protobuf.FieldDescriptorProto.Type`
+mod field_type {
+    pub(super) const DOUBLE: i32 = 1;
+    pub(super) const FLOAT: i32 = 2;
+    pub(super) const INT64: i32 = 3;
+    pub(super) const UINT64: i32 = 4;
+    pub(super) const INT32: i32 = 5;
+    pub(super) const FIXED64: i32 = 6;
+    pub(super) const FIXED32: i32 = 7;
+    pub(super) const BOOL: i32 = 8;
+    pub(super) const STRING: i32 = 9;
+    pub(super) const MESSAGE: i32 = 11;
+    pub(super) const BYTES: i32 = 12;
+    pub(super) const UINT32: i32 = 13;
+    pub(super) const ENUM: i32 = 14;
+    pub(super) const SFIXED32: i32 = 15;
+    pub(super) const SFIXED64: i32 = 16;
+    pub(super) const SINT32: i32 = 17;
+    pub(super) const SINT64: i32 = 18;
+}
+const LABEL_REPEATED: i32 = 3;
+
+// The subset of `google/protobuf/descriptor.proto` needed to drive transcoding. Anything else in
+// the encoded descriptors is skipped over when decoding.
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<FileDescriptorProto>,
+}
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorProto {
+    #[prost(string, tag = "2")]
+    package: String,
+    #[prost(message, repeated, tag = "4")]
+    message_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "5")]
+    enum_type: Vec<EnumDescriptorProto>,
+}
+#[derive(Clone, PartialEq, Message)]
+struct DescriptorProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, repeated, tag = "2")]
+    field: Vec<FieldDescriptorProto>,
+    #[prost(message, repeated, tag = "3")]
+    nested_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "4")]
+    enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, optional, tag = "7")]
+    options: Option<MessageOptions>,
+}
+#[derive(Clone, PartialEq, Message)]
+struct MessageOptions {
+    #[prost(bool, tag = "7")]
+    map_entry: bool,
+}
+#[derive(Clone, PartialEq, Message)]
+struct FieldDescriptorProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(int32, tag = "3")]
+    number: i32,
+    #[prost(int32, tag = "4")]
+    label: i32,
+    #[prost(int32, tag = "5")]
+    r#type: i32,
+    #[prost(string, tag = "6")]
+    type_name: String,
+    #[prost(string, tag = "10")]
+    json_name: String,
+}
+#[derive(Clone, PartialEq, Message)]
+struct EnumDescriptorProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, repeated, tag = "2")]
+    value: Vec<EnumValueDescriptorProto>,
+}
+#[derive(Clone, PartialEq, Message)]
+struct EnumValueDescriptorProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(int32, tag = "2")]
+    number: i32,
+}
+
+/// Message and enum definitions, keyed by fully qualified name (with a leading `.`, which is how
+/// field descriptors refer to them)
+#[derive(Default)]
+struct Descriptors {
+    messages: HashMap<String, MessageDesc>,
+    enums: HashMap<String, EnumDesc>,
+}
+
+struct MessageDesc {
+    /// Keyed by both the JSON (lowerCamelCase) and original names, since parsers must accept either
+    fields: HashMap<String, FieldDesc>,
+    is_map_entry: bool,
+}
+
+struct FieldDesc {
+    number: u32,
+    field_type: i32,
+    repeated: bool,
+    type_name: String,
+}
+
+struct EnumDesc {
+    /// Keyed by both the exact value name, and the normalized form of the value name with and
+    /// without the enum's prefix
+    values: HashMap<String, i32>,
+}
+
+impl Descriptors {
+    fn new(set: FileDescriptorSet) -> Self {
+        let mut me = Self::default();
+        for file in set.file {
+            let scope = if file.package.is_empty() {
+                String::new()
+            } else {
+                format!(".{}", file.package)
+            };
+            for msg in file.message_type {
+                me.add_message(&scope, msg);
+            }
+            for enm in file.enum_type {
+                me.add_enum(&scope, enm);
+            }
+        }
+        me
+    }
+
+    /// Replace the upstream definitions listed in [STAND_IN_TYPE_NAMES] with their local stand-ins
+    fn substitute_stand_ins(&mut self, mut stand_ins: Descriptors) {
+        for (upstream, local) in STAND_IN_TYPE_NAMES {
+            let desc = stand_ins
+                .messages
+                .remove(*local)
+                .unwrap_or_else(|| panic!("Stand-in message {local} must be compiled"));
+            self.messages.insert(upstream.to_string(), desc);
+        }
+    }
+
+    fn add_message(&mut self, scope: &str, msg: DescriptorProto) {
+        let full_name = format!("{scope}.{}", msg.name);
+        let mut fields = HashMap::new();
+        for field in msg.field {
+            let json_name = if field.json_name.is_empty() {
+                lower_camel_case(&field.name)
+            } else {
+                field.json_name.clone()
+            };
+            let desc = || FieldDesc {
+                number: field.number as u32,
+                field_type: field.r#type,
+                repeated: field.label == LABEL_REPEATED,
+                type_name: field.type_name.clone(),
+            };
+            fields.insert(json_name, desc());
+            fields.insert(field.name.clone(), desc());
+        }
+        for nested in msg.nested_type {
+            self.add_message(&full_name, nested);
+        }
+        for enm in msg.enum_type {
+            self.add_enum(&full_name, enm);
+        }
+        self.messages.insert(
+            full_name,
+            MessageDesc {
+                fields,
+                is_map_entry: msg.options.map(|o| o.map_entry).unwrap_or_default(),
+            },
+        );
+    }
+
+    fn add_enum(&mut self, scope: &str, enm: EnumDescriptorProto) {
+        let prefix = format!("{}_", screaming_snake_case(&enm.name));
+        let mut values = HashMap::new();
+        for v in enm.value {
+            values.insert(normalize_enum_name(&v.name), v.number);
+            if let Some(unprefixed) = v.name.strip_prefix(&prefix) {
+                values.insert(normalize_enum_name(unprefixed), v.number);
+            }
+            values.insert(v.name, v.number);
+        }
+        self.enums
+            .insert(format!("{scope}.{}", enm.name), EnumDesc { values });
+    }
+
+    fn encode_message(
+        &self,
+        type_name: &str,
+        val: &Value,
+        path: &str,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), HistoryJsonError> {
+        match type_name {
+            TIMESTAMP_TYPE_NAME => return encode_timestamp(val, path, buf),
+            DURATION_TYPE_NAME => return encode_duration(val, path, buf),
+            t if UNSUPPORTED_TYPE_NAMES.contains(&t) => {
+                return Err(invalid(path, format!("{t} values are not supported")))
+            }
+            _ => {}
+        }
+        let desc = self
+            .messages
+            .get(type_name)
+            .ok_or_else(|| invalid(path, format!("no definition for message {type_name}")))?;
+        let obj = match val {
+            Value::Object(obj) => obj,
+            // Wrapper types (ex: `google.protobuf.BoolValue`) are represented by their bare value
+            other if type_name.starts_with(".google.protobuf.") => {
+                if let Some(field) = desc.fields.get("value") {
+                    return self.encode_field(field, other, path, buf);
+                }
+                return Err(invalid(path, "expected an object"));
+            }
+            _ => return Err(invalid(path, "expected an object")),
+        };
+        for (key, v) in obj {
+            // Fields added to the API after the protos core was built with can't be understood
+            let field = match desc.fields.get(key) {
+                Some(f) => f,
+                None => continue,
+            };
+            if v.is_null() {
+                continue;
+            }
+            let path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+            let map_entry = self
+                .messages
+                .get(&field.type_name)
+                .filter(|m| m.is_map_entry);
+            if let Some(entry_desc) = map_entry {
+                let entries = v
+                    .as_object()
+                    .ok_or_else(|| invalid(&path, "expected an object"))?;
+                for (k, v) in entries {
+                    let entry_path = format!("{path}[{k}]");
+                    let mut entry = vec![];
+                    if let Some(key_field) = entry_desc.fields.get("key") {
+                        self.encode_field(
+                            key_field,
+                            &Value::String(k.clone()),
+                            &entry_path,
+                            &mut entry,
+                        )?;
+                    }
+                    if let Some(val_field) = entry_desc.fields.get("value") {
+                        self.encode_field(val_field, v, &entry_path, &mut entry)?;
+                    }
+                    encode_len_delimited(field.number, &entry, buf);
+                }
+            } else if field.repeated {
+                let items = v
+                    .as_array()
+                    .ok_or_else(|| invalid(&path, "expected an array"))?;
+                for (i, item) in items.iter().enumerate() {
+                    self.encode_field(field, item, &format!("{path}[{i}]"), buf)?;
+                }
+            } else {
+                self.encode_field(field, v, &path, buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode a single (non-repeated) value of the provided field
+    fn encode_field(
+        &self,
+        field: &FieldDesc,
+        val: &Value,
+        path: &str,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), HistoryJsonError> {
+        let tag = field.number;
+        match field.field_type {
+            field_type::MESSAGE => {
+                let mut inner = vec![];
+                self.encode_message(&field.type_name, val, path, &mut inner)?;
+                encode_len_delimited(tag, &inner, buf);
+            }
+            field_type::STRING => {
+                let s = val
+                    .as_str()
+                    .ok_or_else(|| invalid(path, "expected a string"))?;
+                encode_len_delimited(tag, s.as_bytes(), buf);
+            }
+            field_type::BYTES => {
+                let s = val
+                    .as_str()
+                    .ok_or_else(|| invalid(path, "expected a base64 string"))?;
+                let bytes = general_purpose::STANDARD
+                    .decode(s)
+                    .or_else(|_| general_purpose::URL_SAFE.decode(s))
+                    .map_err(|e| invalid(path, format!("invalid base64: {e}")))?;
+                encode_len_delimited(tag, &bytes, buf);
+            }
+            field_type::ENUM => {
+                let num = self.enum_number(&field.type_name, val, path)?;
+                encode_key(tag, WireType::Varint, buf);
+                encode_varint(num as i64 as u64, buf);
+            }
+            field_type::BOOL => {
+                let b = match val {
+                    Value::Bool(b) => *b,
+                    // Map keys are always strings
+                    Value::String(s) if s == "true" => true,
+                    Value::String(s) if s == "false" => false,
+                    _ => return Err(invalid(path, "expected a boolean")),
+                };
+                encode_key(tag, WireType::Varint, buf);
+                encode_varint(b as u64, buf);
+            }
+            field_type::INT32 | field_type::INT64 | field_type::UINT32 | field_type::UINT64 => {
+                encode_key(tag, WireType::Varint, buf);
+                // Truncating the two's complement representation is exactly how protobuf encodes
+                // negative varints
+                encode_varint(parse_integer(val, path)? as u64, buf);
+            }
+            field_type::SINT32 | field_type::SINT64 => {
+                let n = parse_integer(val, path)? as i64;
+                encode_key(tag, WireType::Varint, buf);
+                encode_varint(((n << 1) ^ (n >> 63)) as u64, buf);
+            }
+            field_type::FIXED32 | field_type::SFIXED32 => {
+                encode_key(tag, WireType::ThirtyTwoBit, buf);
+                buf.extend_from_slice(&(parse_integer(val, path)? as u32).to_le_bytes());
+            }
+            field_type::FIXED64 | field_type::SFIXED64 => {
+                encode_key(tag, WireType::SixtyFourBit, buf);
+                buf.extend_from_slice(&(parse_integer(val, path)? as u64).to_le_bytes());
+            }
+            field_type::FLOAT => {
+                encode_key(tag, WireType::ThirtyTwoBit, buf);
+                buf.extend_from_slice(&(parse_float(val, path)? as f32).to_le_bytes());
+            }
+            field_type::DOUBLE => {
+                encode_key(tag, WireType::SixtyFourBit, buf);
+                buf.extend_from_slice(&parse_float(val, path)?.to_le_bytes());
+            }
+            other => return Err(invalid(path, format!("unsupported field type {other}"))),
+        }
+        Ok(())
+    }
+
+    fn enum_number(
+        &self,
+        type_name: &str,
+        val: &Value,
+        path: &str,
+    ) -> Result<i32, HistoryJsonError> {
+        let name = match val {
+            Value::Number(_) => return parse_integer(val, path).map(|n| n as i32),
+            Value::String(s) => s,
+            _ => return Err(invalid(path, "expected an enum value name")),
+        };
+        let values = &self
+            .enums
+            .get(type_name)
+            .ok_or_else(|| invalid(path, format!("no definition for enum {type_name}")))?
+            .values;
+        values
+            .get(name.as_str())
+            .or_else(|| values.get(&normalize_enum_name(name)))
+            .copied()
+            .ok_or_else(|| invalid(path, format!("unknown value {name} for enum {type_name}")))
+    }
+}
+
+fn encode_len_delimited(tag: u32, bytes: &[u8], buf: &mut Vec<u8>) {
+    encode_key(tag, WireType::LengthDelimited, buf);
+    encode_varint(bytes.len() as u64, buf);
+    buf.extend_from_slice(bytes);
+}
+
+/// 64 bit integers are usually written as strings, since JSON numbers can't represent all of them
+fn parse_integer(val: &Value, path: &str) -> Result<i128, HistoryJsonError> {
+    let n = match val {
+        Value::Number(n) => n
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| n.as_u64().map(i128::from))
+            .or_else(|| n.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i128)),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    n.filter(|n| (i64::MIN as i128..=u64::MAX as i128).contains(n))
+        .ok_or_else(|| invalid(path, "expected an integer"))
+}
+
+fn parse_float(val: &Value, path: &str) -> Result<f64, HistoryJsonError> {
+    match val {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => match s.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            s => s.parse().ok(),
+        },
+        _ => None,
+    }
+    .ok_or_else(|| invalid(path, "expected a number"))
+}
+
+/// Timestamps are RFC 3339 strings, ex: `2023-01-01T12:00:00.123Z`
+fn encode_timestamp(val: &Value, path: &str, buf: &mut Vec<u8>) -> Result<(), HistoryJsonError> {
+    let ts: prost_types::Timestamp = serde_json::from_value(val.clone())
+        .map_err(|e| invalid(path, format!("invalid timestamp: {e}")))?;
+    ts.encode_raw(buf);
+    Ok(())
+}
+
+/// Durations are decimal seconds suffixed with `s`, ex: `10s` or `-1.5s`
+fn encode_duration(val: &Value, path: &str, buf: &mut Vec<u8>) -> Result<(), HistoryJsonError> {
+    let err = || invalid(path, "invalid duration, expected a string like \"1.5s\"");
+    let s = val
+        .as_str()
+        .and_then(|s| s.strip_suffix('s'))
+        .ok_or_else(err)?;
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 9 || !frac.chars().all(|c| c.is_ascii_digit()) {
+        return Err(err());
+    }
+    let seconds: i64 = secs.parse().map_err(|_| err())?;
+    let nanos: i32 = if frac.is_empty() {
+        0
+    } else {
+        format!("{frac:0<9}").parse().map_err(|_| err())?
+    };
+    let sign = if negative { -1 } else { 1 };
+    prost_types::Duration {
+        seconds: sign * seconds,
+        nanos: sign as i32 * nanos,
+    }
+    .encode_raw(buf);
+    Ok(())
+}
+
+fn lower_camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper_next = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper_next = true;
+        } else if upper_next {
+            out.push(c.to_ascii_uppercase());
+            upper_next = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn screaming_snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
+
+/// Allows `WorkflowExecutionStarted` (as written by tctl) to match `WORKFLOW_EXECUTION_STARTED`
+fn normalize_enum_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temporal_sdk_core_protos::temporal::api::{
+        enums::v1::{EventType, TaskQueueKind},
+        history::v1::history_event::Attributes,
+    };
+
+    const TCTL_HISTORY: &str = r#"{
+      "events": [
+        {
+          "eventId": "1",
+          "eventTime": "2023-03-01T17:53:01.125493Z",
+          "eventType": "WorkflowExecutionStarted",
+          "version": "0",
+          "taskId": "1048576",
+          "workflowExecutionStartedEventAttributes": {
+            "workflowType": { "name": "sleepy" },
+            "taskQueue": { "name": "q", "kind": "Normal" },
+            "input": {
+              "payloads": [
+                {
+                  "metadata": { "encoding": "anNvbi9wbGFpbg==" },
+                  "data": "IndvcmxkIg=="
+                }
+              ]
+            },
+            "workflowTaskTimeout": "10s",
+            "originalExecutionRunId": "b8a0b1ef-c8ac-4b34-9a86-1b0b6fd9e9d4",
+            "attempt": 1,
+            "header": {},
+            "someFieldFromTheFuture": { "whatever": true }
+          }
+        },
+        {
+          "eventId": "2",
+          "eventTime": "2023-03-01T17:53:01.125525Z",
+          "eventType": "EVENT_TYPE_WORKFLOW_TASK_SCHEDULED",
+          "taskId": "1048577",
+          "workflowTaskScheduledEventAttributes": {
+            "taskQueue": { "name": "q", "kind": "TASK_QUEUE_KIND_NORMAL" },
+            "startToCloseTimeout": "10.5s",
+            "attempt": 1
+          }
+        }
+      ]
+    }"#;
+
+    #[test]
+    fn parses_tctl_json() {
+        let hist = history_from_json(TCTL_HISTORY).unwrap();
+        assert_eq!(hist.events.len(), 2);
+        let started = &hist.events[0];
+        assert_eq!(started.event_id, 1);
+        assert_eq!(started.task_id, 1048576);
+        assert_eq!(started.event_type(), EventType::WorkflowExecutionStarted);
+        assert_eq!(started.event_time.as_ref().unwrap().nanos, 125_493_000);
+        assert_matches!(
+            started.attributes.as_ref().unwrap(),
+            Attributes::WorkflowExecutionStartedEventAttributes(attrs) => {
+                assert_eq!(attrs.workflow_type.as_ref().unwrap().name, "sleepy");
+                assert_eq!(attrs.task_queue.as_ref().unwrap().kind(), TaskQueueKind::Normal);
+                let payload = &attrs.input.as_ref().unwrap().payloads[0];
+                assert_eq!(payload.metadata["encoding"], b"json/plain");
+                assert_eq!(payload.data, b"\"world\"");
+                assert_eq!(attrs.workflow_task_timeout.as_ref().unwrap().seconds, 10);
+                assert_eq!(attrs.original_execution_run_id, "b8a0b1ef-c8ac-4b34-9a86-1b0b6fd9e9d4");
+            }
+        );
+        assert_eq!(
+            hist.events[1].event_type(),
+            EventType::WorkflowTaskScheduled
+        );
+        assert_matches!(
+            hist.events[1].attributes.as_ref().unwrap(),
+            Attributes::WorkflowTaskScheduledEventAttributes(attrs) => {
+                let timeout = attrs.start_to_close_timeout.as_ref().unwrap();
+                assert_eq!((timeout.seconds, timeout.nanos), (10, 500_000_000));
+            }
+        );
+    }
+
+    #[test]
+    fn accepts_bare_event_list() {
+        let hist =
+            history_from_json(r#"[{"eventId": 1, "eventType": "WorkflowExecutionStarted"}]"#)
+                .unwrap();
+        assert_eq!(hist.events.len(), 1);
+    }
+
+    #[test]
+    fn reports_path_of_bad_values() {
+        let err =
+            history_from_json(r#"{"events": [{"eventId": "1"}, {"eventId": "two"}]}"#).unwrap_err();
+        assert_matches!(
+            err,
+            HistoryJsonError::InvalidValue { path, .. } if path == "events[1].eventId"
+        );
+        let err = history_from_json(r#"{"events": [{"eventType": "NotARealEvent"}]}"#).unwrap_err();
+        assert_matches!(
+            err,
+            HistoryJsonError::InvalidValue { path, .. } if path == "events[0].eventType"
+        );
+        assert_matches!(
+            history_from_json("{not json").unwrap_err(),
+            HistoryJsonError::Json(_)
+        );
+    }
+
+    #[test]
+    fn keeps_fields_only_stand_in_messages_have() {
+        let json = r#"[{
+          "eventId": "4",
+          "eventType": "WorkflowTaskCompleted",
+          "workflowTaskCompletedEventAttributes": {
+            "scheduledEventId": "2",
+            "sdkMetadata": {
+              "coreUsedFlags": [1],
+              "sdkName": "temporal-rust",
+              "sdkVersion": "0.1.0"
+            }
+          }
+        }]"#;
+        let hist = history_from_json(json).unwrap();
+        let meta = match hist.events[0].attributes.as_ref().unwrap() {
+            Attributes::WorkflowTaskCompletedEventAttributes(attrs) => {
+                attrs.sdk_metadata.clone().unwrap()
+            }
+            other => panic!("Unexpected attributes {other:?}"),
+        };
+        assert_eq!(meta.core_used_flags, vec![1]);
+        assert_eq!(meta.sdk_name, "temporal-rust");
+        assert_eq!(meta.sdk_version, "0.1.0");
+        // And the fields survive the binary encoding
+        assert_eq!(
+            History::decode(hist.encode_to_vec().as_slice()).unwrap(),
+            hist
+        );
+    }
+
+    /// Stand-ins must stay wire compatible with the upstream messages they replace. If this fails
+    /// after updating the upstream protos, the stand-in needs the new fields, or can be removed.
+    #[test]
+    fn stand_ins_cover_upstream_fields() {
+        let decode =
+            |bytes: &'static [u8]| Descriptors::new(FileDescriptorSet::decode(bytes).unwrap());
+        let upstream = decode(FILE_DESCRIPTOR_SET);
+        let stand_ins = decode(STAND_IN_FILE_DESCRIPTOR_SET);
+        for (upstream_name, local_name) in STAND_IN_TYPE_NAMES {
+            let upstream_msg = &upstream.messages[*upstream_name];
+            let local_msg = &stand_ins.messages[*local_name];
+            for (name, field) in &upstream_msg.fields {
+                let local_field = local_msg
+                    .fields
+                    .get(name)
+                    .unwrap_or_else(|| panic!("{local_name} lacks upstream field {name}"));
+                assert_eq!(
+                    (
+                        local_field.number,
+                        local_field.field_type,
+                        local_field.repeated
+                    ),
+                    (field.number, field.field_type, field.repeated),
+                    "{local_name}.{name} does not match upstream"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_well_known_types() {
+        for type_name in UNSUPPORTED_TYPE_NAMES {
+            let err = DESCRIPTORS
+                .encode_message(
+                    type_name,
+                    &serde_json::json!({"a": 1}),
+                    "events[0].body",
+                    &mut vec![],
+                )
+                .unwrap_err();
+            assert_matches!(
+                err,
+                HistoryJsonError::InvalidValue { path, message }
+                    if path == "events[0].body" && message.contains(type_name)
+            );
+        }
+    }
+}
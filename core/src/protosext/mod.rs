@@ -1,3 +1,8 @@
+mod history_json;
+
+pub(crate) use history_json::history_from_json;
+pub use history_json::HistoryJsonError;
+
 use crate::{
     worker::{LocalActivityExecutionResult, LEGACY_QUERY_ID},
     CompleteActivityError, TaskToken,
@@ -13,10 +18,7 @@ use temporal_sdk_core_protos::{
     constants::{LOCAL_ACTIVITY_MARKER_NAME, PATCH_MARKER_NAME},
     coresdk::{
         activity_result::{activity_execution_result, activity_execution_result::Status},
-        common::{
-            decode_change_marker_details, extract_local_activity_marker_data,
-            extract_local_activity_marker_details,
-        },
+        common::{decode_change_marker_details, decode_local_activity_marker_data},
         external_data::LocalActivityMarkerData,
         workflow_activation::{
             query_to_job, workflow_activation_job, QueryWorkflow, WorkflowActivation,
@@ -25,17 +27,18 @@ use temporal_sdk_core_protos::{
         workflow_commands::{
             query_result, ActivityCancellationType, QueryResult, ScheduleLocalActivity,
         },
-        workflow_completion,
+        workflow_completion, AsJsonPayloadExt, IntoPayloadsExt,
     },
     temporal::api::{
         common::v1::{Payload, RetryPolicy, WorkflowExecution},
         enums::v1::EventType,
-        failure::v1::Failure,
+        failure::v1::{failure, Failure},
         history::v1::{history_event, History, HistoryEvent, MarkerRecordedEventAttributes},
         query::v1::WorkflowQuery,
         workflowservice::v1::PollWorkflowTaskQueueResponse,
     },
     utilities::TryIntoOrNone,
+    PATCHED_MARKER_DETAILS_KEY,
 };
 
 /// A validated version of a [PollWorkflowTaskQueueResponse]
@@ -135,9 +138,15 @@ pub(crate) trait WorkflowActivationExt {
 
 impl WorkflowActivationExt for WorkflowActivation {
     fn is_legacy_query(&self) -> bool {
-        matches!(&self.jobs.as_slice(), &[WorkflowActivationJob {
-                    variant: Some(workflow_activation_job::Variant::QueryWorkflow(qr))
-                }] if qr.query_id == LEGACY_QUERY_ID)
+        match &self.jobs.as_slice() {
+            &[WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::QueryWorkflow(qr)),
+            }] => qr.query_id == LEGACY_QUERY_ID,
+            &[WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::QueryStackTrace(qr)),
+            }] => qr.query_id == LEGACY_QUERY_ID,
+            _ => false,
+        }
     }
 }
 
@@ -157,12 +166,24 @@ pub(crate) trait HistoryEventExt {
     fn get_patch_marker_details(&self) -> Option<(String, bool)>;
     /// If this history event represents a local activity marker, return true.
     fn is_local_activity_marker(&self) -> bool;
+    /// If this history event represents a user-defined marker (IE: one not recorded by core for
+    /// its own purposes), return the marker's name.
+    fn get_user_marker_name(&self) -> Option<&str>;
+    /// If this history event represents a patch marker whose details cannot be decoded, describe
+    /// what is wrong with it.
+    fn malformed_patch_marker(&self) -> Option<MalformedPayload>;
     /// If this history event represents a local activity marker, return the marker id info.
-    /// Returns `None` if it is any other kind of event or marker or the data is invalid.
-    fn extract_local_activity_marker_data(&self) -> Option<LocalActivityMarkerData>;
+    /// Returns `Ok(None)` if it is any other kind of event or marker, and an error if the data is
+    /// invalid.
+    fn extract_local_activity_marker_data(
+        &self,
+    ) -> Result<Option<LocalActivityMarkerData>, MalformedPayload>;
     /// If this history event represents a local activity marker, return all the contained data.
-    /// Returns `None` if it is any other kind of event or marker or the data is invalid.
-    fn into_local_activity_marker_details(self) -> Option<CompleteLocalActivityData>;
+    /// Returns `Ok(None)` if it is any other kind of event or marker, and an error if the data is
+    /// invalid.
+    fn into_local_activity_marker_details(
+        self,
+    ) -> Result<Option<CompleteLocalActivityData>, MalformedPayload>;
 }
 
 impl HistoryEventExt for HistoryEvent {
@@ -194,7 +215,51 @@ impl HistoryEventExt for HistoryEvent {
         false
     }
 
-    fn extract_local_activity_marker_data(&self) -> Option<LocalActivityMarkerData> {
+    fn get_user_marker_name(&self) -> Option<&str> {
+        if self.event_type() == EventType::MarkerRecorded {
+            match &self.attributes {
+                Some(history_event::Attributes::MarkerRecordedEventAttributes(
+                    MarkerRecordedEventAttributes { marker_name, .. },
+                )) if !is_core_marker_name(marker_name) => Some(marker_name.as_str()),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    fn malformed_patch_marker(&self) -> Option<MalformedPayload> {
+        if self.event_type() != EventType::MarkerRecorded {
+            return None;
+        }
+        match &self.attributes {
+            Some(history_event::Attributes::MarkerRecordedEventAttributes(
+                MarkerRecordedEventAttributes {
+                    marker_name,
+                    details,
+                    ..
+                },
+            )) if marker_name == PATCH_MARKER_NAME
+                && decode_change_marker_details(details).is_none() =>
+            {
+                let key = if details.contains_key(PATCHED_MARKER_DETAILS_KEY) {
+                    PATCHED_MARKER_DETAILS_KEY
+                } else {
+                    "patch_id"
+                };
+                Some(MalformedPayload::marker_detail(
+                    self.event_id,
+                    key,
+                    "could not decode patch id and deprecation flag",
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn extract_local_activity_marker_data(
+        &self,
+    ) -> Result<Option<LocalActivityMarkerData>, MalformedPayload> {
         if self.event_type() == EventType::MarkerRecorded {
             match &self.attributes {
                 Some(history_event::Attributes::MarkerRecordedEventAttributes(
@@ -204,16 +269,20 @@ impl HistoryEventExt for HistoryEvent {
                         ..
                     },
                 )) if marker_name == LOCAL_ACTIVITY_MARKER_NAME => {
-                    extract_local_activity_marker_data(details)
+                    decode_local_activity_marker_data(details)
+                        .map(Some)
+                        .map_err(|reason| MalformedPayload::la_marker_data(self.event_id, reason))
                 }
-                _ => None,
+                _ => Ok(None),
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn into_local_activity_marker_details(self) -> Option<CompleteLocalActivityData> {
+    fn into_local_activity_marker_details(
+        self,
+    ) -> Result<Option<CompleteLocalActivityData>, MalformedPayload> {
         if self.event_type() == EventType::MarkerRecorded {
             match self.attributes {
                 Some(history_event::Attributes::MarkerRecordedEventAttributes(
@@ -224,26 +293,68 @@ impl HistoryEventExt for HistoryEvent {
                         ..
                     },
                 )) if marker_name == LOCAL_ACTIVITY_MARKER_NAME => {
-                    let (data, ok_res) = extract_local_activity_marker_details(&mut details);
-                    let data = data?;
+                    let data = decode_local_activity_marker_data(&details).map_err(|reason| {
+                        MalformedPayload::la_marker_data(self.event_id, reason)
+                    })?;
+                    let ok_res = details.remove("result").and_then(|mut p| p.payloads.pop());
                     let result = match (ok_res, failure) {
                         (Some(r), None) => Ok(r),
                         (None | Some(_), Some(f)) => Err(f),
                         (None, None) => Ok(Default::default()),
                     };
-                    Some(CompleteLocalActivityData {
+                    Ok(Some(CompleteLocalActivityData {
                         marker_dat: data,
                         result,
-                    })
+                    }))
                 }
-                _ => None,
+                _ => Ok(None),
             }
         } else {
-            None
+            Ok(None)
         }
     }
 }
 
+/// A payload core needed to decode from a history event was malformed. Identifies the offending
+/// event and attribute so the problem can be tracked down in history.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[error("Malformed payload in event {event_id} at `{attribute_path}`: {reason}")]
+pub(crate) struct MalformedPayload {
+    pub(crate) event_id: i64,
+    pub(crate) attribute_path: String,
+    pub(crate) reason: String,
+}
+
+impl MalformedPayload {
+    fn marker_detail(event_id: i64, key: &str, reason: impl Into<String>) -> Self {
+        Self {
+            event_id,
+            attribute_path: format!("marker_recorded_event_attributes.details[\"{key}\"]"),
+            reason: reason.into(),
+        }
+    }
+
+    fn la_marker_data(event_id: i64, reason: String) -> Self {
+        Self::marker_detail(event_id, "data", reason)
+    }
+
+    /// Converts to a workflow task failure, with the structured error in the failure details
+    pub(crate) fn as_failure(&self) -> Failure {
+        let mut f = Failure::application_failure(self.to_string(), false);
+        if let Some(failure::FailureInfo::ApplicationFailureInfo(ref mut info)) = f.failure_info {
+            info.r#type = "MalformedPayload".to_string();
+            info.details = self.as_json_payload().ok().into_payloads();
+        }
+        f
+    }
+}
+
+/// Returns true if the marker name is one core uses for its own markers, and thus may not be used
+/// for user-defined markers
+pub(crate) fn is_core_marker_name(marker_name: &str) -> bool {
+    marker_name == PATCH_MARKER_NAME || marker_name == LOCAL_ACTIVITY_MARKER_NAME
+}
+
 pub(crate) struct CompleteLocalActivityData {
     pub marker_dat: LocalActivityMarkerData,
     pub result: Result<Payload, Failure>,
@@ -2,9 +2,12 @@
 //! to replay canned histories. It should be used by Lang SDKs to provide replay capabilities to
 //! users during testing.
 
+pub use crate::protosext::HistoryJsonError;
+
 use crate::{
+    protosext::history_from_json,
     worker::{
-        client::{mocks::mock_manual_workflow_client, WorkerClient},
+        client::{decode_history_failures, mocks::mock_manual_workflow_client, WorkerClient},
         PostActivateHookData,
     },
     Worker,
@@ -12,18 +15,26 @@ use crate::{
 use futures::{FutureExt, Stream, StreamExt};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use prost::Message;
 use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
 };
+use temporal_client::WorkflowClientTrait;
+use temporal_sdk_core_api::{errors::ReplayError, worker::FailureConverter};
 use temporal_sdk_core_protos::{
     coresdk::workflow_activation::remove_from_cache::EvictionReason,
     temporal::api::{
         common::v1::WorkflowExecution,
-        history::v1::History,
+        enums::v1::WorkflowTaskFailedCause,
+        failure::v1::Failure,
+        history::v1::{history_event, History},
         workflowservice::v1::{
-            RespondWorkflowTaskCompletedResponse, RespondWorkflowTaskFailedResponse,
+            GetWorkflowExecutionHistoryResponse, RespondWorkflowTaskCompletedResponse,
+            RespondWorkflowTaskFailedResponse,
         },
     },
 };
@@ -42,6 +53,148 @@ pub struct HistoryForReplay {
     workflow_id: String,
 }
 
+impl HistoryForReplay {
+    /// Decode a history serialized as protobuf binary (ex: exported by the CLI, or by encoding the
+    /// result of [Worker::export_history]) and attach the workflow id to replay it under. This
+    /// lets lang SDKs replay histories they only have as bytes without decoding them themselves.
+    /// For histories exported as JSON, use [Self::from_json].
+    pub fn from_proto_bytes(
+        bytes: &[u8],
+        workflow_id: impl Into<String>,
+    ) -> Result<Self, prost::DecodeError> {
+        Ok(Self::new(History::decode(bytes)?, workflow_id.into()))
+    }
+
+    /// Parse a history in the JSON format produced by `tctl workflow show --output json` or the
+    /// `temporal` CLI, and attach the workflow id to replay it under.
+    pub fn from_json(json: &str, workflow_id: impl Into<String>) -> Result<Self, HistoryJsonError> {
+        Ok(Self::new(history_from_json(json)?, workflow_id.into()))
+    }
+
+    /// Decodes the failures in the history, as the worker's client would for histories fetched
+    /// from the server
+    pub(crate) fn decode_failures(&mut self, conv: &dyn FailureConverter) {
+        decode_history_failures(conv, &mut self.hist);
+    }
+}
+
+/// Fetches the complete history of the identified run (or the latest run, if `run_id` is unset)
+/// from the server, paging through it as needed. Useful for checking whether a workflow which ran
+/// in production replays against the current workflow code: pass the history to
+/// [crate::init_batch_replay_worker], drive the worker from lang, and inspect the returned
+/// [ReplayResults] once it has shut down.
+pub async fn fetch_history_for_replay<C>(
+    client: &C,
+    workflow_id: impl Into<String>,
+    run_id: Option<String>,
+) -> Result<HistoryForReplay, ReplayError>
+where
+    C: WorkflowClientTrait + Sync + ?Sized,
+{
+    let workflow_id = workflow_id.into();
+    let hist = fetch_full_history(
+        |page_token| {
+            client.get_workflow_execution_history(workflow_id.clone(), run_id.clone(), page_token)
+        },
+        None,
+    )
+    .await?;
+    Ok(HistoryForReplay::new(hist, workflow_id))
+}
+
+/// Fetches the history of every run in the continue-as-new chain leading up to and including the
+/// identified run (or the latest run, if `run_id` is unset), by following each run's
+/// `continued_execution_run_id` back to the first run of the chain. Histories are returned oldest
+/// first, and may be passed to one of the replay worker init functions to check determinism across
+/// the entire chain in one go. Every run has its own run id, so each is replayed against fresh
+/// machines.
+pub async fn fetch_continue_as_new_chain<C>(
+    client: &C,
+    workflow_id: impl Into<String>,
+    run_id: Option<String>,
+) -> Result<Vec<HistoryForReplay>, ReplayError>
+where
+    C: WorkflowClientTrait + Sync + ?Sized,
+{
+    let workflow_id = workflow_id.into();
+    let mut chain = vec![];
+    let mut seen_run_ids = HashSet::new();
+    let mut next_run_id = run_id;
+    loop {
+        let hist = fetch_full_history(
+            |page_token| {
+                client.get_workflow_execution_history(
+                    workflow_id.clone(),
+                    next_run_id.clone(),
+                    page_token,
+                )
+            },
+            None,
+        )
+        .await?;
+        let started = hist
+            .events
+            .first()
+            .and_then(|e| match &e.attributes {
+                Some(history_event::Attributes::WorkflowExecutionStartedEventAttributes(a)) => {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| {
+                ReplayError::InvalidHistory(format!(
+                    "History of run {next_run_id:?} of workflow {workflow_id} does not begin with \
+                     a workflow execution started event"
+                ))
+            })?;
+        if !seen_run_ids.insert(started.original_execution_run_id.clone()) {
+            return Err(ReplayError::InvalidHistory(format!(
+                "Continue-as-new chain of workflow {workflow_id} loops back to run {}",
+                started.original_execution_run_id
+            )));
+        }
+        next_run_id = Some(started.continued_execution_run_id.clone()).filter(|r| !r.is_empty());
+        chain.push(HistoryForReplay::new(hist, workflow_id.clone()));
+        if next_run_id.is_none() {
+            break;
+        }
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Pages through a run's history using `fetch_page`, which is given each page token in turn. If
+/// `up_to_event_id` is set, stops once that event has been fetched, and leaves out any later ones.
+pub(crate) async fn fetch_full_history<F, Fut>(
+    mut fetch_page: F,
+    up_to_event_id: Option<i64>,
+) -> Result<History, tonic::Status>
+where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = Result<GetWorkflowExecutionHistoryResponse, tonic::Status>>,
+{
+    let mut events = vec![];
+    let mut page_token = vec![];
+    loop {
+        let resp = fetch_page(page_token).await?;
+        if let Some(hist) = resp.history {
+            events.extend(hist.events);
+        }
+        let have_all_wanted = matches!(
+            (up_to_event_id, events.last()),
+            (Some(up_to), Some(last)) if last.event_id >= up_to
+        );
+        if resp.next_page_token.is_empty() || have_all_wanted {
+            break;
+        }
+        page_token = resp.next_page_token;
+    }
+    if let Some(up_to) = up_to_event_id {
+        events.retain(|e| e.event_id <= up_to);
+    }
+    Ok(History { events })
+}
+
 /// Allows lang to feed histories into the replayer one at a time. Simply drop the feeder to signal
 /// to the worker that you're done and it should initiate shutdown.
 pub struct HistoryFeeder {
@@ -64,9 +217,11 @@ impl HistoryFeeder {
     }
     /// Feed a new history into the replayer, blocking if there is not room to accept another
     /// history.
-    pub async fn feed(&self, history: HistoryForReplay) -> anyhow::Result<()> {
-        self.tx.send(history).await?;
-        Ok(())
+    pub async fn feed(&self, history: HistoryForReplay) -> Result<(), ReplayError> {
+        self.tx
+            .send(history)
+            .await
+            .map_err(|_| ReplayError::FeederClosed)
     }
 }
 
@@ -78,6 +233,111 @@ impl Stream for HistoryFeederStream {
     }
 }
 
+/// How replaying a single history turned out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    /// The history has been dispatched, but replay has neither finished nor failed yet
+    InProgress,
+    /// The workflow code replayed the entire history without error
+    Success,
+    /// The workflow code produced commands which did not match the history
+    Nondeterminism {
+        /// Details of the mismatch
+        message: String,
+    },
+    /// Replay failed for any other reason, including workflow code panicking or failing the
+    /// workflow task
+    Failed {
+        /// Details of the failure
+        message: String,
+    },
+}
+
+/// The outcome of replaying one history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The workflow id the history was replayed under
+    pub workflow_id: String,
+    /// The run id of the replayed history
+    pub run_id: String,
+    /// How replay of the history turned out
+    pub outcome: ReplayOutcome,
+}
+
+/// Collects a [ReplayReport] for every history fed to a replay worker, in the order they were
+/// dispatched. Cheaply cloneable, and may be inspected at any time, though the results are only
+/// complete once the worker has shut down.
+#[derive(Clone, Default)]
+pub struct ReplayResults {
+    inner: Arc<Mutex<ReplayResultsInner>>,
+}
+
+#[derive(Default)]
+struct ReplayResultsInner {
+    reports: Vec<ReplayReport>,
+    by_task_token: HashMap<Vec<u8>, usize>,
+    by_run_id: HashMap<String, usize>,
+}
+
+impl ReplayResults {
+    /// Returns reports for every history dispatched so far
+    pub fn reports(&self) -> Vec<ReplayReport> {
+        self.inner.lock().reports.clone()
+    }
+
+    /// Returns true if every history dispatched so far replayed successfully
+    pub fn all_succeeded(&self) -> bool {
+        self.inner
+            .lock()
+            .reports
+            .iter()
+            .all(|r| r.outcome == ReplayOutcome::Success)
+    }
+
+    fn any_in_progress(&self) -> bool {
+        self.inner
+            .lock()
+            .reports
+            .iter()
+            .any(|r| r.outcome == ReplayOutcome::InProgress)
+    }
+
+    fn dispatched(&self, task_token: Vec<u8>, workflow_id: String, run_id: String) {
+        let mut inner = self.inner.lock();
+        let ix = inner.reports.len();
+        inner.reports.push(ReplayReport {
+            workflow_id,
+            run_id: run_id.clone(),
+            outcome: ReplayOutcome::InProgress,
+        });
+        inner.by_task_token.insert(task_token, ix);
+        inner.by_run_id.insert(run_id, ix);
+    }
+
+    fn succeeded(&self, run_id: &str) {
+        let mut inner = self.inner.lock();
+        if let Some(ix) = inner.by_run_id.get(run_id).copied() {
+            let outcome = &mut inner.reports[ix].outcome;
+            // A failure is final, even if the run was later driven past the end of its history
+            if *outcome == ReplayOutcome::InProgress {
+                *outcome = ReplayOutcome::Success;
+            }
+        }
+    }
+
+    fn failed(&self, task_token: &[u8], cause: WorkflowTaskFailedCause, failure: Option<Failure>) {
+        let mut inner = self.inner.lock();
+        if let Some(ix) = inner.by_task_token.get(task_token).copied() {
+            let message = failure.map(|f| f.message).unwrap_or_default();
+            inner.reports[ix].outcome = if cause == WorkflowTaskFailedCause::NonDeterministicError {
+                ReplayOutcome::Nondeterminism { message }
+            } else {
+                ReplayOutcome::Failed { message }
+            };
+        }
+    }
+}
+
 /// Create a mock client which can be used by a replay worker to serve up canned histories. It will
 /// return the entire history in one workflow task. If a workflow task failure is sent to the mock,
 /// it will send the complete response again.
@@ -87,10 +347,13 @@ pub(crate) fn mock_client_from_histories(historator: Historator) -> impl WorkerC
     let mut mg = mock_manual_workflow_client();
 
     let hist_allow_tx = historator.replay_done_tx.clone();
+    let results = historator.results.clone();
+    let fail_results = results.clone();
     let historator = Arc::new(TokioMutex::new(historator));
 
     mg.expect_poll_workflow_task().returning(move |_, _| {
         let historator = historator.clone();
+        let results = results.clone();
         async move {
             let mut hlock = historator.lock().await;
             // Always wait for permission before dispatching the next task
@@ -99,12 +362,24 @@ pub(crate) fn mock_client_from_histories(historator: Historator) -> impl WorkerC
             if let Some(history) = hlock.next().await {
                 let hist_info = HistoryInfo::new_from_history(&history.hist, None).unwrap();
                 let mut resp = hist_info.as_poll_wft_response();
+                results.dispatched(
+                    resp.task_token.clone(),
+                    history.workflow_id.clone(),
+                    hist_info.orig_run_id().to_string(),
+                );
                 resp.workflow_execution = Some(WorkflowExecution {
                     workflow_id: history.workflow_id,
                     run_id: hist_info.orig_run_id().to_string(),
                 });
                 Ok(resp)
             } else {
+                // Other histories may still be replaying if more than one is allowed at once, so
+                // wait for them to finish before shutting down the worker.
+                while results.any_in_progress() {
+                    if hlock.allow_stream.next().await.is_none() {
+                        break;
+                    }
+                }
                 if let Some(wc) = hlock.worker_closer.get() {
                     wc.cancel();
                 }
@@ -117,10 +392,12 @@ pub(crate) fn mock_client_from_histories(historator: Historator) -> impl WorkerC
     mg.expect_complete_workflow_task().returning(move |_| {
         async move { Ok(RespondWorkflowTaskCompletedResponse::default()) }.boxed()
     });
-    mg.expect_fail_workflow_task().returning(move |_, _, _| {
-        hist_allow_tx.send("Failed".to_string()).unwrap();
-        async move { Ok(RespondWorkflowTaskFailedResponse::default()) }.boxed()
-    });
+    mg.expect_fail_workflow_task()
+        .returning(move |tt, cause, failure| {
+            fail_results.failed(&tt.0, cause, failure);
+            hist_allow_tx.send("Failed".to_string()).unwrap();
+            async move { Ok(RespondWorkflowTaskFailedResponse::default()) }.boxed()
+        });
 
     mg
 }
@@ -131,30 +408,45 @@ pub(crate) struct Historator {
     worker_closer: Arc<OnceCell<CancellationToken>>,
     dat: Arc<Mutex<HistoratorDat>>,
     replay_done_tx: UnboundedSender<String>,
+    results: ReplayResults,
 }
 impl Historator {
-    pub(crate) fn new(histories: impl Stream<Item = HistoryForReplay> + Send + 'static) -> Self {
+    /// Create a historator which will allow up to `concurrency` histories to be replaying at once
+    pub(crate) fn new(
+        histories: impl Stream<Item = HistoryForReplay> + Send + 'static,
+        concurrency: usize,
+    ) -> Self {
         let dat = Arc::new(Mutex::new(HistoratorDat::default()));
         let (replay_done_tx, replay_done_rx) = mpsc::unbounded_channel();
-        // Need to allow the first history item
-        replay_done_tx.send("fake".to_string()).unwrap();
+        // Need to allow the first history item(s)
+        for _ in 0..concurrency.max(1) {
+            replay_done_tx.send("fake".to_string()).unwrap();
+        }
         Self {
             iter: Box::pin(histories.fuse()),
             allow_stream: UnboundedReceiverStream::new(replay_done_rx),
             worker_closer: Arc::new(OnceCell::new()),
             dat,
             replay_done_tx,
+            results: Default::default(),
         }
     }
 
+    /// Returns the collector of per-history outcomes
+    pub(crate) fn results(&self) -> ReplayResults {
+        self.results.clone()
+    }
+
     /// Returns a callback that can be used as the post-activation hook for a worker to indicate
     /// we're ready to replay the next history, or whatever else.
     pub(crate) fn get_post_activate_hook(
         &self,
     ) -> impl Fn(&Worker, PostActivateHookData) + Send + Sync {
         let done_tx = self.replay_done_tx.clone();
+        let results = self.results.clone();
         move |worker, data| {
             if !data.replaying {
+                results.succeeded(data.run_id);
                 worker.request_wf_eviction(
                     data.run_id,
                     "Always evict workflows after replay",
@@ -146,7 +146,10 @@ impl<'a> tracing::field::Visit for JsonVisitor<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{telemetry::construct_filter_string, telemetry_init};
+    use crate::{
+        telemetry::{construct_filter_string, RunLogSelector},
+        telemetry_init,
+    };
     use temporal_sdk_core_api::telemetry::{CoreTelemetry, Logger, TelemetryOptionsBuilder};
     use tracing::Level;
 
@@ -187,4 +190,56 @@ mod tests {
         assert_eq!(info_msg.fields.get("bros"), Some(&"brohemian".into()));
         assert_eq!(info_msg.fields.get("thing"), Some(&"hi".into()));
     }
+
+    #[tokio::test]
+    async fn log_filter_changed_at_runtime() {
+        let opts = TelemetryOptionsBuilder::default()
+            .logging(Logger::Forward {
+                filter: construct_filter_string(Level::INFO, Level::WARN),
+            })
+            .build()
+            .unwrap();
+        let instance = telemetry_init(opts).unwrap();
+        let _g = tracing::subscriber::set_default(instance.trace_subscriber.clone());
+        let handle = instance.handle();
+
+        debug!("before");
+        handle
+            .set_log_filter(&construct_filter_string(Level::DEBUG, Level::WARN))
+            .unwrap();
+        debug!("after");
+        assert!(handle
+            .set_log_filter("temporal_sdk_core=notalevel")
+            .is_err());
+
+        let logs = instance.fetch_buffered_logs();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].message, "after");
+    }
+
+    #[tokio::test]
+    async fn selected_run_logged_above_base_level() {
+        let opts = TelemetryOptionsBuilder::default()
+            .logging(Logger::Forward {
+                filter: construct_filter_string(Level::WARN, Level::WARN),
+            })
+            .build()
+            .unwrap();
+        let instance = telemetry_init(opts).unwrap();
+        let _g = tracing::subscriber::set_default(instance.trace_subscriber.clone());
+
+        // Both spans are below the base level and exist before the run is selected
+        let selected = span!(Level::INFO, "run", run_id = "run-1", workflow_id = "wf-1");
+        let other = span!(Level::INFO, "run", run_id = "run-2", workflow_id = "wf-2");
+        instance.enable_run_logging(RunLogSelector::RunId("run-1".to_string()), Level::DEBUG);
+        selected.in_scope(|| {
+            debug!("selected debug");
+            trace!("selected trace");
+        });
+        other.in_scope(|| debug!("other debug"));
+
+        let logs = instance.fetch_buffered_logs();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].message, "selected debug");
+    }
 }
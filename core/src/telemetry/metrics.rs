@@ -10,7 +10,16 @@ use opentelemetry::{
     },
     Context, KeyValue,
 };
-use std::{ops::Deref, sync::Arc, time::Duration};
+use parking_lot::RwLock;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use temporal_client::ClientMetricProvider;
 
 /// Used to track context associated with metrics, and record/update them
@@ -26,13 +35,87 @@ pub(crate) struct MetricsContext {
 
 /// Wraps OTel's [Meter] to ensure we name our metrics properly, or any other temporal-specific
 /// metrics customizations
-#[derive(derive_more::Constructor)]
 pub struct TemporalMeter<'a> {
     inner: &'a Meter,
-    metrics_prefix: &'static str,
+    metrics_prefix: &'a str,
+    custom_gauges: Option<&'a CustomGaugeNames>,
+}
+
+/// Names (including prefix) of gauges created by lang. Like core's own gauges they are recorded
+/// with histograms, so the aggregator selector needs to know to use last-value aggregation for
+/// them.
+pub(crate) type CustomGaugeNames = Arc<RwLock<HashSet<String>>>;
+
+/// A gauge created by lang with [TemporalMeter::custom_gauge]. Exports the last recorded value.
+#[derive(Clone)]
+pub struct CustomGauge(Histogram<u64>);
+
+impl CustomGauge {
+    /// Set the gauge's value for the given attributes
+    pub fn record(&self, value: u64, attributes: &[KeyValue]) {
+        self.0.record(&Context::current(), value, attributes)
+    }
 }
 
 impl<'a> TemporalMeter<'a> {
+    pub(crate) fn new(inner: &'a Meter, metrics_prefix: &'a str) -> Self {
+        Self {
+            inner,
+            metrics_prefix,
+            custom_gauges: None,
+        }
+    }
+
+    pub(crate) fn with_custom_gauges(mut self, custom_gauges: &'a CustomGaugeNames) -> Self {
+        self.custom_gauges = Some(custom_gauges);
+        self
+    }
+
+    /// Create a counter for metrics defined by lang (ex: user metrics emitted from workflows). It
+    /// is exported through the same pipeline as core's metrics, with the same prefix and global
+    /// tags.
+    pub fn custom_counter(
+        &self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Counter<u64> {
+        self.inner
+            .u64_counter(self.metrics_prefix.to_string() + &name.into())
+            .with_description(description)
+            .init()
+    }
+
+    /// Create a histogram for metrics defined by lang. See [Self::custom_counter]. Uses core's
+    /// default buckets, which are meant for latencies in milliseconds.
+    pub fn custom_histogram(
+        &self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Histogram<u64> {
+        self.inner
+            .u64_histogram(self.metrics_prefix.to_string() + &name.into())
+            .with_description(description)
+            .init()
+    }
+
+    /// Create a gauge for metrics defined by lang. See [Self::custom_counter].
+    pub fn custom_gauge(
+        &self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> CustomGauge {
+        let name = self.metrics_prefix.to_string() + &name.into();
+        if let Some(gauges) = self.custom_gauges {
+            gauges.write().insert(name.clone());
+        }
+        CustomGauge(
+            self.inner
+                .u64_histogram(name)
+                .with_description(description)
+                .init(),
+        )
+    }
+
     pub(crate) fn counter(&self, name: &'static str) -> Counter<u64> {
         self.inner
             .u64_counter(self.metrics_prefix.to_string() + name)
@@ -73,6 +156,7 @@ struct Instruments {
     wf_task_queue_poll_empty_counter: Counter<u64>,
     wf_task_queue_poll_succeed_counter: Counter<u64>,
     wf_task_execution_failure_counter: Counter<u64>,
+    wf_task_history_failure_counter: Counter<u64>,
     wf_task_sched_to_start_latency: Histogram<u64>,
     wf_task_replay_latency: Histogram<u64>,
     wf_task_execution_latency: Histogram<u64>,
@@ -83,11 +167,20 @@ struct Instruments {
     act_exec_latency: Histogram<u64>,
     worker_registered: Counter<u64>,
     num_pollers: Histogram<u64>,
+    target_num_pollers: Histogram<u64>,
     task_slots_available: Histogram<u64>,
+    task_slots_used: Histogram<u64>,
     sticky_cache_hit: Counter<u64>,
     sticky_cache_miss: Counter<u64>,
     sticky_cache_size: Histogram<u64>,
     sticky_cache_evictions: Counter<u64>,
+    leaked_tasks: Counter<u64>,
+    wf_command_queue_depth: Histogram<u64>,
+    wf_oldest_queued_command_age: Histogram<u64>,
+    wf_machine_events_handled: Counter<u64>,
+    wf_machine_handle_latency: Histogram<u64>,
+    /// Whether metrics recorded per run on every activation should be recorded at all
+    detailed_enabled: Arc<AtomicBool>,
 }
 
 impl MetricsContext {
@@ -95,10 +188,10 @@ impl MetricsContext {
         Self {
             ctx: Default::default(),
             kvs: Default::default(),
-            instruments: Arc::new(Instruments::new_explicit(TemporalMeter::new(
-                &NoopMeterProvider::new().meter("fakemeter"),
-                "fakemetrics",
-            ))),
+            instruments: Arc::new(Instruments::new_explicit(
+                TemporalMeter::new(&NoopMeterProvider::new().meter("fakemeter"), "fakemetrics"),
+                Arc::new(AtomicBool::new(true)),
+            )),
         }
     }
 
@@ -148,6 +241,13 @@ impl MetricsContext {
             .add(&self.ctx, 1, &self.kvs);
     }
 
+    /// A workflow task attempt was seen failed or timed out in history
+    pub(crate) fn wf_task_attempt_failed_in_history(&self) {
+        self.instruments
+            .wf_task_history_failure_counter
+            .add(&self.ctx, 1, &self.kvs);
+    }
+
     /// A workflow completed successfully
     pub(crate) fn wf_completed(&self) {
         self.instruments
@@ -262,6 +362,13 @@ impl MetricsContext {
             .record(&self.ctx, num as u64, &self.kvs)
     }
 
+    /// Record current number of task slots in use. Context should have worker type set.
+    pub(crate) fn used_task_slots(&self, num: usize) {
+        self.instruments
+            .task_slots_used
+            .record(&self.ctx, num as u64, &self.kvs)
+    }
+
     /// Record current number of pollers. Context should include poller type / task queue tag.
     pub(crate) fn record_num_pollers(&self, num: usize) {
         self.instruments
@@ -269,6 +376,14 @@ impl MetricsContext {
             .record(&self.ctx, num as u64, &self.kvs);
     }
 
+    /// Record the number of pollers an autoscaling poller currently wants to be polling. Context
+    /// should include poller type / task queue tag.
+    pub(crate) fn record_target_num_pollers(&self, num: usize) {
+        self.instruments
+            .target_num_pollers
+            .record(&self.ctx, num as u64, &self.kvs);
+    }
+
     /// A workflow task found a cached workflow to run against
     pub(crate) fn sticky_cache_hit(&self) {
         self.instruments
@@ -290,12 +405,55 @@ impl MetricsContext {
             .record(&self.ctx, size, &self.kvs);
     }
 
-    /// Count a workflow being evicted from the cache
+    /// Count a workflow being evicted from the cache. Context should include the eviction reason,
+    /// so LRU evictions can be told apart from those caused by errors.
     pub(crate) fn cache_eviction(&self) {
         self.instruments
             .sticky_cache_evictions
             .add(&self.ctx, 1, &self.kvs);
     }
+
+    /// Count a task or activation which lang appears to have never completed
+    pub(crate) fn task_leaked(&self) {
+        self.instruments.leaked_tasks.add(&self.ctx, 1, &self.kvs);
+    }
+
+    /// Whether detailed (per-run, per-activation) metrics are currently enabled. Callers should
+    /// skip computing such metrics entirely if not.
+    pub(crate) fn detailed_metrics_enabled(&self) -> bool {
+        self.instruments.detailed_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Record the number of commands in one of a run's command queues. Context should include the
+    /// command queue tag.
+    pub(crate) fn wf_command_queue_depth(&self, depth: usize) {
+        self.instruments
+            .wf_command_queue_depth
+            .record(&self.ctx, depth as u64, &self.kvs);
+    }
+
+    /// Record how long the oldest command queued by a run has been waiting, in milliseconds
+    pub(crate) fn wf_oldest_queued_command_age(&self, age: Duration) {
+        self.instruments.wf_oldest_queued_command_age.record(
+            &self.ctx,
+            age.as_millis() as u64,
+            &self.kvs,
+        );
+    }
+
+    /// Count an event handled by a state machine, and record how long handling it (including
+    /// processing the machine's responses) took, in microseconds. Context should include the
+    /// machine kind tag.
+    pub(crate) fn wf_machine_event_handled(&self, dur: Duration) {
+        self.instruments
+            .wf_machine_events_handled
+            .add(&self.ctx, 1, &self.kvs);
+        self.instruments.wf_machine_handle_latency.record(
+            &self.ctx,
+            dur.as_micros() as u64,
+            &self.kvs,
+        );
+    }
 }
 
 impl Instruments {
@@ -307,10 +465,10 @@ impl Instruments {
             no_op_meter = NoopMeterProvider::default().meter("no_op");
             TemporalMeter::new(&no_op_meter, "fakemetrics")
         };
-        Self::new_explicit(meter)
+        Self::new_explicit(meter, telem.handle().detailed_metrics_flag())
     }
 
-    fn new_explicit(meter: TemporalMeter) -> Self {
+    fn new_explicit(meter: TemporalMeter, detailed_enabled: Arc<AtomicBool>) -> Self {
         Self {
             wf_completed_counter: meter.counter("workflow_completed"),
             wf_canceled_counter: meter.counter("workflow_canceled"),
@@ -320,6 +478,7 @@ impl Instruments {
             wf_task_queue_poll_empty_counter: meter.counter("workflow_task_queue_poll_empty"),
             wf_task_queue_poll_succeed_counter: meter.counter("workflow_task_queue_poll_succeed"),
             wf_task_execution_failure_counter: meter.counter("workflow_task_execution_failed"),
+            wf_task_history_failure_counter: meter.counter("workflow_task_history_failure"),
             wf_task_sched_to_start_latency: meter.histogram(WF_TASK_SCHED_TO_START_LATENCY_NAME),
             wf_task_replay_latency: meter.histogram(WF_TASK_REPLAY_LATENCY_NAME),
             wf_task_execution_latency: meter.histogram(WF_TASK_EXECUTION_LATENCY_NAME),
@@ -331,11 +490,19 @@ impl Instruments {
             // name kept as worker start for compat with old sdk / what users expect
             worker_registered: meter.counter("worker_start"),
             num_pollers: meter.histogram(NUM_POLLERS_NAME),
+            target_num_pollers: meter.histogram(TARGET_NUM_POLLERS_NAME),
             task_slots_available: meter.histogram(TASK_SLOTS_AVAILABLE_NAME),
+            task_slots_used: meter.histogram(TASK_SLOTS_USED_NAME),
             sticky_cache_hit: meter.counter("sticky_cache_hit"),
             sticky_cache_miss: meter.counter("sticky_cache_miss"),
             sticky_cache_size: meter.histogram(STICKY_CACHE_SIZE_NAME),
             sticky_cache_evictions: meter.counter("sticky_cache_total_forced_eviction"),
+            leaked_tasks: meter.counter("leaked_task_detected"),
+            wf_command_queue_depth: meter.histogram(WF_COMMAND_QUEUE_DEPTH_NAME),
+            wf_oldest_queued_command_age: meter.histogram(WF_OLDEST_QUEUED_COMMAND_AGE_NAME),
+            wf_machine_events_handled: meter.counter("workflow_machine_events_handled"),
+            wf_machine_handle_latency: meter.histogram(WF_MACHINE_HANDLE_LATENCY_NAME),
+            detailed_enabled,
         }
     }
 }
@@ -347,6 +514,11 @@ const KEY_ACT_TYPE: &str = "activity_type";
 const KEY_POLLER_TYPE: &str = "poller_type";
 const KEY_WORKER_TYPE: &str = "worker_type";
 const KEY_EAGER: &str = "eager";
+const KEY_LEAKED_TASK_KIND: &str = "task_kind";
+const KEY_WFT_FAILURE_CAUSE: &str = "failure_cause";
+const KEY_COMMAND_QUEUE: &str = "command_queue";
+const KEY_MACHINE_KIND: &str = "machine_kind";
+const KEY_EVICTION_REASON: &str = "eviction_reason";
 
 pub(crate) fn workflow_poller() -> KeyValue {
     KeyValue::new(KEY_POLLER_TYPE, "workflow_task")
@@ -378,6 +550,21 @@ pub(crate) fn local_activity_worker_type() -> KeyValue {
 pub(crate) fn eager(is_eager: bool) -> KeyValue {
     KeyValue::new(KEY_EAGER, is_eager)
 }
+pub(crate) fn leaked_task_kind(kind: &'static str) -> KeyValue {
+    KeyValue::new(KEY_LEAKED_TASK_KIND, kind)
+}
+pub(crate) fn wft_failure_cause(cause: &'static str) -> KeyValue {
+    KeyValue::new(KEY_WFT_FAILURE_CAUSE, cause)
+}
+pub(crate) fn command_queue(queue: &'static str) -> KeyValue {
+    KeyValue::new(KEY_COMMAND_QUEUE, queue)
+}
+pub(crate) fn machine_kind(kind: String) -> KeyValue {
+    KeyValue::new(KEY_MACHINE_KIND, kind)
+}
+pub(crate) fn eviction_reason(reason: &'static str) -> KeyValue {
+    KeyValue::new(KEY_EVICTION_REASON, reason)
+}
 
 const WF_E2E_LATENCY_NAME: &str = "workflow_endtoend_latency";
 const WF_TASK_SCHED_TO_START_LATENCY_NAME: &str = "workflow_task_schedule_to_start_latency";
@@ -386,8 +573,13 @@ const WF_TASK_EXECUTION_LATENCY_NAME: &str = "workflow_task_execution_latency";
 const ACT_SCHED_TO_START_LATENCY_NAME: &str = "activity_schedule_to_start_latency";
 const ACT_EXEC_LATENCY_NAME: &str = "activity_execution_latency";
 const NUM_POLLERS_NAME: &str = "num_pollers";
+const TARGET_NUM_POLLERS_NAME: &str = "target_num_pollers";
 const TASK_SLOTS_AVAILABLE_NAME: &str = "worker_task_slots_available";
+const TASK_SLOTS_USED_NAME: &str = "worker_task_slots_used";
 const STICKY_CACHE_SIZE_NAME: &str = "sticky_cache_size";
+const WF_COMMAND_QUEUE_DEPTH_NAME: &str = "workflow_command_queue_depth";
+const WF_OLDEST_QUEUED_COMMAND_AGE_NAME: &str = "workflow_oldest_queued_command_age";
+const WF_MACHINE_HANDLE_LATENCY_NAME: &str = "workflow_machine_handle_latency_us";
 
 /// Artisanal, handcrafted latency buckets for workflow e2e latency which should expose a useful
 /// set of buckets for < 1 day runtime workflows. Beyond that, this metric probably isn't very
@@ -423,6 +615,14 @@ static ACT_EXE_MS_BUCKETS: &[f64] = &[50., 100., 500., 1000., 5000., 10_000., 60
 static TASK_SCHED_TO_START_MS_BUCKETS: &[f64] =
     &[100., 500., 1000., 5000., 10_000., 100_000., 1_000_000.];
 
+/// Runs normally only have a handful of commands queued at once. Large depths mean something is
+/// accumulating commands it never gets rid of.
+static COMMAND_QUEUE_DEPTH_BUCKETS: &[f64] = &[1., 5., 10., 50., 100., 500., 1000., 5000.];
+
+/// Handling a single event is normally a matter of microseconds. Anything in the upper buckets is a
+/// machine doing far more work per event than it should.
+static MACHINE_HANDLE_US_BUCKETS: &[f64] = &[10., 50., 100., 500., 1000., 10_000., 100_000.];
+
 /// Default buckets. Should never really be used as they will be meaningless for many things, but
 /// broadly it's trying to represent latencies in millis.
 pub(super) static DEFAULT_MS_BUCKETS: &[f64] = &[50., 100., 500., 1000., 2500., 10_000.];
@@ -430,7 +630,11 @@ pub(super) static DEFAULT_MS_BUCKETS: &[f64] = &[50., 100., 500., 1000., 2500.,
 /// Chooses appropriate aggregators for our metrics
 #[derive(Debug, Clone)]
 pub struct SDKAggSelector {
-    pub metric_prefix: &'static str,
+    /// Prefix of every metric name, which is stripped before choosing buckets
+    pub metric_prefix: String,
+    pub(crate) custom_gauges: CustomGaugeNames,
+    /// User-specified bucket boundaries, keyed by metric name without prefix
+    pub(crate) bucket_overrides: Arc<HashMap<String, Vec<f64>>>,
 }
 
 impl AggregatorSelector for SDKAggSelector {
@@ -441,18 +645,27 @@ impl AggregatorSelector for SDKAggSelector {
         }
 
         if *descriptor.instrument_kind() == InstrumentKind::Histogram {
+            if self.custom_gauges.read().contains(descriptor.name()) {
+                return Some(Arc::new(last_value()));
+            }
             let dname = descriptor
                 .name()
-                .strip_prefix(self.metric_prefix)
+                .strip_prefix(self.metric_prefix.as_str())
                 .unwrap_or_else(|| descriptor.name());
             // Some recorders are just gauges
             match dname {
-                STICKY_CACHE_SIZE_NAME | NUM_POLLERS_NAME | TASK_SLOTS_AVAILABLE_NAME => {
-                    return Some(Arc::new(last_value()))
-                }
+                STICKY_CACHE_SIZE_NAME
+                | NUM_POLLERS_NAME
+                | TARGET_NUM_POLLERS_NAME
+                | TASK_SLOTS_AVAILABLE_NAME
+                | TASK_SLOTS_USED_NAME => return Some(Arc::new(last_value())),
                 _ => (),
             }
 
+            if let Some(buckets) = self.bucket_overrides.get(dname) {
+                return Some(Arc::new(histogram(buckets)));
+            }
+
             // Other recorders will select their appropriate buckets
             let buckets = match dname {
                 WF_E2E_LATENCY_NAME => WF_LATENCY_MS_BUCKETS,
@@ -461,6 +674,9 @@ impl AggregatorSelector for SDKAggSelector {
                     TASK_SCHED_TO_START_MS_BUCKETS
                 }
                 ACT_EXEC_LATENCY_NAME => ACT_EXE_MS_BUCKETS,
+                WF_COMMAND_QUEUE_DEPTH_NAME => COMMAND_QUEUE_DEPTH_BUCKETS,
+                WF_OLDEST_QUEUED_COMMAND_AGE_NAME => TASK_SCHED_TO_START_MS_BUCKETS,
+                WF_MACHINE_HANDLE_LATENCY_NAME => MACHINE_HANDLE_US_BUCKETS,
                 _ => DEFAULT_MS_BUCKETS,
             };
             return Some(Arc::new(histogram(buckets)));
@@ -4,11 +4,16 @@
 mod log_export;
 pub(crate) mod metrics;
 mod prometheus_server;
+mod run_filter;
+
+pub use metrics::{CustomGauge, TemporalMeter};
+pub use run_filter::RunLogSelector;
 
 use crate::telemetry::{
     log_export::{CoreLogExportLayer, CoreLogsOut},
-    metrics::SDKAggSelector,
+    metrics::{CustomGaugeNames, SDKAggSelector},
     prometheus_server::PromServer,
+    run_filter::{RunLogFilter, RunLogSelectors, SharedEnvFilter},
 };
 use crossbeam::channel::Receiver;
 use itertools::Itertools;
@@ -24,7 +29,7 @@ use opentelemetry::{
     KeyValue,
 };
 use opentelemetry_otlp::WithExportConfig;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use std::{
     cell::RefCell,
     collections::{HashMap, VecDeque},
@@ -43,7 +48,7 @@ use temporal_sdk_core_api::telemetry::{
 };
 use tonic::metadata::MetadataMap;
 use tracing::{Level, Subscriber};
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer};
+use tracing_subscriber::{filter::ParseError, layer::SubscriberExt, EnvFilter, Layer};
 
 const TELEM_SERVICE_NAME: &str = "temporal-core-sdk";
 
@@ -57,21 +62,67 @@ pub fn construct_filter_string(core_level: Level, other_level: Level) -> String
 
 /// Holds initialized tracing/metrics exporters, etc
 pub struct TelemetryInstance {
-    metric_prefix: &'static str,
+    metric_prefix: String,
     logs_out: Option<Mutex<CoreLogsOut>>,
     metrics: Option<(Box<dyn MeterProvider + Send + Sync + 'static>, Meter)>,
+    custom_gauges: CustomGaugeNames,
     trace_subscriber: Arc<dyn Subscriber + Send + Sync>,
     prom_binding: Option<SocketAddr>,
+    run_log_selectors: Arc<RunLogSelectors>,
+    control: TelemetryHandle,
     _keepalive_rx: Receiver<()>,
 }
 
+/// A cheaply cloneable handle for adjusting telemetry at runtime, so that observability can be
+/// turned up during an incident without restarting workers (and losing their caches). Obtained
+/// from [TelemetryInstance::handle].
+#[derive(Clone)]
+pub struct TelemetryHandle {
+    log_filter: Option<SharedEnvFilter>,
+    detailed_metrics: Arc<AtomicBool>,
+}
+
+impl TelemetryHandle {
+    /// Replace the configured log filter with a new [EnvFilter] compatible filter string. Has no
+    /// effect if no logger was configured. Per-run overrides set via
+    /// [TelemetryInstance::enable_run_logging] continue to apply on top of the new filter.
+    ///
+    /// The old filter is discarded entirely, including what it recorded about spans which are
+    /// currently open. Directives in the new filter which match on span names or fields (ex:
+    /// `[my_span]=debug`) therefore only take effect for spans created after the call.
+    pub fn set_log_filter(&self, filter: &str) -> Result<(), ParseError> {
+        let new_filter = EnvFilter::try_new(filter)?;
+        if let Some(lf) = self.log_filter.as_ref() {
+            *lf.write() = new_filter;
+            // Interest in callsites was cached based on the old filter
+            tracing::callsite::rebuild_interest_cache();
+        }
+        Ok(())
+    }
+
+    /// Enable or disable metrics which are recorded for every run on every activation, and can
+    /// therefore be expensive on workers with many cached workflows. Currently these are the
+    /// per-run command queue depth and age histograms. Enabled by default.
+    pub fn set_detailed_metrics_enabled(&self, enabled: bool) {
+        self.detailed_metrics.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn detailed_metrics_flag(&self) -> Arc<AtomicBool> {
+        self.detailed_metrics.clone()
+    }
+}
+
 impl TelemetryInstance {
+    #[allow(clippy::too_many_arguments)] // Not much worth combining here
     fn new(
         trace_subscriber: Arc<dyn Subscriber + Send + Sync>,
         logs_out: Option<Mutex<CoreLogsOut>>,
-        metric_prefix: &'static str,
+        metric_prefix: String,
         mut meter_provider: Option<Box<dyn MeterProvider + Send + Sync + 'static>>,
+        custom_gauges: CustomGaugeNames,
         prom_binding: Option<SocketAddr>,
+        run_log_selectors: Arc<RunLogSelectors>,
+        log_filter: Option<SharedEnvFilter>,
         keepalive_rx: Receiver<()>,
     ) -> Self {
         let metrics = meter_provider.take().map(|mp| {
@@ -82,12 +133,24 @@ impl TelemetryInstance {
             metric_prefix,
             logs_out,
             metrics,
+            custom_gauges,
             trace_subscriber,
             prom_binding,
+            run_log_selectors,
+            control: TelemetryHandle {
+                log_filter,
+                detailed_metrics: Arc::new(AtomicBool::new(true)),
+            },
             _keepalive_rx: keepalive_rx,
         }
     }
 
+    /// Returns a handle which can be used to adjust log filtering and metric collection while
+    /// running
+    pub fn handle(&self) -> TelemetryHandle {
+        self.control.clone()
+    }
+
     /// Returns a trace subscriber which can be used with the tracing crate, or with our own
     /// [set_trace_subscriber_for_current_thread] function.
     pub fn trace_subscriber(&self) -> Arc<dyn Subscriber + Send + Sync> {
@@ -99,11 +162,25 @@ impl TelemetryInstance {
         self.prom_binding
     }
 
-    /// Returns our wrapper for OTel metric meters, can be used to, ex: initialize clients
+    /// Returns our wrapper for OTel metric meters, can be used to, ex: initialize clients, or to
+    /// create lang-defined metrics which are exported alongside core's
     pub fn get_metric_meter(&self) -> Option<TemporalMeter> {
-        self.metrics
-            .as_ref()
-            .map(|(_, m)| TemporalMeter::new(m, self.metric_prefix))
+        self.metrics.as_ref().map(|(_, m)| {
+            TemporalMeter::new(m, &self.metric_prefix).with_custom_gauges(&self.custom_gauges)
+        })
+    }
+
+    /// Emit logs at `level` (in addition to whatever the configured log filter allows) for
+    /// anything core does on behalf of runs matching `selector`. Useful for debugging a specific
+    /// misbehaving workflow without flooding logs from every other workflow on the worker. Calling
+    /// again with the same selector replaces its level.
+    pub fn enable_run_logging(&self, selector: RunLogSelector, level: Level) {
+        self.run_log_selectors.enable(selector, level)
+    }
+
+    /// Undoes [TelemetryInstance::enable_run_logging] for the provided selector
+    pub fn disable_run_logging(&self, selector: &RunLogSelector) {
+        self.run_log_selectors.disable(selector)
     }
 }
 
@@ -129,11 +206,13 @@ pub fn remove_trace_subscriber_for_current_thread() {
     SUB_GUARD.with(|sg| sg.take());
 }
 
-fn metric_prefix(opts: &TelemetryOptions) -> &'static str {
-    if opts.no_temporal_prefix_for_metrics {
-        ""
+fn metric_prefix(opts: &TelemetryOptions) -> String {
+    if let Some(ref prefix) = opts.metric_prefix {
+        prefix.clone()
+    } else if opts.no_temporal_prefix_for_metrics {
+        "".to_string()
     } else {
-        "temporal_"
+        "temporal_".to_string()
     }
 }
 
@@ -171,7 +250,10 @@ pub fn telemetry_init(opts: TelemetryOptions) -> Result<TelemetryInstance, anyho
         // Parts of telem dat ====
         let mut logs_out = None;
         let metric_prefix = metric_prefix(&opts);
+        let custom_gauges = CustomGaugeNames::default();
         let mut prom_binding = None;
+        let run_log_selectors = Arc::new(RunLogSelectors::default());
+        let mut log_filter = None;
         // =======================
 
         // Tracing subscriber layers =========
@@ -182,8 +264,13 @@ pub fn telemetry_init(opts: TelemetryOptions) -> Result<TelemetryInstance, anyho
         // ===================================
 
         if let Some(ref logger) = opts.logging {
+            let filter = match logger {
+                Logger::Console { filter } | Logger::Forward { filter } => filter,
+            };
+            let shared_filter: SharedEnvFilter = Arc::new(RwLock::new(EnvFilter::new(filter)));
+            log_filter = Some(shared_filter.clone());
             match logger {
-                Logger::Console { filter } => {
+                Logger::Console { .. } => {
                     // This is silly dupe but can't be avoided without boxing.
                     if env::var("TEMPORAL_CORE_PRETTY_LOGS").is_ok() {
                         console_pretty_layer = Some(
@@ -194,7 +281,10 @@ pub fn telemetry_init(opts: TelemetryOptions) -> Result<TelemetryInstance, anyho
                                         .pretty()
                                         .with_source_location(false),
                                 )
-                                .with_filter(EnvFilter::new(filter)),
+                                .with_filter(RunLogFilter::new(
+                                    shared_filter.clone(),
+                                    run_log_selectors.clone(),
+                                )),
                         )
                     } else {
                         console_compact_layer = Some(
@@ -205,20 +295,30 @@ pub fn telemetry_init(opts: TelemetryOptions) -> Result<TelemetryInstance, anyho
                                         .compact()
                                         .with_source_location(false),
                                 )
-                                .with_filter(EnvFilter::new(filter)),
+                                .with_filter(RunLogFilter::new(
+                                    shared_filter.clone(),
+                                    run_log_selectors.clone(),
+                                )),
                         )
                     }
                 }
-                Logger::Forward { filter } => {
+                Logger::Forward { .. } => {
                     let (export_layer, lo) = CoreLogExportLayer::new();
                     logs_out = Some(Mutex::new(lo));
-                    forward_layer = Some(export_layer.with_filter(EnvFilter::new(filter)));
+                    forward_layer = Some(export_layer.with_filter(RunLogFilter::new(
+                        shared_filter.clone(),
+                        run_log_selectors.clone(),
+                    )));
                 }
             };
         };
 
         let meter_provider = if let Some(ref metrics) = opts.metrics {
-            let aggregator = SDKAggSelector { metric_prefix };
+            let aggregator = SDKAggSelector {
+                metric_prefix: metric_prefix.clone(),
+                custom_gauges: custom_gauges.clone(),
+                bucket_overrides: Arc::new(opts.histogram_bucket_overrides.clone()),
+            };
             match metrics {
                 MetricsExporter::Prometheus(addr) => {
                     let srv = runtime.block_on(async {
@@ -302,7 +402,10 @@ pub fn telemetry_init(opts: TelemetryOptions) -> Result<TelemetryInstance, anyho
             logs_out,
             metric_prefix,
             meter_provider,
+            custom_gauges,
             prom_binding,
+            run_log_selectors,
+            log_filter,
             keepalive_rx,
         ))
         .expect("Must be able to send telem instance out of thread");
@@ -0,0 +1,297 @@
+//! Allows turning up log verbosity for specific workflow runs at runtime, without changing the
+//! filter for the rest of the worker. Log filters configured via [Logger] are wrapped in a
+//! [RunLogFilter], which lets through anything the configured filter does, plus any event emitted
+//! inside a span belonging to a selected run (identified by the `run_id` / `workflow_id` fields
+//! core attaches to its spans).
+//!
+//! [Logger]: temporal_sdk_core_api::telemetry::Logger
+
+use parking_lot::RwLock;
+use std::{fmt::Debug, sync::Arc};
+use tracing::{
+    field::{Field, Visit},
+    metadata::LevelFilter,
+    span, Level, Metadata, Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, Filter},
+    registry::LookupSpan,
+    EnvFilter,
+};
+
+/// Identifies which runs should have their logging verbosity increased
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunLogSelector {
+    /// Matches exactly the run with this run id
+    RunId(String),
+    /// Matches all runs whose workflow id starts with this prefix
+    WorkflowIdPrefix(String),
+}
+
+impl RunLogSelector {
+    fn matches(&self, ids: &RunIdentity) -> bool {
+        match self {
+            RunLogSelector::RunId(rid) => ids.run_id.as_deref() == Some(rid.as_str()),
+            RunLogSelector::WorkflowIdPrefix(prefix) => ids
+                .workflow_id
+                .as_deref()
+                .map(|wid| wid.starts_with(prefix.as_str()))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The set of currently selected runs, shared between the [TelemetryInstance] and all the filters
+/// it created.
+///
+/// [TelemetryInstance]: super::TelemetryInstance
+#[derive(Default)]
+pub(super) struct RunLogSelectors {
+    selected: RwLock<Vec<(RunLogSelector, Level)>>,
+}
+
+impl RunLogSelectors {
+    pub(super) fn enable(&self, selector: RunLogSelector, level: Level) {
+        {
+            let mut selected = self.selected.write();
+            selected.retain(|(s, _)| s != &selector);
+            selected.push((selector, level));
+        }
+        // Callsites the configured filter disabled have had that decision cached, so make sure
+        // they get re-evaluated now that they may be enabled for some runs.
+        tracing::callsite::rebuild_interest_cache();
+    }
+
+    pub(super) fn disable(&self, selector: &RunLogSelector) {
+        self.selected.write().retain(|(s, _)| s != selector);
+        tracing::callsite::rebuild_interest_cache();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.selected.read().is_empty()
+    }
+
+    /// The most verbose level selected for the identified run, if any
+    fn level_for(&self, ids: &RunIdentity) -> Option<Level> {
+        self.selected
+            .read()
+            .iter()
+            .filter(|(s, _)| s.matches(ids))
+            .map(|(_, l)| *l)
+            .max()
+    }
+}
+
+/// The configured log filter, which may be replaced at runtime
+pub(super) type SharedEnvFilter = Arc<RwLock<EnvFilter>>;
+
+/// Wraps a configured [EnvFilter], additionally enabling events for selected runs
+pub(super) struct RunLogFilter {
+    base: SharedEnvFilter,
+    selectors: Arc<RunLogSelectors>,
+}
+
+impl RunLogFilter {
+    pub(super) fn new(base: SharedEnvFilter, selectors: Arc<RunLogSelectors>) -> Self {
+        Self { base, selectors }
+    }
+}
+
+/// Run identifying fields recorded on a span, stored in the span's extensions
+#[derive(Debug, Default)]
+struct RunIdentity {
+    run_id: Option<String>,
+    workflow_id: Option<String>,
+}
+
+impl Visit for RunIdentity {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "run_id" => self.run_id = Some(value.to_string()),
+            "workflow_id" => self.workflow_id = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if matches!(field.name(), "run_id" | "workflow_id") {
+            let formatted = format!("{value:?}");
+            self.record_str(field, formatted.trim_matches('"'));
+        }
+    }
+}
+
+/// Returns true for spans carrying the fields runs can be selected by. These are always enabled,
+/// even if the configured filter would disable them, since otherwise the run they belong to would
+/// never be recorded and could not be selected later. Spans produce no output on their own.
+fn identifies_run(meta: &Metadata<'_>) -> bool {
+    meta.is_span()
+        && (meta.fields().field("run_id").is_some() || meta.fields().field("workflow_id").is_some())
+}
+
+impl<S> Filter<S> for RunLogFilter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        if identifies_run(meta) || Filter::<S>::enabled(&*self.base.read(), meta, cx) {
+            return true;
+        }
+        if self.selectors.is_empty() {
+            return false;
+        }
+        let level = cx.lookup_current().and_then(|current| {
+            current.scope().find_map(|span| {
+                span.extensions()
+                    .get::<RunIdentity>()
+                    .and_then(|ids| self.selectors.level_for(ids))
+            })
+        });
+        level.map(|l| meta.level() <= &l).unwrap_or_default()
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> tracing::subscriber::Interest {
+        if identifies_run(meta) {
+            return tracing::subscriber::Interest::always();
+        }
+        let interest = Filter::<S>::callsite_enabled(&*self.base.read(), meta);
+        if interest.is_never() && !self.selectors.is_empty() {
+            tracing::subscriber::Interest::sometimes()
+        } else {
+            interest
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        // Spans identifying runs are enabled at any level, and runs may be selected at any time,
+        // so no level can be ruled out up front. Hinting the configured filter's level here would
+        // let tracing skip run spans below it before this filter ever saw them.
+        None
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if extensions.get_mut::<RunIdentity>().is_none() {
+                let mut ids = RunIdentity::default();
+                attrs.record(&mut ids);
+                extensions.insert(ids);
+            }
+        }
+        Filter::<S>::on_new_span(&*self.base.read(), attrs, id, ctx)
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(ids) = span.extensions_mut().get_mut::<RunIdentity>() {
+                values.record(ids);
+            }
+        }
+        Filter::<S>::on_record(&*self.base.read(), id, values, ctx)
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        Filter::<S>::on_enter(&*self.base.read(), id, ctx)
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        Filter::<S>::on_exit(&*self.base.read(), id, ctx)
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        Filter::<S>::on_close(&*self.base.read(), id, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::{debug, info_span, trace};
+    use tracing_subscriber::{layer::SubscriberExt, Layer};
+
+    /// Collects the messages of every event it is handed
+    #[derive(Clone, Default)]
+    struct CollectingLayer {
+        messages: Arc<parking_lot::Mutex<Vec<String>>>,
+    }
+
+    impl<S: Subscriber> Layer<S> for CollectingLayer {
+        fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+            struct MessageVisitor(Option<String>);
+            impl Visit for MessageVisitor {
+                fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                    if field.name() == "message" {
+                        self.0 = Some(format!("{value:?}"));
+                    }
+                }
+            }
+            let mut visitor = MessageVisitor(None);
+            event.record(&mut visitor);
+            if let Some(message) = visitor.0 {
+                self.messages.lock().push(message);
+            }
+        }
+    }
+
+    #[test]
+    fn selectors_pick_most_verbose_matching_level() {
+        let selectors = RunLogSelectors::default();
+        let ids = RunIdentity {
+            run_id: Some("run-1".to_string()),
+            workflow_id: Some("order-123".to_string()),
+        };
+        assert_eq!(selectors.level_for(&ids), None);
+
+        selectors.enable(
+            RunLogSelector::WorkflowIdPrefix("order-".to_string()),
+            Level::DEBUG,
+        );
+        assert_eq!(selectors.level_for(&ids), Some(Level::DEBUG));
+        selectors.enable(RunLogSelector::RunId("run-1".to_string()), Level::TRACE);
+        assert_eq!(selectors.level_for(&ids), Some(Level::TRACE));
+        selectors.enable(RunLogSelector::RunId("run-2".to_string()), Level::TRACE);
+
+        selectors.disable(&RunLogSelector::RunId("run-1".to_string()));
+        assert_eq!(selectors.level_for(&ids), Some(Level::DEBUG));
+        selectors.disable(&RunLogSelector::WorkflowIdPrefix("order-".to_string()));
+        assert_eq!(selectors.level_for(&ids), None);
+    }
+
+    #[test]
+    fn selected_run_events_reach_real_subscriber() {
+        let selectors = Arc::new(RunLogSelectors::default());
+        let base: SharedEnvFilter = Arc::new(RwLock::new(EnvFilter::new("warn")));
+        let collector = CollectingLayer::default();
+        let subscriber = tracing_subscriber::registry().with(
+            collector
+                .clone()
+                .with_filter(RunLogFilter::new(base, selectors.clone())),
+        );
+        // The global max level must not exclude run spans, which sit below the configured level
+        assert!(subscriber
+            .max_level_hint()
+            .map(|l| l >= LevelFilter::INFO)
+            .unwrap_or(true));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let selected = info_span!("run", run_id = "run-1", workflow_id = "wf-1");
+            let other = info_span!("run", run_id = "run-2", workflow_id = "wf-2");
+            selected.in_scope(|| debug!("before selecting"));
+            selectors.enable(RunLogSelector::RunId("run-1".to_string()), Level::DEBUG);
+            selected.in_scope(|| {
+                debug!("selected debug");
+                trace!("selected trace");
+            });
+            other.in_scope(|| debug!("other debug"));
+            tracing::warn!("unrelated warning");
+        });
+
+        assert_eq!(
+            *collector.messages.lock(),
+            vec![
+                "selected debug".to_string(),
+                "unrelated warning".to_string()
+            ]
+        );
+    }
+}
@@ -14,6 +14,7 @@ use crate::{
         TrackedOwnedMeteredSemPermit, UsedMeteredSemPermit,
     },
     pollers::BoxedActPoller,
+    retry_logic::RetryPolicyExt,
     telemetry::metrics::{activity_type, eager, workflow_type, MetricsContext},
     worker::{
         activities::{
@@ -48,6 +49,7 @@ use temporal_sdk_core_protos::{
         ActivityHeartbeat,
     },
     temporal::api::{
+        common::v1::RetryPolicy,
         failure::v1::{failure::FailureInfo, ApplicationFailureInfo, CanceledFailureInfo, Failure},
         workflowservice::v1::PollActivityTaskQueueResponse,
     },
@@ -83,11 +85,36 @@ struct InFlightActInfo {
     start_time: Instant,
 }
 
+/// Information about a (non-local) activity task which has been handed to lang but not yet
+/// completed
+#[derive(Debug, Clone)]
+pub struct PendingActivityInfo {
+    /// The activity's task token
+    pub task_token: TaskToken,
+    /// The activity's type
+    pub activity_type: String,
+    /// Id of the workflow which scheduled the activity
+    pub workflow_id: String,
+    /// Run id of the workflow which scheduled the activity
+    pub workflow_run_id: String,
+    /// The attempt currently being executed, starting at 1
+    pub attempt: u32,
+    /// The backoff the activity's retry policy prescribes before the next attempt, should the
+    /// current one fail with a retryable error. This is a prediction computed the same way core
+    /// computes local activity backoffs. The server makes the real decision, and may differ (ex: if
+    /// the schedule-to-close timeout would elapse first). `None` if the current attempt is the last
+    /// one the policy allows.
+    pub next_retry_backoff: Option<Duration>,
+}
+
 /// Augments [InFlightActInfo] with details specific to remote activities
 struct RemoteInFlightActInfo {
     pub base: InFlightActInfo,
     /// Used to calculate aggregation delay between activity heartbeats.
     pub heartbeat_timeout: Option<prost_types::Duration>,
+    /// The attempt number and retry policy from the poll response, used for introspection
+    pub attempt: u32,
+    pub retry_policy: RetryPolicy,
     /// Set if we have already issued a cancellation activation to lang for this activity, with
     /// the original reason we issued the cancel.
     pub issued_cancel_to_lang: Option<ActivityCancelReason>,
@@ -110,6 +137,8 @@ impl RemoteInFlightActInfo {
                 start_time: Instant::now(),
             },
             heartbeat_timeout: poll_resp.heartbeat_timeout.clone(),
+            attempt: poll_resp.attempt.max(1) as u32,
+            retry_policy: poll_resp.retry_policy.clone().unwrap_or_default(),
             issued_cancel_to_lang: None,
             known_not_found: false,
             _permit: permit,
@@ -412,6 +441,25 @@ impl WorkerActivityTasks {
         self.heartbeat_manager.record(details, throttle_interval)
     }
 
+    /// Returns information about all activities which have been handed to lang but not yet
+    /// completed
+    pub(crate) fn pending_activities(&self) -> Vec<PendingActivityInfo> {
+        self.outstanding_activity_tasks
+            .iter()
+            .map(|entry| {
+                let info = entry.value();
+                PendingActivityInfo {
+                    task_token: entry.key().clone(),
+                    activity_type: info.base.activity_type.clone(),
+                    workflow_id: info.base.workflow_id.clone(),
+                    workflow_run_id: info.base.workflow_run_id.clone(),
+                    attempt: info.attempt,
+                    next_retry_backoff: info.retry_policy.should_retry(info.attempt as usize, None),
+                }
+            })
+            .collect()
+    }
+
     /// Returns a handle that the workflows management side can use to interact with this manager
     pub(crate) fn get_handle_for_workflows(&self) -> ActivitiesFromWFTsHandle {
         ActivitiesFromWFTsHandle {
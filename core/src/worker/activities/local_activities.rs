@@ -12,9 +12,11 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     fmt::{Debug, Formatter},
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
     time::{Duration, Instant, SystemTime},
 };
+use temporal_sdk_core_api::worker::SlotSupplier;
 use temporal_sdk_core_protos::{
     coresdk::{
         activity_result::{Cancellation, Failure as ActFail, Success},
@@ -196,7 +198,7 @@ impl LAMData {
 
 impl LocalActivityManager {
     pub(crate) fn new(
-        max_concurrent: usize,
+        slots: Arc<dyn SlotSupplier>,
         namespace: String,
         heartbeat_timeout_tx: UnboundedSender<HeartbeatTimeoutMsg>,
         metrics_context: MetricsContext,
@@ -204,8 +206,8 @@ impl LocalActivityManager {
         let (act_req_tx, act_req_rx) = unbounded_channel();
         let (cancels_req_tx, cancels_req_rx) = unbounded_channel();
         let shutdown_complete_tok = CancellationToken::new();
-        let semaphore = MeteredSemaphore::new(
-            max_concurrent,
+        let semaphore = MeteredSemaphore::from_supplier(
+            slots,
             metrics_context,
             MetricsContext::available_task_slots,
         );
@@ -235,7 +237,9 @@ impl LocalActivityManager {
     fn test(max_concurrent: usize) -> Self {
         let (hb_tx, _hb_rx) = unbounded_channel();
         Self::new(
-            max_concurrent,
+            Arc::new(temporal_sdk_core_api::worker::FixedSizeSlotSupplier::new(
+                max_concurrent,
+            )),
             "fake_ns".to_string(),
             hb_tx,
             MetricsContext::no_op(),
@@ -722,10 +726,7 @@ impl RcvChans {
         let new_stream = UnboundedReceiverStream::new(new_reqs)
             // Get a permit for each new activity request
             .zip(stream::unfold(new_sem, |new_sem| async move {
-                let permit = new_sem
-                    .acquire_owned()
-                    .await
-                    .expect("Local activity semaphore is never closed");
+                let permit = new_sem.acquire_owned().await;
                 Some((permit, new_sem))
             }))
             .map(|(req, permit)| NewOrCancel::New(req, permit));
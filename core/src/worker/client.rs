@@ -2,20 +2,25 @@
 
 pub(crate) mod mocks;
 
+use crate::worker::workflow::MAX_EVENTS_PER_UPDATE;
+use std::sync::{Arc, Once};
 use temporal_client::{Client, RetryClient, WorkflowService};
+use temporal_sdk_core_api::worker::FailureConverter;
 use temporal_sdk_core_protos::{
     coresdk::workflow_commands::QueryResult,
     temporal::api::{
-        command::v1::Command,
+        command::v1::{command, Command},
         common::v1::{
             MeteringMetadata, Payloads, WorkerVersionCapabilities, WorkerVersionStamp,
             WorkflowExecution,
         },
         enums::v1::{TaskQueueKind, WorkflowTaskFailedCause},
         failure::v1::Failure,
+        history::v1::{history_event, History},
         query::v1::WorkflowQueryResult,
         sdk::v1::WorkflowTaskCompletedMetadata,
         taskqueue::v1::{StickyExecutionAttributes, TaskQueue, TaskQueueMetadata},
+        update::v1::outcome,
         workflowservice::v1::{get_system_info_response::Capabilities, *},
     },
     TaskToken,
@@ -29,7 +34,10 @@ pub(crate) struct WorkerClientBag {
     namespace: String,
     identity: String,
     worker_build_id: String,
-    use_versioning: bool,
+    versioning_requested: bool,
+    versioning_unsupported_warning: Once,
+    history_page_size: Option<u32>,
+    failure_converter: Option<Arc<dyn FailureConverter>>,
 }
 
 impl WorkerClientBag {
@@ -39,22 +47,118 @@ impl WorkerClientBag {
         identity: String,
         worker_build_id: String,
         use_versioning: bool,
+        history_page_size: Option<u32>,
+        failure_converter: Option<Arc<dyn FailureConverter>>,
     ) -> Self {
         Self {
             client,
             namespace,
             identity,
             worker_build_id,
-            use_versioning,
+            versioning_requested: use_versioning,
+            versioning_unsupported_warning: Once::new(),
+            history_page_size,
+            failure_converter,
         }
     }
+
+    /// Fetches the server's capabilities if they aren't known yet (ex: a lazily connected client).
+    /// Must happen before every poll, since they decide how the request is built and which
+    /// internal flags runs may use.
+    async fn ensure_capabilities(&self) -> Result<()> {
+        let caps = self
+            .client
+            .get_client()
+            .inner()
+            .fetch_capabilities()
+            .await?;
+        if self.versioning_requested && !caps.build_id_based_versioning {
+            self.versioning_unsupported_warning.call_once(|| {
+                warn!(
+                    "Worker versioning was requested, but the server does not support it. \
+                     Falling back to unversioned polling."
+                )
+            });
+        }
+        Ok(())
+    }
+
+    /// Servers which predate build-id based versioning ignore the version capabilities, so the
+    /// build id must then be reported the legacy way. Versioning is never assumed to be supported
+    /// while capabilities are unknown.
+    fn use_versioning(&self) -> bool {
+        self.versioning_requested
+            && self
+                .capabilities()
+                .map_or(false, |c| c.build_id_based_versioning)
+    }
+
     fn versioning_build_id(&self) -> String {
-        if self.use_versioning {
+        if self.use_versioning() {
             self.worker_build_id.clone()
         } else {
             "".to_string()
         }
     }
+
+    fn encode_failure(&self, failure: &mut Option<Failure>) {
+        if let (Some(conv), Some(f)) = (self.failure_converter.as_ref(), failure.as_mut()) {
+            conv.encode(f);
+        }
+    }
+
+    fn decode_history(&self, history: Option<&mut History>) {
+        if let (Some(conv), Some(h)) = (self.failure_converter.as_ref(), history) {
+            decode_history_failures(conv.as_ref(), h);
+        }
+    }
+}
+
+/// Applies [FailureConverter::encode] to every failure in the commands
+fn encode_command_failures(conv: &dyn FailureConverter, commands: &mut [Command]) {
+    for cmd in commands {
+        let failure = match cmd.attributes.as_mut() {
+            Some(command::Attributes::FailWorkflowExecutionCommandAttributes(a)) => &mut a.failure,
+            Some(command::Attributes::RecordMarkerCommandAttributes(a)) => &mut a.failure,
+            Some(command::Attributes::ContinueAsNewWorkflowExecutionCommandAttributes(a)) => {
+                &mut a.failure
+            }
+            _ => continue,
+        };
+        if let Some(f) = failure.as_mut() {
+            conv.encode(f);
+        }
+    }
+}
+
+/// Applies [FailureConverter::decode] to every failure in the history. Core doesn't support
+/// workflow updates, but their failures are decoded too, so that exported histories are readable.
+pub(crate) fn decode_history_failures(conv: &dyn FailureConverter, history: &mut History) {
+    use history_event::Attributes as A;
+    for event in history.events.iter_mut() {
+        let failure = match event.attributes.as_mut() {
+            Some(A::WorkflowExecutionStartedEventAttributes(a)) => a.continued_failure.as_mut(),
+            Some(A::WorkflowExecutionFailedEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::WorkflowExecutionContinuedAsNewEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::WorkflowTaskFailedEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::ActivityTaskStartedEventAttributes(a)) => a.last_failure.as_mut(),
+            Some(A::ActivityTaskFailedEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::ActivityTaskTimedOutEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::MarkerRecordedEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::ChildWorkflowExecutionFailedEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::WorkflowExecutionUpdateRejectedEventAttributes(a)) => a.failure.as_mut(),
+            Some(A::WorkflowExecutionUpdateCompletedEventAttributes(a)) => {
+                match a.outcome.as_mut().and_then(|o| o.value.as_mut()) {
+                    Some(outcome::Value::Failure(f)) => Some(f),
+                    _ => None,
+                }
+            }
+            _ => continue,
+        };
+        if let Some(f) = failure {
+            conv.decode(f);
+        }
+    }
 }
 
 /// This trait contains everything workers need to interact with Temporal, and hence provides a
@@ -113,6 +217,11 @@ pub(crate) trait WorkerClient: Sync + Send {
         task_token: TaskToken,
         query_result: QueryResult,
     ) -> Result<RespondQueryTaskCompletedResponse>;
+    async fn reset_sticky_task_queue(
+        &self,
+        workflow_id: String,
+        run_id: String,
+    ) -> Result<ResetStickyTaskQueueResponse>;
 
     #[allow(clippy::needless_lifetimes)] // Clippy is wrong here
     fn capabilities<'a>(&'a self) -> Option<&'a get_system_info_response::Capabilities>;
@@ -125,6 +234,7 @@ impl WorkerClient for WorkerClientBag {
         task_queue: String,
         is_sticky: bool,
     ) -> Result<PollWorkflowTaskQueueResponse> {
+        self.ensure_capabilities().await?;
         let request = PollWorkflowTaskQueueRequest {
             namespace: self.namespace.clone(),
             task_queue: Some(TaskQueue {
@@ -136,7 +246,7 @@ impl WorkerClient for WorkerClientBag {
                 } as i32,
             }),
             identity: self.identity.clone(),
-            binary_checksum: if self.use_versioning {
+            binary_checksum: if self.use_versioning() {
                 "".to_string()
             } else {
                 self.worker_build_id.clone()
@@ -146,12 +256,24 @@ impl WorkerClient for WorkerClientBag {
             }),
         };
 
-        Ok(self
+        let mut resp = self
             .client
             .clone()
             .poll_workflow_task_queue(request)
             .await?
-            .into_inner())
+            .into_inner();
+        // Servers don't advertise whether they'll deliver workflow updates, so there is no
+        // capability to gate them on. Core doesn't speak the update protocol: its messages are
+        // dropped here, and update events in history fail the task as an unsupported feature.
+        if !resp.messages.is_empty() {
+            warn!(
+                count = resp.messages.len(),
+                "Ignoring protocol messages (ex: workflow updates) this worker does not support"
+            );
+            resp.messages.clear();
+        }
+        self.decode_history(resp.history.as_mut());
+        Ok(resp)
     }
 
     async fn poll_activity_task(
@@ -159,6 +281,7 @@ impl WorkerClient for WorkerClientBag {
         task_queue: String,
         max_tasks_per_sec: Option<f64>,
     ) -> Result<PollActivityTaskQueueResponse> {
+        self.ensure_capabilities().await?;
         let request = PollActivityTaskQueueRequest {
             namespace: self.namespace.clone(),
             task_queue: Some(TaskQueue {
@@ -184,8 +307,11 @@ impl WorkerClient for WorkerClientBag {
 
     async fn complete_workflow_task(
         &self,
-        request: WorkflowTaskCompletion,
+        mut request: WorkflowTaskCompletion,
     ) -> Result<RespondWorkflowTaskCompletedResponse> {
+        if let Some(conv) = self.failure_converter.as_ref() {
+            encode_command_failures(conv.as_ref(), &mut request.commands);
+        }
         let request = RespondWorkflowTaskCompletedRequest {
             task_token: request.task_token.into(),
             commands: request.commands,
@@ -283,8 +409,9 @@ impl WorkerClient for WorkerClientBag {
     async fn fail_activity_task(
         &self,
         task_token: TaskToken,
-        failure: Option<Failure>,
+        mut failure: Option<Failure>,
     ) -> Result<RespondActivityTaskFailedResponse> {
+        self.encode_failure(&mut failure);
         Ok(self
             .client
             .clone()
@@ -304,8 +431,9 @@ impl WorkerClient for WorkerClientBag {
         &self,
         task_token: TaskToken,
         cause: WorkflowTaskFailedCause,
-        failure: Option<Failure>,
+        mut failure: Option<Failure>,
     ) -> Result<RespondWorkflowTaskFailedResponse> {
+        self.encode_failure(&mut failure);
         let request = RespondWorkflowTaskFailedRequest {
             task_token: task_token.0,
             cause: cause as i32,
@@ -329,20 +457,22 @@ impl WorkerClient for WorkerClientBag {
         run_id: Option<String>,
         page_token: Vec<u8>,
     ) -> Result<GetWorkflowExecutionHistoryResponse> {
-        Ok(self
+        let mut resp = self
             .client
             .clone()
-            .get_workflow_execution_history(GetWorkflowExecutionHistoryRequest {
-                namespace: self.namespace.clone(),
-                execution: Some(WorkflowExecution {
+            .get_workflow_execution_history(history_page_request(
+                self.namespace.clone(),
+                WorkflowExecution {
                     workflow_id,
                     run_id: run_id.unwrap_or_default(),
-                }),
-                next_page_token: page_token,
-                ..Default::default()
-            })
+                },
+                self.history_page_size,
+                page_token,
+            ))
             .await?
-            .into_inner())
+            .into_inner();
+        self.decode_history(resp.history.as_mut());
+        Ok(resp)
     }
 
     async fn respond_legacy_query(
@@ -365,6 +495,25 @@ impl WorkerClient for WorkerClientBag {
             .into_inner())
     }
 
+    async fn reset_sticky_task_queue(
+        &self,
+        workflow_id: String,
+        run_id: String,
+    ) -> Result<ResetStickyTaskQueueResponse> {
+        Ok(self
+            .client
+            .clone()
+            .reset_sticky_task_queue(ResetStickyTaskQueueRequest {
+                namespace: self.namespace.clone(),
+                execution: Some(WorkflowExecution {
+                    workflow_id,
+                    run_id,
+                }),
+            })
+            .await?
+            .into_inner())
+    }
+
     fn capabilities(&self) -> Option<&Capabilities> {
         self.client.get_client().inner().capabilities()
     }
@@ -391,3 +540,136 @@ pub(crate) struct WorkflowTaskCompletion {
     /// Metering info
     pub metering_metadata: MeteringMetadata,
 }
+
+/// Builds the request for one page of a workflow's history. If `page_size` is unset, pages are
+/// limited to [MAX_EVENTS_PER_UPDATE] events, so that a page never holds more events than the
+/// paginator hands the machines at once.
+fn history_page_request(
+    namespace: String,
+    execution: WorkflowExecution,
+    page_size: Option<u32>,
+    page_token: Vec<u8>,
+) -> GetWorkflowExecutionHistoryRequest {
+    GetWorkflowExecutionHistoryRequest {
+        namespace,
+        execution: Some(execution),
+        maximum_page_size: page_size
+            .map(|s| i32::try_from(s).unwrap_or(i32::MAX))
+            .unwrap_or(MAX_EVENTS_PER_UPDATE as i32),
+        next_page_token: page_token,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temporal_sdk_core_api::worker::EncodedAttributesFailureConverter;
+    use temporal_sdk_core_protos::temporal::api::{
+        command::v1::FailWorkflowExecutionCommandAttributes,
+        history::v1::{
+            ActivityTaskFailedEventAttributes, HistoryEvent,
+            WorkflowExecutionUpdateCompletedEventAttributes,
+        },
+        update::v1::Outcome,
+    };
+
+    fn failure_with_cause() -> Failure {
+        Failure {
+            message: "secret".to_string(),
+            stack_trace: "at secret.rs".to_string(),
+            cause: Some(Box::new(Failure {
+                message: "inner secret".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn failures_round_trip_through_converter() {
+        let conv = EncodedAttributesFailureConverter;
+        let mut commands = vec![Command {
+            attributes: Some(command::Attributes::FailWorkflowExecutionCommandAttributes(
+                FailWorkflowExecutionCommandAttributes {
+                    failure: Some(failure_with_cause()),
+                },
+            )),
+            ..Default::default()
+        }];
+        encode_command_failures(&conv, &mut commands);
+        let encoded = match commands.pop().unwrap().attributes {
+            Some(command::Attributes::FailWorkflowExecutionCommandAttributes(a)) => {
+                a.failure.unwrap()
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(encoded.message, "Encoded failure");
+        assert!(encoded.stack_trace.is_empty());
+        assert!(encoded.encoded_attributes.is_some());
+        assert_eq!(encoded.cause.as_ref().unwrap().message, "Encoded failure");
+
+        // Reading the failure back from history restores it, including from update outcomes
+        let mut history = History {
+            events: vec![
+                HistoryEvent {
+                    attributes: Some(
+                        history_event::Attributes::ActivityTaskFailedEventAttributes(
+                            ActivityTaskFailedEventAttributes {
+                                failure: Some(encoded.clone()),
+                                ..Default::default()
+                            },
+                        ),
+                    ),
+                    ..Default::default()
+                },
+                HistoryEvent {
+                    attributes: Some(
+                        history_event::Attributes::WorkflowExecutionUpdateCompletedEventAttributes(
+                            WorkflowExecutionUpdateCompletedEventAttributes {
+                                outcome: Some(Outcome {
+                                    value: Some(outcome::Value::Failure(encoded)),
+                                }),
+                                ..Default::default()
+                            },
+                        ),
+                    ),
+                    ..Default::default()
+                },
+            ],
+        };
+        decode_history_failures(&conv, &mut history);
+        match history.events.pop().unwrap().attributes {
+            Some(history_event::Attributes::WorkflowExecutionUpdateCompletedEventAttributes(a)) => {
+                assert_eq!(
+                    a.outcome.unwrap().value,
+                    Some(outcome::Value::Failure(failure_with_cause()))
+                );
+            }
+            _ => unreachable!(),
+        }
+        match history.events.pop().unwrap().attributes {
+            Some(history_event::Attributes::ActivityTaskFailedEventAttributes(a)) => {
+                assert_eq!(a.failure, Some(failure_with_cause()));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn history_page_size_sent_as_maximum_page_size() {
+        let req = |page_size| {
+            history_page_request(
+                "ns".to_string(),
+                WorkflowExecution::default(),
+                page_size,
+                vec![1],
+            )
+        };
+        assert_eq!(req(Some(50)).maximum_page_size, 50);
+        assert_eq!(req(Some(u32::MAX)).maximum_page_size, i32::MAX);
+        // Unconfigured fetches are still bounded, rather than using the server's default
+        assert_eq!(req(None).maximum_page_size, MAX_EVENTS_PER_UPDATE as i32);
+        assert_eq!(req(None).next_page_token, vec![1]);
+    }
+}
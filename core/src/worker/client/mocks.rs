@@ -1,5 +1,5 @@
 use super::*;
-use futures::Future;
+use futures::{Future, FutureExt};
 
 pub(crate) static DEFAULT_TEST_CAPABILITIES: &Capabilities = &Capabilities {
     signal_and_query_header: true,
@@ -19,6 +19,8 @@ pub(crate) fn mock_workflow_client() -> MockWorkerClient {
     let mut r = MockWorkerClient::new();
     r.expect_capabilities()
         .returning(|| Some(DEFAULT_TEST_CAPABILITIES));
+    r.expect_reset_sticky_task_queue()
+        .returning(|_, _| Ok(Default::default()));
     r
 }
 
@@ -27,6 +29,8 @@ pub(crate) fn mock_manual_workflow_client() -> MockManualWorkerClient {
     let mut r = MockManualWorkerClient::new();
     r.expect_capabilities()
         .returning(|| Some(DEFAULT_TEST_CAPABILITIES));
+    r.expect_reset_sticky_task_queue()
+        .returning(|_, _| async { Ok(Default::default()) }.boxed());
     r
 }
 
@@ -102,6 +106,13 @@ mockall::mock! {
         ) -> impl Future<Output = Result<RespondQueryTaskCompletedResponse>> + Send + 'b
             where 'a: 'b, Self: 'b;
 
+        fn reset_sticky_task_queue<'a, 'b>(
+            &self,
+            workflow_id: String,
+            run_id: String,
+        ) -> impl Future<Output = Result<ResetStickyTaskQueueResponse>> + Send + 'b
+            where 'a: 'b, Self: 'b;
+
         fn capabilities(&self) -> Option<&'static get_system_info_response::Capabilities>;
     }
 }
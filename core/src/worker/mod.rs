@@ -1,15 +1,26 @@
 mod activities;
 pub(crate) mod client;
+mod slot_tuning;
+mod task_registry;
 mod workflow;
 
 pub use temporal_sdk_core_api::worker::{WorkerConfig, WorkerConfigBuilder};
 #[cfg(feature = "save_wf_inputs")]
 pub use workflow::replay_wf_state_inputs;
+#[cfg(feature = "machine_diagrams")]
+pub use workflow::{
+    render_machine_diagrams, DiagramFormat, MachineTransitions, TransitionRecording,
+};
 
+pub use activities::PendingActivityInfo;
 pub(crate) use activities::{
     ExecutingLAId, LocalActRequest, LocalActivityExecutionResult, LocalActivityResolution,
     NewLocalAct,
 };
+pub use slot_tuning::{
+    ProcResourceInfo, ResourceBasedSlotSupplier, ResourceBasedTargets, ResourceSlotOptions,
+    SystemResourceInfo,
+};
 pub(crate) use workflow::{wft_poller::new_wft_poller, LEGACY_QUERY_ID};
 
 #[cfg(test)]
@@ -17,9 +28,10 @@ pub(crate) use workflow::ManagedWFFunc;
 
 use crate::{
     abstractions::MeteredSemaphore,
-    errors::CompleteWfError,
+    errors::{CompleteWfError, HistoryExportError},
     pollers::{new_activity_task_buffer, new_workflow_task_buffer, WorkflowTaskPoller},
     protosext::validate_activity_completion,
+    replay::fetch_full_history,
     telemetry::{
         metrics::{
             activity_poller, activity_worker_type, local_activity_worker_type, workflow_poller,
@@ -30,11 +42,16 @@ use crate::{
     worker::{
         activities::{DispatchOrTimeoutLA, LACompleteAction, LocalActivityManager},
         client::WorkerClient,
-        workflow::{LAReqSink, LocalResolution, WorkflowBasics, Workflows},
+        task_registry::{OutstandingTaskRegistry, LEAK_CHECK_INTERVAL},
+        workflow::{
+            LAReqSink, LocalResolution, PayloadSizeLimits, ServerCapabilitiesFn, WorkflowBasics,
+            WorkflowStateInfo, Workflows,
+        },
     },
     ActivityHeartbeat, CompleteActivityError, PollActivityError, PollWfError, WorkerTrait,
 };
 use activities::{LocalInFlightActInfo, WorkerActivityTasks};
+use prost::Message;
 use std::{
     convert::TryInto,
     future,
@@ -43,23 +60,25 @@ use std::{
         Arc,
     },
 };
+use temporal_sdk_core_api::worker::MachinesDump;
 use temporal_sdk_core_protos::{
     coresdk::{
-        activity_result::activity_execution_result,
-        activity_task::ActivityTask,
+        activity_result::{self, activity_execution_result},
+        activity_task::{activity_task, ActivityTask},
         workflow_activation::{remove_from_cache::EvictionReason, WorkflowActivation},
         workflow_completion::WorkflowActivationCompletion,
         ActivityTaskCompletion,
     },
     temporal::api::{
         enums::v1::TaskQueueKind,
+        failure::v1::Failure,
+        history::v1::History,
         taskqueue::v1::{StickyExecutionAttributes, TaskQueue},
-        workflowservice::v1::get_system_info_response,
     },
     TaskToken,
 };
 use tokio::sync::mpsc::unbounded_channel;
-use tokio_util::sync::CancellationToken;
+use tokio_util::sync::{CancellationToken, DropGuard};
 
 use crate::pollers::BoxedActPoller;
 #[cfg(test)]
@@ -78,8 +97,8 @@ pub struct Worker {
     config: WorkerConfig,
     wf_client: Arc<dyn WorkerClient>,
 
-    /// Manages all workflows and WFT processing
-    workflows: Workflows,
+    /// Manages all workflows and WFT processing. Not present on activity-only workers.
+    workflows: Option<Workflows>,
     /// Manages activity tasks for this worker/task queue
     at_task_mgr: Option<WorkerActivityTasks>,
     /// Manages local activities
@@ -93,19 +112,47 @@ pub struct Worker {
     non_local_activities_complete: Arc<AtomicBool>,
     /// Set when local activities are complete and should stop being polled
     local_activities_complete: Arc<AtomicBool>,
+    /// Tracks activity tasks and activations lang has not yet completed, to detect leaks
+    task_registry: Arc<OutstandingTaskRegistry>,
+    /// Stops the leak check task if the worker is dropped without being shut down
+    _leak_check_guard: DropGuard,
 }
 
 #[async_trait::async_trait]
 impl WorkerTrait for Worker {
     async fn poll_workflow_activation(&self) -> Result<WorkflowActivation, PollWfError> {
-        self.next_workflow_activation().await
+        let mut act = self.next_workflow_activation().await?;
+        if let Some(interceptor) = self.config.task_interceptor.as_ref() {
+            intercept_keeping_id(
+                &mut act,
+                |a| &mut a.run_id,
+                |a| interceptor.on_workflow_activation(a),
+            );
+        }
+        Ok(act)
     }
 
-    #[instrument(skip(self))]
+    #[instrument(skip(self),
+                 fields(namespace=%self.config.namespace, task_queue=%self.config.task_queue))]
     async fn poll_activity_task(&self) -> Result<ActivityTask, PollActivityError> {
         loop {
             match self.activity_poll().await.transpose() {
-                Some(r) => break r,
+                Some(mut r) => {
+                    if let Ok(task) = r.as_mut() {
+                        if self.fail_if_unregistered_activity(task).await {
+                            continue;
+                        }
+                        self.task_registry.activity_issued(task);
+                        if let Some(interceptor) = self.config.task_interceptor.as_ref() {
+                            intercept_keeping_id(
+                                task,
+                                |t| &mut t.task_token,
+                                |t| interceptor.on_activity_task(t),
+                            );
+                        }
+                    }
+                    break r;
+                }
                 None => {
                     tokio::task::yield_now().await;
                     continue;
@@ -116,15 +163,29 @@ impl WorkerTrait for Worker {
 
     async fn complete_workflow_activation(
         &self,
-        completion: WorkflowActivationCompletion,
+        mut completion: WorkflowActivationCompletion,
     ) -> Result<(), CompleteWfError> {
+        if let Some(interceptor) = self.config.task_interceptor.as_ref() {
+            intercept_keeping_id(
+                &mut completion,
+                |c| &mut c.run_id,
+                |c| interceptor.on_workflow_activation_completion(c),
+            );
+        }
         self.complete_workflow_activation(completion).await
     }
 
     async fn complete_activity_task(
         &self,
-        completion: ActivityTaskCompletion,
+        mut completion: ActivityTaskCompletion,
     ) -> Result<(), CompleteActivityError> {
+        if let Some(interceptor) = self.config.task_interceptor.as_ref() {
+            intercept_keeping_id(
+                &mut completion,
+                |c| &mut c.task_token,
+                |c| interceptor.on_activity_task_completion(c),
+            );
+        }
         let task_token = TaskToken(completion.task_token);
         let status = if let Some(s) = completion.result.and_then(|r| r.status) {
             s
@@ -171,7 +232,12 @@ impl WorkerTrait for Worker {
         // Let the manager know that shutdown has been initiated to try to unblock the local
         // activity poll in case this worker is an activity-only worker.
         self.local_act_mgr.shutdown_initiated();
-        if !self.workflows.ever_polled() {
+        let workflows_ever_polled = self
+            .workflows
+            .as_ref()
+            .map(|w| w.ever_polled())
+            .unwrap_or_default();
+        if !workflows_ever_polled {
             self.local_act_mgr.workflows_have_shutdown();
         }
     }
@@ -227,73 +293,70 @@ impl Worker {
         telem_instance: Option<&TelemetryInstance>,
     ) -> Self {
         let shutdown_token = CancellationToken::new();
-        let wft_semaphore = Arc::new(MeteredSemaphore::new(
-            config.max_outstanding_workflow_tasks,
+        let wft_semaphore = Arc::new(MeteredSemaphore::from_supplier(
+            config.workflow_task_slots(),
             metrics.with_new_attrs([workflow_worker_type()]),
             MetricsContext::available_task_slots,
         ));
-        let act_semaphore = Arc::new(MeteredSemaphore::new(
-            config.max_outstanding_activities,
+        let act_semaphore = Arc::new(MeteredSemaphore::from_supplier(
+            config.activity_slots(),
             metrics.with_new_attrs([activity_worker_type()]),
             MetricsContext::available_task_slots,
         ));
 
         let (wft_stream, act_poller) = match task_pollers {
             TaskPollers::Real => {
-                let max_nonsticky_polls = if sticky_queue_name.is_some() {
-                    config.max_nonsticky_polls()
+                let wft_stream = if config.no_workflows {
+                    None
                 } else {
-                    config.max_concurrent_wft_polls
-                };
-                let max_sticky_polls = config.max_sticky_polls();
-                let wft_metrics = metrics.with_new_attrs([workflow_poller()]);
-                let wf_task_poll_buffer = new_workflow_task_buffer(
-                    client.clone(),
-                    config.task_queue.clone(),
-                    false,
-                    max_nonsticky_polls,
-                    wft_semaphore.clone(),
-                    shutdown_token.child_token(),
-                    Some(move |np| {
-                        wft_metrics.record_num_pollers(np);
-                    }),
-                );
-                let sticky_queue_poller = sticky_queue_name.as_ref().map(|sqn| {
-                    let sticky_metrics = metrics.with_new_attrs([workflow_sticky_poller()]);
-                    new_workflow_task_buffer(
+                    let nonsticky_behavior = if sticky_queue_name.is_some() {
+                        config.nonsticky_wft_poller_behavior()
+                    } else {
+                        config.wft_poller_behavior()
+                    };
+                    let wf_task_poll_buffer = new_workflow_task_buffer(
                         client.clone(),
-                        sqn.clone(),
-                        true,
-                        max_sticky_polls,
+                        config.task_queue.clone(),
+                        false,
+                        nonsticky_behavior,
                         wft_semaphore.clone(),
                         shutdown_token.child_token(),
-                        Some(move |np| {
-                            sticky_metrics.record_num_pollers(np);
-                        }),
-                    )
-                });
+                        Some(metrics.with_new_attrs([workflow_poller()])),
+                    );
+                    let sticky_queue_poller = sticky_queue_name.as_ref().map(|sqn| {
+                        new_workflow_task_buffer(
+                            client.clone(),
+                            sqn.clone(),
+                            true,
+                            config.sticky_wft_poller_behavior(),
+                            wft_semaphore.clone(),
+                            shutdown_token.child_token(),
+                            Some(metrics.with_new_attrs([workflow_sticky_poller()])),
+                        )
+                    });
+                    let wf_task_poll_buffer = Box::new(WorkflowTaskPoller::new(
+                        wf_task_poll_buffer,
+                        sticky_queue_poller,
+                    ));
+                    let wft_stream = new_wft_poller(wf_task_poll_buffer, metrics.clone());
+                    #[cfg(test)]
+                    let wft_stream = wft_stream.left_stream();
+                    Some(wft_stream)
+                };
                 let act_poll_buffer = if config.no_remote_activities {
                     None
                 } else {
-                    let act_metrics = metrics.with_new_attrs([activity_poller()]);
                     let ap = new_activity_task_buffer(
                         client.clone(),
                         config.task_queue.clone(),
-                        config.max_concurrent_at_polls,
+                        config.activity_poller_behavior(),
                         act_semaphore.clone(),
                         config.max_task_queue_activities_per_second,
                         shutdown_token.child_token(),
-                        Some(move |np| act_metrics.record_num_pollers(np)),
+                        Some(metrics.with_new_attrs([activity_poller()])),
                     );
                     Some(Box::from(ap) as BoxedActPoller)
                 };
-                let wf_task_poll_buffer = Box::new(WorkflowTaskPoller::new(
-                    wf_task_poll_buffer,
-                    sticky_queue_poller,
-                ));
-                let wft_stream = new_wft_poller(wf_task_poll_buffer, metrics.clone());
-                #[cfg(test)]
-                let wft_stream = wft_stream.left_stream();
                 (wft_stream, act_poll_buffer)
             }
             #[cfg(test)]
@@ -307,21 +370,21 @@ impl Worker {
                 let wfs = wft_stream.then(move |s| {
                     let wft_semaphore = wft_semaphore.clone();
                     async move {
-                        let permit = wft_semaphore
-                            .acquire_owned()
-                            .await
-                            .expect("Mock WFT stream should not see closed semaphore");
+                        let permit = wft_semaphore.acquire_owned().await;
                         s.map(|s| (s, permit))
                     }
                 });
                 let wfs = wfs.right_stream();
-                (wfs, ap.map(|ap| Box::new(ap) as BoxedActPoller))
+                (
+                    (!config.no_workflows).then_some(wfs),
+                    ap.map(|ap| Box::new(ap) as BoxedActPoller),
+                )
             }
         };
 
         let (hb_tx, hb_rx) = unbounded_channel();
         let local_act_mgr = Arc::new(LocalActivityManager::new(
-            config.max_outstanding_local_activities,
+            config.local_activity_slots(),
             config.namespace.clone(),
             hb_tx,
             metrics.with_new_attrs([local_activity_worker_type()]),
@@ -343,14 +406,30 @@ impl Worker {
             info!("Activity polling is disabled for this worker");
         };
         let la_sink = LAReqSink::new(local_act_mgr.clone(), config.wf_state_inputs.clone());
-        Self {
-            wf_client: client.clone(),
-            workflows: Workflows::new(
+        let task_registry = Arc::new(OutstandingTaskRegistry::new(metrics.clone()));
+        let leak_check_registry = task_registry.clone();
+        // Stops on shutdown, or when the worker is dropped
+        let leak_check_shutdown = shutdown_token.child_token();
+        let leak_check_guard = leak_check_shutdown.clone().drop_guard();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(LEAK_CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        leak_check_registry.check_for_leaks();
+                    }
+                    _ = leak_check_shutdown.cancelled() => break,
+                }
+            }
+        });
+        let caps_client = client.clone();
+        let workflows = wft_stream.map(|wft_stream| {
+            Workflows::new(
                 build_wf_basics(
                     &mut config,
                     metrics,
                     shutdown_token.child_token(),
-                    client.capabilities().cloned().unwrap_or_default(),
+                    Arc::new(move || caps_client.capabilities().cloned().unwrap_or_default()),
                 ),
                 sticky_queue_name.map(|sq| StickyExecutionAttributes {
                     worker_task_queue: Some(TaskQueue {
@@ -364,7 +443,7 @@ impl Worker {
                             .expect("timeout fits into proto"),
                     ),
                 }),
-                client,
+                client.clone(),
                 wft_semaphore,
                 wft_stream,
                 la_sink,
@@ -374,7 +453,11 @@ impl Worker {
                     .as_ref()
                     .map(|mgr| mgr.get_handle_for_workflows()),
                 telem_instance,
-            ),
+            )
+        });
+        Self {
+            wf_client: client,
+            workflows,
             at_task_mgr,
             local_act_mgr,
             config,
@@ -383,23 +466,50 @@ impl Worker {
             // Complete if there configured not to poll on non-local activities.
             non_local_activities_complete: Arc::new(AtomicBool::new(!poll_on_non_local_activities)),
             local_activities_complete: Default::default(),
+            task_registry,
+            _leak_check_guard: leak_check_guard,
         }
     }
 
     /// Will shutdown the worker. Does not resolve until all outstanding workflow tasks have been
-    /// completed
+    /// completed, or the workflow force eviction timeout (if configured) has elapsed and the runs
+    /// they belong to were forcibly evicted.
     async fn shutdown(&self) {
         self.initiate_shutdown();
-        // We need to wait for all local activities to finish so no more workflow task heartbeats
-        // will be generated
-        self.local_act_mgr
-            .wait_all_outstanding_tasks_finished()
-            .await;
-        // Wait for workflows to finish
-        self.workflows
-            .shutdown()
-            .await
-            .expect("Workflow processing terminates cleanly");
+        if let Some(workflows) = self.workflows.as_ref() {
+            let drain_workflows = async {
+                // We need to wait for all local activities to finish so no more workflow task
+                // heartbeats will be generated
+                self.local_act_mgr
+                    .wait_all_outstanding_tasks_finished()
+                    .await;
+                // Wait for workflows to finish
+                workflows
+                    .shutdown()
+                    .await
+                    .expect("Workflow processing terminates cleanly");
+            };
+            if let Some(evict_timeout) = self.config.workflow_force_eviction_timeout {
+                if tokio::time::timeout(evict_timeout, drain_workflows)
+                    .await
+                    .is_err()
+                {
+                    warn!(
+                        task_queue=%self.config.task_queue,
+                        "Workflow force eviction timeout elapsed with workflow work outstanding, \
+                         forcibly evicting all cached runs"
+                    );
+                    workflows.force_shutdown().await;
+                    self.local_act_mgr.workflows_have_shutdown();
+                    workflows
+                        .shutdown()
+                        .await
+                        .expect("Workflow processing terminates cleanly");
+                }
+            } else {
+                drain_workflows.await;
+            }
+        }
         // Wait for activities to finish
         if let Some(acts) = self.at_task_mgr.as_ref() {
             acts.shutdown().await;
@@ -420,30 +530,85 @@ impl Worker {
 
     /// Returns number of currently cached workflows
     pub async fn cached_workflows(&self) -> usize {
-        self.workflows
-            .get_state_info()
+        self.workflow_state_info()
             .await
             .map(|r| r.cached_workflows)
             .unwrap_or_default()
     }
 
+    /// Returns information about all (non-local) activity tasks which lang has been given but not
+    /// yet completed
+    pub fn pending_activities(&self) -> Vec<PendingActivityInfo> {
+        self.at_task_mgr
+            .as_ref()
+            .map(|mgr| mgr.pending_activities())
+            .unwrap_or_default()
+    }
+
+    /// Exports the history of a cached workflow run, up to and including the last event core has
+    /// applied to it, fetching every page from server. The result is a standard history which can
+    /// be encoded as protobuf (or as JSON, with the protos crate's `serde_serialize` feature) and
+    /// later replayed, which is useful for capturing reproductions from a live worker.
+    pub async fn export_history(&self, run_id: &str) -> Result<History, HistoryExportError> {
+        let info = match self.workflows.as_ref() {
+            Some(workflows) => workflows.get_run_history_info(run_id).await,
+            None => None,
+        }
+        .ok_or_else(|| HistoryExportError::RunNotCached {
+            run_id: run_id.to_string(),
+        })?;
+        Ok(fetch_full_history(
+            |page_token| {
+                self.wf_client.get_workflow_execution_history(
+                    info.workflow_id.clone(),
+                    Some(run_id.to_string()),
+                    page_token,
+                )
+            },
+            Some(info.last_applied_event_id),
+        )
+        .await?)
+    }
+
+    /// Returns a snapshot of the state machines of a cached workflow run - their kinds, states,
+    /// unresolved commands, and the history events routed to them. Useful for debugging runs which
+    /// are stuck or nondeterministic. Returns `None` if the run is not cached.
+    pub async fn machines_dump(&self, run_id: &str) -> Option<MachinesDump> {
+        match self.workflows.as_ref() {
+            Some(workflows) => workflows.get_machines_dump(run_id).await,
+            None => None,
+        }
+    }
+
     /// Returns number of currently outstanding workflow tasks
     #[cfg(test)]
     pub(crate) async fn outstanding_workflow_tasks(&self) -> usize {
-        self.workflows
-            .get_state_info()
+        self.workflow_state_info()
             .await
             .map(|r| r.outstanding_wft)
             .unwrap_or_default()
     }
 
+    async fn workflow_state_info(&self) -> Option<WorkflowStateInfo> {
+        match self.workflows.as_ref() {
+            Some(workflows) => workflows.get_state_info().await,
+            None => None,
+        }
+    }
+
     #[allow(unused)]
     pub(crate) fn available_wft_permits(&self) -> usize {
-        self.workflows.available_wft_permits()
+        self.workflows
+            .as_ref()
+            .map(|w| w.available_wft_permits())
+            .unwrap_or_default()
     }
     #[cfg(test)]
     pub(crate) fn unused_wft_permits(&self) -> usize {
-        self.workflows.unused_wft_permits()
+        self.workflows
+            .as_ref()
+            .map(|w| w.unused_wft_permits())
+            .unwrap_or_default()
     }
 
     /// Get new activity tasks (may be local or nonlocal). Local activities are returned first
@@ -513,6 +678,42 @@ impl Worker {
         }
     }
 
+    /// If the task starts a (non-local) activity of a type lang has not registered, immediately
+    /// fail it rather than handing it to lang. Returns true if the task was failed.
+    async fn fail_if_unregistered_activity(&self, task: &ActivityTask) -> bool {
+        let unregistered_type = match (
+            self.config.registered_activity_types.as_ref(),
+            task.variant.as_ref(),
+        ) {
+            (Some(registered), Some(activity_task::Variant::Start(start)))
+                if !start.is_local && !registered.contains(&start.activity_type) =>
+            {
+                &start.activity_type
+            }
+            _ => return false,
+        };
+        if let Some(atm) = self.at_task_mgr.as_ref() {
+            warn!(activity_type=%unregistered_type, task_token=%TaskToken(task.task_token.clone()),
+                  "Failing activity task of unregistered type");
+            // Left retryable, so the server can hand the activity to a worker which does have
+            // the type registered, rather than one misconfigured worker failing it for good.
+            let failure = Failure::application_failure(
+                format!("Activity type {unregistered_type} is not registered on this worker"),
+                false,
+            );
+            atm.complete(
+                TaskToken(task.task_token.clone()),
+                activity_execution_result::Status::Failed(activity_result::Failure {
+                    failure: Some(failure),
+                }),
+                &*self.wf_client,
+            )
+            .await;
+            return true;
+        }
+        false
+    }
+
     /// Attempt to record an activity heartbeat
     pub(crate) fn record_heartbeat(&self, details: ActivityHeartbeat) {
         if let Some(at_mgr) = self.at_task_mgr.as_ref() {
@@ -525,13 +726,15 @@ impl Worker {
 
     #[instrument(skip(self, task_token, status),
                  fields(task_token=%&task_token, status=%&status,
-                        task_queue=%self.config.task_queue, workflow_id, run_id))]
+                        namespace=%self.config.namespace, task_queue=%self.config.task_queue,
+                        workflow_id, run_id))]
     pub(crate) async fn complete_activity(
         &self,
         task_token: TaskToken,
         status: activity_execution_result::Status,
     ) -> Result<(), CompleteActivityError> {
         validate_activity_completion(&status)?;
+        self.task_registry.activity_completed(&task_token);
         if task_token.is_local_activity_task() {
             let as_la_res: LocalActivityExecutionResult = status.try_into()?;
             match self.local_act_mgr.complete(&task_token, &as_la_res) {
@@ -555,6 +758,7 @@ impl Worker {
         }
 
         if let Some(atm) = &self.at_task_mgr {
+            let status = self.check_activity_payload_sizes(&task_token, status);
             atm.complete(task_token, status, &*self.wf_client).await;
         } else {
             error!(
@@ -565,9 +769,58 @@ impl Worker {
         Ok(())
     }
 
-    #[instrument(skip(self), fields(run_id, workflow_id, task_queue=%self.config.task_queue))]
+    /// Warns about activity results or failures holding more payload bytes than configured, or,
+    /// if they exceed the limit, fails the activity with a message saying so rather than sending
+    /// them to the server only to be rejected there.
+    fn check_activity_payload_sizes(
+        &self,
+        task_token: &TaskToken,
+        status: activity_execution_result::Status,
+    ) -> activity_execution_result::Status {
+        let (field, size) = match &status {
+            activity_execution_result::Status::Completed(c) => {
+                ("result", c.result.as_ref().map_or(0, Message::encoded_len))
+            }
+            activity_execution_result::Status::Failed(f) => (
+                "failure",
+                f.failure.as_ref().map_or(0, Message::encoded_len),
+            ),
+            _ => return status,
+        };
+        if let Some(limit) = self.config.payload_size_limit.filter(|l| size > *l) {
+            let message = format!(
+                "Field `{field}` of activity completion holds {size} bytes of payloads, exceeding \
+                 the configured limit of {limit} bytes"
+            );
+            warn!(task_token=%task_token, "{message}");
+            // Retrying would most likely produce an equally oversized result
+            return activity_execution_result::Status::Failed(activity_result::Failure {
+                failure: Some(Failure::application_failure(message, true)),
+            });
+        }
+        if matches!(self.config.payload_size_warning_threshold, Some(t) if size > t) {
+            warn!(task_token=%task_token, field, size,
+                  "Activity completion payloads exceed the configured warning threshold");
+        }
+        status
+    }
+
+    #[instrument(skip(self),
+                 fields(run_id, workflow_id, namespace=%self.config.namespace,
+                        task_queue=%self.config.task_queue))]
     pub(crate) async fn next_workflow_activation(&self) -> Result<WorkflowActivation, PollWfError> {
-        let r = self.workflows.next_workflow_activation().await;
+        let workflows = match self.workflows.as_ref() {
+            Some(workflows) => workflows,
+            None => {
+                // Activity-only workers never have any activations to hand out
+                self.shutdown_token.cancelled().await;
+                return Err(PollWfError::ShutDown);
+            }
+        };
+        let r = workflows.next_workflow_activation().await;
+        if let Ok(act) = r.as_ref() {
+            self.task_registry.activation_issued(act);
+        }
         // In the event workflows are shutdown, begin shutdown of everything else, since that's
         // about to happen anyway. Tell the local activity manager that, so that it can know to
         // cancel any remaining outstanding LAs and shutdown.
@@ -581,12 +834,22 @@ impl Worker {
 
     #[instrument(skip(self, completion),
                  fields(completion=%&completion, run_id=%completion.run_id, workflow_id,
-                        task_queue=%self.config.task_queue))]
+                        namespace=%self.config.namespace, task_queue=%self.config.task_queue))]
     pub(crate) async fn complete_workflow_activation(
         &self,
         completion: WorkflowActivationCompletion,
     ) -> Result<(), CompleteWfError> {
-        self.workflows
+        self.task_registry.activation_completed(&completion.run_id);
+        let workflows = match self.workflows.as_ref() {
+            Some(workflows) => workflows,
+            None => {
+                return Err(CompleteWfError::MalformedWorkflowCompletion {
+                    reason: "This worker does not process workflows".to_string(),
+                    run_id: completion.run_id,
+                })
+            }
+        };
+        workflows
             .activation_completed(
                 completion,
                 false,
@@ -605,7 +868,9 @@ impl Worker {
         message: impl Into<String>,
         reason: EvictionReason,
     ) {
-        self.workflows.request_eviction(run_id, message, reason);
+        if let Some(workflows) = self.workflows.as_ref() {
+            workflows.request_eviction(run_id, message, reason);
+        }
     }
 
     /// Sets a function to be called at the end of each activation completion
@@ -636,7 +901,9 @@ impl Worker {
     }
 
     fn notify_local_result(&self, run_id: &str, res: LocalResolution) {
-        self.workflows.notify_of_local_result(run_id, res);
+        if let Some(workflows) = self.workflows.as_ref() {
+            workflows.notify_of_local_result(run_id, res);
+        }
     }
 }
 
@@ -650,7 +917,7 @@ fn build_wf_basics(
     config: &mut WorkerConfig,
     metrics: MetricsContext,
     shutdown_token: CancellationToken,
-    server_capabilities: get_system_info_response::Capabilities,
+    server_capabilities: ServerCapabilitiesFn,
 ) -> WorkflowBasics {
     WorkflowBasics {
         max_cached_workflows: config.max_cached_workflows,
@@ -660,12 +927,49 @@ fn build_wf_basics(
         task_queue: config.task_queue.clone(),
         ignore_evicts_on_shutdown: config.ignore_evicts_on_shutdown,
         fetching_concurrency: config.fetching_concurrency,
+        activation_shard_count: config.activation_shard_count,
+        sdk_name_and_version: sdk_name_and_version(config),
+        registered_workflow_types: config.registered_workflow_types.clone(),
+        registered_activity_types: config.registered_activity_types.clone(),
         server_capabilities,
+        run_state_dump: config.run_state_dump.clone(),
+        payload_size_limits: PayloadSizeLimits {
+            warning_threshold: config.payload_size_warning_threshold,
+            limit: config.payload_size_limit,
+        },
+        activation_deadline: config.workflow_activation_deadline,
         #[cfg(feature = "save_wf_inputs")]
         wf_state_inputs: config.wf_state_inputs.take(),
     }
 }
 
+/// The SDK name and version to report in workflow task completions. Lang's, if configured, with
+/// core's version appended as build metadata - otherwise core's own.
+fn sdk_name_and_version(config: &WorkerConfig) -> (String, String) {
+    const CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
+    match (&config.sdk_name, &config.sdk_version) {
+        (Some(name), Some(version)) => (name.clone(), format!("{version}+core.{CORE_VERSION}")),
+        _ => ("temporal-core".to_string(), CORE_VERSION.to_string()),
+    }
+}
+
+/// Runs a [TaskInterceptor](temporal_sdk_core_api::worker::TaskInterceptor) hook on a task,
+/// reverting any change it makes to the field core identifies the task by. Core's bookkeeping is
+/// keyed on it, so a changed id would orphan the task.
+fn intercept_keeping_id<T, I: Clone + PartialEq>(
+    task: &mut T,
+    id: impl Fn(&mut T) -> &mut I,
+    hook: impl FnOnce(&mut T),
+) {
+    let orig_id = id(task).clone();
+    hook(task);
+    let new_id = id(task);
+    if *new_id != orig_id {
+        warn!("Task interceptor changed a task's run id or task token. The change was reverted.");
+        *new_id = orig_id;
+    }
+}
+
 pub(crate) enum TaskPollers {
     Real,
     #[cfg(test)]
@@ -682,6 +986,7 @@ mod tests {
         advance_fut, test_help::test_worker_cfg, worker::client::mocks::mock_workflow_client,
     };
     use futures::FutureExt;
+    use temporal_sdk_core_api::worker::PollerBehavior;
 
     use temporal_sdk_core_protos::temporal::api::workflowservice::v1::PollActivityTaskQueueResponse;
 
@@ -746,4 +1051,146 @@ mod tests {
             .build()
             .is_err());
     }
+
+    #[test]
+    fn nonsticky_poll_ratio_adjusts_split() {
+        let cfg = test_worker_cfg()
+            .max_concurrent_wft_polls(10_usize)
+            .nonsticky_to_sticky_poll_ratio(0.5)
+            .build()
+            .unwrap();
+        assert_eq!(cfg.max_nonsticky_polls(), 5);
+        assert_eq!(cfg.max_sticky_polls(), 5);
+        for bad_ratio in [0.0, -0.5, 1.5, f32::NAN] {
+            assert!(test_worker_cfg()
+                .nonsticky_to_sticky_poll_ratio(bad_ratio)
+                .build()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn invalid_task_queue_activity_rate_is_err() {
+        for bad_rate in [0.0, -1.0, f64::NAN] {
+            assert!(test_worker_cfg()
+                .max_task_queue_activities_per_second(bad_rate)
+                .build()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn activity_only_worker_rejects_workflow_options() {
+        assert!(test_worker_cfg().no_workflows(true).build().is_ok());
+        assert!(test_worker_cfg()
+            .no_workflows(true)
+            .no_remote_activities(true)
+            .build()
+            .is_err());
+        assert!(test_worker_cfg()
+            .no_workflows(true)
+            .max_cached_workflows(10_usize)
+            .max_outstanding_workflow_tasks(10_usize)
+            .build()
+            .is_err());
+        assert!(test_worker_cfg()
+            .no_workflows(true)
+            .activation_shard_count(4_u32)
+            .build()
+            .is_err());
+        // Payload size checks also apply to activity completions
+        assert!(test_worker_cfg()
+            .no_workflows(true)
+            .payload_size_warning_threshold(Some(50))
+            .payload_size_limit(Some(100))
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn workflow_only_worker_ignores_activity_polling_options() {
+        assert!(test_worker_cfg().no_remote_activities(true).build().is_ok());
+        // Only warned about, since they're harmless
+        assert!(test_worker_cfg()
+            .no_remote_activities(true)
+            .activity_task_poller_behavior(PollerBehavior::SimpleMaximum(5))
+            .build()
+            .is_ok());
+        assert!(test_worker_cfg()
+            .no_remote_activities(true)
+            .max_task_queue_activities_per_second(10.0)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn sticky_queue_name_override() {
+        let cfg = test_worker_cfg()
+            .max_cached_workflows(5_usize)
+            .sticky_queue_name_override(Some("my-sticky-q".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            crate::sticky_q_name_for_worker("ident", &cfg).as_deref(),
+            Some("my-sticky-q")
+        );
+        let cfg = test_worker_cfg()
+            .sticky_queue_name_override(Some("my-sticky-q".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(crate::sticky_q_name_for_worker("ident", &cfg), None);
+        let task_queue = cfg.task_queue.clone();
+        assert!(test_worker_cfg()
+            .sticky_queue_name_override(Some(task_queue))
+            .build()
+            .is_err());
+        assert!(test_worker_cfg()
+            .sticky_queue_schedule_to_start_timeout(std::time::Duration::ZERO)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn autoscaling_poll_bounds_split_between_queues() {
+        let cfg = test_worker_cfg()
+            .workflow_task_poller_behavior(PollerBehavior::Autoscaling {
+                minimum: 2,
+                maximum: 20,
+                initial: 5,
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            cfg.nonsticky_wft_poller_behavior(),
+            PollerBehavior::Autoscaling {
+                minimum: 1,
+                maximum: 4,
+                initial: 1,
+            }
+        );
+        assert_eq!(
+            cfg.sticky_wft_poller_behavior(),
+            PollerBehavior::Autoscaling {
+                minimum: 1,
+                maximum: 16,
+                initial: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_autoscaling_bounds_is_err() {
+        assert!(test_worker_cfg()
+            .activity_task_poller_behavior(PollerBehavior::Autoscaling {
+                minimum: 3,
+                maximum: 10,
+                initial: 2,
+            })
+            .build()
+            .is_err());
+        assert!(test_worker_cfg()
+            .workflow_task_poller_behavior(PollerBehavior::SimpleMaximum(0))
+            .build()
+            .is_err());
+    }
 }
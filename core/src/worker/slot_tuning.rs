@@ -0,0 +1,349 @@
+//! A [SlotSupplier] which decides how many slots to hand out based on how heavily the host's CPU
+//! and memory are being used, for users who would rather not hand-tune the `max_outstanding_*`
+//! worker options.
+
+use parking_lot::{Mutex, MutexGuard};
+use std::{
+    fmt::Debug,
+    fs,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use temporal_sdk_core_api::worker::SlotSupplier;
+use tokio::sync::Notify;
+
+/// How often utilization is re-sampled, and how often a caller waiting on a slot re-checks it
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Utilization levels a [ResourceBasedSlotSupplier] tries to keep the system below
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceBasedTargets {
+    /// Fraction of system memory, in `[0, 1]`, at or above which no slots beyond the minimum are
+    /// handed out
+    pub target_mem_usage: f64,
+    /// Fraction of total CPU time, in `[0, 1]`, at or above which no slots beyond the minimum are
+    /// handed out
+    pub target_cpu_usage: f64,
+}
+
+/// Bounds on how many slots a [ResourceBasedSlotSupplier] hands out, regardless of utilization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceSlotOptions {
+    /// This many slots are always available, even if utilization is above target
+    pub min_slots: usize,
+    /// No more than this many slots are ever handed out. When used for workflow task slots, this
+    /// should not exceed [crate::WorkerConfig::max_cached_workflows].
+    pub max_slots: usize,
+    /// Once past the minimum, slots are handed out no more often than this. Tasks generally take a
+    /// moment to start consuming resources, so this keeps a burst of tasks from being accepted
+    /// before utilization has had a chance to reflect the ones already running.
+    pub ramp_throttle: Duration,
+}
+
+impl ResourceSlotOptions {
+    /// Reasonable bounds for workflow task slots. Workflow tasks are typically short and cheap, so
+    /// they are not throttled.
+    pub fn workflow_defaults() -> Self {
+        Self {
+            min_slots: 2,
+            max_slots: 500,
+            ramp_throttle: Duration::ZERO,
+        }
+    }
+
+    /// Reasonable bounds for activity and local activity slots
+    pub fn activity_defaults() -> Self {
+        Self {
+            min_slots: 1,
+            max_slots: 1000,
+            ramp_throttle: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Reports how heavily system resources are being used. The default, [ProcResourceInfo], reads
+/// host-wide utilization on Linux. Implement this to measure something else, ex: a container's
+/// cgroup limits.
+pub trait SystemResourceInfo: Debug + Send + Sync {
+    /// Fraction of memory currently in use, in `[0, 1]`
+    fn used_mem_ratio(&self) -> f64;
+    /// Fraction of CPU time currently in use, in `[0, 1]`
+    fn used_cpu_ratio(&self) -> f64;
+}
+
+/// Samples host-wide utilization from `/proc`. Only supported on Linux - elsewhere, utilization is
+/// always reported as zero, so only the [ResourceSlotOptions] bounds apply.
+#[derive(Debug, Default)]
+pub struct ProcResourceInfo {
+    samples: Mutex<ProcSamples>,
+}
+
+#[derive(Debug, Default)]
+struct ProcSamples {
+    taken_at: Option<Instant>,
+    mem_ratio: f64,
+    cpu_ratio: f64,
+    /// Busy and total cpu time as of the last sample, since `/proc/stat` is cumulative
+    last_cpu_times: Option<(u64, u64)>,
+}
+
+impl ProcResourceInfo {
+    fn refresh(&self) -> MutexGuard<'_, ProcSamples> {
+        let mut samples = self.samples.lock();
+        if samples
+            .taken_at
+            .map(|t| t.elapsed() < SAMPLE_INTERVAL)
+            .unwrap_or_default()
+        {
+            return samples;
+        }
+        samples.taken_at = Some(Instant::now());
+        if let Some(ratio) = fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|s| parse_mem_ratio(&s))
+        {
+            samples.mem_ratio = ratio;
+        }
+        if let Some((busy, total)) = fs::read_to_string("/proc/stat")
+            .ok()
+            .and_then(|s| parse_cpu_times(&s))
+        {
+            if let Some((last_busy, last_total)) = samples.last_cpu_times {
+                let total_delta = total.saturating_sub(last_total);
+                if total_delta > 0 {
+                    samples.cpu_ratio = busy.saturating_sub(last_busy) as f64 / total_delta as f64;
+                }
+            }
+            samples.last_cpu_times = Some((busy, total));
+        }
+        samples
+    }
+}
+
+impl SystemResourceInfo for ProcResourceInfo {
+    fn used_mem_ratio(&self) -> f64 {
+        self.refresh().mem_ratio
+    }
+
+    fn used_cpu_ratio(&self) -> f64 {
+        self.refresh().cpu_ratio
+    }
+}
+
+fn parse_mem_ratio(meminfo: &str) -> Option<f64> {
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|kb| kb.parse::<u64>().ok())
+    };
+    let total = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+    if total == 0 {
+        return None;
+    }
+    Some(1.0 - available.min(total) as f64 / total as f64)
+}
+
+/// Returns the busy and total cpu time from the aggregate `cpu` line of `/proc/stat`
+fn parse_cpu_times(stat: &str) -> Option<(u64, u64)> {
+    let times: Vec<u64> = stat
+        .lines()
+        .find_map(|l| l.strip_prefix("cpu "))?
+        .split_whitespace()
+        .filter_map(|t| t.parse().ok())
+        .collect();
+    // Fields are user, nice, system, idle, iowait, irq, softirq, steal, guest, guest_nice. Idle
+    // and iowait are not busy time, and guest time is already included in user and nice time.
+    let idle = times.get(3)? + times.get(4).copied().unwrap_or_default();
+    let total = times.iter().take(8).sum::<u64>();
+    Some((total.saturating_sub(idle), total))
+}
+
+/// Hands out slots while the system's CPU and memory utilization are below the configured
+/// [ResourceBasedTargets], within the bounds set by [ResourceSlotOptions]. A separate instance
+/// should be used for each kind of slot, but they may share one [SystemResourceInfo].
+#[derive(Debug)]
+pub struct ResourceBasedSlotSupplier {
+    targets: ResourceBasedTargets,
+    options: ResourceSlotOptions,
+    resource_info: Arc<dyn SystemResourceInfo>,
+    issued: Mutex<IssuedSlots>,
+    slot_released: Notify,
+}
+
+#[derive(Debug, Default)]
+struct IssuedSlots {
+    outstanding: usize,
+    last_issued_at: Option<Instant>,
+}
+
+impl ResourceBasedSlotSupplier {
+    /// Create a supplier which samples host-wide utilization using [ProcResourceInfo]
+    pub fn new(targets: ResourceBasedTargets, options: ResourceSlotOptions) -> Self {
+        Self::with_resource_info(targets, options, Arc::new(ProcResourceInfo::default()))
+    }
+
+    /// Create a supplier which measures utilization with the provided [SystemResourceInfo]
+    pub fn with_resource_info(
+        targets: ResourceBasedTargets,
+        options: ResourceSlotOptions,
+        resource_info: Arc<dyn SystemResourceInfo>,
+    ) -> Self {
+        Self {
+            targets,
+            options,
+            resource_info,
+            issued: Default::default(),
+            slot_released: Notify::new(),
+        }
+    }
+
+    fn under_target(&self) -> bool {
+        self.resource_info.used_mem_ratio() < self.targets.target_mem_usage
+            && self.resource_info.used_cpu_ratio() < self.targets.target_cpu_usage
+    }
+
+    fn can_issue(&self, issued: &IssuedSlots, under_target: bool) -> bool {
+        if issued.outstanding < self.options.min_slots {
+            return true;
+        }
+        if issued.outstanding >= self.options.max_slots {
+            return false;
+        }
+        let throttled = issued
+            .last_issued_at
+            .map(|t| t.elapsed() < self.options.ramp_throttle)
+            .unwrap_or_default();
+        !throttled && under_target
+    }
+}
+
+#[async_trait::async_trait]
+impl SlotSupplier for ResourceBasedSlotSupplier {
+    async fn reserve_slot(&self) {
+        loop {
+            // Register interest before checking, so a release between the check and the wait
+            // isn't missed
+            let released = self.slot_released.notified();
+            if self.try_reserve_slot() {
+                return;
+            }
+            // Utilization may drop without any slot being released, so re-check periodically
+            tokio::select! {
+                _ = released => {}
+                _ = tokio::time::sleep(SAMPLE_INTERVAL) => {}
+            }
+        }
+    }
+
+    fn try_reserve_slot(&self) -> bool {
+        // Sampling utilization may mean reading from `/proc`, which shouldn't hold up releases
+        let under_target = self.under_target();
+        let mut issued = self.issued.lock();
+        if !self.can_issue(&issued, under_target) {
+            return false;
+        }
+        issued.outstanding += 1;
+        issued.last_issued_at = Some(Instant::now());
+        true
+    }
+
+    fn mark_slot_used(&self) {}
+
+    fn release_slot(&self) {
+        {
+            let mut issued = self.issued.lock();
+            issued.outstanding = issued.outstanding.saturating_sub(1);
+        }
+        self.slot_released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Debug, Default)]
+    struct FakeResourceInfo {
+        mem_ratio_bits: AtomicU64,
+        sampled: Notify,
+    }
+    impl FakeResourceInfo {
+        fn set_mem_ratio(&self, ratio: f64) {
+            self.mem_ratio_bits.store(ratio.to_bits(), Ordering::SeqCst);
+        }
+    }
+    impl SystemResourceInfo for FakeResourceInfo {
+        fn used_mem_ratio(&self) -> f64 {
+            self.sampled.notify_waiters();
+            f64::from_bits(self.mem_ratio_bits.load(Ordering::SeqCst))
+        }
+        fn used_cpu_ratio(&self) -> f64 {
+            0.0
+        }
+    }
+
+    fn supplier(info: Arc<FakeResourceInfo>) -> ResourceBasedSlotSupplier {
+        ResourceBasedSlotSupplier::with_resource_info(
+            ResourceBasedTargets {
+                target_mem_usage: 0.8,
+                target_cpu_usage: 0.9,
+            },
+            ResourceSlotOptions {
+                min_slots: 1,
+                max_slots: 3,
+                ramp_throttle: Duration::ZERO,
+            },
+            info,
+        )
+    }
+
+    #[test]
+    fn issues_slots_within_bounds_while_under_target() {
+        let info = Arc::new(FakeResourceInfo::default());
+        let slots = supplier(info.clone());
+        info.set_mem_ratio(0.95);
+        // The minimum is always available
+        assert!(slots.try_reserve_slot());
+        assert!(!slots.try_reserve_slot());
+        info.set_mem_ratio(0.5);
+        assert!(slots.try_reserve_slot());
+        assert!(slots.try_reserve_slot());
+        // Never more than the maximum
+        assert!(!slots.try_reserve_slot());
+        slots.release_slot();
+        assert!(slots.try_reserve_slot());
+    }
+
+    #[tokio::test]
+    async fn waiting_reservation_proceeds_once_slot_released() {
+        let info = Arc::new(FakeResourceInfo::default());
+        info.set_mem_ratio(0.95);
+        let slots = Arc::new(supplier(info.clone()));
+        slots.reserve_slot().await;
+        let sampled = info.sampled.notified();
+        let waiter = tokio::spawn({
+            let slots = slots.clone();
+            async move { slots.reserve_slot().await }
+        });
+        // Once the waiter has seen utilization is over target, it can only proceed on a release
+        sampled.await;
+        assert!(!waiter.is_finished());
+        slots.release_slot();
+        waiter.await.unwrap();
+    }
+
+    #[test]
+    fn parses_proc_files() {
+        let meminfo = "MemTotal: 16000000 kB\nMemFree: 1000 kB\nMemAvailable: 4000000 kB\n";
+        assert_eq!(parse_mem_ratio(meminfo), Some(0.75));
+        let stat = "cpu  10 0 10 70 10 0 0 0 0 0\ncpu0 10 0 10 70 10 0 0 0 0 0\n";
+        assert_eq!(parse_cpu_times(stat), Some((20, 100)));
+        // Guest time is part of user time, so isn't counted twice
+        let stat = "cpu  10 0 10 70 10 0 0 0 5 0\n";
+        assert_eq!(parse_cpu_times(stat), Some((20, 100)));
+    }
+}
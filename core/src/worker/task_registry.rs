@@ -0,0 +1,216 @@
+//! Keeps track of every activity task and workflow activation which has been handed to lang but
+//! not yet completed, along with the run it belongs to. Periodically scans for entries which have
+//! been outstanding far longer than they ever should be, which almost always means lang (or the
+//! bridge between it and core) dropped a completion on the floor.
+
+use crate::telemetry::metrics::{leaked_task_kind, MetricsContext};
+use dashmap::DashMap;
+use std::time::Duration;
+use temporal_sdk_core_protos::{
+    coresdk::{
+        activity_task::{activity_task, ActivityTask},
+        workflow_activation::WorkflowActivation,
+    },
+    TaskToken,
+};
+use tokio::time::Instant;
+
+/// How often the worker scans the registry for leaked tasks
+pub(crate) const LEAK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// An activity is considered leaked once it has been outstanding for this many multiples of its
+/// start-to-close (or schedule-to-close, if unset) timeout. The server will have long since timed
+/// it out by then.
+const ACTIVITY_LEAK_TIMEOUT_MULTIPLIER: u32 = 2;
+/// Workflow activations have no timeout of their own, but workflow code should never block for
+/// anywhere near this long, and the workflow task will have timed out well before.
+const ACTIVATION_LEAK_THRESHOLD: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum OutstandingKey {
+    Activity(TaskToken),
+    Activation { run_id: String },
+}
+
+#[derive(Debug)]
+struct OutstandingEntry {
+    run_id: String,
+    issued_at: Instant,
+    /// If unset, the entry is tracked but never considered leaked
+    leak_threshold: Option<Duration>,
+    /// Each leak is only reported once
+    reported_leaked: bool,
+}
+
+/// Correlates outstanding activity task tokens and workflow activations to the runs they belong to
+pub(crate) struct OutstandingTaskRegistry {
+    entries: DashMap<OutstandingKey, OutstandingEntry>,
+    metrics: MetricsContext,
+}
+
+impl OutstandingTaskRegistry {
+    pub(crate) fn new(metrics: MetricsContext) -> Self {
+        Self {
+            entries: DashMap::new(),
+            metrics,
+        }
+    }
+
+    /// Record an activity task being handed to lang. Cancels are ignored, since they refer to a
+    /// task which is already being tracked.
+    pub(crate) fn activity_issued(&self, task: &ActivityTask) {
+        if let Some(activity_task::Variant::Start(start)) = task.variant.as_ref() {
+            let timeout = start
+                .start_to_close_timeout
+                .clone()
+                .or_else(|| start.schedule_to_close_timeout.clone())
+                .and_then(|d| Duration::try_from(d).ok())
+                .filter(|d| !d.is_zero());
+            self.entries.insert(
+                OutstandingKey::Activity(TaskToken(task.task_token.clone())),
+                OutstandingEntry {
+                    run_id: start
+                        .workflow_execution
+                        .as_ref()
+                        .map(|we| we.run_id.clone())
+                        .unwrap_or_default(),
+                    issued_at: Instant::now(),
+                    leak_threshold: timeout.map(|t| t * ACTIVITY_LEAK_TIMEOUT_MULTIPLIER),
+                    reported_leaked: false,
+                },
+            );
+        }
+    }
+
+    pub(crate) fn activity_completed(&self, task_token: &TaskToken) {
+        self.remove(&OutstandingKey::Activity(task_token.clone()));
+    }
+
+    /// Record a workflow activation being handed to lang
+    pub(crate) fn activation_issued(&self, activation: &WorkflowActivation) {
+        self.entries.insert(
+            OutstandingKey::Activation {
+                run_id: activation.run_id.clone(),
+            },
+            OutstandingEntry {
+                run_id: activation.run_id.clone(),
+                issued_at: Instant::now(),
+                leak_threshold: Some(ACTIVATION_LEAK_THRESHOLD),
+                reported_leaked: false,
+            },
+        );
+    }
+
+    pub(crate) fn activation_completed(&self, run_id: &str) {
+        self.remove(&OutstandingKey::Activation {
+            run_id: run_id.to_string(),
+        });
+    }
+
+    /// Number of activity tasks and activations currently outstanding for the provided run
+    #[cfg(test)]
+    pub(crate) fn outstanding_for_run(&self, run_id: &str) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.value().run_id == run_id)
+            .count()
+    }
+
+    /// Scan for entries which have been outstanding longer than their leak threshold, reporting
+    /// any which have not already been reported. Returns the number of newly detected leaks.
+    pub(crate) fn check_for_leaks(&self) -> usize {
+        let mut newly_leaked = 0;
+        for mut entry in self.entries.iter_mut() {
+            let elapsed = entry.issued_at.elapsed();
+            let past_threshold = entry
+                .leak_threshold
+                .map(|t| elapsed > t)
+                .unwrap_or_default();
+            if !past_threshold || entry.reported_leaked {
+                continue;
+            }
+            entry.reported_leaked = true;
+            newly_leaked += 1;
+            let kind = match entry.key() {
+                OutstandingKey::Activity(tt) => {
+                    warn!(task_token=%tt, run_id=%entry.run_id, outstanding_for=?elapsed,
+                          "Activity task has been outstanding far longer than its timeout. Lang \
+                           may have dropped its completion.");
+                    "activity"
+                }
+                OutstandingKey::Activation { .. } => {
+                    warn!(run_id=%entry.run_id, outstanding_for=?elapsed,
+                          "Workflow activation has been outstanding for an unreasonably long \
+                           time. Lang may have dropped its completion.");
+                    "workflow_activation"
+                }
+            };
+            self.metrics
+                .with_new_attrs([leaked_task_kind(kind)])
+                .task_leaked();
+        }
+        newly_leaked
+    }
+
+    fn remove(&self, key: &OutstandingKey) {
+        if let Some((_, entry)) = self.entries.remove(key) {
+            if entry.reported_leaked {
+                info!(run_id=%entry.run_id, outstanding_for=?entry.issued_at.elapsed(),
+                      "Task previously reported as leaked was eventually completed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temporal_sdk_core_protos::{
+        coresdk::activity_task::Start, temporal::api::common::v1::WorkflowExecution,
+    };
+
+    fn activity_start(tt: &[u8], run_id: &str, start_to_close: Duration) -> ActivityTask {
+        ActivityTask {
+            task_token: tt.to_vec(),
+            variant: Some(activity_task::Variant::Start(Start {
+                workflow_execution: Some(WorkflowExecution {
+                    workflow_id: "wfid".to_string(),
+                    run_id: run_id.to_string(),
+                }),
+                start_to_close_timeout: Some(start_to_close.try_into().unwrap()),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn correlates_tasks_to_runs() {
+        let reg = OutstandingTaskRegistry::new(MetricsContext::no_op());
+        reg.activity_issued(&activity_start(b"a", "run1", Duration::from_secs(10)));
+        reg.activity_issued(&activity_start(b"b", "run2", Duration::from_secs(10)));
+        reg.activation_issued(&WorkflowActivation {
+            run_id: "run1".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(reg.outstanding_for_run("run1"), 2);
+        assert_eq!(reg.outstanding_for_run("run2"), 1);
+
+        reg.activity_completed(&TaskToken(b"a".to_vec()));
+        reg.activation_completed("run1");
+        assert_eq!(reg.outstanding_for_run("run1"), 0);
+        assert_eq!(reg.outstanding_for_run("run2"), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn leaks_reported_once() {
+        let reg = OutstandingTaskRegistry::new(MetricsContext::no_op());
+        reg.activity_issued(&activity_start(b"a", "run1", Duration::from_millis(1)));
+        reg.activity_issued(&activity_start(b"b", "run1", Duration::from_secs(100)));
+        tokio::time::advance(Duration::from_millis(10)).await;
+        assert_eq!(reg.check_for_leaks(), 1);
+        assert_eq!(reg.check_for_leaks(), 0);
+        // Leaked tasks remain tracked until they are actually completed
+        assert_eq!(reg.outstanding_for_run("run1"), 2);
+        reg.activity_completed(&TaskToken(b"a".to_vec()));
+        assert_eq!(reg.outstanding_for_run("run1"), 1);
+    }
+}
@@ -30,6 +30,17 @@ lazy_static::lazy_static! {
         = tonic::Status::unknown("Received an empty workflow task with no queries or history");
 }
 
+/// Upper bound on the number of events the paginator hands out in a single [HistoryUpdate] (beyond
+/// however many are needed to complete the WFT sequence which crosses it). Pages from server - or,
+/// during replay, entire histories - can contain far more events than are needed to make progress.
+/// Complete WFT sequences beyond this many events are left buffered in the paginator and handed out
+/// as later updates, rather than being copied into one huge update which the machines then
+/// repeatedly drain from the front of.
+///
+/// Unless configured otherwise, this is also the page size requested when fetching history, so that
+/// no more than this many events are held in memory per page either.
+pub(crate) const MAX_EVENTS_PER_UPDATE: usize = 1000;
+
 /// Represents one or more complete WFT sequences. History events are expected to be consumed from
 /// it and applied to the state machines via [HistoryUpdate::take_next_wft_sequence]
 #[cfg_attr(
@@ -246,9 +257,19 @@ impl HistoryPaginator {
     ///
     /// If there are insufficient events to constitute two WFTs, then we will fetch pages until
     /// we have two, or until we are at the end of history.
+    ///
+    /// Updates are bounded to roughly [MAX_EVENTS_PER_UPDATE] events. If the buffered events
+    /// already exceed that, they are used without fetching another page.
     pub(crate) async fn extract_next_update(&mut self) -> Result<HistoryUpdate, tonic::Status> {
+        let mut fetch_page = self.event_queue.len() < MAX_EVENTS_PER_UPDATE;
         loop {
-            let no_next_page = !self.get_next_page().await?;
+            let no_next_page = if fetch_page {
+                !self.get_next_page().await?
+            } else {
+                matches!(self.next_page_token, NextPageToken::Done)
+            };
+            // If we go around again, it's because the buffered events weren't enough
+            fetch_page = true;
             let current_events = mem::take(&mut self.event_queue);
             let seen_enough_events = current_events
                 .back()
@@ -300,11 +321,12 @@ impl HistoryPaginator {
             // We only *really* have the last WFT if the events go all the way up to at least the
             // WFT started event id. Otherwise we somehow still have partial history.
             let no_more = matches!(self.next_page_token, NextPageToken::Done) && seen_enough_events;
-            let (update, extra) = HistoryUpdate::from_events(
+            let (update, extra) = HistoryUpdate::from_events_bounded(
                 current_events,
                 self.previous_wft_started_id,
                 self.wft_started_event_id,
                 no_more,
+                MAX_EVENTS_PER_UPDATE,
             );
 
             // If there are potentially more events and we haven't extracted two WFTs yet, keep
@@ -468,16 +490,22 @@ impl HistoryUpdate {
     fn assert_contiguous(&self) -> bool {
         use crate::abstractions::dbg_panic;
 
-        for win in self.events.as_slice().windows(2) {
-            if let &[e1, e2] = &win {
-                if e2.event_id != e1.event_id + 1 {
-                    dbg_panic!("HistoryUpdate isn't contiguous! {:?} -> {:?}", e1, e2);
-                }
-            }
+        if let Err(e) = self.validate_event_ids() {
+            dbg_panic!("HistoryUpdate isn't contiguous! {}", e);
         }
         true
     }
 
+    /// Verifies that the ids of the events in this update increase by exactly one from each event
+    /// to the next. Server-provided history which fails this check is corrupt, and applying it
+    /// would only result in confusing errors further down the line.
+    pub fn validate_event_ids(&self) -> Result<(), EventIdSequenceError> {
+        match self.events.first() {
+            Some(first) => validate_event_id_sequence(first.event_id - 1, &self.events),
+            None => Ok(()),
+        }
+    }
+
     /// Create an instance of an update directly from events. If the passed in event iterator has a
     /// partial WFT sequence at the end, all events after the last complete WFT sequence (ending
     /// with WFT started) are returned back to the caller, since the history update only works in
@@ -488,6 +516,29 @@ impl HistoryUpdate {
         wft_started_id: i64,
         has_last_wft: bool,
     ) -> (Self, Vec<HistoryEvent>)
+    where
+        <I as IntoIterator>::IntoIter: Send + 'static,
+    {
+        Self::from_events_bounded(
+            events,
+            previous_wft_started_id,
+            wft_started_id,
+            has_last_wft,
+            usize::MAX,
+        )
+    }
+
+    /// Like [Self::from_events], but once the update holds at least two WFT sequences and
+    /// `max_events` or more events, no further sequences are added to it. Those are returned to
+    /// the caller along with any partial sequence, and the update will not claim to contain the
+    /// last WFT even if `has_last_wft` was set.
+    pub(crate) fn from_events_bounded<I: IntoIterator<Item = HistoryEvent>>(
+        events: I,
+        previous_wft_started_id: i64,
+        wft_started_id: i64,
+        has_last_wft: bool,
+        max_events: usize,
+    ) -> (Self, Vec<HistoryEvent>)
     where
         <I as IntoIterator>::IntoIter: Send + 'static,
     {
@@ -523,8 +574,26 @@ impl HistoryUpdate {
             };
         }
         let mut wft_count = 0;
+        let mut truncated = false;
+        let (mut seq_start_ix, mut seq_from_id) = (0, previous_wft_started_id);
         while let NextWFTSeqEndIndex::Complete(next_end_ix) = last_end {
             wft_count += 1;
+            if wft_count >= 2 && next_end_ix + 1 >= max_events && next_end_ix + 1 < all_events.len()
+            {
+                // Only stop here if the sequence is still seen as complete once the events after
+                // it (which may have been used to look ahead) are no longer in the update.
+                let seq_end = find_end_index_of_next_wft_seq(
+                    &all_events[seq_start_ix..=next_end_ix],
+                    seq_from_id,
+                    false,
+                );
+                if matches!(seq_end, NextWFTSeqEndIndex::Complete(ix)
+                            if ix + seq_start_ix == next_end_ix)
+                {
+                    truncated = true;
+                    break;
+                }
+            }
             let next_end_eid = all_events[next_end_ix].event_id;
             // To save skipping all events at the front of this slice, only pass the relevant
             // portion, but that means the returned index must be adjusted, hence the addition.
@@ -537,10 +606,12 @@ impl HistoryUpdate {
             if matches!(next_end, NextWFTSeqEndIndex::Incomplete(_)) {
                 break;
             }
+            (seq_start_ix, seq_from_id) = (next_end_ix, next_end_eid);
             last_end = next_end;
         }
         // If we have the last WFT, there's no point in there being "remaining" events, because
         // they must be considered part of the last sequence
+        let has_last_wft = has_last_wft && !truncated;
         let remaining_events = if all_events.is_empty() || has_last_wft {
             vec![]
         } else {
@@ -678,6 +749,36 @@ impl HistoryUpdate {
     }
 }
 
+/// History contained an event whose id did not immediately follow the id of the event before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "History event ids must increase by exactly one, but event {event_id} follows event \
+     {previous_event_id}"
+)]
+pub struct EventIdSequenceError {
+    pub previous_event_id: i64,
+    pub event_id: i64,
+}
+
+/// Verifies that the provided events immediately follow the event with id `previous_event_id`,
+/// and that their ids increase by exactly one from each event to the next
+pub(crate) fn validate_event_id_sequence(
+    previous_event_id: i64,
+    events: &[HistoryEvent],
+) -> Result<(), EventIdSequenceError> {
+    let mut previous_event_id = previous_event_id;
+    for event in events {
+        if event.event_id != previous_event_id + 1 {
+            return Err(EventIdSequenceError {
+                previous_event_id,
+                event_id: event.event_id,
+            });
+        }
+        previous_event_id = event.event_id;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Copy, Clone)]
 enum NextWFTSeqEndIndex {
     /// The next WFT sequence is completely contained within the passed-in iterator
@@ -843,6 +944,37 @@ pub mod tests {
         assert_eq!(seq_2.last().unwrap().event_id, 8);
     }
 
+    #[test]
+    fn detects_event_id_gaps_and_reordering() {
+        let timer_hist = canned_histories::single_timer("t");
+        let events = timer_hist
+            .get_full_history_info()
+            .unwrap()
+            .events()
+            .to_vec();
+        assert_eq!(timer_hist.as_history_update().validate_event_ids(), Ok(()));
+
+        let mut gap = events.clone();
+        gap.remove(4);
+        assert_eq!(
+            HistoryUpdate::new_from_events(gap, 0, 3).validate_event_ids(),
+            Err(EventIdSequenceError {
+                previous_event_id: 4,
+                event_id: 6
+            })
+        );
+
+        let mut reordered = events;
+        reordered.swap(2, 3);
+        assert_eq!(
+            HistoryUpdate::new_from_events(reordered, 0, 3).validate_event_ids(),
+            Err(EventIdSequenceError {
+                previous_event_id: 2,
+                event_id: 4
+            })
+        );
+    }
+
     #[test]
     fn skips_wft_failed() {
         let failed_hist = canned_histories::workflow_fails_with_reset_after_timer("t", "runid");
@@ -1017,6 +1149,101 @@ pub mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn paginator_bounds_updates_from_large_pages() {
+        let wft_count = 600;
+        // The entire history arrives in the first page
+        let mut paginator =
+            paginator_setup(canned_histories::long_sequential_timers(wft_count), 10_000);
+        let mut update = paginator.extract_next_update().await.unwrap();
+        let mut num_updates = 1;
+        let mut last_event_id = 0;
+        let mut last_started_id = 0;
+        loop {
+            assert!(update.events.len() < MAX_EVENTS_PER_UPDATE + 5);
+            let seq = match update.take_next_wft_sequence(last_started_id) {
+                NextWFT::WFT(seq, _) => seq,
+                NextWFT::NeedFetch => {
+                    update = paginator.extract_next_update().await.unwrap();
+                    num_updates += 1;
+                    continue;
+                }
+                NextWFT::ReplayOver => break,
+            };
+            for e in &seq {
+                last_event_id += 1;
+                assert_eq!(e.event_id, last_event_id);
+            }
+            last_started_id = last_event_id;
+        }
+        assert_eq!(last_event_id as usize, (wft_count + 1) * 5);
+        assert!(num_updates > 1);
+    }
+
+    #[tokio::test]
+    async fn paginator_bounds_updates_from_fetched_pages_over_the_page_size() {
+        let wft_count = 600;
+        let hinfo = canned_histories::long_sequential_timers(wft_count)
+            .get_full_history_info()
+            .unwrap();
+        let wft_started = hinfo.workflow_task_started_event_id();
+        let full_hist = hinfo.into_events();
+        let initial_hist = full_hist[..10].to_vec();
+        let rest = full_hist[10..].to_vec();
+        assert!(rest.len() > MAX_EVENTS_PER_UPDATE);
+        let mut mock_client = mock_workflow_client();
+        // Server ignores the requested page size and sends everything left in one page, which
+        // must still be handed out in bounded updates without fetching it again
+        mock_client
+            .expect_get_workflow_execution_history()
+            .times(1)
+            .returning(move |_, _, _| {
+                Ok(GetWorkflowExecutionHistoryResponse {
+                    history: Some(History {
+                        events: rest.clone(),
+                    }),
+                    raw_history: vec![],
+                    next_page_token: vec![],
+                    archived: false,
+                })
+            });
+        let mut paginator = HistoryPaginator::new(
+            History {
+                events: initial_hist,
+            },
+            0,
+            wft_started,
+            "wfid".to_string(),
+            "runid".to_string(),
+            vec![1],
+            Arc::new(mock_client),
+        );
+
+        let mut update = paginator.extract_next_update().await.unwrap();
+        let mut num_updates = 1;
+        let mut last_event_id = 0;
+        let mut last_started_id = 0;
+        loop {
+            assert!(update.events.len() < MAX_EVENTS_PER_UPDATE + 5);
+            let seq = match update.take_next_wft_sequence(last_started_id) {
+                NextWFT::WFT(seq, _) => seq,
+                NextWFT::NeedFetch => {
+                    update = paginator.extract_next_update().await.unwrap();
+                    num_updates += 1;
+                    continue;
+                }
+                NextWFT::ReplayOver => break,
+            };
+            for e in &seq {
+                last_event_id += 1;
+                assert_eq!(e.event_id, last_event_id);
+            }
+            last_started_id = last_event_id;
+        }
+        assert_eq!(last_event_id as usize, (wft_count + 1) * 5);
+        assert!(num_updates > 2);
+    }
+
     #[tokio::test]
     async fn paginator_streams() {
         let wft_count = 10;
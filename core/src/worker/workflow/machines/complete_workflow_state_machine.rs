@@ -1,6 +1,6 @@
 use super::{
-    workflow_machines::MachineResponse, Cancellable, EventInfo, NewMachineWithCommand,
-    OnEventWrapper, WFMachinesAdapter, WFMachinesError,
+    is_continued_as_new_by, workflow_machines::MachineResponse, Cancellable, EventInfo,
+    NewMachineWithCommand, OnEventWrapper, WFMachinesAdapter, WFMachinesError,
 };
 use crate::worker::workflow::machines::HistEventData;
 use rustfsm::{fsm, StateMachine, TransitionResult};
@@ -9,11 +9,15 @@ use temporal_sdk_core_protos::{
     coresdk::workflow_commands::CompleteWorkflowExecution,
     temporal::api::{
         command::v1::Command,
-        enums::v1::{CommandType, EventType},
+        enums::v1::{CommandType, ContinueAsNewInitiator, EventType},
         history::v1::HistoryEvent,
     },
 };
 
+/// Completing a cron workflow starts its next run, which the server may record as a
+/// continue-as-new instead of a completion
+const COMPLETION_INITIATORS: &[ContinueAsNewInitiator] = &[ContinueAsNewInitiator::CronSchedule];
+
 fsm! {
     pub(super)
     name CompleteWorkflowMachine;
@@ -66,6 +70,11 @@ impl TryFrom<HistEventData> for CompleteWorkflowMachineEvents {
         let e = e.event;
         Ok(match e.event_type() {
             EventType::WorkflowExecutionCompleted => Self::WorkflowExecutionCompleted,
+            EventType::WorkflowExecutionContinuedAsNew
+                if is_continued_as_new_by(&e, COMPLETION_INITIATORS) =>
+            {
+                Self::WorkflowExecutionCompleted
+            }
             _ => {
                 return Err(WFMachinesError::Nondeterminism(format!(
                     "Complete workflow machine does not handle this event: {e}"
@@ -129,6 +138,7 @@ impl WFMachinesAdapter for CompleteWorkflowMachine {
 
     fn matches_event(&self, event: &HistoryEvent) -> bool {
         event.event_type() == EventType::WorkflowExecutionCompleted
+            || is_continued_as_new_by(event, COMPLETION_INITIATORS)
     }
 }
 
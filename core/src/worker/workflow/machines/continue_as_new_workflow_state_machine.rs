@@ -4,12 +4,14 @@ use super::{
 };
 use crate::worker::workflow::machines::HistEventData;
 use rustfsm::{fsm, StateMachine, TransitionResult};
-use std::convert::TryFrom;
+use std::{collections::HashMap, convert::TryFrom};
 use temporal_sdk_core_protos::{
     coresdk::workflow_commands::ContinueAsNewWorkflowExecution,
     temporal::api::{
         command::v1::Command,
+        common::v1::{Memo, Payload, RetryPolicy},
         enums::v1::{CommandType, EventType},
+        history::v1::WorkflowExecutionStartedEventAttributes,
     },
 };
 
@@ -30,7 +32,66 @@ fsm! {
 #[derive(Debug, derive_more::Display)]
 pub(super) enum ContinueAsNewWorkflowCommand {}
 
-pub(super) fn continue_as_new(attribs: ContinueAsNewWorkflowExecution) -> NewMachineWithCommand {
+/// Properties of the current run which are carried over to the next run on continue-as-new unless
+/// lang explicitly sets them. Seeded from the workflow execution started event and kept up to date
+/// as the workflow upserts search attributes or modifies its memo, so the new run sees the values
+/// as they are at the time of continuing, not as they were when this run started.
+#[derive(Debug, Default, Clone)]
+pub(super) struct InheritedRunProperties {
+    memo: HashMap<String, Payload>,
+    search_attributes: HashMap<String, Payload>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl InheritedRunProperties {
+    pub(super) fn new(attrs: &WorkflowExecutionStartedEventAttributes) -> Self {
+        Self {
+            memo: attrs.memo.clone().map(Into::into).unwrap_or_default(),
+            search_attributes: attrs
+                .search_attributes
+                .clone()
+                .map(Into::into)
+                .unwrap_or_default(),
+            retry_policy: attrs.retry_policy.clone(),
+        }
+    }
+
+    pub(super) fn upsert_search_attributes(&mut self, upserted: &HashMap<String, Payload>) {
+        self.search_attributes
+            .extend(upserted.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// Merges the upserted memo fields, removing any set to an empty payload
+    pub(super) fn upsert_memo(&mut self, upserted: &Memo) {
+        for (k, v) in &upserted.fields {
+            if v.data.is_empty() {
+                self.memo.remove(k);
+            } else {
+                self.memo.insert(k.clone(), v.clone());
+            }
+        }
+    }
+
+    fn fill_unset(&self, attribs: &mut ContinueAsNewWorkflowExecution) {
+        if attribs.memo.is_empty() {
+            attribs.memo = self.memo.clone();
+        }
+        if attribs.search_attributes.is_empty() {
+            attribs.search_attributes = self.search_attributes.clone();
+        }
+        if attribs.retry_policy.is_none() {
+            attribs.retry_policy = self.retry_policy.clone();
+        }
+    }
+}
+
+/// Instantiates a continue as new machine, filling in any of the inheritable properties lang left
+/// unset with those of the current run.
+pub(super) fn continue_as_new(
+    mut attribs: ContinueAsNewWorkflowExecution,
+    inherited: &InheritedRunProperties,
+) -> NewMachineWithCommand {
+    inherited.fill_unset(&mut attribs);
     let mut machine = ContinueAsNewWorkflowMachine::from_parts(Created {}.into(), ());
     OnEventWrapper::on_event_mut(&mut machine, ContinueAsNewWorkflowMachineEvents::Schedule)
         .expect("Scheduling continue as new machine doesn't fail");
@@ -115,9 +176,17 @@ impl Cancellable for ContinueAsNewWorkflowMachine {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{test_help::canned_histories, worker::workflow::ManagedWFFunc};
+    use crate::{
+        replay::{default_wes_attribs, TestHistoryBuilder},
+        test_help::canned_histories,
+        worker::workflow::ManagedWFFunc,
+    };
     use std::time::Duration;
     use temporal_sdk::{WfContext, WfExitValue, WorkflowFunction, WorkflowResult};
+    use temporal_sdk_core_protos::{
+        coresdk::AsJsonPayloadExt,
+        temporal::api::{command::v1::command::Attributes, common::v1::SearchAttributes},
+    };
 
     async fn wf_with_timer(ctx: WfContext) -> WorkflowResult<()> {
         ctx.timer(Duration::from_millis(500)).await;
@@ -152,4 +221,65 @@ mod tests {
         assert_eq!(commands.len(), 0);
         wfm.shutdown().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn continue_as_new_inherits_current_run_properties() {
+        let func = WorkflowFunction::new(|ctx: WfContext| async move {
+            ctx.upsert_search_attributes([(
+                "status".to_string(),
+                "shipped".as_json_payload().unwrap(),
+            )]);
+            ctx.upsert_memo([
+                ("note".to_string(), "updated".as_json_payload().unwrap()),
+                ("stale".to_string(), Payload::default()),
+            ]);
+            Ok(WfExitValue::continue_as_new(
+                ContinueAsNewWorkflowExecution::default(),
+            ))
+        });
+        let mut t = TestHistoryBuilder::default();
+        t.add(WorkflowExecutionStartedEventAttributes {
+            memo: Some(Memo {
+                fields: HashMap::from([
+                    ("note".to_string(), "original".as_json_payload().unwrap()),
+                    ("stale".to_string(), "remove me".as_json_payload().unwrap()),
+                ]),
+            }),
+            search_attributes: Some(SearchAttributes {
+                indexed_fields: HashMap::from([
+                    ("status".to_string(), "placed".as_json_payload().unwrap()),
+                    ("customer".to_string(), "bob".as_json_payload().unwrap()),
+                ]),
+            }),
+            retry_policy: Some(RetryPolicy {
+                maximum_attempts: 3,
+                ..Default::default()
+            }),
+            ..default_wes_attribs()
+        });
+        t.add_full_wf_task();
+        t.add_continued_as_new();
+
+        let mut wfm = ManagedWFFunc::new(t, func, vec![]);
+        wfm.get_next_activation().await.unwrap();
+        let commands = wfm.get_server_commands().commands;
+        assert_matches!(
+            commands.last().unwrap().attributes.clone().unwrap(),
+            Attributes::ContinueAsNewWorkflowExecutionCommandAttributes(attrs) => {
+                assert_eq!(
+                    attrs.memo.unwrap().fields,
+                    HashMap::from([("note".to_string(), "updated".as_json_payload().unwrap())])
+                );
+                assert_eq!(
+                    attrs.search_attributes.unwrap().indexed_fields,
+                    HashMap::from([
+                        ("status".to_string(), "shipped".as_json_payload().unwrap()),
+                        ("customer".to_string(), "bob".as_json_payload().unwrap()),
+                    ])
+                );
+                assert_eq!(attrs.retry_policy.unwrap().maximum_attempts, 3);
+            }
+        );
+        wfm.shutdown().await.unwrap();
+    }
 }
@@ -0,0 +1,146 @@
+//! Renders core's workflow state machines as Mermaid or Graphviz DOT diagrams, optionally
+//! annotated with the path a replayed history took through them. Only built with the
+//! `machine_diagrams` feature, which is meant for documentation and debugging rather than
+//! production SDKs.
+
+use super::{
+    ActivityMachine, CancelExternalMachine, CancelWorkflowMachine, ChildWorkflowMachine,
+    CompleteWorkflowMachine, ContinueAsNewWorkflowMachine, FailWorkflowMachine,
+    LocalActivityMachine, ModifyWorkflowPropertiesMachine, PatchMachine, SignalExternalMachine,
+    TimerMachine, UpsertSearchAttributesMachine, UserMarkerMachine, WorkflowTaskMachine,
+};
+use parking_lot::Mutex;
+use rustfsm::{StateMachine, TakenTransition};
+use std::collections::HashMap;
+
+/// Transitions taken by each kind of machine, in the order they were taken, keyed by machine name
+/// (ex: `TimerMachine`)
+pub type MachineTransitions = HashMap<String, Vec<TakenTransition>>;
+
+static RECORDING: Mutex<Option<MachineTransitions>> = parking_lot::const_mutex(None);
+
+/// The format to render machine diagrams in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramFormat {
+    /// A Mermaid state diagram
+    Mermaid,
+    /// A Graphviz DOT graph
+    Dot,
+}
+
+/// Renders every workflow state machine in core, returning each machine's name along with its
+/// diagram. Transitions listed in `taken` for a machine are highlighted and labeled with the steps
+/// at which they were taken. Pass an empty map to render just the definitions.
+pub fn render_machine_diagrams(
+    format: DiagramFormat,
+    taken: &MachineTransitions,
+) -> Vec<(&'static str, String)> {
+    macro_rules! render {
+        ($($machine:ident),* $(,)?) => {
+            vec![$({
+                let name = stringify!($machine);
+                let taken = taken.get(name).map(Vec::as_slice).unwrap_or_default();
+                let diagram = match format {
+                    DiagramFormat::Mermaid => $machine::mermaid_visualizer(taken),
+                    DiagramFormat::Dot => $machine::dot_visualizer(taken),
+                };
+                (name, diagram)
+            }),*]
+        };
+    }
+    render!(
+        ActivityMachine,
+        CancelExternalMachine,
+        CancelWorkflowMachine,
+        ChildWorkflowMachine,
+        CompleteWorkflowMachine,
+        ContinueAsNewWorkflowMachine,
+        FailWorkflowMachine,
+        LocalActivityMachine,
+        ModifyWorkflowPropertiesMachine,
+        PatchMachine,
+        SignalExternalMachine,
+        TimerMachine,
+        UpsertSearchAttributesMachine,
+        UserMarkerMachine,
+        WorkflowTaskMachine,
+    )
+}
+
+/// Records every transition taken by any workflow state machine in this process, from when it is
+/// started until it is finished. Recording is process-wide, so only the history of interest
+/// should be replayed (ex: with a replay worker) while a recording is in progress.
+#[derive(Debug)]
+pub struct TransitionRecording {
+    _private: (),
+}
+
+impl TransitionRecording {
+    /// Starts recording. Returns `None` if another recording is already in progress.
+    pub fn start() -> Option<Self> {
+        let mut recording = RECORDING.lock();
+        if recording.is_some() {
+            return None;
+        }
+        *recording = Some(Default::default());
+        Some(Self { _private: () })
+    }
+
+    /// Stops recording and returns the transitions taken since it started, suitable for passing to
+    /// [render_machine_diagrams]
+    pub fn finish(self) -> MachineTransitions {
+        RECORDING.lock().take().unwrap_or_default()
+    }
+}
+
+impl Drop for TransitionRecording {
+    fn drop(&mut self) {
+        RECORDING.lock().take();
+    }
+}
+
+/// Called by every machine after it successfully transitions
+pub(super) fn record_transition(machine: &str, from: String, to: String, event: String) {
+    if let Some(recording) = RECORDING.lock().as_mut() {
+        recording
+            .entry(machine.to_string())
+            .or_default()
+            .push(TakenTransition { from, to, event });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_help::canned_histories, worker::workflow::ManagedWFFunc};
+    use std::time::Duration;
+    use temporal_sdk::{WfContext, WorkflowFunction};
+
+    #[tokio::test]
+    async fn replayed_path_is_annotated() {
+        let func = WorkflowFunction::new(|ctx: WfContext| async move {
+            ctx.timer(Duration::from_secs(5)).await;
+            Ok(().into())
+        });
+        let recording = TransitionRecording::start().unwrap();
+        assert!(TransitionRecording::start().is_none());
+        let mut wfm = ManagedWFFunc::new(canned_histories::single_timer("1"), func, vec![]);
+        wfm.process_all_activations().await.unwrap();
+        wfm.shutdown().await.unwrap();
+        let taken = recording.finish();
+
+        let diagrams = render_machine_diagrams(DiagramFormat::Mermaid, &taken);
+        assert_eq!(diagrams.len(), 15);
+        let (_, timer) = diagrams
+            .iter()
+            .find(|(name, _)| *name == "TimerMachine")
+            .unwrap();
+        assert!(timer.contains("    [*] --> Created\n"));
+        // Other tests may be running machines at the same time, so the exact steps can vary
+        assert!(timer.contains("    Created --> StartCommandCreated: Schedule (#"));
+        assert!(timer.contains("class "));
+
+        let diagrams = render_machine_diagrams(DiagramFormat::Dot, &Default::default());
+        assert!(diagrams.iter().all(|(_, d)| d.starts_with("digraph {\n")));
+    }
+}
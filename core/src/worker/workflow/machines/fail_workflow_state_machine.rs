@@ -1,6 +1,6 @@
 use super::{
-    workflow_machines::MachineResponse, Cancellable, EventInfo, NewMachineWithCommand,
-    OnEventWrapper, WFMachinesAdapter, WFMachinesError,
+    is_continued_as_new_by, workflow_machines::MachineResponse, Cancellable, EventInfo,
+    NewMachineWithCommand, OnEventWrapper, WFMachinesAdapter, WFMachinesError,
 };
 use crate::worker::workflow::machines::HistEventData;
 use rustfsm::{fsm, StateMachine, TransitionResult};
@@ -9,11 +9,18 @@ use temporal_sdk_core_protos::{
     coresdk::workflow_commands::FailWorkflowExecution,
     temporal::api::{
         command::v1::Command as ProtoCommand,
-        enums::v1::{CommandType, EventType},
+        enums::v1::{CommandType, ContinueAsNewInitiator, EventType},
         history::v1::HistoryEvent,
     },
 };
 
+/// Failing a workflow with a retry policy or cron schedule starts its next run, which the server
+/// may record as a continue-as-new instead of a failure
+const FAILURE_INITIATORS: &[ContinueAsNewInitiator] = &[
+    ContinueAsNewInitiator::Retry,
+    ContinueAsNewInitiator::CronSchedule,
+];
+
 fsm! {
     pub(super) name FailWorkflowMachine;
     command FailWFCommand;
@@ -89,6 +96,11 @@ impl TryFrom<HistEventData> for FailWorkflowMachineEvents {
         let e = e.event;
         Ok(match e.event_type() {
             EventType::WorkflowExecutionFailed => Self::WorkflowExecutionFailed,
+            EventType::WorkflowExecutionContinuedAsNew
+                if is_continued_as_new_by(&e, FAILURE_INITIATORS) =>
+            {
+                Self::WorkflowExecutionFailed
+            }
             _ => {
                 return Err(WFMachinesError::Nondeterminism(format!(
                     "Fail workflow machine does not handle this event: {e}"
@@ -120,6 +132,7 @@ impl WFMachinesAdapter for FailWorkflowMachine {
 
     fn matches_event(&self, event: &HistoryEvent) -> bool {
         event.event_type() == EventType::WorkflowExecutionFailed
+            || is_continued_as_new_by(event, FAILURE_INITIATORS)
     }
 }
 
@@ -0,0 +1,208 @@
+//! Randomized tests for [super::WorkflowMachines]. Each case generates a random (but legal) plan
+//! of timers and activities, a workflow which issues the corresponding commands, and the history
+//! the server would have produced for it - with resolutions delivered in arbitrary order, split
+//! across workflow tasks, and interleaved with signals. The history is then replayed against the
+//! workflow, which must neither fail nor panic, must see every resolution and signal exactly once,
+//! and must end up completing.
+//!
+//! Cases are derived from fixed seeds so that any failure can be reproduced by re-running the
+//! offending seed, which is included in every assertion message.
+
+use crate::{replay::TestHistoryBuilder, worker::workflow::ManagedWFFunc};
+use futures::{future::join_all, FutureExt};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::time::Duration;
+use temporal_sdk::{ActivityOptions, WfContext, WorkflowFunction};
+use temporal_sdk_core_protos::{
+    coresdk::{workflow_activation::workflow_activation_job, AsJsonPayloadExt},
+    temporal::api::{
+        enums::v1::{CommandType, EventType},
+        failure::v1::Failure,
+        history::v1::{ActivityTaskFailedEventAttributes, TimerStartedEventAttributes},
+    },
+    DEFAULT_ACTIVITY_TYPE,
+};
+
+const NUM_CASES: u64 = 250;
+const MAX_BATCHES: usize = 5;
+const MAX_BATCH_SIZE: usize = 4;
+const MAX_SIGNALS_PER_WFT: usize = 2;
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Timer,
+    Activity { fails: bool },
+}
+
+/// A randomly generated workflow. Each batch is a set of commands the workflow issues together
+/// and then waits on in full before moving on to the next batch.
+#[derive(Debug, Clone)]
+struct Plan {
+    batches: Vec<Vec<Step>>,
+}
+
+impl Plan {
+    fn generate(rng: &mut StdRng) -> Self {
+        let batches = (0..rng.gen_range(1..=MAX_BATCHES))
+            .map(|_| {
+                (0..rng.gen_range(1..=MAX_BATCH_SIZE))
+                    .map(|_| {
+                        if rng.gen_bool(0.5) {
+                            Step::Timer
+                        } else {
+                            Step::Activity {
+                                fails: rng.gen_bool(0.25),
+                            }
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { batches }
+    }
+
+    fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.batches.iter().flatten()
+    }
+
+    fn workflow(&self) -> WorkflowFunction {
+        let batches = self.batches.clone();
+        WorkflowFunction::new(move |ctx: WfContext| {
+            let batches = batches.clone();
+            async move {
+                for batch in batches {
+                    let futs = batch.into_iter().map(|step| match step {
+                        Step::Timer => ctx.timer(Duration::from_secs(1)).map(|_| ()).boxed(),
+                        Step::Activity { .. } => ctx
+                            .activity(ActivityOptions {
+                                activity_type: DEFAULT_ACTIVITY_TYPE.to_string(),
+                                start_to_close_timeout: Some(Duration::from_secs(5)),
+                                ..Default::default()
+                            })
+                            .map(|_| ())
+                            .boxed(),
+                    });
+                    join_all(futs).await;
+                }
+                Ok(().into())
+            }
+        })
+    }
+}
+
+/// Builds the history the server would have recorded for the plan. Returns it along with the
+/// number of signals it contains.
+fn history_for(plan: &Plan, rng: &mut StdRng) -> (TestHistoryBuilder, usize) {
+    let mut t = TestHistoryBuilder::default();
+    let mut signals = 0;
+    let mut add_wft = |t: &mut TestHistoryBuilder, rng: &mut StdRng| {
+        for _ in 0..rng.gen_range(0..=MAX_SIGNALS_PER_WFT) {
+            t.add_we_signaled("signal", vec![signals.as_json_payload().unwrap()]);
+            signals += 1;
+        }
+        t.add_full_wf_task();
+    };
+
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    add_wft(&mut t, rng);
+    let (mut timer_seq, mut activity_seq) = (1, 1);
+    for batch in &plan.batches {
+        // Commands are recorded in the order the workflow issued them
+        let mut resolutions = vec![];
+        for step in batch {
+            match step {
+                Step::Timer => {
+                    let timer_id = timer_seq.to_string();
+                    let started = t.add(TimerStartedEventAttributes {
+                        timer_id: timer_id.clone(),
+                        ..Default::default()
+                    });
+                    resolutions.push((*step, started, timer_id));
+                    timer_seq += 1;
+                }
+                Step::Activity { .. } => {
+                    let scheduled = t.add_activity_task_scheduled(activity_seq.to_string());
+                    resolutions.push((*step, scheduled, String::new()));
+                    activity_seq += 1;
+                }
+            }
+        }
+        // ...but may be resolved in any order, and across more than one workflow task
+        resolutions.shuffle(rng);
+        let split_at = rng.gen_range(1..=resolutions.len());
+        for (i, (step, initiated_id, timer_id)) in resolutions.into_iter().enumerate() {
+            if i == split_at {
+                add_wft(&mut t, rng);
+            }
+            match step {
+                Step::Timer => t.add_timer_fired(initiated_id, timer_id),
+                Step::Activity { fails: false } => {
+                    let started = t.add_activity_task_started(initiated_id);
+                    t.add_activity_task_completed(
+                        initiated_id,
+                        started,
+                        "done".as_json_payload().unwrap(),
+                    );
+                }
+                Step::Activity { fails: true } => {
+                    let started = t.add_activity_task_started(initiated_id);
+                    t.add(ActivityTaskFailedEventAttributes {
+                        scheduled_event_id: initiated_id,
+                        started_event_id: started,
+                        failure: Some(Failure {
+                            message: "boom".to_string(),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        add_wft(&mut t, rng);
+    }
+    t.add_workflow_execution_completed();
+    (t, signals)
+}
+
+#[tokio::test]
+async fn random_histories_replay_cleanly() {
+    for seed in 0..NUM_CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let plan = Plan::generate(&mut rng);
+        let (t, expected_signals) = history_for(&plan, &mut rng);
+        let mut wfm = ManagedWFFunc::new(t, plan.workflow(), vec![]);
+
+        let (mut timers, mut activities, mut signals) = (0, 0, 0);
+        loop {
+            let act = wfm
+                .get_next_activation()
+                .await
+                .unwrap_or_else(|e| panic!("Seed {seed} ({plan:?}) failed replay: {e:?}"));
+            if act.jobs.is_empty() {
+                break;
+            }
+            for job in act.jobs {
+                match job.variant {
+                    Some(workflow_activation_job::Variant::FireTimer(_)) => timers += 1,
+                    Some(workflow_activation_job::Variant::ResolveActivity(_)) => activities += 1,
+                    Some(workflow_activation_job::Variant::SignalWorkflow(_)) => signals += 1,
+                    _ => {}
+                }
+            }
+        }
+        let commands = wfm.get_server_commands().commands;
+        wfm.shutdown().await.unwrap();
+
+        let expected_timers = plan.steps().filter(|s| matches!(s, Step::Timer)).count();
+        let expected_activities = plan.steps().count() - expected_timers;
+        assert_eq!(timers, expected_timers, "Seed {seed}: {plan:?}");
+        assert_eq!(activities, expected_activities, "Seed {seed}: {plan:?}");
+        assert_eq!(signals, expected_signals, "Seed {seed}: {plan:?}");
+        assert_eq!(commands.len(), 1, "Seed {seed}: {plan:?}");
+        assert_eq!(
+            commands[0].command_type,
+            CommandType::CompleteWorkflowExecution as i32,
+            "Seed {seed}: {plan:?}"
+        );
+    }
+}
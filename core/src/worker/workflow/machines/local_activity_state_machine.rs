@@ -829,10 +829,12 @@ impl TryFrom<HistEventData> for LocalActivityMachineEvents {
             )));
         }
 
-        match e.into_local_activity_marker_details() {
+        match e.into_local_activity_marker_details()? {
             Some(marker_dat) => Ok(LocalActivityMachineEvents::MarkerRecorded(marker_dat)),
-            _ => Err(WFMachinesError::Nondeterminism(
-                "Local activity machine encountered an unparsable marker".to_string(),
+            None => Err(WFMachinesError::Nondeterminism(
+                "Local activity machine encountered a marker which is not a local activity \
+                 marker"
+                    .to_string(),
             )),
         }
     }
@@ -882,7 +884,7 @@ mod tests {
         replay::TestHistoryBuilder, test_help::canned_histories, worker::workflow::ManagedWFFunc,
     };
     use rstest::rstest;
-    use std::time::Duration;
+    use std::{collections::HashMap, time::Duration};
     use temporal_sdk::{
         CancellableFuture, LocalActivityOptions, WfContext, WorkflowFunction, WorkflowResult,
     };
@@ -892,7 +894,8 @@ mod tests {
             workflow_activation::{workflow_activation_job, WorkflowActivationJob},
         },
         temporal::api::{
-            command::v1::command, enums::v1::WorkflowTaskFailedCause, failure::v1::Failure,
+            command::v1::command, common::v1::Payloads, enums::v1::WorkflowTaskFailedCause,
+            failure::v1::Failure,
         },
         DEFAULT_ACTIVITY_TYPE,
     };
@@ -1393,6 +1396,32 @@ mod tests {
         wfm.shutdown().await.unwrap();
     }
 
+    #[tokio::test]
+    async fn malformed_marker_data_identifies_event() {
+        let func = WorkflowFunction::new(la_wf);
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        t.add_marker(
+            LOCAL_ACTIVITY_MARKER_NAME,
+            HashMap::from([(
+                "data".to_string(),
+                Payloads {
+                    payloads: vec![b"not json".into()],
+                },
+            )]),
+        );
+        t.add_workflow_execution_completed();
+
+        let mut wfm = ManagedWFFunc::new(t, func, vec![]);
+        let err = wfm.process_all_activations().await.unwrap_err();
+        assert_matches!(err, WFMachinesError::MalformedPayload(mp) => {
+            assert_eq!(mp.event_id, 5);
+            assert_eq!(mp.attribute_path, "marker_recorded_event_attributes.details[\"data\"]");
+        });
+        wfm.shutdown().await.unwrap();
+    }
+
     #[rstest]
     #[tokio::test]
     async fn immediate_cancel(
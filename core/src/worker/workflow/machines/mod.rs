@@ -13,11 +13,20 @@ mod patch_state_machine;
 mod signal_external_state_machine;
 mod timer_state_machine;
 mod upsert_search_attributes_state_machine;
+mod user_marker_state_machine;
 mod workflow_task_state_machine;
 
+#[cfg(feature = "machine_diagrams")]
+mod diagrams;
+#[cfg(test)]
+mod history_fuzz;
 #[cfg(test)]
 mod transition_coverage;
 
+#[cfg(feature = "machine_diagrams")]
+pub use diagrams::{
+    render_machine_diagrams, DiagramFormat, MachineTransitions, TransitionRecording,
+};
 pub(crate) use workflow_machines::WorkflowMachines;
 
 use crate::{telemetry::VecDisplayer, worker::workflow::WFMachinesError};
@@ -39,11 +48,12 @@ use std::{
 };
 use temporal_sdk_core_protos::temporal::api::{
     command::v1::Command as ProtoCommand,
-    enums::v1::{CommandType, EventType},
-    history::v1::HistoryEvent,
+    enums::v1::{CommandType, ContinueAsNewInitiator, EventType},
+    history::v1::{history_event, HistoryEvent},
 };
 use timer_state_machine::TimerMachine;
 use upsert_search_attributes_state_machine::UpsertSearchAttributesMachine;
+use user_marker_state_machine::UserMarkerMachine;
 use workflow_machines::MachineResponse;
 use workflow_task_state_machine::WorkflowTaskMachine;
 
@@ -67,6 +77,7 @@ enum Machines {
     WorkflowTaskMachine,
     UpsertSearchAttributesMachine,
     ModifyWorkflowPropertiesMachine,
+    UserMarkerMachine,
 }
 
 /// Extends [rustfsm::StateMachine] with some functionality specific to the temporal SDK.
@@ -103,6 +114,9 @@ trait TemporalStateMachine {
 
     /// Returns a friendly name for the type of this machine
     fn name(&self) -> &str;
+
+    /// Returns the name of the state the machine is currently in
+    fn state_name(&self) -> String;
 }
 
 impl<SM> TemporalStateMachine for SM
@@ -200,6 +214,23 @@ where
     fn name(&self) -> &str {
         self.name()
     }
+
+    fn state_name(&self) -> String {
+        self.state().to_string()
+    }
+}
+
+/// Returns true if the event is a continue-as-new which the server recorded for one of the given
+/// reasons, rather than because the workflow asked for it. When starting the next run of a cron
+/// workflow, or retrying one, older servers record these in place of the completion or failure
+/// the workflow asked for.
+fn is_continued_as_new_by(event: &HistoryEvent, initiators: &[ContinueAsNewInitiator]) -> bool {
+    match &event.attributes {
+        Some(history_event::Attributes::WorkflowExecutionContinuedAsNewEventAttributes(attrs)) => {
+            initiators.contains(&attrs.initiator())
+        }
+        _ => false,
+    }
 }
 
 fn process_machine_commands<SM>(
@@ -290,13 +321,20 @@ where
         &mut self,
         event: Self::Event,
     ) -> Result<Vec<Self::Command>, MachineError<Self::Error>> {
-        #[cfg(test)]
+        #[cfg(any(test, feature = "machine_diagrams"))]
         let from_state = self.state().to_string();
-        #[cfg(test)]
+        #[cfg(any(test, feature = "machine_diagrams"))]
         let converted_event_str = event.to_string();
 
         let res = StateMachine::on_event(self, event);
         if res.is_ok() {
+            #[cfg(feature = "machine_diagrams")]
+            diagrams::record_transition(
+                self.name(),
+                from_state.clone(),
+                self.state().to_string(),
+                converted_event_str.clone(),
+            );
             #[cfg(test)]
             add_coverage(
                 self.name().to_owned(),
@@ -263,9 +263,14 @@ impl TryFrom<HistEventData> for PatchMachineEvents {
         let e = e.event;
         match e.get_patch_marker_details() {
             Some((id, _)) => Ok(Self::MarkerRecorded(id)),
-            _ => Err(WFMachinesError::Nondeterminism(format!(
-                "Change machine cannot handle this event: {e}"
-            ))),
+            None => Err(e
+                .malformed_patch_marker()
+                .map(Into::into)
+                .unwrap_or_else(|| {
+                    WFMachinesError::Nondeterminism(format!(
+                        "Change machine cannot handle this event: {e}"
+                    ))
+                })),
         }
     }
 }
@@ -820,4 +825,35 @@ mod tests {
         }
         wfm.shutdown().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn deprecated_marker_after_last_command_is_nondeterministic() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        let timer_started_event_id = t.add_by_type(EventType::TimerStarted);
+        t.add_has_change_marker(MY_PATCH_ID, true);
+        t.add_upsert_search_attrs_for_patch(&[MY_PATCH_ID.to_string()]);
+        t.add(TimerFiredEventAttributes {
+            started_event_id: timer_started_event_id,
+            timer_id: "1".to_string(),
+        });
+        t.add_full_wf_task();
+        t.add_workflow_execution_completed();
+
+        let mut wfm = ManagedWFFunc::new(
+            t,
+            WorkflowFunction::new(|ctx: WfContext| async move {
+                ctx.timer(ONE_SECOND).await;
+                Ok(().into())
+            }),
+            vec![],
+        );
+        // There is no command left for the marker to be checked against
+        assert_matches!(
+            wfm.process_all_activations().await.unwrap_err(),
+            WFMachinesError::Nondeterminism(_)
+        );
+        wfm.shutdown().await.unwrap();
+    }
 }
@@ -6,20 +6,27 @@ use super::{
 };
 use crate::worker::workflow::{machines::HistEventData, WFMachinesError};
 use rustfsm::{fsm, MachineError, StateMachine, TransitionResult};
-use std::convert::TryFrom;
+use std::{convert::TryFrom, time::Duration};
 use temporal_sdk_core_protos::{
-    coresdk::{
-        workflow_activation::FireTimer,
-        workflow_commands::{CancelTimer, StartTimer},
-        HistoryEventId,
-    },
+    coresdk::{workflow_activation::FireTimer, workflow_commands::StartTimer, HistoryEventId},
     temporal::api::{
-        command::v1::Command,
+        command::v1::{CancelTimerCommandAttributes, Command, StartTimerCommandAttributes},
         enums::v1::{CommandType, EventType},
         history::v1::{history_event, HistoryEvent, TimerFiredEventAttributes},
     },
 };
 
+/// The longest duration core will ask the server to wait for with a single timer. Timers requested
+/// by lang which are longer than this are transparently split into a chain of timers, each no
+/// longer than this, and only the firing of the last one in the chain is reported to lang.
+///
+/// The server is written in Go, where durations (`time.Duration`) and instants stored as
+/// nanoseconds since the epoch are 64 bit integers, so they cannot go past roughly 292 years, or
+/// the year 2262, respectively. 100 years stays comfortably inside both limits for any timer
+/// started this century, and costs long timers only one extra timer event per hundred years.
+pub(crate) const MAX_SERVER_TIMER_DURATION: Duration =
+    Duration::from_secs(60 * 60 * 24 * 365 * 100);
+
 fsm! {
     pub(super) name TimerMachine;
     command TimerMachineCommand;
@@ -33,6 +40,10 @@ fsm! {
     StartCommandCreated --(Cancel, shared on_cancel) --> Canceled;
 
     StartCommandRecorded --(TimerFired(TimerFiredEventAttributes), shared on_timer_fired) --> Fired;
+    // Timers longer than the server maximum are split, and each intermediate segment firing
+    // schedules the next one
+    StartCommandRecorded --(TimerFired(TimerFiredEventAttributes), shared on_timer_fired)
+        --> StartCommandCreated;
     StartCommandRecorded --(Cancel, shared on_cancel) --> CancelTimerCommandCreated;
 
     CancelTimerCommandCreated --(Cancel) --> CancelTimerCommandCreated;
@@ -50,6 +61,8 @@ fsm! {
 pub(super) enum TimerMachineCommand {
     Complete,
     IssueCancelCmd(Command),
+    /// Start the next segment of a timer which has been split
+    StartNextSegment(Command),
     // We don't issue activations for timer cancellations. Lang SDK is expected to cancel
     // it's own timers when user calls cancel, and they cannot be cancelled by any other
     // means.
@@ -59,6 +72,42 @@ pub(super) enum TimerMachineCommand {
 pub(super) struct SharedState {
     attrs: StartTimer,
     cancelled_before_sent: bool,
+    /// Which segment of a (possibly) split timer is currently outstanding. Zero for the first.
+    segment: u32,
+    /// How much of the requested duration is left to be covered by segments after the current one
+    remaining: Duration,
+}
+
+impl SharedState {
+    /// The timer id used for the current segment. The first segment uses the lang sequence number,
+    /// so timers which never need splitting look exactly as they always have.
+    fn timer_id(&self) -> String {
+        if self.segment == 0 {
+            self.attrs.seq.to_string()
+        } else {
+            format!("{}-{}", self.attrs.seq, self.segment)
+        }
+    }
+
+    /// Take the next segment's worth of duration off of the remaining total
+    fn take_segment(&mut self) -> Duration {
+        let seg = self.remaining.min(MAX_SERVER_TIMER_DURATION);
+        self.remaining -= seg;
+        seg
+    }
+
+    fn start_segment_command(&self, duration: Duration) -> Command {
+        Command {
+            command_type: CommandType::StartTimer as i32,
+            attributes: Some(
+                StartTimerCommandAttributes {
+                    timer_id: self.timer_id(),
+                    start_to_fire_timeout: duration.try_into().ok(),
+                }
+                .into(),
+            ),
+        }
+    }
 }
 
 /// Creates a new, scheduled, timer as a [CancellableCommand]
@@ -76,19 +125,33 @@ impl TimerMachine {
         let mut s = Self::new(attribs);
         OnEventWrapper::on_event_mut(&mut s, TimerMachineEvents::Schedule)
             .expect("Scheduling timers doesn't fail");
-        let cmd = Command {
-            command_type: CommandType::StartTimer as i32,
-            attributes: Some(s.shared_state().attrs.clone().into()),
+        // At this point the entire requested duration is "remaining". Durations we can't
+        // interpret are passed along untouched, and the server gets to decide what to do with them.
+        let cmd = if s.shared_state.remaining.is_zero() {
+            Command {
+                command_type: CommandType::StartTimer as i32,
+                attributes: Some(s.shared_state().attrs.clone().into()),
+            }
+        } else {
+            let first_seg = s.shared_state.take_segment();
+            s.shared_state.start_segment_command(first_seg)
         };
         (s, cmd)
     }
 
     fn new(attribs: StartTimer) -> Self {
+        let remaining: Duration = attribs
+            .start_to_fire_timeout
+            .clone()
+            .and_then(|d| d.try_into().ok())
+            .unwrap_or_default();
         Self::from_parts(
             Created {}.into(),
             SharedState {
                 attrs: attribs,
                 cancelled_before_sent: false,
+                segment: 0,
+                remaining,
             },
         )
     }
@@ -192,14 +255,25 @@ impl StartCommandRecorded {
         self,
         dat: &mut SharedState,
         attrs: TimerFiredEventAttributes,
-    ) -> TimerMachineTransition<Fired> {
-        if dat.attrs.seq.to_string() == attrs.timer_id {
+    ) -> TimerMachineTransition<FiredOrStartCommandCreated> {
+        let expected_id = dat.timer_id();
+        if expected_id != attrs.timer_id {
+            return TransitionResult::Err(WFMachinesError::Fatal(format!(
+                "Timer fired event did not have expected timer id {expected_id}, it was {}!",
+                attrs.timer_id
+            )));
+        }
+        if dat.remaining.is_zero() {
             TransitionResult::ok(vec![TimerMachineCommand::Complete], Fired::default())
         } else {
-            TransitionResult::Err(WFMachinesError::Fatal(format!(
-                "Timer fired event did not have expected timer id {}, it was {}!",
-                dat.attrs.seq, attrs.timer_id
-            )))
+            dat.segment += 1;
+            let next_seg = dat.take_segment();
+            TransitionResult::ok(
+                vec![TimerMachineCommand::StartNextSegment(
+                    dat.start_segment_command(next_seg),
+                )],
+                StartCommandCreated::default(),
+            )
         }
     }
 
@@ -209,7 +283,12 @@ impl StartCommandRecorded {
     ) -> TimerMachineTransition<CancelTimerCommandCreated> {
         let cmd = Command {
             command_type: CommandType::CancelTimer as i32,
-            attributes: Some(CancelTimer { seq: dat.attrs.seq }.into()),
+            attributes: Some(
+                CancelTimerCommandAttributes {
+                    timer_id: dat.timer_id(),
+                }
+                .into(),
+            ),
         };
         TransitionResult::ok(
             vec![TimerMachineCommand::IssueCancelCmd(cmd)],
@@ -230,7 +309,9 @@ impl WFMachinesAdapter for TimerMachine {
                 seq: self.shared_state.attrs.seq,
             }
             .into()],
-            TimerMachineCommand::IssueCancelCmd(c) => vec![MachineResponse::IssueNewCommand(c)],
+            TimerMachineCommand::IssueCancelCmd(c) | TimerMachineCommand::StartNextSegment(c) => {
+                vec![MachineResponse::IssueNewCommand(c)]
+            }
         })
     }
 
@@ -267,8 +348,12 @@ mod test {
         replay::TestHistoryBuilder, test_help::canned_histories, worker::workflow::ManagedWFFunc,
     };
     use rstest::{fixture, rstest};
-    use std::{mem::discriminant, time::Duration};
+    use std::mem::discriminant;
     use temporal_sdk::{CancellableFuture, WfContext, WorkflowFunction};
+    use temporal_sdk_core_protos::{
+        coresdk::workflow_activation::{workflow_activation_job, WorkflowActivationJob},
+        temporal::api::command::v1::command,
+    };
 
     #[fixture]
     fn happy_wfm() -> ManagedWFFunc {
@@ -414,6 +499,90 @@ mod test {
         wfm.shutdown().await.unwrap();
     }
 
+    #[fixture]
+    fn split_timer_wfm() -> ManagedWFFunc {
+        let func = WorkflowFunction::new(|ctx: WfContext| async move {
+            ctx.timer(MAX_SERVER_TIMER_DURATION + Duration::from_secs(60))
+                .await;
+            Ok(().into())
+        });
+
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        let first_seg_id = t.add_by_type(EventType::TimerStarted);
+        t.add_timer_fired(first_seg_id, "1".to_string());
+        t.add_full_wf_task();
+        let second_seg_id = t.add_by_type(EventType::TimerStarted);
+        t.add_timer_fired(second_seg_id, "1-1".to_string());
+        t.add_full_wf_task();
+        t.add_workflow_execution_completed();
+        ManagedWFFunc::new(t, func, vec![])
+    }
+
+    fn timer_attrs(cmd: &Command) -> &StartTimerCommandAttributes {
+        match cmd.attributes.as_ref() {
+            Some(command::Attributes::StartTimerCommandAttributes(a)) => a,
+            _ => panic!("Expected start timer command, got {cmd:?}"),
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn long_timer_is_split_inc(#[from(split_timer_wfm)] mut wfm: ManagedWFFunc) {
+        wfm.get_next_activation().await.unwrap();
+        let commands = wfm.get_server_commands().commands;
+        assert_eq!(commands.len(), 1);
+        let attrs = timer_attrs(&commands[0]);
+        assert_eq!(attrs.timer_id, "1");
+        assert_eq!(
+            attrs.start_to_fire_timeout,
+            MAX_SERVER_TIMER_DURATION.try_into().ok()
+        );
+
+        // The first segment firing is invisible to lang, and schedules the rest of the timer
+        let act = wfm.get_next_activation().await.unwrap();
+        assert!(act.jobs.is_empty());
+        let commands = wfm.get_server_commands().commands;
+        assert_eq!(commands.len(), 1);
+        let attrs = timer_attrs(&commands[0]);
+        assert_eq!(attrs.timer_id, "1-1");
+        assert_eq!(
+            attrs.start_to_fire_timeout,
+            Duration::from_secs(60).try_into().ok()
+        );
+
+        let act = wfm.get_next_activation().await.unwrap();
+        assert_matches!(
+            act.jobs.as_slice(),
+            [WorkflowActivationJob {
+                variant: Some(workflow_activation_job::Variant::FireTimer(FireTimer {
+                    seq: 1
+                }))
+            }]
+        );
+        let commands = wfm.get_server_commands().commands;
+        assert_eq!(commands.len(), 1);
+        assert_eq!(
+            commands[0].command_type,
+            CommandType::CompleteWorkflowExecution as i32
+        );
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn long_timer_is_split_full(#[from(split_timer_wfm)] mut wfm: ManagedWFFunc) {
+        wfm.process_all_activations().await.unwrap();
+        let commands = wfm.get_server_commands().commands;
+        assert_eq!(commands.len(), 1);
+        assert_eq!(
+            commands[0].command_type,
+            CommandType::CompleteWorkflowExecution as i32
+        );
+        wfm.shutdown().await.unwrap();
+    }
+
     #[test]
     fn cancels_ignored_terminal() {
         for state in [TimerMachineState::Canceled(Canceled {}), Fired {}.into()] {
@@ -80,6 +80,7 @@ mod machine_coverage_report {
         patch_state_machine::PatchMachine, signal_external_state_machine::SignalExternalMachine,
         timer_state_machine::TimerMachine,
         upsert_search_attributes_state_machine::UpsertSearchAttributesMachine,
+        user_marker_state_machine::UserMarkerMachine,
         workflow_task_state_machine::WorkflowTaskMachine,
     };
     use rustfsm::StateMachine;
@@ -118,6 +119,7 @@ mod machine_coverage_report {
         let mut la_mach = LocalActivityMachine::visualizer().to_owned();
         let mut upsert_search_attr = UpsertSearchAttributesMachine::visualizer().to_owned();
         let mut modify_wf_props = ModifyWorkflowPropertiesMachine::visualizer().to_owned();
+        let mut user_marker = UserMarkerMachine::visualizer().to_owned();
 
         // This isn't at all efficient but doesn't need to be.
         // Replace transitions in the vizzes with green color if they are covered.
@@ -144,6 +146,7 @@ mod machine_coverage_report {
                 m @ "ModifyWorkflowPropertiesMachine" => {
                     cover_transitions(m, &mut modify_wf_props, coverage)
                 }
+                m @ "UserMarkerMachine" => cover_transitions(m, &mut user_marker, coverage),
                 m => panic!("Unknown machine {m}"),
             }
         }
@@ -0,0 +1,228 @@
+//! Records arbitrary, user-defined markers in history. Lang SDKs can use these to durably record
+//! things like saga compensation steps or idempotency tokens. Upon replay the machine only verifies
+//! that the marker in history has the same name as the one the workflow recorded - the details are
+//! not compared, since lang is expected to read them back out of history if it needs them.
+
+use super::{workflow_machines::MachineResponse, NewMachineWithCommand};
+use crate::{
+    protosext::{is_core_marker_name, HistoryEventExt},
+    worker::workflow::{
+        machines::{Cancellable, EventInfo, HistEventData, WFMachinesAdapter},
+        WFMachinesError,
+    },
+};
+use rustfsm::{fsm, StateMachine, TransitionResult};
+use temporal_sdk_core_protos::{
+    coresdk::workflow_commands::RecordMarker,
+    temporal::api::{command::v1::Command, enums::v1::CommandType, history::v1::HistoryEvent},
+};
+
+fsm! {
+    pub(super) name UserMarkerMachine;
+    command UserMarkerMachineCommand;
+    error WFMachinesError;
+    shared_state SharedState;
+
+    Created --(CommandRecordMarker) --> MarkerCommandCreated;
+    MarkerCommandCreated --(MarkerRecorded(String), shared on_marker_recorded)
+      --> MarkerCommandRecorded;
+}
+
+/// Instantiates a UserMarkerMachine and packs it together with the command to be sent to server.
+/// Fails if lang attempts to use one of the marker names reserved by core.
+pub(super) fn record_marker(
+    lang_cmd: RecordMarker,
+) -> Result<NewMachineWithCommand, WFMachinesError> {
+    if is_core_marker_name(&lang_cmd.marker_name) {
+        return Err(WFMachinesError::Fatal(format!(
+            "Marker name {} is reserved for use by core",
+            lang_cmd.marker_name
+        )));
+    }
+    let sm = UserMarkerMachine::from_parts(
+        Created {}.into(),
+        SharedState {
+            marker_name: lang_cmd.marker_name.clone(),
+        },
+    );
+    let cmd = Command {
+        command_type: CommandType::RecordMarker as i32,
+        attributes: Some(lang_cmd.into()),
+    };
+    Ok(NewMachineWithCommand {
+        command: cmd,
+        machine: sm.into(),
+    })
+}
+
+#[derive(Clone)]
+pub(super) struct SharedState {
+    marker_name: String,
+}
+
+#[derive(Debug, derive_more::Display)]
+pub(super) enum UserMarkerMachineCommand {}
+
+#[derive(Debug, Default, Clone, derive_more::Display)]
+pub(super) struct Created {}
+
+#[derive(Debug, Default, Clone, derive_more::Display)]
+pub(super) struct MarkerCommandCreated {}
+
+impl MarkerCommandCreated {
+    pub(super) fn on_marker_recorded(
+        self,
+        dat: &mut SharedState,
+        name: String,
+    ) -> UserMarkerMachineTransition<MarkerCommandRecorded> {
+        if name != dat.marker_name {
+            return TransitionResult::Err(WFMachinesError::Nondeterminism(format!(
+                "Marker name {} does not match expected name {}",
+                name, dat.marker_name
+            )));
+        }
+        TransitionResult::default()
+    }
+}
+
+#[derive(Debug, Default, Clone, derive_more::Display)]
+pub(super) struct MarkerCommandRecorded {}
+
+impl From<Created> for MarkerCommandCreated {
+    fn from(_: Created) -> Self {
+        Self {}
+    }
+}
+
+impl WFMachinesAdapter for UserMarkerMachine {
+    fn adapt_response(
+        &self,
+        _my_command: Self::Command,
+        _event_info: Option<EventInfo>,
+    ) -> Result<Vec<MachineResponse>, Self::Error> {
+        Err(Self::Error::Nondeterminism(
+            "UserMarkerMachine does not use state machine commands".to_string(),
+        ))
+    }
+
+    fn matches_event(&self, event: &HistoryEvent) -> bool {
+        event.get_user_marker_name() == Some(self.shared_state.marker_name.as_str())
+    }
+}
+
+impl Cancellable for UserMarkerMachine {}
+
+impl TryFrom<HistEventData> for UserMarkerMachineEvents {
+    type Error = WFMachinesError;
+
+    fn try_from(e: HistEventData) -> Result<Self, Self::Error> {
+        let e = e.event;
+        match e.get_user_marker_name() {
+            Some(name) => Ok(Self::MarkerRecorded(name.to_string())),
+            None => Err(Self::Error::Nondeterminism(format!(
+                "UserMarkerMachine does not handle {e}"
+            ))),
+        }
+    }
+}
+
+impl TryFrom<CommandType> for UserMarkerMachineEvents {
+    type Error = WFMachinesError;
+
+    fn try_from(c: CommandType) -> Result<Self, Self::Error> {
+        match c {
+            CommandType::RecordMarker => Ok(Self::CommandRecordMarker),
+            _ => Err(Self::Error::Nondeterminism(format!(
+                "UserMarkerMachine does not handle command type {c:?}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{replay::TestHistoryBuilder, worker::workflow::ManagedWFFunc};
+    use std::collections::HashMap;
+    use temporal_sdk::{WfContext, WorkflowFunction};
+    use temporal_sdk_core_protos::{
+        coresdk::AsJsonPayloadExt,
+        temporal::api::{
+            command::v1::command::Attributes, common::v1::Payloads, enums::v1::EventType,
+        },
+    };
+
+    const SAGA_MARKER: &str = "saga_step";
+
+    fn marker_details() -> HashMap<String, Payloads> {
+        HashMap::from([(
+            "step".to_string(),
+            Payloads {
+                payloads: vec!["reserve-inventory".as_json_payload().unwrap()],
+            },
+        )])
+    }
+
+    fn marker_wf(name: &'static str) -> WorkflowFunction {
+        WorkflowFunction::new(move |ctx: WfContext| async move {
+            ctx.record_marker(name, marker_details());
+            Ok(().into())
+        })
+    }
+
+    fn marker_hist() -> TestHistoryBuilder {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        t.add_marker(SAGA_MARKER, marker_details());
+        t.add_workflow_execution_completed();
+        t
+    }
+
+    #[tokio::test]
+    async fn records_user_marker() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        t.add_workflow_execution_completed();
+
+        let mut wfm = ManagedWFFunc::new(t, marker_wf(SAGA_MARKER), vec![]);
+        wfm.get_next_activation().await.unwrap();
+        let commands = wfm.get_server_commands().commands;
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].command_type, CommandType::RecordMarker as i32);
+        assert_matches!(
+            commands[0].attributes.clone().unwrap(),
+            Attributes::RecordMarkerCommandAttributes(attrs) => {
+                assert_eq!(attrs.marker_name, SAGA_MARKER);
+                assert_eq!(attrs.details, marker_details());
+            }
+        );
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn replays_user_marker() {
+        let mut wfm = ManagedWFFunc::new(marker_hist(), marker_wf(SAGA_MARKER), vec![]);
+        wfm.process_all_activations().await.unwrap();
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn mismatched_marker_name_is_nondeterminism() {
+        let mut wfm = ManagedWFFunc::new(marker_hist(), marker_wf("other_marker"), vec![]);
+        wfm.get_next_activation().await.unwrap();
+        let err = wfm.get_next_activation().await.unwrap_err();
+        assert_matches!(err, WFMachinesError::Nondeterminism(_));
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn core_marker_names_rejected() {
+        let res = record_marker(RecordMarker {
+            marker_name: temporal_sdk_core_protos::constants::PATCH_MARKER_NAME.to_string(),
+            details: Default::default(),
+        });
+        assert_matches!(res, Err(WFMachinesError::Fatal(_)));
+    }
+}
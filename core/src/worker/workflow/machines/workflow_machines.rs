@@ -4,47 +4,54 @@ use super::{
     cancel_external_state_machine::new_external_cancel,
     cancel_workflow_state_machine::cancel_workflow,
     complete_workflow_state_machine::complete_workflow,
-    continue_as_new_workflow_state_machine::continue_as_new,
-    fail_workflow_state_machine::fail_workflow, local_activity_state_machine::new_local_activity,
-    patch_state_machine::has_change, signal_external_state_machine::new_external_signal,
-    timer_state_machine::new_timer, upsert_search_attributes_state_machine::upsert_search_attrs,
+    continue_as_new_workflow_state_machine::{continue_as_new, InheritedRunProperties},
+    fail_workflow_state_machine::fail_workflow,
+    is_continued_as_new_by,
+    local_activity_state_machine::new_local_activity,
+    patch_state_machine::has_change,
+    signal_external_state_machine::new_external_signal,
+    timer_state_machine::new_timer,
+    upsert_search_attributes_state_machine::upsert_search_attrs,
     workflow_machines::local_acts::LocalActivityData,
-    workflow_task_state_machine::WorkflowTaskMachine, Machines, NewMachineWithCommand,
-    TemporalStateMachine,
+    workflow_task_state_machine::{HistorySizeInfo, WFTAttemptFailure, WorkflowTaskMachine},
+    Machines, NewMachineWithCommand, TemporalStateMachine,
 };
 use crate::{
     internal_flags::InternalFlags,
     protosext::{HistoryEventExt, ValidScheduleLA},
-    telemetry::{metrics::MetricsContext, VecDisplayer},
+    telemetry::{
+        metrics::{command_queue, machine_kind, wft_failure_cause, MetricsContext},
+        VecDisplayer,
+    },
     worker::{
         workflow::{
-            history_update::NextWFT,
+            history_update::{validate_event_id_sequence, NextWFT},
             machines::{
                 activity_state_machine::ActivityMachine,
                 child_workflow_state_machine::ChildWorkflowMachine,
                 modify_workflow_properties_state_machine::modify_workflow_properties,
                 patch_state_machine::VERSION_SEARCH_ATTR_KEY,
                 upsert_search_attributes_state_machine::upsert_search_attrs_internal,
-                HistEventData,
+                user_marker_state_machine::record_marker, HistEventData,
             },
             CommandID, DrivenWorkflow, HistoryUpdate, InternalFlagsRef, LocalResolution,
-            OutgoingJob, RunBasics, WFCommand, WFMachinesError, WorkflowFetcher,
+            OutgoingJob, PayloadSizeLimits, RunBasics, WFCommand, WFMachinesError, WorkflowFetcher,
             WorkflowStartedInfo,
         },
         ExecutingLAId, LocalActRequest, LocalActivityExecutionResult, LocalActivityResolution,
     },
 };
-use siphasher::sip::SipHasher13;
+use prost::Message;
 use slotmap::{SlotMap, SparseSecondaryMap};
 use std::{
     borrow::{Borrow, BorrowMut},
     cell::RefCell,
     collections::{HashMap, VecDeque},
     convert::TryInto,
-    hash::{Hash, Hasher},
     rc::Rc,
     time::{Duration, Instant, SystemTime},
 };
+use temporal_sdk_core_api::worker::{MachineDump, MachinesDump, RunStateDump};
 use temporal_sdk_core_protos::{
     coresdk::{
         common::NamespacedWorkflowExecution,
@@ -52,14 +59,13 @@ use temporal_sdk_core_protos::{
         workflow_activation::{
             workflow_activation_job, NotifyHasPatch, UpdateRandomSeed, WorkflowActivation,
         },
-        workflow_commands::{
-            request_cancel_external_workflow_execution as cancel_we, ContinueAsNewWorkflowExecution,
-        },
+        workflow_commands::request_cancel_external_workflow_execution as cancel_we,
     },
+    deterministic_hash::{deterministic_hash, randomness_seed_from_run_id, HashVersion},
     temporal::api::{
         command::v1::{command::Attributes as ProtoCmdAttrs, Command as ProtoCommand},
-        enums::v1::EventType,
-        history::v1::{history_event, history_event::Attributes, HistoryEvent},
+        enums::v1::{ContinueAsNewInitiator, EventType},
+        history::v1::{history_event, history_event::Attributes, History, HistoryEvent},
         sdk::v1::WorkflowTaskCompletedMetadata,
     },
 };
@@ -93,21 +99,34 @@ pub(crate) struct WorkflowMachines {
     pub workflow_id: String,
     /// Workflow type identifier. (Function name, class, etc)
     pub workflow_type: String,
-    /// Identifies the current run
+    /// Identifies the current run. This is the run id the run was dispatched under, which is also
+    /// its cache key, so it is left alone if history shows the workflow was reset.
     pub run_id: String,
+    /// The `original_execution_run_id` from the WEStarted event, which is preserved across
+    /// resets. Empty until that event has been applied.
+    pub original_run_id: String,
+    /// If history shows the workflow was reset, the run id produced by the most recent reset
+    pub reset_run_id: Option<String>,
+    /// The shard this run's activations are labeled with, if activation sharding is configured
+    pub activation_shard: u32,
     /// The time the workflow execution began, as told by the WEStarted event
     workflow_start_time: Option<SystemTime>,
     /// The time the workflow execution finished, as determined by when the machines handled
-    /// a terminal workflow command. If this is `Some`, you know the workflow is ended.
+    /// a terminal workflow command, or by the event in which the server ended the run. If this is
+    /// `Some`, you know the workflow is ended.
     workflow_end_time: Option<SystemTime>,
     /// The WFT start time if it has been established
     wft_start_time: Option<SystemTime>,
     /// The current workflow time if it has been established. This may differ from the WFT start
     /// time since local activities may advance the clock
     current_wf_time: Option<SystemTime>,
+    /// The history size reported by the most recently applied WFT started event
+    history_size: HistorySizeInfo,
     /// The internal flags which have been seen so far during this run's execution and thus are
     /// usable during replay.
     observed_internal_flags: InternalFlagsRef,
+    /// The SDK name and version reported in WFT completion metadata
+    sdk_name_and_version: (String, String),
 
     all_machines: SlotMap<MachineKey, Machines>,
     /// If a machine key is in this map, that machine was created internally by core, not as a
@@ -134,15 +153,30 @@ pub(crate) struct WorkflowMachines {
     /// Contains extra local-activity related data
     local_activity_data: LocalActivityData,
 
+    /// The current values of the run properties inherited by continue-as-new if lang doesn't
+    /// override them
+    inherited_run_props: InheritedRunProperties,
+
     /// The workflow that is being driven by this instance of the machines
     drive_me: DrivenWorkflow,
 
     /// Is set to true once we've seen the final event in workflow history, to avoid accidentally
     /// re-applying the final workflow task.
     pub have_seen_terminal_event: bool,
+    /// Is set to true if the run was ended by the server (terminated, timed out, or continued as
+    /// new by a retry or cron schedule) rather than by a command from lang. Lang never learns of
+    /// such an ending through a job, so the run must be evicted for its workflow to be torn down.
+    pub ended_by_server: bool,
 
     /// Metrics context
     pub metrics: MetricsContext,
+    /// Metrics contexts additionally labeled with each kind of machine the run has had, so that
+    /// recording per-event machine metrics does not build a new set of attributes every time
+    machine_kind_metrics: HashMap<String, MetricsContext>,
+
+    /// Every event applied so far, retained only if run state dumps are enabled
+    applied_events: Option<Vec<HistoryEvent>>,
+    payload_size_limits: PayloadSizeLimits,
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -150,6 +184,8 @@ pub(crate) struct WorkflowMachines {
 struct CommandAndMachine {
     command: MachineAssociatedCommand,
     machine: MachineKey,
+    /// When the command was first queued, used to monitor for commands which never leave
+    queued_at: Instant,
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -186,9 +222,13 @@ pub(super) enum MachineResponse {
     TriggerWFTaskStarted {
         task_started_event_id: i64,
         time: SystemTime,
+        history_size: HistorySizeInfo,
     },
     #[display(fmt = "UpdateRunIdOnWorkflowReset({run_id})")]
     UpdateRunIdOnWorkflowReset { run_id: String },
+    /// History shows a workflow task attempt failed or timed out
+    #[display(fmt = "WFTaskAttemptFailed({_0:?})")]
+    WFTaskAttemptFailed(WFTAttemptFailure),
 
     /// Queue a local activity to be processed by the worker
     #[display(fmt = "QueueLocalActivity")]
@@ -223,15 +263,25 @@ impl WorkflowMachines {
         if let Some(attrs) = basics.history.peek_next_wft_completed(0) {
             observed_internal_flags.add_from_complete(attrs);
         };
+        let activation_shard = basics
+            .activation_shard_count
+            .map(|n| activation_shard(&basics.workflow_id, n))
+            .unwrap_or_default();
+        let applied_events = basics.run_state_dump.as_ref().map(|_| vec![]);
+        let payload_size_limits = basics.payload_size_limits;
         Self {
             last_history_from_server: basics.history,
             namespace: basics.namespace,
             workflow_id: basics.workflow_id,
             workflow_type: basics.workflow_type,
             run_id: basics.run_id,
+            original_run_id: String::new(),
+            reset_run_id: None,
+            activation_shard,
             drive_me: driven_wf,
             replaying,
             metrics: basics.metrics,
+            machine_kind_metrics: Default::default(),
             // In an ideal world one could say ..Default::default() here and it'd still work.
             current_started_event_id: 0,
             next_started_event_id: 0,
@@ -240,7 +290,9 @@ impl WorkflowMachines {
             workflow_end_time: None,
             wft_start_time: None,
             current_wf_time: None,
+            history_size: Default::default(),
             observed_internal_flags: Rc::new(RefCell::new(observed_internal_flags)),
+            sdk_name_and_version: basics.sdk_name_and_version,
             all_machines: Default::default(),
             machine_is_core_created: Default::default(),
             machines_by_event_id: Default::default(),
@@ -249,7 +301,81 @@ impl WorkflowMachines {
             current_wf_task_commands: Default::default(),
             encountered_change_markers: Default::default(),
             local_activity_data: LocalActivityData::default(),
+            inherited_run_props: Default::default(),
             have_seen_terminal_event: false,
+            ended_by_server: false,
+            applied_events,
+            payload_size_limits,
+        }
+    }
+
+    /// Snapshot the state of this run for debugging a failure. History is only included if run
+    /// state dumps were enabled when the run was created.
+    pub(crate) fn state_dump(&self, error: String) -> RunStateDump {
+        RunStateDump {
+            workflow_id: self.workflow_id.clone(),
+            run_id: self.run_id.clone(),
+            original_run_id: self.original_run_id.clone(),
+            reset_run_id: self.reset_run_id.clone(),
+            workflow_type: self.workflow_type.clone(),
+            error,
+            history: History {
+                events: self.applied_events.clone().unwrap_or_default(),
+            },
+            pending_commands: self
+                .commands
+                .iter()
+                .chain(self.current_wf_task_commands.iter())
+                .map(|c| c.command.to_string())
+                .collect(),
+            machine_states: self
+                .all_machines
+                .values()
+                .map(|m| format!("{}: {}", m.name(), m.state_name()))
+                .collect(),
+        }
+    }
+
+    /// Snapshot the state of every machine in this run, along with the commands and history events
+    /// associated with each of them
+    pub(crate) fn machines_dump(&self) -> MachinesDump {
+        let mut event_ids: HashMap<MachineKey, Vec<i64>> = HashMap::new();
+        for (eid, mk) in &self.machines_by_event_id {
+            event_ids.entry(*mk).or_default().push(*eid);
+        }
+        let mut pending_commands: HashMap<MachineKey, Vec<String>> = HashMap::new();
+        for c in self
+            .commands
+            .iter()
+            .chain(self.current_wf_task_commands.iter())
+        {
+            pending_commands
+                .entry(c.machine)
+                .or_default()
+                .push(c.command.to_string());
+        }
+        let machines = self
+            .all_machines
+            .iter()
+            .map(|(mk, m)| {
+                let mut event_ids = event_ids.remove(&mk).unwrap_or_default();
+                event_ids.sort_unstable();
+                MachineDump {
+                    kind: m.name().to_string(),
+                    state: m.state_name(),
+                    core_created: self.machine_is_core_created.contains_key(mk),
+                    pending_commands: pending_commands.remove(&mk).unwrap_or_default(),
+                    event_ids,
+                }
+            })
+            .collect();
+        MachinesDump {
+            workflow_id: self.workflow_id.clone(),
+            run_id: self.run_id.clone(),
+            workflow_type: self.workflow_type.clone(),
+            last_processed_event: self.last_processed_event,
+            replaying: self.replaying,
+            machines,
         }
     }
 
@@ -267,6 +393,7 @@ impl WorkflowMachines {
     }
 
     pub(crate) fn new_history_from_server(&mut self, update: HistoryUpdate) -> Result<()> {
+        update.validate_event_ids()?;
         self.last_history_from_server = update;
         self.replaying = self.last_history_from_server.previous_wft_started_id > 0;
         self.apply_next_wft_from_history()?;
@@ -369,6 +496,9 @@ impl WorkflowMachines {
                 .borrow()
                 .all_lang()
                 .collect(),
+            shard: self.activation_shard,
+            history_size_bytes: self.history_size.size_bytes,
+            continue_as_new_suggested: self.history_size.suggest_continue_as_new,
         }
     }
 
@@ -383,10 +513,19 @@ impl WorkflowMachines {
             .any(|v| v.is_la_resolution)
     }
 
+    /// Returns the (sorted) IDs of every patch this run has encountered, whether from markers in
+    /// history or from patch commands issued by lang
+    pub(crate) fn encountered_patches(&self) -> Vec<String> {
+        let mut patches: Vec<_> = self.encountered_change_markers.keys().cloned().collect();
+        patches.sort();
+        patches
+    }
+
     pub(crate) fn get_metadata_for_wft_complete(&self) -> WorkflowTaskCompletedMetadata {
+        let (sdk_name, sdk_version) = &self.sdk_name_and_version;
         (*self.observed_internal_flags)
             .borrow_mut()
-            .gather_for_wft_complete()
+            .gather_for_wft_complete(sdk_name, sdk_version)
     }
 
     pub(crate) fn add_lang_used_flags(&self, flags: Vec<u32>) {
@@ -401,6 +540,7 @@ impl WorkflowMachines {
         let results = self.drive_me.fetch_workflow_iteration_output();
         self.handle_driven_results(results)?;
         self.prepare_commands()?;
+        self.record_command_queue_metrics();
         if self.workflow_is_finished() {
             if let Some(rt) = self.total_runtime() {
                 self.metrics.wf_e2e_latency(rt);
@@ -454,6 +594,7 @@ impl WorkflowMachines {
             NextWFT::WFT(mut evts, has_final_event) => {
                 // Do not re-process events we have already processed
                 evts.retain(|e| e.event_id > self.last_processed_event);
+                validate_event_id_sequence(self.last_processed_event, &evts)?;
                 (evts, has_final_event)
             }
             NextWFT::NeedFetch => {
@@ -465,6 +606,11 @@ impl WorkflowMachines {
             }
         };
         let num_events_to_process = events.len();
+        // The whole task is recorded up front, so that a dump taken after failing partway through
+        // it can still be used to reproduce the failure.
+        if let Some(applied) = self.applied_events.as_mut() {
+            applied.extend(events.iter().cloned());
+        }
 
         // We're caught up on reply if there are no new events to process
         if events.is_empty() {
@@ -482,12 +628,6 @@ impl WorkflowMachines {
         let mut do_handle_event = true;
         let mut history = events.into_iter().peekable();
         while let Some(event) = history.next() {
-            if event.event_id != self.last_processed_event + 1 {
-                return Err(WFMachinesError::Fatal(format!(
-                    "History is out of order. Last processed event: {}, event id: {}",
-                    self.last_processed_event, event.event_id
-                )));
-            }
             let next_event = history.peek();
             let eid = event.event_id;
 
@@ -507,21 +647,16 @@ impl WorkflowMachines {
             }
 
             if do_handle_event {
-                let eho = self.handle_event(
-                    HistEventData {
-                        event,
-                        replaying: self.replaying,
-                        current_task_is_last_in_history: has_final_event,
-                    },
-                    next_event,
-                )?;
-                if matches!(
-                    eho,
-                    EventHandlingOutcome::SkipEvent {
-                        skip_next_event: true
-                    }
-                ) {
-                    do_handle_event = false;
+                match self.fast_path_skip(&event, next_event) {
+                    Some(skip_next_event) => do_handle_event = !skip_next_event,
+                    None => self.handle_event(
+                        HistEventData {
+                            event,
+                            replaying: self.replaying,
+                            current_task_is_last_in_history: has_final_event,
+                        },
+                        next_event,
+                    )?,
                 }
             } else {
                 do_handle_event = true;
@@ -549,7 +684,7 @@ impl WorkflowMachines {
                         .into(),
                 );
             } else if e.is_local_activity_marker() {
-                if let Some(la_dat) = e.clone().into_local_activity_marker_details() {
+                if let Some(la_dat) = e.clone().into_local_activity_marker_details()? {
                     if let Ok(mk) =
                         self.get_machine_key(CommandID::LocalActivity(la_dat.marker_dat.seq))
                     {
@@ -557,10 +692,6 @@ impl WorkflowMachines {
                     } else {
                         self.local_activity_data.insert_peeked_marker(la_dat);
                     }
-                } else {
-                    return Err(WFMachinesError::Fatal(format!(
-                        "Local activity marker was unparsable: {e:?}"
-                    )));
                 }
             }
         }
@@ -576,7 +707,18 @@ impl WorkflowMachines {
             }
         }
 
+        // Machines may issue commands of their own accord while handling events. Ex: when an
+        // intermediate segment of a split timer fires, its machine issues the start command for the
+        // next segment, but lang is not told anything. Commands are normally prepared after lang
+        // completes an activation, which in that case never happens. Without preparing them here,
+        // the command would not be sent to the server with this WFT's completion, and when
+        // replaying, the next segment's TimerStarted event would not match any command.
+        if !self.has_pending_jobs() && !self.current_wf_task_commands.is_empty() {
+            self.prepare_commands()?;
+        }
+
         update_internal_flags(self);
+        self.record_command_queue_metrics();
 
         if !self.replaying {
             self.metrics.wf_task_replay_latency(replay_start.elapsed());
@@ -597,42 +739,47 @@ impl WorkflowMachines {
         &mut self,
         event_dat: HistEventData,
         next_event: Option<&HistoryEvent>,
-    ) -> Result<EventHandlingOutcome> {
+    ) -> Result<()> {
         let event = &event_dat.event;
         if event.is_final_wf_execution_event() {
             self.have_seen_terminal_event = true;
         }
-        if matches!(
-            event.event_type(),
-            EventType::WorkflowExecutionTerminated | EventType::WorkflowExecutionTimedOut
-        ) {
+        // Lang may have asked to end the run itself, if the server recorded that as a
+        // continue-as-new. Then the event belongs to lang's command like any other.
+        let server_ended_run = is_server_initiated_run_end(event)
+            && !self
+                .commands
+                .iter()
+                .any(|c| self.machine(c.machine).matches_event(event));
+        if server_ended_run {
             let are_more_events =
                 next_event.is_some() || !event_dat.current_task_is_last_in_history;
-            return if are_more_events {
-                Err(WFMachinesError::Fatal(
+            if are_more_events {
+                return Err(WFMachinesError::Fatal(
                     "Machines were fed a history which has an event after workflow execution was \
                      terminated!"
                         .to_string(),
-                ))
-            } else {
-                Ok(EventHandlingOutcome::Normal)
-            };
+                ));
+            }
+        }
+        // Ignorable events of these kinds never make it here, see [Self::fast_path_skip]
+        if let Some(feature) = unsupported_feature(event) {
+            return Err(WFMachinesError::UnsupportedFeature {
+                event_id: event.event_id,
+                event_type: EventType::from_i32(event.event_type)
+                    .map(|et| format!("{et:?}"))
+                    .unwrap_or_else(|| format!("<unknown: {}>", event.event_type)),
+                feature,
+            });
         }
         if event.event_type() == EventType::Unspecified || event.attributes.is_none() {
-            return if !event.worker_may_ignore {
-                Err(WFMachinesError::Fatal(format!(
-                    "Event type is unspecified! This history is invalid. Event detail: {event:?}"
-                )))
-            } else {
-                debug!("Event is ignorable");
-                Ok(EventHandlingOutcome::SkipEvent {
-                    skip_next_event: false,
-                })
-            };
+            return Err(WFMachinesError::Fatal(format!(
+                "Event type is unspecified! This history is invalid. Event detail: {event:?}"
+            )));
         }
 
-        if event.is_command_event() {
-            return self.handle_command_event(event_dat, next_event);
+        if event.is_command_event() && !server_ended_run {
+            return self.handle_command_event(event_dat);
         }
 
         if let Some(initial_cmd_id) = event.get_initial_command_event_id() {
@@ -658,7 +805,45 @@ impl WorkflowMachines {
             self.handle_non_stateful_event(event_dat)?;
         }
 
-        Ok(EventHandlingOutcome::Normal)
+        Ok(())
+    }
+
+    /// Cheaply determines whether an event can be skipped without going through
+    /// [Self::handle_event] at all, because it cannot affect any machine or be observed by lang.
+    /// Full handling has a fixed per-event cost (a tracing span, matching against machines, etc)
+    /// which adds up when replaying large histories. If the event can be skipped, returns whether
+    /// the event following it should be skipped too.
+    fn fast_path_skip(
+        &self,
+        event: &HistoryEvent,
+        next_event: Option<&HistoryEvent>,
+    ) -> Option<bool> {
+        // Events the server says we may ignore, which we could not have handled anyway
+        if event.worker_may_ignore
+            && (event.event_type() == EventType::Unspecified
+                || event.attributes.is_none()
+                || unsupported_feature(event).is_some())
+        {
+            return Some(false);
+        }
+        // Deprecated patch markers may be ignored unless the workflow made the corresponding
+        // patch call, in which case the next command is for that patch. So may the patch search
+        // attribute upsert which is recorded right after them. If no command is queued at all,
+        // full handling reports the marker as nondeterministic.
+        //
+        // Other search attribute upserts are never skipped. Each is recorded for a command, and
+        // must be matched against it for nondeterminism to be detected.
+        if let Some((_, true)) = event.get_patch_marker_details() {
+            let next_cmd = self.commands.front()?;
+            if !self.machine(next_cmd.machine).matches_event(event) {
+                trace!(
+                    event_id = event.event_id,
+                    "Skipping deprecated patch marker"
+                );
+                return Some(is_patch_search_attr_upsert(next_event));
+            }
+        }
+        None
     }
 
     /// A command event is an event which is generated from a command emitted as a result of
@@ -670,15 +855,11 @@ impl WorkflowMachines {
     /// The handling consists of verifying that the next command in the commands queue is associated
     /// with a state machine, which is then notified about the event and the command is removed from
     /// the commands queue.
-    fn handle_command_event(
-        &mut self,
-        event_dat: HistEventData,
-        next_event: Option<&HistoryEvent>,
-    ) -> Result<EventHandlingOutcome> {
+    fn handle_command_event(&mut self, event_dat: HistEventData) -> Result<()> {
         let event = &event_dat.event;
 
         if event.is_local_activity_marker() {
-            let deets = event.extract_local_activity_marker_data().ok_or_else(|| {
+            let deets = event.extract_local_activity_marker_data()?.ok_or_else(|| {
                 WFMachinesError::Fatal(format!("Local activity marker was unparsable: {event:?}"))
             })?;
             let cmdid = CommandID::LocalActivity(deets.seq);
@@ -686,7 +867,7 @@ impl WorkflowMachines {
             if let Machines::LocalActivityMachine(lam) = self.machine(mkey) {
                 if lam.marker_should_get_special_handling()? {
                     self.submachine_handle_event(mkey, event_dat)?;
-                    return Ok(EventHandlingOutcome::Normal);
+                    return Ok(());
                 }
             } else {
                 return Err(WFMachinesError::Fatal(format!(
@@ -701,12 +882,11 @@ impl WorkflowMachines {
         let consumed_cmd = loop {
             if let Some(peek_machine) = self.commands.front() {
                 let mach = self.machine(peek_machine.machine);
-                match change_marker_handling(event, mach, next_event)? {
+                match change_marker_handling(event, mach)? {
                     EventHandlingOutcome::SkipCommand => {
                         self.commands.pop_front();
                         continue;
                     }
-                    eho @ EventHandlingOutcome::SkipEvent { .. } => return Ok(eho),
                     EventHandlingOutcome::Normal => {}
                 }
             }
@@ -736,7 +916,7 @@ impl WorkflowMachines {
                 .insert(event_id, consumed_cmd.machine);
         }
 
-        Ok(EventHandlingOutcome::Normal)
+        Ok(())
     }
 
     fn handle_non_stateful_event(&mut self, event_dat: HistEventData) -> Result<()> {
@@ -759,10 +939,12 @@ impl WorkflowMachines {
                         // workflow time set.
                         self.set_current_time(as_systime);
                     }
+                    self.inherited_run_props = InheritedRunProperties::new(&attrs);
+                    self.original_run_id = attrs.original_execution_run_id.clone();
                     // Notify the lang sdk that it's time to kick off a workflow
                     self.drive_me.start(
                         self.workflow_id.clone(),
-                        str_to_randomness_seed(&attrs.original_execution_run_id),
+                        randomness_seed_from_run_id(&attrs.original_execution_run_id),
                         event_dat.event.event_time.unwrap_or_default(),
                         attrs,
                     );
@@ -802,6 +984,22 @@ impl WorkflowMachines {
                     // err
                 }
             }
+            Some(
+                EventType::WorkflowExecutionTerminated
+                | EventType::WorkflowExecutionTimedOut
+                | EventType::WorkflowExecutionContinuedAsNew,
+            ) => {
+                // The server ended the run without any command from lang. There is nothing for lang
+                // to do about it, so record that the run is over and when it ended. The run is then
+                // evicted, which is how lang learns it should drop the workflow.
+                self.have_seen_terminal_event = true;
+                self.ended_by_server = true;
+                let end_time = match event_dat.event.event_time.clone() {
+                    Some(et) => et.try_into()?,
+                    None => SystemTime::now(),
+                };
+                self.workflow_end_time = Some(end_time);
+            }
             _ => {
                 return Err(WFMachinesError::Fatal(format!(
                     "The event is not a non-stateful event, but we tried to handle it as one: {event_dat}"
@@ -822,11 +1020,49 @@ impl WorkflowMachines {
     /// Wrapper for calling [TemporalStateMachine::handle_event] which appropriately takes action
     /// on the returned machine responses
     fn submachine_handle_event(&mut self, sm: MachineKey, event: HistEventData) -> Result<()> {
+        let start = Instant::now();
         let machine_responses = self.machine_mut(sm).handle_event(event)?;
         self.process_machine_responses(sm, machine_responses)?;
+        if self.metrics.detailed_metrics_enabled() {
+            let kind = self.machine(sm).name();
+            match self.machine_kind_metrics.get(kind) {
+                Some(metrics) => metrics.wf_machine_event_handled(start.elapsed()),
+                None => {
+                    let kind = kind.to_string();
+                    let metrics = self.metrics.with_new_attrs([machine_kind(kind.clone())]);
+                    metrics.wf_machine_event_handled(start.elapsed());
+                    self.machine_kind_metrics.insert(kind, metrics);
+                }
+            }
+        }
         Ok(())
     }
 
+    /// Records the depths of the command queues and the age of the oldest command in them. Commands
+    /// only leave `commands` once they're matched with events in history, so steady growth of
+    /// either measure means commands are piling up which never will be.
+    fn record_command_queue_metrics(&self) {
+        if !self.metrics.detailed_metrics_enabled() {
+            return;
+        }
+        self.metrics
+            .with_new_attrs([command_queue("commands")])
+            .wf_command_queue_depth(self.commands.len());
+        self.metrics
+            .with_new_attrs([command_queue("current_wf_task_commands")])
+            .wf_command_queue_depth(self.current_wf_task_commands.len());
+        // Commands move from the current task's queue to the back of `commands`, so the oldest is
+        // at the front of the latter if it has any.
+        if let Some(oldest) = self
+            .commands
+            .front()
+            .or_else(|| self.current_wf_task_commands.front())
+        {
+            self.metrics
+                .wf_oldest_queued_command_age(oldest.queued_at.elapsed());
+        }
+    }
+
     /// Transfer commands from `current_wf_task_commands` to `commands`, so they may be sent off
     /// to the server. While doing so, [TemporalStateMachine::handle_command] is called on the
     /// machine associated with the command.
@@ -849,6 +1085,9 @@ impl WorkflowMachines {
             {
                 match &c.command {
                     MachineAssociatedCommand::Real(cmd) => {
+                        if !self.replaying {
+                            self.check_payload_sizes(cmd)?;
+                        }
                         let machine_responses = self
                             .machine_mut(c.machine)
                             .handle_command(cmd.command_type())?;
@@ -863,6 +1102,33 @@ impl WorkflowMachines {
         Ok(())
     }
 
+    /// Warns about or rejects commands with any field holding payloads larger than configured
+    fn check_payload_sizes(&self, cmd: &ProtoCommand) -> Result<()> {
+        let limits = self.payload_size_limits;
+        if limits.warning_threshold.is_none() && limits.limit.is_none() {
+            return Ok(());
+        }
+        let attrs = match cmd.attributes.as_ref() {
+            Some(a) => a,
+            None => return Ok(()),
+        };
+        for (field, size) in command_payload_sizes(attrs) {
+            if let Some(limit) = limits.limit.filter(|l| size > *l) {
+                return Err(WFMachinesError::PayloadTooLarge {
+                    command: format!("{:?}", cmd.command_type()),
+                    field,
+                    size,
+                    limit,
+                });
+            }
+            if matches!(limits.warning_threshold, Some(t) if size > t) {
+                warn!(run_id=%self.run_id, command=?cmd.command_type(), field, size,
+                      "Command payloads exceed the configured warning threshold");
+            }
+        }
+        Ok(())
+    }
+
     /// After a machine handles either an event or a command, it produces [MachineResponses] which
     /// this function uses to drive sending jobs to lang, triggering new workflow tasks, etc.
     fn process_machine_responses(
@@ -894,21 +1160,30 @@ impl WorkflowMachines {
                 MachineResponse::TriggerWFTaskStarted {
                     task_started_event_id,
                     time,
+                    history_size,
                 } => {
+                    self.history_size = history_size;
                     self.task_started(task_started_event_id, time)?;
                 }
                 MachineResponse::UpdateRunIdOnWorkflowReset { run_id: new_run_id } => {
+                    debug!(run_id=%self.run_id, original_run_id=%self.original_run_id,
+                           new_run_id=%new_run_id, "History shows workflow was reset");
                     self.drive_me.send_job(
                         workflow_activation_job::Variant::UpdateRandomSeed(UpdateRandomSeed {
-                            randomness_seed: str_to_randomness_seed(&new_run_id),
+                            randomness_seed: randomness_seed_from_run_id(&new_run_id),
                         })
                         .into(),
                     );
+                    self.reset_run_id = Some(new_run_id);
+                }
+                MachineResponse::WFTaskAttemptFailed(failure) => {
+                    self.wft_attempt_failed(failure);
                 }
                 MachineResponse::IssueNewCommand(c) => {
                     self.current_wf_task_commands.push_back(CommandAndMachine {
                         command: MachineAssociatedCommand::Real(Box::new(c)),
                         machine: smk,
+                        queued_at: Instant::now(),
                     })
                 }
                 MachineResponse::NewCoreOriginatedCommand(attrs) => match attrs {
@@ -941,6 +1216,7 @@ impl WorkflowMachines {
                     self.current_wf_task_commands.push_back(CommandAndMachine {
                         command: MachineAssociatedCommand::FakeLocalActivityMarker(seq),
                         machine: smk,
+                        queued_at: Instant::now(),
                     });
                 }
                 MachineResponse::QueueLocalActivity(act) => {
@@ -1022,6 +1298,23 @@ impl WorkflowMachines {
         Ok(())
     }
 
+    /// Called when history shows a workflow task attempt failed or timed out. Server discards any
+    /// commands produced in response to a failed attempt. Nothing needs undoing for that, since
+    /// commands stay in [Self::commands] until the events they produce are seen, and every
+    /// completion sends all of them - so they simply go out again with the next one.
+    fn wft_attempt_failed(&mut self, failure: WFTAttemptFailure) {
+        debug!(run_id=%self.run_id, cause=failure.cause, replaying=self.replaying,
+               "Workflow task attempt failed or timed out");
+        self.metrics
+            .with_new_attrs([wft_failure_cause(failure.cause)])
+            .wf_task_attempt_failed_in_history();
+        if failure.after_start && !self.commands.is_empty() {
+            debug!(commands = %self.commands.display(),
+                   "Commands from failed workflow task attempt were not recorded and will be \
+                    sent again");
+        }
+    }
+
     /// Handles results of the workflow activation, delegating work to the appropriate state
     /// machine. Returns a list of workflow jobs that should be queued in the pending activation for
     /// the next poll. This list will be populated only if state machine produced lang activations
@@ -1036,6 +1329,8 @@ impl WorkflowMachines {
                     self.add_cmd_to_wf_task(new_timer(attrs), CommandID::Timer(seq).into());
                 }
                 WFCommand::UpsertSearchAttributes(attrs) => {
+                    self.inherited_run_props
+                        .upsert_search_attributes(&attrs.search_attributes);
                     self.add_cmd_to_wf_task(
                         upsert_search_attrs(
                             attrs,
@@ -1096,8 +1391,7 @@ impl WorkflowMachines {
                 }
                 WFCommand::ContinueAsNew(attrs) => {
                     self.metrics.wf_continued_as_new();
-                    let attrs = self.augment_continue_as_new_with_current_values(attrs);
-                    self.add_terminal_command(continue_as_new(attrs));
+                    self.add_terminal_command(continue_as_new(attrs, &self.inherited_run_props));
                 }
                 WFCommand::CancelWorkflow(attrs) => {
                     self.metrics.wf_canceled();
@@ -1186,15 +1480,24 @@ impl WorkflowMachines {
                     self.process_cancellation(CommandID::SignalExternal(attrs.seq))?;
                 }
                 WFCommand::QueryResponse(_) => {
-                    // Nothing to do here, queries are handled above the machine level
-                    unimplemented!("Query responses should not make it down into the machines")
+                    // Queries are answered above the machine level, by the run which strips their
+                    // responses out of completions before handing commands to the machines.
+                    return Err(WFMachinesError::Fatal(
+                        "Query responses should not make it down into the machines".to_string(),
+                    ));
                 }
                 WFCommand::ModifyWorkflowProperties(attrs) => {
+                    if let Some(memo) = attrs.upserted_memo.as_ref() {
+                        self.inherited_run_props.upsert_memo(memo);
+                    }
                     self.add_cmd_to_wf_task(
                         modify_workflow_properties(attrs),
                         CommandIdKind::NeverResolves,
                     );
                 }
+                WFCommand::RecordMarker(attrs) => {
+                    self.add_cmd_to_wf_task(record_marker(attrs)?, CommandIdKind::NeverResolves);
+                }
                 WFCommand::NoCommandsFromLang => (),
             }
         }
@@ -1247,6 +1550,7 @@ impl WorkflowMachines {
         CommandAndMachine {
             command: MachineAssociatedCommand::Real(Box::new(machine.command)),
             machine: k,
+            queued_at: Instant::now(),
         }
     }
 
@@ -1263,76 +1567,482 @@ impl WorkflowMachines {
             .expect("Machine must exist")
             .borrow_mut()
     }
+}
+
+/// Maps a workflow id onto one of `num_shards` shards using jump consistent hashing, so that
+/// changing the number of shards moves as few workflows between shards as possible. Like the
+/// randomness seed, the hash must remain stable across releases since lang may persist routing
+/// decisions.
+fn activation_shard(workflow_id: &str, num_shards: u32) -> u32 {
+    let mut key = deterministic_hash(HashVersion::V1, workflow_id);
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < num_shards as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b.max(0) as u32
+}
 
-    fn augment_continue_as_new_with_current_values(
-        &self,
-        mut attrs: ContinueAsNewWorkflowExecution,
-    ) -> ContinueAsNewWorkflowExecution {
-        if let Some(started_info) = self.drive_me.get_started_info() {
-            if attrs.memo.is_empty() {
-                attrs.memo = started_info
-                    .memo
-                    .clone()
-                    .map(Into::into)
-                    .unwrap_or_default();
-            }
-            if attrs.search_attributes.is_empty() {
-                attrs.search_attributes = started_info
-                    .search_attrs
-                    .clone()
-                    .map(Into::into)
-                    .unwrap_or_default();
+/// The encoded size of each field of a command which holds payloads, named as in its proto
+fn command_payload_sizes(attrs: &ProtoCmdAttrs) -> Vec<(&'static str, usize)> {
+    fn len<M: Message>(m: &Option<M>) -> usize {
+        m.as_ref().map_or(0, Message::encoded_len)
+    }
+    match attrs {
+        ProtoCmdAttrs::ScheduleActivityTaskCommandAttributes(a) => {
+            vec![("input", len(&a.input)), ("header", len(&a.header))]
+        }
+        ProtoCmdAttrs::CompleteWorkflowExecutionCommandAttributes(a) => {
+            vec![("result", len(&a.result))]
+        }
+        ProtoCmdAttrs::FailWorkflowExecutionCommandAttributes(a) => {
+            vec![("failure", len(&a.failure))]
+        }
+        ProtoCmdAttrs::CancelWorkflowExecutionCommandAttributes(a) => {
+            vec![("details", len(&a.details))]
+        }
+        ProtoCmdAttrs::RecordMarkerCommandAttributes(a) => vec![
+            (
+                "details",
+                a.details.values().map(Message::encoded_len).sum(),
+            ),
+            ("header", len(&a.header)),
+            ("failure", len(&a.failure)),
+        ],
+        ProtoCmdAttrs::ContinueAsNewWorkflowExecutionCommandAttributes(a) => vec![
+            ("input", len(&a.input)),
+            ("header", len(&a.header)),
+            ("memo", len(&a.memo)),
+            ("search_attributes", len(&a.search_attributes)),
+            ("failure", len(&a.failure)),
+            ("last_completion_result", len(&a.last_completion_result)),
+        ],
+        ProtoCmdAttrs::StartChildWorkflowExecutionCommandAttributes(a) => vec![
+            ("input", len(&a.input)),
+            ("header", len(&a.header)),
+            ("memo", len(&a.memo)),
+            ("search_attributes", len(&a.search_attributes)),
+        ],
+        ProtoCmdAttrs::SignalExternalWorkflowExecutionCommandAttributes(a) => {
+            vec![("input", len(&a.input)), ("header", len(&a.header))]
+        }
+        ProtoCmdAttrs::UpsertWorkflowSearchAttributesCommandAttributes(a) => {
+            vec![("search_attributes", len(&a.search_attributes))]
+        }
+        ProtoCmdAttrs::ModifyWorkflowPropertiesCommandAttributes(a) => {
+            vec![("upserted_memo", len(&a.upserted_memo))]
+        }
+        _ => vec![],
+    }
+}
+
+/// Returns true if the event may end the run without having been caused by a command from lang.
+/// Continue-as-new is normally requested by the workflow, but older servers also record it when
+/// retrying a workflow or starting the next run of a cron workflow. That may still have been
+/// caused by lang completing or failing the workflow, so the caller must check for such commands.
+fn is_server_initiated_run_end(event: &HistoryEvent) -> bool {
+    match event.event_type() {
+        EventType::WorkflowExecutionTerminated | EventType::WorkflowExecutionTimedOut => true,
+        EventType::WorkflowExecutionContinuedAsNew => is_continued_as_new_by(
+            event,
+            &[
+                ContinueAsNewInitiator::Retry,
+                ContinueAsNewInitiator::CronSchedule,
+            ],
+        ),
+        _ => false,
+    }
+}
+
+/// If the event was produced by a feature this worker build does not support, returns a
+/// description of that feature. Such events can't be handled by any machine, and reporting them
+/// as such is much more useful than the generic error they would otherwise produce.
+fn unsupported_feature(event: &HistoryEvent) -> Option<&'static str> {
+    match EventType::from_i32(event.event_type) {
+        // Event types newer than the API definitions this build was compiled with
+        None => Some("event type is unknown to this worker build"),
+        Some(
+            EventType::WorkflowExecutionUpdateAccepted
+            | EventType::WorkflowExecutionUpdateRejected
+            | EventType::WorkflowExecutionUpdateCompleted,
+        ) => Some("workflow update"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::TestHistoryBuilder, test_help::canned_histories, worker::workflow::ManagedWFFunc,
+    };
+    use futures::StreamExt;
+    use temporal_sdk::{WfContext, WorkflowFunction};
+    use temporal_sdk_core_protos::{
+        coresdk::{
+            workflow_activation::{remove_from_cache::EvictionReason, WorkflowActivationJob},
+            AsJsonPayloadExt,
+        },
+        temporal::api::{
+            common::v1::Payloads,
+            enums::v1::{CommandType, WorkflowTaskFailedCause},
+            history::v1::{
+                history_event::Attributes, WorkflowExecutionContinuedAsNewEventAttributes,
+                WorkflowExecutionTerminatedEventAttributes, WorkflowTaskStartedEventAttributes,
+            },
+        },
+    };
+
+    fn never_completing_wf() -> WorkflowFunction {
+        WorkflowFunction::new(|_: WfContext| async {
+            futures::future::pending::<()>().await;
+            Ok(().into())
+        })
+    }
+
+    #[tokio::test]
+    async fn update_events_are_unsupported() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        let update_eid = t.add(Attributes::WorkflowExecutionUpdateAcceptedEventAttributes(
+            Default::default(),
+        ));
+        t.add_workflow_task_scheduled_and_started();
+
+        let mut wfm = ManagedWFFunc::new(t, never_completing_wf(), vec![]);
+        wfm.get_next_activation().await.unwrap();
+        let err = wfm.get_next_activation().await.unwrap_err();
+        assert_matches!(
+            &err,
+            WFMachinesError::UnsupportedFeature { event_id, feature: "workflow update", .. }
+                if *event_id == update_eid
+        );
+        assert!(err.to_string().contains("WorkflowExecutionUpdateAccepted"));
+        assert_eq!(err.evict_reason(), EvictionReason::Fatal);
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn ignorable_unknown_events_are_skipped() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        t.add(Attributes::WorkflowExecutionUpdateAcceptedEventAttributes(
+            Default::default(),
+        ));
+        t.modify_event(5, |e| {
+            e.event_type = 10_000;
+            e.attributes = None;
+            e.worker_may_ignore = true;
+        });
+        t.add_workflow_task_scheduled_and_started();
+
+        let mut wfm = ManagedWFFunc::new(t, never_completing_wf(), vec![]);
+        wfm.get_next_activation().await.unwrap();
+        wfm.get_next_activation().await.unwrap();
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reset_records_run_ids_and_delivers_reapplied_signals() {
+        let mut t = canned_histories::single_timer("1");
+        t.add_workflow_task_failed_new_id(WorkflowTaskFailedCause::ResetWorkflow, "reset-run");
+        t.add_we_signaled("sig", vec![]);
+        t.add_workflow_task_scheduled_and_started();
+        let orig_run_id = t.get_full_history_info().unwrap().orig_run_id().to_string();
+
+        let mut wfm = ManagedWFFunc::new(
+            t,
+            WorkflowFunction::new(|ctx: WfContext| async move {
+                ctx.timer(Duration::from_secs(1)).await;
+                ctx.make_signal_channel("sig").next().await;
+                futures::future::pending::<()>().await;
+                Ok(().into())
+            }),
+            vec![],
+        );
+        wfm.get_next_activation().await.unwrap();
+        let act = wfm.get_next_activation().await.unwrap();
+        assert_matches!(
+            act.jobs.as_slice(),
+            [
+                WorkflowActivationJob {
+                    variant: Some(workflow_activation_job::Variant::FireTimer(_)),
+                },
+                WorkflowActivationJob {
+                    variant: Some(workflow_activation_job::Variant::UpdateRandomSeed(_)),
+                },
+                WorkflowActivationJob {
+                    variant: Some(workflow_activation_job::Variant::SignalWorkflow(_)),
+                },
+            ]
+        );
+        assert_eq!(wfm.machines().run_id, "runid");
+        assert_eq!(wfm.machines().original_run_id, orig_run_id);
+        assert_eq!(wfm.machines().reset_run_id.as_deref(), Some("reset-run"));
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn start_workflow_carries_original_run_id_when_replaying_reset_history() {
+        let mut t = canned_histories::single_timer("1");
+        t.add_workflow_task_failed_new_id(WorkflowTaskFailedCause::ResetWorkflow, "reset-run");
+        t.add_workflow_task_scheduled_and_started();
+        let orig_run_id = t.get_full_history_info().unwrap().orig_run_id().to_string();
+
+        let mut wfm = ManagedWFFunc::new(
+            t,
+            WorkflowFunction::new(|ctx: WfContext| async move {
+                ctx.timer(Duration::from_secs(1)).await;
+                futures::future::pending::<()>().await;
+                Ok(().into())
+            }),
+            vec![],
+        );
+        let act = wfm.get_next_activation().await.unwrap();
+        assert!(act.is_replaying);
+        assert_eq!(act.run_id, "runid");
+        let start = act
+            .jobs
+            .iter()
+            .find_map(|j| match &j.variant {
+                Some(workflow_activation_job::Variant::StartWorkflow(sw)) => Some(sw),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(start.original_execution_run_id, orig_run_id);
+        assert_ne!(act.run_id, start.original_execution_run_id);
+        // The reset is only seen once replay reaches it, after StartWorkflow was delivered. Lang
+        // learns of it through the reseeded randomness.
+        assert_eq!(wfm.machines().reset_run_id, None);
+        let act = wfm.get_next_activation().await.unwrap();
+        let reseed = act
+            .jobs
+            .iter()
+            .find_map(|j| match &j.variant {
+                Some(workflow_activation_job::Variant::UpdateRandomSeed(u)) => Some(u),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            reseed.randomness_seed,
+            randomness_seed_from_run_id("reset-run")
+        );
+        assert_eq!(wfm.machines().reset_run_id.as_deref(), Some("reset-run"));
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn history_size_and_continue_as_new_suggestion_reach_lang() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        t.add_we_signaled("sig", vec![]);
+        t.add_workflow_task_scheduled_and_started();
+        t.modify_event(7, |e| {
+            e.attributes = Some(
+                WorkflowTaskStartedEventAttributes {
+                    scheduled_event_id: 6,
+                    history_size_bytes: 2048,
+                    suggest_continue_as_new: true,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        });
+
+        let mut wfm = ManagedWFFunc::new(t, never_completing_wf(), vec![]);
+        let act = wfm.get_next_activation().await.unwrap();
+        assert_eq!(act.history_size_bytes, 0);
+        assert!(!act.continue_as_new_suggested);
+        let act = wfm.get_next_activation().await.unwrap();
+        assert_eq!(act.history_length, 7);
+        assert_eq!(act.history_size_bytes, 2048);
+        assert!(act.continue_as_new_suggested);
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn event_id_gap_is_invalid_history() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        t.add_we_signaled("sig", vec![]);
+        t.add_full_wf_task();
+        t.modify_event(5, |e| e.event_id = 7);
+
+        let mut wfm = ManagedWFFunc::new(t, never_completing_wf(), vec![]);
+        wfm.get_next_activation().await.unwrap();
+        let err = wfm.get_next_activation().await.unwrap_err();
+        assert_matches!(
+            &err,
+            WFMachinesError::InvalidHistory(e)
+                if e.previous_event_id == 4 && e.event_id == 7
+        );
+        assert_eq!(err.evict_reason(), EvictionReason::Fatal);
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[rstest::rstest]
+    #[case::terminated(EventType::WorkflowExecutionTerminated)]
+    #[case::timed_out(EventType::WorkflowExecutionTimedOut)]
+    #[case::cron_continue_as_new(EventType::WorkflowExecutionContinuedAsNew)]
+    #[tokio::test]
+    async fn server_ended_run_does_not_cancel_workflow(#[case] end_type: EventType) {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        match end_type {
+            EventType::WorkflowExecutionTerminated => {
+                t.add(WorkflowExecutionTerminatedEventAttributes {
+                    details: Some(Payloads {
+                        payloads: vec!["reason".as_json_payload().unwrap()],
+                    }),
+                    ..Default::default()
+                });
             }
-            if attrs.retry_policy.is_none() {
-                attrs.retry_policy = started_info.retry_policy.clone();
+            EventType::WorkflowExecutionContinuedAsNew => {
+                t.add(WorkflowExecutionContinuedAsNewEventAttributes {
+                    initiator: ContinueAsNewInitiator::CronSchedule as i32,
+                    ..Default::default()
+                });
             }
+            _ => t.add_workflow_execution_timed_out(),
         }
-        attrs
+
+        let mut wfm = ManagedWFFunc::new(t, never_completing_wf(), vec![]);
+        wfm.get_next_activation().await.unwrap();
+        let act = wfm.get_next_activation().await.unwrap();
+        assert!(!act.jobs.iter().any(|j| matches!(
+            j.variant,
+            Some(workflow_activation_job::Variant::CancelWorkflow(_))
+        )));
+        assert!(wfm.machines().have_seen_terminal_event);
+        assert!(wfm.machines().ended_by_server);
+        wfm.shutdown().await.unwrap();
     }
-}
 
-fn str_to_randomness_seed(run_id: &str) -> u64 {
-    // This was originally `DefaultHasher` but that is potentially unstable across Rust releases.
-    // This must forever be `SipHasher13` now or we risk breaking history compat.
-    let mut s = SipHasher13::new();
-    run_id.hash(&mut s);
-    s.finish()
+    #[rstest::rstest]
+    #[case::cron_completion(ContinueAsNewInitiator::CronSchedule, false)]
+    #[case::cron_failure(ContinueAsNewInitiator::CronSchedule, true)]
+    #[case::retry(ContinueAsNewInitiator::Retry, true)]
+    #[tokio::test]
+    async fn server_recorded_continue_as_new_matches_lang_run_end(
+        #[case] initiator: ContinueAsNewInitiator,
+        #[case] fails: bool,
+    ) {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_full_wf_task();
+        t.add(WorkflowExecutionContinuedAsNewEventAttributes {
+            initiator: initiator as i32,
+            ..Default::default()
+        });
+
+        let wff = WorkflowFunction::new(move |_: WfContext| async move {
+            if fails {
+                Err(anyhow::anyhow!("Oh no"))
+            } else {
+                Ok(().into())
+            }
+        });
+        let mut wfm = ManagedWFFunc::new(t, wff, vec![]);
+        wfm.process_all_activations().await.unwrap();
+        // Lang's command ended the run, so it's not treated as ended by the server
+        assert!(wfm.machines().have_seen_terminal_event);
+        assert!(!wfm.machines().ended_by_server);
+        assert!(wfm.get_server_commands().commands.is_empty());
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn commands_from_failed_wft_attempt_are_sent_again() {
+        let mut t = TestHistoryBuilder::default();
+        t.add_by_type(EventType::WorkflowExecutionStarted);
+        t.add_workflow_task_scheduled_and_started();
+        t.add_workflow_task_failed_with_failure(
+            WorkflowTaskFailedCause::WorkflowWorkerUnhandledFailure,
+            Default::default(),
+        );
+        t.add_workflow_task_scheduled_and_started();
+
+        let mut wfm = ManagedWFFunc::new_from_update(
+            t.get_history_info(1).unwrap().into(),
+            WorkflowFunction::new(|ctx: WfContext| async move {
+                ctx.timer(Duration::from_secs(1)).await;
+                Ok(().into())
+            }),
+            vec![],
+        );
+        wfm.get_next_activation().await.unwrap();
+        let commands = wfm.get_server_commands().commands;
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].command_type, CommandType::StartTimer as i32);
+
+        // The attempt fails, and the server hands us the next one
+        wfm.new_history(t.get_full_history_info().unwrap().into())
+            .await
+            .unwrap();
+        let commands = wfm.get_server_commands().commands;
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].command_type, CommandType::StartTimer as i32);
+        wfm.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn activation_shards_are_consistent() {
+        let wf_ids: Vec<_> = (0..1000).map(|i| format!("wf-{i}")).collect();
+        let shards_of = |n| {
+            wf_ids
+                .iter()
+                .map(|id| activation_shard(id, n))
+                .collect::<Vec<_>>()
+        };
+        let four = shards_of(4);
+        assert_eq!(four, shards_of(4));
+        assert!(four.iter().all(|s| *s < 4));
+        for shard in 0..4 {
+            assert!(four.iter().any(|s| *s == shard));
+        }
+        // Adding a shard only ever moves workflows onto the new shard
+        let five = shards_of(5);
+        assert!(four.iter().zip(five.iter()).all(|(f, v)| f == v || *v == 4));
+        assert!(shards_of(1).iter().all(|s| *s == 0));
+    }
 }
 
 #[must_use]
 enum EventHandlingOutcome {
-    SkipEvent { skip_next_event: bool },
     SkipCommand,
     Normal,
 }
 
-/// Special handling for patch markers, when handling command events as in
+/// True if the event is the upsert of the patch version search attribute which is recorded right
+/// after a patch marker
+fn is_patch_search_attr_upsert(event: Option<&HistoryEvent>) -> bool {
+    match event.and_then(|e| e.attributes.as_ref()) {
+        Some(Attributes::UpsertWorkflowSearchAttributesEventAttributes(atts)) => atts
+            .search_attributes
+            .as_ref()
+            .map(|sa| sa.indexed_fields.contains_key(VERSION_SEARCH_ATTR_KEY))
+            .unwrap_or_default(),
+        _ => false,
+    }
+}
+
+/// Special handling for patch and user-defined markers, when handling command events as in
 /// [WorkflowMachines::handle_command_event]
-fn change_marker_handling(
-    event: &HistoryEvent,
-    mach: &Machines,
-    next_event: Option<&HistoryEvent>,
-) -> Result<EventHandlingOutcome> {
+fn change_marker_handling(event: &HistoryEvent, mach: &Machines) -> Result<EventHandlingOutcome> {
     if !mach.matches_event(event) {
-        // Version markers can be skipped in the event they are deprecated
-        if let Some((patch_name, deprecated)) = event.get_patch_marker_details() {
-            // Is deprecated. We can simply ignore this event, as deprecated change
-            // markers are allowed without matching changed calls.
-            if deprecated {
-                debug!("Deprecated patch marker tried against wrong machine, skipping.");
-
-                // Also ignore the subsequent upsert event if present
-                let mut skip_next_event = false;
-                if let Some(Attributes::UpsertWorkflowSearchAttributesEventAttributes(atts)) =
-                    next_event.and_then(|ne| ne.attributes.as_ref())
-                {
-                    if let Some(ref sa) = atts.search_attributes {
-                        skip_next_event = sa.indexed_fields.contains_key(VERSION_SEARCH_ATTR_KEY);
-                    }
-                }
-
-                return Ok(EventHandlingOutcome::SkipEvent { skip_next_event });
-            }
+        if let Some(malformed) = event.malformed_patch_marker() {
+            return Err(malformed.into());
+        }
+        // Deprecated patch markers which don't match the next command never make it here, since
+        // they are skipped by [WorkflowMachines::fast_path_skip]
+        if let Some((patch_name, _)) = event.get_patch_marker_details() {
             return Err(WFMachinesError::Nondeterminism(format!(
                 "Non-deprecated patch marker encountered for change {patch_name}, \
                             but there is no corresponding change command!"
@@ -1345,6 +2055,14 @@ fn change_marker_handling(
             debug!("Skipping non-matching event against patch machine");
             return Ok(EventHandlingOutcome::SkipCommand);
         }
+        // User markers must be recorded in the same order, with the same names, as they were
+        // originally
+        if let Some(marker_name) = event.get_user_marker_name() {
+            return Err(WFMachinesError::Nondeterminism(format!(
+                "Marker {marker_name} was recorded in history, but the next command is for a {}",
+                mach.name()
+            )));
+        }
     }
     Ok(EventHandlingOutcome::Normal)
 }
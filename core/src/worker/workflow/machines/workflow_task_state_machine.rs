@@ -10,8 +10,14 @@ use std::{
     time::SystemTime,
 };
 use temporal_sdk_core_protos::temporal::api::{
-    enums::v1::{CommandType, EventType, WorkflowTaskFailedCause},
-    history::v1::{history_event::Attributes::WorkflowTaskFailedEventAttributes, HistoryEvent},
+    enums::v1::{CommandType, EventType, TimeoutType, WorkflowTaskFailedCause},
+    history::v1::{
+        history_event::Attributes::{
+            WorkflowTaskFailedEventAttributes, WorkflowTaskStartedEventAttributes,
+            WorkflowTaskTimedOutEventAttributes,
+        },
+        HistoryEvent,
+    },
 };
 
 fsm! {
@@ -23,11 +29,11 @@ fsm! {
     Created --(WorkflowTaskScheduled) --> Scheduled;
 
     Scheduled --(WorkflowTaskStarted(WFTStartedDat), shared on_workflow_task_started) --> Started;
-    Scheduled --(WorkflowTaskTimedOut) --> TimedOut;
+    Scheduled --(WorkflowTaskTimedOut(TimeoutType), on_workflow_task_timed_out) --> TimedOut;
 
     Started --(WorkflowTaskCompleted, on_workflow_task_completed) --> Completed;
     Started --(WorkflowTaskFailed(WFTFailedDat), on_workflow_task_failed) --> Failed;
-    Started --(WorkflowTaskTimedOut) --> TimedOut;
+    Started --(WorkflowTaskTimedOut(TimeoutType), on_workflow_task_timed_out) --> TimedOut;
 }
 
 impl WorkflowTaskMachine {
@@ -48,9 +54,30 @@ pub(super) enum WFTaskMachineCommand {
     WFTaskStartedTrigger {
         task_started_event_id: i64,
         time: SystemTime,
+        history_size: HistorySizeInfo,
     },
     #[display(fmt = "RunIdOnWorkflowResetUpdate({run_id})")]
     RunIdOnWorkflowResetUpdate { run_id: String },
+    /// Issued when history shows a workflow task failed or timed out
+    #[display(fmt = "AttemptFailed({_0:?})")]
+    AttemptFailed(WFTAttemptFailure),
+}
+
+/// What the server reported about the size of history in a workflow task started event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct HistorySizeInfo {
+    pub(super) size_bytes: u64,
+    pub(super) suggest_continue_as_new: bool,
+}
+
+/// Describes a workflow task attempt which history shows as having failed or timed out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct WFTAttemptFailure {
+    /// The failure cause or timeout type, as its proto enum name
+    pub(super) cause: &'static str,
+    /// True if the task had been started, and thus any commands produced in response to it were
+    /// discarded by server
+    pub(super) after_start: bool,
 }
 
 impl WFMachinesAdapter for WorkflowTaskMachine {
@@ -63,6 +90,7 @@ impl WFMachinesAdapter for WorkflowTaskMachine {
             WFTaskMachineCommand::WFTaskStartedTrigger {
                 task_started_event_id,
                 time,
+                history_size,
             } => {
                 let (event_id, event_type) = if let Some(ei) = event_info {
                     (ei.event_id, ei.event_type)
@@ -81,11 +109,15 @@ impl WFMachinesAdapter for WorkflowTaskMachine {
                 Ok(vec![MachineResponse::TriggerWFTaskStarted {
                     task_started_event_id,
                     time,
+                    history_size,
                 }])
             }
             WFTaskMachineCommand::RunIdOnWorkflowResetUpdate { run_id } => {
                 Ok(vec![MachineResponse::UpdateRunIdOnWorkflowReset { run_id }])
             }
+            WFTaskMachineCommand::AttemptFailed(failure) => {
+                Ok(vec![MachineResponse::WFTaskAttemptFailed(failure)])
+            }
         }
     }
 
@@ -124,27 +156,43 @@ impl TryFrom<HistEventData> for WorkflowTaskMachineEvents {
                         "Workflow task started event must contain timestamp: {e}"
                     )));
                 };
+                let history_size = match &e.attributes {
+                    Some(WorkflowTaskStartedEventAttributes(a)) => HistorySizeInfo {
+                        size_bytes: a.history_size_bytes.max(0) as u64,
+                        suggest_continue_as_new: a.suggest_continue_as_new,
+                    },
+                    _ => HistorySizeInfo::default(),
+                };
                 WFTStartedDat {
                     started_event_id: e.event_id,
                     current_time_millis: time,
+                    history_size,
                 }
             }),
-            EventType::WorkflowTaskTimedOut => Self::WorkflowTaskTimedOut,
+            EventType::WorkflowTaskTimedOut => Self::WorkflowTaskTimedOut(match e.attributes {
+                Some(WorkflowTaskTimedOutEventAttributes(a)) => {
+                    TimeoutType::from_i32(a.timeout_type).unwrap_or(TimeoutType::Unspecified)
+                }
+                _ => TimeoutType::Unspecified,
+            }),
             EventType::WorkflowTaskCompleted => Self::WorkflowTaskCompleted,
             EventType::WorkflowTaskFailed => {
                 if let Some(attributes) = e.attributes {
-                    Self::WorkflowTaskFailed(WFTFailedDat {
-                        new_run_id: match attributes {
-                            WorkflowTaskFailedEventAttributes(a) => {
-                                let cause = WorkflowTaskFailedCause::from_i32(a.cause);
-                                match cause {
-                                    Some(WorkflowTaskFailedCause::ResetWorkflow) => {
-                                        Some(a.new_run_id)
-                                    }
+                    Self::WorkflowTaskFailed(match attributes {
+                        WorkflowTaskFailedEventAttributes(a) => {
+                            let cause = WorkflowTaskFailedCause::from_i32(a.cause)
+                                .unwrap_or(WorkflowTaskFailedCause::Unspecified);
+                            WFTFailedDat {
+                                new_run_id: match cause {
+                                    WorkflowTaskFailedCause::ResetWorkflow => Some(a.new_run_id),
                                     _ => None,
-                                }
+                                },
+                                cause,
                             }
-                            _ => None,
+                        }
+                        _ => WFTFailedDat {
+                            new_run_id: None,
+                            cause: WorkflowTaskFailedCause::Unspecified,
                         },
                     })
                 } else {
@@ -192,10 +240,12 @@ pub(super) struct Scheduled {}
 pub(super) struct WFTStartedDat {
     current_time_millis: SystemTime,
     started_event_id: i64,
+    history_size: HistorySizeInfo,
 }
 
 pub(super) struct WFTFailedDat {
     new_run_id: Option<String>,
+    cause: WorkflowTaskFailedCause,
 }
 
 impl Scheduled {
@@ -205,21 +255,38 @@ impl Scheduled {
         WFTStartedDat {
             current_time_millis,
             started_event_id,
+            history_size,
         }: WFTStartedDat,
     ) -> WorkflowTaskMachineTransition<Started> {
         TransitionResult::ok(
             vec![WFTaskMachineCommand::WFTaskStartedTrigger {
                 task_started_event_id: shared.wf_task_started_event_id,
                 time: current_time_millis,
+                history_size,
             }],
             Started {
                 current_time_millis,
                 started_event_id,
+                history_size,
             },
         )
     }
 }
 
+impl Scheduled {
+    pub(super) fn on_workflow_task_timed_out(
+        self,
+        timeout_type: TimeoutType,
+    ) -> WorkflowTaskMachineTransition<TimedOut> {
+        TransitionResult::commands(vec![WFTaskMachineCommand::AttemptFailed(
+            WFTAttemptFailure {
+                cause: timeout_type.as_str_name(),
+                after_start: false,
+            },
+        )])
+    }
+}
+
 impl From<Created> for Scheduled {
     fn from(_: Created) -> Self {
         Self::default()
@@ -232,6 +299,8 @@ pub(super) struct Started {
     current_time_millis: SystemTime,
     /// Started event's id
     started_event_id: i64,
+    /// History size reported in the started event
+    history_size: HistorySizeInfo,
 }
 
 impl Started {
@@ -239,31 +308,149 @@ impl Started {
         TransitionResult::commands(vec![WFTaskMachineCommand::WFTaskStartedTrigger {
             task_started_event_id: self.started_event_id,
             time: self.current_time_millis,
+            history_size: self.history_size,
         }])
     }
     pub(super) fn on_workflow_task_failed(
         self,
         data: WFTFailedDat,
     ) -> WorkflowTaskMachineTransition<Failed> {
-        let commands = match data.new_run_id {
-            Some(run_id) => vec![WFTaskMachineCommand::RunIdOnWorkflowResetUpdate { run_id }],
-            None => vec![],
-        };
+        let mut commands = vec![WFTaskMachineCommand::AttemptFailed(WFTAttemptFailure {
+            cause: data.cause.as_str_name(),
+            after_start: true,
+        })];
+        if let Some(run_id) = data.new_run_id {
+            commands.push(WFTaskMachineCommand::RunIdOnWorkflowResetUpdate { run_id });
+        }
         TransitionResult::commands(commands)
     }
+    pub(super) fn on_workflow_task_timed_out(
+        self,
+        timeout_type: TimeoutType,
+    ) -> WorkflowTaskMachineTransition<TimedOut> {
+        TransitionResult::commands(vec![WFTaskMachineCommand::AttemptFailed(
+            WFTAttemptFailure {
+                cause: timeout_type.as_str_name(),
+                after_start: true,
+            },
+        )])
+    }
 }
 
 #[derive(Default, Clone)]
 pub(super) struct TimedOut {}
 
-impl From<Scheduled> for TimedOut {
-    fn from(_: Scheduled) -> Self {
-        Self::default()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::workflow::machines::OnEventWrapper;
+    use temporal_sdk_core_protos::temporal::api::history::v1::{
+        self as history, history_event::Attributes,
+    };
+
+    fn event(event_type: EventType, attributes: Option<Attributes>) -> WorkflowTaskMachineEvents {
+        HistEventData {
+            event: HistoryEvent {
+                event_id: 3,
+                event_time: Some(SystemTime::now().into()),
+                event_type: event_type as i32,
+                attributes,
+                ..Default::default()
+            },
+            replaying: true,
+            current_task_is_last_in_history: false,
+        }
+        .try_into()
+        .unwrap()
     }
-}
 
-impl From<Started> for TimedOut {
-    fn from(_: Started) -> Self {
-        Self::default()
+    fn started_machine() -> WorkflowTaskMachine {
+        let mut sm = WorkflowTaskMachine::new(3);
+        OnEventWrapper::on_event_mut(&mut sm, event(EventType::WorkflowTaskScheduled, None))
+            .unwrap();
+        OnEventWrapper::on_event_mut(
+            &mut sm,
+            event(
+                EventType::WorkflowTaskStarted,
+                Some(history::WorkflowTaskStartedEventAttributes::default().into()),
+            ),
+        )
+        .unwrap();
+        sm
+    }
+
+    #[test]
+    fn failed_attempt_reports_cause() {
+        let mut sm = started_machine();
+        let cmds = OnEventWrapper::on_event_mut(
+            &mut sm,
+            event(
+                EventType::WorkflowTaskFailed,
+                Some(
+                    history::WorkflowTaskFailedEventAttributes {
+                        cause: WorkflowTaskFailedCause::UnhandledCommand as i32,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+            ),
+        )
+        .unwrap();
+        assert_matches!(
+            cmds.as_slice(),
+            [WFTaskMachineCommand::AttemptFailed(WFTAttemptFailure {
+                cause: "WORKFLOW_TASK_FAILED_CAUSE_UNHANDLED_COMMAND",
+                after_start: true,
+            })]
+        );
+    }
+
+    #[test]
+    fn timed_out_attempts_report_timeout_type() {
+        let timed_out = |timeout_type: TimeoutType| {
+            Some(
+                history::WorkflowTaskTimedOutEventAttributes {
+                    timeout_type: timeout_type as i32,
+                    ..Default::default()
+                }
+                .into(),
+            )
+        };
+
+        let mut sm = started_machine();
+        let cmds = OnEventWrapper::on_event_mut(
+            &mut sm,
+            event(
+                EventType::WorkflowTaskTimedOut,
+                timed_out(TimeoutType::StartToClose),
+            ),
+        )
+        .unwrap();
+        assert_matches!(
+            cmds.as_slice(),
+            [WFTaskMachineCommand::AttemptFailed(WFTAttemptFailure {
+                cause: "TIMEOUT_TYPE_START_TO_CLOSE",
+                after_start: true,
+            })]
+        );
+
+        let mut sm = WorkflowTaskMachine::new(3);
+        OnEventWrapper::on_event_mut(&mut sm, event(EventType::WorkflowTaskScheduled, None))
+            .unwrap();
+        let cmds = OnEventWrapper::on_event_mut(
+            &mut sm,
+            event(
+                EventType::WorkflowTaskTimedOut,
+                timed_out(TimeoutType::ScheduleToStart),
+            ),
+        )
+        .unwrap();
+        assert_matches!(
+            cmds.as_slice(),
+            [WFTaskMachineCommand::AttemptFailed(WFTAttemptFailure {
+                cause: "TIMEOUT_TYPE_SCHEDULE_TO_START",
+                after_start: false,
+            })]
+        );
     }
 }
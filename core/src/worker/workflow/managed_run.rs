@@ -5,8 +5,8 @@ mod managed_wf_test;
 pub(crate) use managed_wf_test::ManagedWFFunc;
 
 use crate::{
-    abstractions::dbg_panic,
-    protosext::WorkflowActivationExt,
+    abstractions::{dbg_panic, UsedMeteredSemPermit},
+    protosext::{legacy_query_failure, WorkflowActivationExt},
     worker::{
         workflow::{
             history_update::HistoryPaginator, machines::WorkflowMachines, ActivationAction,
@@ -14,29 +14,35 @@ use crate::{
             EvictionRequestResult, FailedActivationWFTReport, HeartbeatTimeoutMsg, HistoryUpdate,
             LocalActivityRequestSink, LocalResolution, NextPageReq, OutgoingServerCommands,
             OutstandingActivation, OutstandingTask, PermittedWFT, RequestEvictMsg, RunBasics,
-            ServerCommandsWithWorkflowInfo, WFCommand, WFMachinesError, WFTReportStatus,
-            WorkflowBridge, WorkflowTaskInfo, WFT_HEARTBEAT_TIMEOUT_FRACTION,
+            RunHistoryInfo, ServerCommandsWithWorkflowInfo, WFCommand, WFMachinesError,
+            WFTReportStatus, WorkflowBridge, WorkflowTaskInfo, WFT_HEARTBEAT_TIMEOUT_FRACTION,
         },
         LocalActRequest, LEGACY_QUERY_ID,
     },
     MetricsContext,
 };
 use futures_util::future::AbortHandle;
+use prost::Message;
 use std::{
     collections::HashSet,
+    fs, io,
     ops::Add,
+    path::Path,
     rc::Rc,
     sync::mpsc::Sender,
     time::{Duration, Instant},
 };
+use temporal_sdk_core_api::worker::{MachinesDump, RunStateDump, RunStateDumpTarget};
 use temporal_sdk_core_protos::{
+    constants::{ENHANCED_STACK_TRACE_QUERY_TYPE, LIST_PATCHES_QUERY_TYPE, STACK_TRACE_QUERY_TYPE},
     coresdk::{
         workflow_activation::{
             create_evict_activation, query_to_job, remove_from_cache::EvictionReason,
-            workflow_activation_job, RemoveFromCache, WorkflowActivation,
+            workflow_activation_job, QueryStackTrace, QueryWorkflow, RemoveFromCache,
+            WorkflowActivation, WorkflowActivationJob,
         },
-        workflow_commands::QueryResult,
-        workflow_completion,
+        workflow_commands::{query_result, QueryResult, QuerySuccess},
+        workflow_completion, AsJsonPayloadExt,
     },
     temporal::api::{enums::v1::WorkflowTaskFailedCause, failure::v1::Failure},
     TaskToken,
@@ -76,6 +82,9 @@ pub(super) struct ManagedRun {
     wft: Option<OutstandingTask>,
     /// An outstanding activation to lang
     activation: Option<OutstandingActivation>,
+    /// Set once lang's completion of the outstanding activation has been accepted, so that any
+    /// further completions of it can be rejected
+    activation_completed: bool,
     /// If set, it indicates there is a buffered poll response from the server that applies to this
     /// run. This can happen when lang takes too long to complete a task and the task times out, for
     /// example. Upon next completion, the buffered response will be removed and can be made ready
@@ -92,6 +101,13 @@ pub(super) struct ManagedRun {
     /// We store the paginator used for our own run's history fetching
     paginator: Option<HistoryPaginator>,
     completion_waiting_on_page_fetch: Option<RunActivationCompletion>,
+    /// Responses to queries core answered itself, which are sent along with the completion of the
+    /// activation they were stripped from
+    builtin_query_responses: Vec<QueryResult>,
+    /// Set while a query-only task is being answered concurrently with the run's in-flight WFT
+    concurrent_query: Option<ConcurrentQuery>,
+    /// Where to send a dump of this run's state if it fails with an error worth reproducing
+    run_state_dump: Option<RunStateDumpTarget>,
 }
 impl ManagedRun {
     pub(super) fn new(
@@ -99,6 +115,7 @@ impl ManagedRun {
         local_activity_request_sink: Rc<dyn LocalActivityRequestSink>,
     ) -> Self {
         let metrics = basics.metrics.clone();
+        let run_state_dump = basics.run_state_dump.clone();
         let wfm = WorkflowManager::new(basics);
         Self {
             wfm,
@@ -107,12 +124,16 @@ impl ManagedRun {
             am_broken: false,
             wft: None,
             activation: None,
+            activation_completed: false,
             buffered_resp: None,
             trying_to_evict: None,
             recorded_span_ids: Default::default(),
             metrics,
             paginator: None,
             completion_waiting_on_page_fetch: None,
+            builtin_query_responses: vec![],
+            concurrent_query: None,
+            run_state_dump,
         }
     }
 
@@ -130,6 +151,11 @@ impl ManagedRun {
         self.wfm.machines.have_seen_terminal_event
     }
 
+    /// The shard this run's activations are labeled with
+    pub(super) fn activation_shard(&self) -> u32 {
+        self.wfm.machines.activation_shard
+    }
+
     /// Returns a ref to info about the currently tracked workflow task, if any.
     pub(super) fn wft(&self) -> Option<&OutstandingTask> {
         self.wft.as_ref()
@@ -140,11 +166,29 @@ impl ManagedRun {
         self.activation.as_ref()
     }
 
+    /// Returns the execution this run belongs to and the last history event applied to it
+    pub(super) fn history_info(&self) -> RunHistoryInfo {
+        RunHistoryInfo {
+            workflow_id: self.wfm.machines.workflow_id.clone(),
+            last_applied_event_id: self.wfm.machines.last_processed_event,
+        }
+    }
+
+    /// Returns a snapshot of this run's state machines
+    pub(super) fn machines_dump(&self) -> MachinesDump {
+        self.wfm.machines.machines_dump()
+    }
+
     /// Returns true if this run has already been told it will be evicted.
     pub(super) fn is_trying_to_evict(&self) -> bool {
         self.trying_to_evict.is_some()
     }
 
+    /// Why this run is being evicted, if it is
+    pub(super) fn eviction_reason(&self) -> Option<EvictionReason> {
+        self.trying_to_evict.as_ref().map(|te| te.reason)
+    }
+
     /// Called whenever a new workflow task is obtained for this run
     pub(super) fn incoming_wft(&mut self, pwft: PermittedWFT) -> RunUpdateAct {
         let res = self._incoming_wft(pwft);
@@ -202,6 +246,7 @@ impl ManagedRun {
             info: wft_info,
             hit_cache: !did_miss_cache,
             pending_queries,
+            outstanding_queries: Default::default(),
             start_time,
             permit: pwft.permit,
         });
@@ -288,8 +333,9 @@ impl ManagedRun {
     }
 
     fn _check_more_activations(&mut self) -> Result<Option<ActivationOrAuto>, RunUpdateErr> {
-        // No point in checking for more activations if there's already an outstanding activation.
-        if self.activation.is_some() {
+        // No point in checking for more activations if there's already an outstanding activation,
+        // or lang is busy answering a concurrent query.
+        if self.activation.is_some() || self.concurrent_query.is_some() {
             return Ok(None);
         }
         // In the event it's time to evict this run, cancel any outstanding LAs
@@ -350,6 +396,15 @@ impl ManagedRun {
         used_flags: Vec<u32>,
         resp_chan: Option<oneshot::Sender<ActivationCompleteResult>>,
     ) -> Result<RunUpdateAct, NextPageReq> {
+        commands.extend(
+            self.builtin_query_responses
+                .drain(..)
+                .map(WFCommand::QueryResponse),
+        );
+        if self.is_answering_concurrent_query() {
+            self.complete_concurrent_query(commands, resp_chan);
+            return Ok(None);
+        }
         let activation_was_only_eviction = self.activation_has_only_eviction();
         let (task_token, has_pending_query, start_time) = if let Some(entry) = self.wft.as_ref() {
             (
@@ -416,6 +471,7 @@ impl ManagedRun {
                     i += 1;
                 }
             }
+            self.reconcile_query_responses(&mut query_responses);
 
             if activation_was_only_eviction && !commands.is_empty() {
                 dbg_panic!("Reply to an eviction only containing an eviction included commands");
@@ -506,6 +562,27 @@ impl ManagedRun {
         failure: workflow_completion::Failure,
         resp_chan: Option<oneshot::Sender<ActivationCompleteResult>>,
     ) -> RunUpdateAct {
+        if self.is_answering_concurrent_query() {
+            // Failing to answer a query doesn't affect the in-flight WFT, so there's no need to
+            // evict the run.
+            self.builtin_query_responses.clear();
+            let task_token = self
+                .concurrent_query
+                .as_mut()
+                .map(|cq| {
+                    cq.answered = true;
+                    cq.task_token.clone()
+                })
+                .expect("Concurrent query is being answered");
+            self.reply_to_complete(
+                ActivationCompleteOutcome::AnswerConcurrentQuery(
+                    task_token,
+                    Box::new(legacy_query_failure(failure)),
+                ),
+                resp_chan,
+            );
+            return None;
+        }
         let tt = if let Some(tt) = self.wft.as_ref().map(|t| t.info.task_token.clone()) {
             tt
         } else {
@@ -518,6 +595,7 @@ impl ManagedRun {
         };
 
         self.metrics.wf_task_failed();
+        self.builtin_query_responses.clear();
         let message = format!("Workflow activation completion failed: {:?}", &failure);
         // Blow up any cached data associated with the workflow
         let evict_req_outcome = self.request_eviction(RequestEvictMsg {
@@ -548,6 +626,21 @@ impl ManagedRun {
         rur
     }
 
+    /// Returns true if lang is expected to complete an activation for this run, marking the
+    /// outstanding activation as completed. Completions arriving when this returns false (ex: the
+    /// activation was already completed, or none was ever issued) must be rejected rather than
+    /// applied to the machines.
+    pub(super) fn accept_completion(&mut self) -> bool {
+        if self.is_answering_concurrent_query() {
+            return true;
+        }
+        if self.activation.is_none() || self.activation_completed {
+            return false;
+        }
+        self.activation_completed = true;
+        true
+    }
+
     /// Delete the currently tracked workflow activation and return it, if any. Should be called
     /// after the processing of the activation completion, and WFT reporting.
     pub(super) fn delete_activation(
@@ -733,9 +826,15 @@ impl ManagedRun {
         } else {
             self.buffered_resp.is_some()
         };
+        let query_work = self.concurrent_query.is_some();
         trace!(wft=self.wft.is_some(), buffered=?buffered, more_work=?self.more_pending_work(),
-               act_work, evict_work, "Does run have pending work?");
-        self.wft.is_some() || buffered || self.more_pending_work() || act_work || evict_work
+               act_work, evict_work, query_work, "Does run have pending work?");
+        self.wft.is_some()
+            || buffered
+            || self.more_pending_work()
+            || act_work
+            || evict_work
+            || query_work
     }
 
     /// Stores some work if there is any outstanding WFT or activation for the run. If there was
@@ -746,7 +845,7 @@ impl ManagedRun {
     ) -> Option<PermittedWFT> {
         let about_to_issue_evict = self.trying_to_evict.is_some();
         let has_wft = self.wft().is_some();
-        let has_activation = self.activation().is_some();
+        let has_activation = self.activation().is_some() || self.concurrent_query.is_some();
         if has_wft || has_activation || about_to_issue_evict || self.more_pending_work() {
             debug!(run_id = %self.run_id(),
                    "Got new WFT for a run with outstanding work, buffering it");
@@ -767,6 +866,113 @@ impl ManagedRun {
         self.buffered_resp.take()
     }
 
+    /// Returns true if the provided work is a query-only task which can be answered right away,
+    /// rather than being buffered behind the WFT this run is currently processing. That's only the
+    /// case while lang has no outstanding activation for this run - either because there is none,
+    /// or because lang already completed it and we're just holding the completion until local
+    /// activities resolve. Lang's workflow state then reflects the last completed activation, and
+    /// queries are read-only, so they can't interfere with the in-flight WFT.
+    pub(super) fn can_answer_query_concurrently(&self, work: &PermittedWFT) -> bool {
+        let lang_is_idle = self.activation.is_none()
+            || (self.activation_completed
+                && self
+                    .waiting_on_la
+                    .as_ref()
+                    .map(|w| w.completion_dat.is_some())
+                    .unwrap_or_default());
+        work.work.legacy_query.is_some()
+            && self.wft.is_some()
+            && lang_is_idle
+            && self.concurrent_query.is_none()
+            && self.trying_to_evict.is_none()
+            && !self.am_broken
+            && !self.wfm.machines.has_pending_jobs()
+    }
+
+    /// Issue an activation answering a query-only task against the run's current state, without
+    /// disturbing the in-flight WFT. Must only be called if [Self::can_answer_query_concurrently]
+    /// returned true for the work.
+    pub(super) fn answer_query_concurrently(&mut self, pwft: PermittedWFT) -> RunUpdateAct {
+        let work = pwft.work;
+        let query = if let Some(q) = work.legacy_query {
+            q
+        } else {
+            dbg_panic!("Tried to concurrently answer a WFT which was not a query");
+            return None;
+        };
+        debug!(run_id=%self.run_id(), task_token=%work.task_token,
+               "Answering query concurrently with in-flight workflow task");
+        self.concurrent_query = Some(ConcurrentQuery {
+            task_token: work.task_token,
+            _permit: pwft.permit,
+            answered: false,
+        });
+        let mut act = self.wfm.machines.get_wf_activation();
+        act.jobs = vec![query_activation_job(query_to_job(
+            LEGACY_QUERY_ID.to_string(),
+            query,
+        ))];
+        self.answer_builtin_queries(&mut act);
+        if act.jobs.is_empty() {
+            Some(ActivationOrAuto::Autocomplete { run_id: act.run_id })
+        } else {
+            Some(ActivationOrAuto::LangActivation(act))
+        }
+    }
+
+    /// Called once the response to a concurrently answered query has been sent to server, freeing
+    /// up the task's permit.
+    pub(super) fn concurrent_query_answered(&mut self) {
+        if self.concurrent_query.take().is_none() {
+            dbg_panic!("Concurrent query was answered, but none was being tracked");
+        }
+    }
+
+    fn complete_concurrent_query(
+        &mut self,
+        mut commands: Vec<WFCommand>,
+        resp_chan: Option<oneshot::Sender<ActivationCompleteResult>>,
+    ) {
+        let task_token = if let Some(cq) = self.concurrent_query.as_mut() {
+            cq.answered = true;
+            cq.task_token.clone()
+        } else {
+            return;
+        };
+        let response = commands
+            .iter()
+            .position(
+                |c| matches!(c, WFCommand::QueryResponse(qr) if qr.query_id == LEGACY_QUERY_ID),
+            )
+            .map(|i| commands.remove(i));
+        if !commands.is_empty() {
+            warn!(run_id=%self.run_id(), commands=?commands,
+                  "Lang issued commands in response to a concurrently answered query. They will \
+                   be ignored.");
+        }
+        let result = match response {
+            Some(WFCommand::QueryResponse(qr)) => qr,
+            _ => legacy_query_failure(
+                Failure::application_failure(
+                    "Lang did not respond to the query".to_string(),
+                    false,
+                )
+                .into(),
+            ),
+        };
+        self.reply_to_complete(
+            ActivationCompleteOutcome::AnswerConcurrentQuery(task_token, Box::new(result)),
+            resp_chan,
+        );
+    }
+
+    fn is_answering_concurrent_query(&self) -> bool {
+        self.concurrent_query
+            .as_ref()
+            .map(|cq| !cq.answered)
+            .unwrap_or_default()
+    }
+
     pub(super) fn request_eviction(&mut self, info: RequestEvictMsg) -> EvictionRequestResult {
         let attempts = self.wft.as_ref().map(|wt| wt.info.attempt);
 
@@ -819,6 +1025,7 @@ impl ManagedRun {
     ) -> RunUpdateAct {
         match outcome {
             Ok(act_or_fulfill) => {
+                self.evict_if_ended_by_server();
                 let (mut maybe_act, maybe_fulfill) = match act_or_fulfill {
                     ActOrFulfill::OutgoingAct(a) => (a, None),
                     ActOrFulfill::FulfillableComplete(c) => (None, c),
@@ -844,6 +1051,7 @@ impl ManagedRun {
                             // will need to be dealt with once replay is over
                             if wft.hit_cache {
                                 put_queries_in_act(&mut activation, wft);
+                                self.answer_builtin_queries(&mut activation);
                             }
                         }
 
@@ -855,7 +1063,13 @@ impl ManagedRun {
                     Some(ActivationOrAuto::ReadyForQueries(mut act)) => {
                         if let Some(wft) = self.wft.as_mut() {
                             put_queries_in_act(&mut act, wft);
-                            Some(ActivationOrAuto::LangActivation(act))
+                            self.answer_builtin_queries(&mut act);
+                            if act.jobs.is_empty() {
+                                // Core answered every query itself, lang needn't be involved
+                                Some(ActivationOrAuto::Autocomplete { run_id: act.run_id })
+                            } else {
+                                Some(ActivationOrAuto::LangActivation(act))
+                            }
                         } else {
                             dbg_panic!("Ready for queries but no WFT!");
                             None
@@ -868,7 +1082,10 @@ impl ManagedRun {
                         if let Some(reason) = self.trying_to_evict.as_ref() {
                             // If we had nothing to do, but we're trying to evict, just do that now
                             // as long as there's no other outstanding work.
-                            if self.activation.is_none() && !self.more_pending_work() {
+                            if self.activation.is_none()
+                                && self.concurrent_query.is_none()
+                                && !self.more_pending_work()
+                            {
                                 let mut evict_act = create_evict_activation(
                                     self.run_id().to_string(),
                                     reason.message.clone(),
@@ -876,6 +1093,7 @@ impl ManagedRun {
                                 );
                                 evict_act.history_length =
                                     self.most_recently_processed_event_number() as u32;
+                                evict_act.shard = self.wfm.machines.activation_shard;
                                 Some(ActivationOrAuto::LangActivation(evict_act))
                             } else {
                                 None
@@ -909,6 +1127,7 @@ impl ManagedRun {
             }
             Err(fail) => {
                 self.am_broken = true;
+                self.dump_state(&fail.source);
                 let rur = if let Some(resp_chan) = fail.complete_resp_chan {
                     // Automatically fail the workflow task in the event we couldn't update machines
                     let fail_cause = if matches!(&fail.source, WFMachinesError::Nondeterminism(_)) {
@@ -916,11 +1135,19 @@ impl ManagedRun {
                     } else {
                         WorkflowTaskFailedCause::Unspecified
                     };
-                    let wft_fail_str = format!("{:?}", fail.source);
+                    let failure = match &fail.source {
+                        WFMachinesError::MalformedPayload(mp) => mp.as_failure(),
+                        e @ (WFMachinesError::UnsupportedFeature { .. }
+                        | WFMachinesError::InvalidHistory(_)
+                        | WFMachinesError::PayloadTooLarge { .. }) => {
+                            Failure::application_failure(e.to_string(), false)
+                        }
+                        e => Failure::application_failure(format!("{e:?}"), false),
+                    };
                     self.failed_completion(
                         fail_cause,
                         fail.source.evict_reason(),
-                        Failure::application_failure(wft_fail_str, false).into(),
+                        failure.into(),
                         Some(resp_chan),
                     )
                 } else {
@@ -935,6 +1162,112 @@ impl ManagedRun {
         }
     }
 
+    /// Sends a dump of this run's state to the configured target, if there is one and the error
+    /// is of a kind worth reproducing offline. Delivering the dump may block (ex: on disk, or in a
+    /// user callback), so it happens on a blocking thread rather than holding up every other run.
+    fn dump_state(&self, err: &WFMachinesError) {
+        let target = match self.run_state_dump.as_ref() {
+            Some(t) => t,
+            None => return,
+        };
+        if !matches!(
+            err,
+            WFMachinesError::Fatal(_)
+                | WFMachinesError::Nondeterminism(_)
+                | WFMachinesError::MalformedPayload(_)
+                | WFMachinesError::UnsupportedFeature { .. }
+        ) {
+            return;
+        }
+        let dump = self.wfm.machines.state_dump(err.to_string());
+        let target = target.clone();
+        tokio::task::spawn_blocking(move || match target {
+            RunStateDumpTarget::Callback(cb) => cb(dump),
+            RunStateDumpTarget::Directory(dir) => {
+                if let Err(e) = write_state_dump(&dir, &dump) {
+                    warn!(run_id=%dump.run_id, dir=?dir, error=%e,
+                          "Failed to write run state dump");
+                }
+            }
+        });
+    }
+
+    /// Removes any queries core knows how to answer on its own from the activation, storing their
+    /// responses to be sent when the activation is completed.
+    fn answer_builtin_queries(&mut self, act: &mut WorkflowActivation) {
+        let mut builtin_query_ids = vec![];
+        act.jobs.retain(|j| match &j.variant {
+            Some(workflow_activation_job::Variant::QueryWorkflow(q))
+                if q.query_type == LIST_PATCHES_QUERY_TYPE =>
+            {
+                builtin_query_ids.push(q.query_id.clone());
+                false
+            }
+            _ => true,
+        });
+        for query_id in builtin_query_ids {
+            let patches = self
+                .wfm
+                .machines
+                .encountered_patches()
+                .as_json_payload()
+                .expect("A list of strings is always serializable");
+            self.builtin_query_responses.push(QueryResult {
+                query_id,
+                variant: Some(
+                    QuerySuccess {
+                        response: Some(patches),
+                    }
+                    .into(),
+                ),
+            });
+        }
+    }
+
+    /// Matches query responses from a completion against the queries that were dispatched to lang.
+    /// Responses to queries which were never dispatched (or were already answered) are dropped,
+    /// and any dispatched query lang did not answer is failed, so that every query in the task
+    /// receives exactly one response. Legacy queries are answered separately and left alone.
+    fn reconcile_query_responses(&mut self, query_responses: &mut Vec<QueryResult>) {
+        let outstanding = match self.wft.as_mut() {
+            Some(wft) => &mut wft.outstanding_queries,
+            None => return,
+        };
+        query_responses.retain(|qr| {
+            let known = qr.query_id == LEGACY_QUERY_ID || outstanding.remove(&qr.query_id);
+            if !known {
+                warn!(query_id=%qr.query_id,
+                      "Dropping response to query which is not outstanding for this run");
+            }
+            known
+        });
+        for query_id in outstanding.drain() {
+            warn!(query_id=%query_id, "Workflow did not respond to query, failing it");
+            query_responses.push(QueryResult {
+                query_id,
+                variant: Some(query_result::Variant::Failed(Failure::application_failure(
+                    "Workflow activation was completed without responding to query".to_string(),
+                    false,
+                ))),
+            });
+        }
+    }
+
+    /// If the server ended this run, queue up its eviction. It is delivered once lang has handled
+    /// whatever jobs the final workflow task produced, since lang is never told about the ending
+    /// any other way and would otherwise keep the workflow alive forever.
+    fn evict_if_ended_by_server(&mut self) {
+        if self.wfm.machines.ended_by_server && self.trying_to_evict.is_none() {
+            debug!(run_id=%self.run_id(), "Run was ended by server, evicting");
+            self.trying_to_evict = Some(RequestEvictMsg {
+                run_id: self.run_id().to_string(),
+                message: "Workflow run was ended by the server".to_string(),
+                reason: EvictionReason::ServerEndedRun,
+                auto_reply_fail_tt: None,
+            });
+        }
+    }
+
     fn insert_outstanding_activation(&mut self, act: &ActivationOrAuto) {
         let act_type = match &act {
             ActivationOrAuto::LangActivation(act) | ActivationOrAuto::ReadyForQueries(act) => {
@@ -960,6 +1293,7 @@ impl ManagedRun {
             );
         }
         self.activation = Some(act_type);
+        self.activation_completed = false;
     }
 
     fn prepare_complete_resp(
@@ -1066,10 +1400,19 @@ impl ManagedRun {
         &self,
         outcome: ActivationCompleteOutcome,
     ) -> ActivationCompleteResult {
+        // Includes runs pushed out just to make room in the cache. Their next task would otherwise
+        // go to this worker's sticky queue, only to be a cache miss.
+        let sticky_queue_stale = self
+            .activation
+            .map(OutstandingActivation::has_eviction)
+            .unwrap_or_default()
+            && !self.wfm.machines.workflow_is_finished();
         ActivationCompleteResult {
             outcome,
             most_recently_processed_event: self.most_recently_processed_event_number() as usize,
             replaying: self.wfm.machines.replaying,
+            stale_sticky_workflow_id: sticky_queue_stale
+                .then(|| self.wfm.machines.workflow_id.clone()),
         }
     }
 
@@ -1121,11 +1464,26 @@ fn put_queries_in_act(act: &mut WorkflowActivation, wft: &mut OutstandingTask) {
     }
 
     debug!(queries=?wft.pending_queries, "Dispatching queries");
-    let query_jobs = wft
-        .pending_queries
-        .drain(..)
-        .map(|q| workflow_activation_job::Variant::QueryWorkflow(q).into());
-    act.jobs.extend(query_jobs);
+    if !has_legacy {
+        wft.outstanding_queries
+            .extend(wft.pending_queries.iter().map(|q| q.query_id.clone()));
+    }
+    act.jobs
+        .extend(wft.pending_queries.drain(..).map(query_activation_job));
+}
+
+/// Turns a query into the job used to deliver it to lang
+fn query_activation_job(q: QueryWorkflow) -> WorkflowActivationJob {
+    match q.query_type.as_str() {
+        STACK_TRACE_QUERY_TYPE | ENHANCED_STACK_TRACE_QUERY_TYPE => {
+            workflow_activation_job::Variant::QueryStackTrace(QueryStackTrace {
+                enhanced: q.query_type == ENHANCED_STACK_TRACE_QUERY_TYPE,
+                query_id: q.query_id,
+            })
+        }
+        _ => workflow_activation_job::Variant::QueryWorkflow(q),
+    }
+    .into()
 }
 fn sink_heartbeat_timeout_start(
     run_id: String,
@@ -1147,6 +1505,16 @@ fn sink_heartbeat_timeout_start(
     abort_handle
 }
 
+/// A query-only task being answered concurrently with the run's in-flight WFT
+#[derive(Debug)]
+struct ConcurrentQuery {
+    task_token: TaskToken,
+    /// The query task's own WFT permit, released once it has been answered
+    _permit: UsedMeteredSemPermit,
+    /// Set once lang has completed the query activation
+    answered: bool,
+}
+
 /// If an activation completion needed to wait on LA completions (or heartbeat timeout) we use
 /// this struct to store the data we need to finish the completion once that has happened
 struct WaitingOnLAs {
@@ -1327,6 +1695,29 @@ enum ActOrFulfill {
     FulfillableComplete(Option<FulfillableActivationComplete>),
 }
 
+/// Writes the history of the dump as protobuf (so it can be fed directly to a replay worker), and
+/// everything else as text
+fn write_state_dump(dir: &Path, dump: &RunStateDump) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(
+        dir.join(format!("{}.history.bin", dump.run_id)),
+        dump.history.encode_to_vec(),
+    )?;
+    let state = format!(
+        "workflow_id: {}\nrun_id: {}\noriginal_run_id: {}\nreset_run_id: {}\nworkflow_type: {}\n\
+         error: {}\n\npending commands:\n{}\n\nmachine states:\n{}\n",
+        dump.workflow_id,
+        dump.run_id,
+        dump.original_run_id,
+        dump.reset_run_id.as_deref().unwrap_or_default(),
+        dump.workflow_type,
+        dump.error,
+        dump.pending_commands.join("\n"),
+        dump.machine_states.join("\n"),
+    );
+    fs::write(dir.join(format!("{}.state.txt", dump.run_id)), state)
+}
+
 #[derive(derive_more::DebugCustom)]
 #[debug(fmt = "RunUpdateErr({source:?})")]
 struct RunUpdateErr {
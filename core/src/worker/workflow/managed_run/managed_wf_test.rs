@@ -96,6 +96,10 @@ impl ManagedWFFunc {
                 history: hist,
                 metrics: MetricsContext::no_op(),
                 capabilities: DEFAULT_TEST_CAPABILITIES,
+                activation_shard_count: None,
+                sdk_name_and_version: Default::default(),
+                run_state_dump: None,
+                payload_size_limits: Default::default(),
             },
             Box::new(driver).into(),
         );
@@ -118,6 +122,10 @@ impl ManagedWFFunc {
         Ok(res)
     }
 
+    pub(crate) fn machines(&self) -> &WorkflowMachines {
+        &self.mgr.machines
+    }
+
     /// Return outgoing server commands as of the last iteration
     pub(crate) fn get_server_commands(&mut self) -> OutgoingServerCommands {
         self.mgr.get_server_commands()
@@ -12,12 +12,16 @@ mod wft_extraction;
 pub(crate) mod wft_poller;
 mod workflow_stream;
 
+#[cfg(feature = "machine_diagrams")]
+pub use machines::{
+    render_machine_diagrams, DiagramFormat, MachineTransitions, TransitionRecording,
+};
 #[cfg(feature = "save_wf_inputs")]
 pub use workflow_stream::replay_wf_state_inputs;
 
 pub(crate) use bridge::WorkflowBridge;
 pub(crate) use driven_workflow::{DrivenWorkflow, WorkflowFetcher};
-pub(crate) use history_update::HistoryUpdate;
+pub(crate) use history_update::{HistoryUpdate, MAX_EVENTS_PER_UPDATE};
 #[cfg(test)]
 pub(crate) use managed_run::ManagedWFFunc;
 
@@ -27,13 +31,13 @@ use crate::{
         UsedMeteredSemPermit,
     },
     internal_flags::InternalFlags,
-    protosext::legacy_query_failure,
+    protosext::{legacy_query_failure, MalformedPayload},
     telemetry::{set_trace_subscriber_for_current_thread, TelemetryInstance, VecDisplayer},
     worker::{
         activities::{ActivitiesFromWFTsHandle, LocalActivityManager, TrackedPermittedTqResp},
         client::{WorkerClient, WorkflowTaskCompletion},
         workflow::{
-            history_update::HistoryPaginator,
+            history_update::{EventIdSequenceError, HistoryPaginator},
             managed_run::RunUpdateAct,
             wft_extraction::{HistoryFetchReq, WFTExtractor, WFTStreamIn},
             wft_poller::validate_wft,
@@ -51,7 +55,7 @@ use prost_types::TimestampError;
 use std::{
     cell::RefCell,
     cmp::Ordering,
-    collections::VecDeque,
+    collections::{HashMap, HashSet, VecDeque},
     fmt::Debug,
     future::Future,
     mem::discriminant,
@@ -62,7 +66,10 @@ use std::{
     thread,
     time::{Duration, Instant},
 };
-use temporal_sdk_core_api::errors::{CompleteWfError, PollWfError};
+use temporal_sdk_core_api::{
+    errors::{CompleteWfError, PollWfError},
+    worker::{MachinesDump, RunStateDumpTarget},
+};
 use temporal_sdk_core_protos::{
     coresdk::{
         workflow_activation::{
@@ -77,8 +84,9 @@ use temporal_sdk_core_protos::{
     },
     temporal::api::{
         command::v1::{command::Attributes, Command as ProtoCommand, Command},
-        common::v1::{Memo, MeteringMetadata, RetryPolicy, SearchAttributes, WorkflowExecution},
+        common::v1::{MeteringMetadata, WorkflowExecution},
         enums::v1::WorkflowTaskFailedCause,
+        failure::v1::Failure as TemporalFailure,
         query::v1::WorkflowQuery,
         sdk::v1::WorkflowTaskCompletedMetadata,
         taskqueue::v1::StickyExecutionAttributes,
@@ -91,7 +99,7 @@ use tokio::{
         mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
-    task::{spawn_blocking, LocalSet},
+    task::{spawn_blocking, JoinHandle, LocalSet},
 };
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_util::sync::CancellationToken;
@@ -102,6 +110,10 @@ pub(crate) const LEGACY_QUERY_ID: &str = "legacy_query";
 /// necessary.
 const WFT_HEARTBEAT_TIMEOUT_FRACTION: f32 = 0.8;
 const MAX_EAGER_ACTIVITY_RESERVATIONS_PER_WORKFLOW_TASK: usize = 3;
+/// Reporting a workflow task is retried this many times if server is overloaded. This is on top of
+/// any retrying done by the client, since giving up means the workflow task must time out.
+const WFT_REPORT_MAX_RETRIES: u32 = 3;
+const WFT_REPORT_RETRY_BACKOFF: Duration = Duration::from_millis(100);
 
 type Result<T, E = WFMachinesError> = result::Result<T, E>;
 type BoxedActivationStream = BoxStream<'static, Result<ActivationOrAuto, PollWfError>>;
@@ -112,6 +124,10 @@ pub(crate) struct Workflows {
     task_queue: String,
     local_tx: UnboundedSender<LocalInput>,
     processing_task: TakeCell<thread::JoinHandle<()>>,
+    /// Joins the processing thread once shutdown has begun. Lives here rather than inside the
+    /// shutdown future so that a shutdown which is abandoned part way through (ex: when the force
+    /// eviction timeout elapses) is picked back up by the next call.
+    processing_join: tokio::sync::Mutex<Option<JoinHandle<thread::Result<()>>>>,
     activation_stream: tokio::sync::Mutex<(
         BoxedActivationStream,
         // Used to indicate polling may begin
@@ -127,6 +143,38 @@ pub(crate) struct Workflows {
     wft_semaphore: Arc<MeteredSemaphore>,
     local_act_mgr: Arc<LocalActivityManager>,
     ever_polled: AtomicBool,
+    /// Set once the workflow force eviction timeout has elapsed and all runs were forcibly evicted
+    shutdown_forced: AtomicBool,
+    /// If set, workflows of any type not in this set are failed without being started in lang
+    registered_workflow_types: Option<HashSet<String>>,
+    /// If set, eager execution is never requested for activities of any type not in this set
+    registered_activity_types: Option<HashSet<String>>,
+    /// If set, lang must complete activations within this long or the run is evicted
+    activation_deadline: Option<Duration>,
+    activation_deadlines: Arc<parking_lot::Mutex<ActivationDeadlines>>,
+}
+
+/// Tracks the activations lang is expected to complete before [Workflows::activation_deadline]
+#[derive(Default)]
+struct ActivationDeadlines {
+    next_seq: u64,
+    /// Activations handed to lang which it has not completed yet, oldest first, per run. Lang
+    /// completes the activations of a run in the order it received them, so each completion
+    /// belongs to the activation at the front of its run's queue.
+    outstanding: HashMap<String, VecDeque<WatchedActivation>>,
+}
+
+struct WatchedActivation {
+    /// Identifies the activation among all those this worker has handed to lang
+    seq: u64,
+    /// Fires if lang does not complete the activation in time. Unset for eviction-only
+    /// activations, which involve no workflow code.
+    timer: Option<JoinHandle<()>>,
+    /// Set once the deadline elapsed and the run was evicted because of it
+    deadline_exceeded: bool,
+    /// True for the eviction core issues after evicting a deadlocked run. The run no longer
+    /// exists, so lang's completion of this activation needs no processing.
+    is_deadlock_eviction: bool,
 }
 
 pub(crate) struct WorkflowBasics {
@@ -137,11 +185,24 @@ pub(crate) struct WorkflowBasics {
     pub task_queue: String,
     pub ignore_evicts_on_shutdown: bool,
     pub fetching_concurrency: usize,
-    pub server_capabilities: get_system_info_response::Capabilities,
+    pub activation_shard_count: Option<u32>,
+    /// The SDK name and version recorded in workflow task completion metadata
+    pub sdk_name_and_version: (String, String),
+    pub registered_workflow_types: Option<HashSet<String>>,
+    pub registered_activity_types: Option<HashSet<String>>,
+    pub server_capabilities: ServerCapabilitiesFn,
+    pub run_state_dump: Option<RunStateDumpTarget>,
+    pub payload_size_limits: PayloadSizeLimits,
+    pub activation_deadline: Option<Duration>,
     #[cfg(feature = "save_wf_inputs")]
     pub wf_state_inputs: Option<UnboundedSender<Vec<u8>>>,
 }
 
+/// Produces the server capabilities as currently known. Runs read them when they are created,
+/// since workers using a lazily connected client only learn them once they first poll.
+pub(crate) type ServerCapabilitiesFn =
+    Arc<dyn Fn() -> get_system_info_response::Capabilities + Send + Sync>;
+
 pub(crate) struct RunBasics<'a> {
     pub namespace: String,
     pub workflow_id: String,
@@ -150,6 +211,18 @@ pub(crate) struct RunBasics<'a> {
     pub history: HistoryUpdate,
     pub metrics: MetricsContext,
     pub capabilities: &'a get_system_info_response::Capabilities,
+    pub activation_shard_count: Option<u32>,
+    pub sdk_name_and_version: (String, String),
+    pub run_state_dump: Option<RunStateDumpTarget>,
+    pub payload_size_limits: PayloadSizeLimits,
+}
+
+/// Thresholds on the size of the payloads held by any one field of a command lang produces. See
+/// [temporal_sdk_core_api::worker::WorkerConfig::payload_size_limit].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PayloadSizeLimits {
+    pub warning_threshold: Option<usize>,
+    pub limit: Option<usize>,
 }
 
 impl Workflows {
@@ -170,6 +243,9 @@ impl Workflows {
         let (fetch_tx, fetch_rx) = unbounded_channel();
         let shutdown_tok = basics.shutdown_token.clone();
         let task_queue = basics.task_queue.clone();
+        let registered_workflow_types = basics.registered_workflow_types.clone();
+        let registered_activity_types = basics.registered_activity_types.clone();
+        let activation_deadline = basics.activation_deadline;
         let extracted_wft_stream = WFTExtractor::build(
             client.clone(),
             basics.fetching_concurrency,
@@ -241,6 +317,7 @@ impl Workflows {
             task_queue,
             local_tx,
             processing_task: TakeCell::new(processing_task),
+            processing_join: Default::default(),
             activation_stream: tokio::sync::Mutex::new((
                 UnboundedReceiverStream::new(activation_rx).boxed(),
                 Some(start_polling_tx),
@@ -251,6 +328,11 @@ impl Workflows {
             wft_semaphore,
             local_act_mgr,
             ever_polled: AtomicBool::new(false),
+            shutdown_forced: AtomicBool::new(false),
+            registered_workflow_types,
+            registered_activity_types,
+            activation_deadline,
+            activation_deadlines: Default::default(),
         }
     }
 
@@ -269,6 +351,22 @@ impl Workflows {
             match al {
                 ActivationOrAuto::LangActivation(mut act)
                 | ActivationOrAuto::ReadyForQueries(mut act) => {
+                    self.watch_activation_deadline(&act);
+                    if let Some(wf_type) = self.unregistered_workflow_type(&act) {
+                        warn!(run_id=%act.run_id, workflow_type=%wf_type,
+                              "Failing workflow task for unregistered workflow type");
+                        let failure = TemporalFailure::application_failure(
+                            format!("Workflow type {wf_type} is not registered on this worker"),
+                            false,
+                        );
+                        self.activation_completed(
+                            WorkflowActivationCompletion::fail(act.run_id, failure),
+                            false,
+                            Option::<Box<dyn Fn(PostActivateHookData) + Send>>::None,
+                        )
+                        .await?;
+                        continue;
+                    }
                     sort_act_jobs(&mut act);
                     debug!(activation=%act, "Sending activation to lang");
                     break Ok(act);
@@ -319,15 +417,36 @@ impl Workflows {
         let is_empty_completion = completion.is_empty();
         let completion = validate_completion(completion)?;
         let run_id = completion.run_id().to_string();
+        let shut_down_result = |run_id: String| {
+            if is_empty_completion {
+                // Empty complete which is likely an evict reply, we can just ignore.
+                Ok(())
+            } else {
+                Err(CompleteWfError::ShutDown { run_id })
+            }
+        };
+        if self.shutdown_forced.load(atomic::Ordering::Acquire) {
+            return shut_down_result(run_id);
+        }
+        if !is_autocomplete {
+            if let Some(watched) = self.stop_watching_activation_deadline(&run_id) {
+                if watched.is_deadlock_eviction {
+                    return Ok(());
+                }
+                if watched.deadline_exceeded {
+                    return Err(CompleteWfError::ActivationDeadlineExceeded { run_id });
+                }
+            }
+        }
         let (tx, rx) = oneshot::channel();
         let was_sent = self.send_local(WFActCompleteMsg {
             completion,
+            is_autocomplete,
             response_tx: Some(tx),
         });
         if !was_sent {
-            if is_empty_completion {
-                // Empty complete which is likely an evict reply, we can just ignore.
-                return Ok(());
+            if is_empty_completion || self.shutdown_forced.load(atomic::Ordering::Acquire) {
+                return shut_down_result(run_id);
             }
             panic!(
                 "A non-empty completion was not processed. Workflow processing may have \
@@ -338,6 +457,9 @@ impl Workflows {
         let completion_outcome = if let Ok(c) = rx.await {
             c
         } else {
+            if self.shutdown_forced.load(atomic::Ordering::Acquire) {
+                return shut_down_result(run_id);
+            }
             dbg_panic!("Send half of activation complete response channel went missing");
             self.request_eviction(
                 run_id,
@@ -387,18 +509,29 @@ impl Workflows {
                     }
                     completion.sticky_attributes = sticky_attrs;
 
-                    self.handle_wft_reporting_errs(&run_id, || async {
-                        let maybe_wft = self.client.complete_workflow_task(completion).await?;
-                        if let Some(wft) = maybe_wft.workflow_task {
-                            wft_from_complete = Some(validate_wft(wft)?);
+                    let task_token = completion.task_token.clone();
+                    let resp = self
+                        .report_wft(&run_id, &task_token, true, || {
+                            self.client.complete_workflow_task(completion.clone())
+                        })
+                        .await;
+                    if let Some(resp) = resp {
+                        if let Some(wft) = resp.workflow_task {
+                            match validate_wft(wft) {
+                                Ok(wft) => wft_from_complete = Some(wft),
+                                Err(e) => {
+                                    warn!(error=%e, run_id, "Invalid workflow task in completion \
+                                                             response");
+                                    self.request_eviction(
+                                        &run_id,
+                                        "Invalid workflow task in completion response",
+                                        EvictionReason::Fatal,
+                                    );
+                                }
+                            }
                         }
-                        self.handle_eager_activities(
-                            reserved_act_permits,
-                            maybe_wft.activity_tasks,
-                        );
-                        Ok(())
-                    })
-                    .await;
+                        self.handle_eager_activities(reserved_act_permits, resp.activity_tasks);
+                    }
                     WFTReportStatus::Reported
                 }
                 ServerCommandsWithWorkflowInfo {
@@ -412,10 +545,12 @@ impl Workflows {
             ActivationCompleteOutcome::ReportWFTFail(outcome) => match outcome {
                 FailedActivationWFTReport::Report(tt, cause, failure) => {
                     warn!(run_id=%run_id, failure=?failure, "Failing workflow task");
-                    self.handle_wft_reporting_errs(&run_id, || async {
-                        self.client
-                            .fail_workflow_task(tt, cause, failure.failure.map(Into::into))
-                            .await
+                    self.report_wft(&run_id, &tt, false, || {
+                        self.client.fail_workflow_task(
+                            tt.clone(),
+                            cause,
+                            failure.failure.clone().map(Into::into),
+                        )
                     })
                     .await;
                     WFTReportStatus::Reported
@@ -427,8 +562,15 @@ impl Workflows {
                     WFTReportStatus::Reported
                 }
             },
+            ActivationCompleteOutcome::AnswerConcurrentQuery(task_token, result) => {
+                self.respond_legacy_query(task_token, *result).await;
+                WFTReportStatus::ConcurrentQueryAnswered
+            }
             ActivationCompleteOutcome::WFTFailedDontReport => WFTReportStatus::DropWft,
             ActivationCompleteOutcome::DoNothing => WFTReportStatus::NotReported,
+            ActivationCompleteOutcome::NoOutstandingActivation => {
+                return Err(CompleteWfError::NoOutstandingActivation { run_id });
+            }
         };
 
         let maybe_pwft = if let Some(wft) = wft_from_complete {
@@ -447,6 +589,10 @@ impl Workflows {
             None
         };
 
+        if let Some(workflow_id) = completion_outcome.stale_sticky_workflow_id {
+            self.reset_sticky_queue(workflow_id, &run_id);
+        }
+
         if let Some(h) = post_activate_hook {
             h(PostActivateHookData {
                 run_id: &run_id,
@@ -465,6 +611,27 @@ impl Workflows {
         Ok(())
     }
 
+    /// Once a run is evicted, further tasks for it which the server sends to our sticky queue would
+    /// only be cache misses. Tell the server to send them to the normal queue (with full history)
+    /// instead, where any worker may pick them up. The call happens in the background, since
+    /// nothing about completing the eviction depends on it.
+    fn reset_sticky_queue(&self, workflow_id: String, run_id: &str) {
+        if self.sticky_attrs.is_none() {
+            return;
+        }
+        debug!(run_id, "Resetting sticky queue for evicted run");
+        let client = self.client.clone();
+        let run_id = run_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = client
+                .reset_sticky_task_queue(workflow_id, run_id.clone())
+                .await
+            {
+                warn!(error=%e, run_id, "Failed to reset sticky queue for evicted run");
+            }
+        });
+    }
+
     /// Tell workflow that a local activity has finished with the provided result
     pub(super) fn notify_of_local_result(
         &self,
@@ -499,8 +666,48 @@ impl Workflows {
         async move { rx.await.ok() }
     }
 
+    /// Returns info needed to assemble the applied history of the run, if it is cached
+    pub(super) fn get_run_history_info(
+        &self,
+        run_id: &str,
+    ) -> impl Future<Output = Option<RunHistoryInfo>> {
+        let (tx, rx) = oneshot::channel();
+        self.send_local(GetRunHistoryInfoMsg {
+            run_id: run_id.to_string(),
+            response_tx: tx,
+        });
+        async move { rx.await.ok().flatten() }
+    }
+
+    /// Returns a snapshot of the run's state machines, if it is cached
+    pub(super) fn get_machines_dump(
+        &self,
+        run_id: &str,
+    ) -> impl Future<Output = Option<MachinesDump>> {
+        let (tx, rx) = oneshot::channel();
+        self.send_local(GetMachinesDumpMsg {
+            run_id: run_id.to_string(),
+            response_tx: tx,
+        });
+        async move { rx.await.ok().flatten() }
+    }
+
+    /// If the activation would start a workflow of a type lang has not registered, returns that
+    /// type
+    fn unregistered_workflow_type(&self, act: &WorkflowActivation) -> Option<String> {
+        let registered = self.registered_workflow_types.as_ref()?;
+        act.jobs.iter().find_map(|j| match j.variant.as_ref() {
+            Some(workflow_activation_job::Variant::StartWorkflow(s))
+                if !registered.contains(&s.workflow_type) =>
+            {
+                Some(s.workflow_type.clone())
+            }
+            _ => None,
+        })
+    }
+
     pub(super) fn available_wft_permits(&self) -> usize {
-        self.wft_semaphore.available_permits()
+        self.wft_semaphore.available_permits().unwrap_or_default()
     }
     #[cfg(test)]
     pub(super) fn unused_wft_permits(&self) -> usize {
@@ -508,7 +715,11 @@ impl Workflows {
     }
 
     pub(super) async fn shutdown(&self) -> Result<(), anyhow::Error> {
+        let mut processing_join = self.processing_join.lock().await;
         if let Some(jh) = self.processing_task.take_once() {
+            *processing_join = Some(spawn_blocking(move || jh.join()));
+        }
+        if let Some(jh) = processing_join.as_mut() {
             // This serves to drive the stream if it is still alive and wouldn't otherwise receive
             // another message. It allows it to shut itself down.
             let (waker, stop_waker) = abortable(async {
@@ -518,19 +729,138 @@ impl Workflows {
                     let _ = self.get_state_info().await;
                 }
             });
-            let (_, jh_res) = tokio::join!(
-                waker,
-                spawn_blocking(move || {
-                    let r = jh.join();
-                    stop_waker.abort();
-                    r
-                })
-            );
+            let join = async {
+                let r = jh.await;
+                stop_waker.abort();
+                r
+            };
+            let (_, jh_res) = tokio::join!(waker, join);
+            *processing_join = None;
             jh_res?.map_err(|e| anyhow!("Error joining workflow processing thread: {e:?}"))?;
         }
         Ok(())
     }
 
+    /// Forcibly finish workflow processing, used once the worker's force eviction timeout has
+    /// elapsed. Every cached run is evicted without waiting on lang, and the workflow tasks they
+    /// (or buffered polls) held are failed so the server can hand them to another worker right
+    /// away, rather than waiting for them to time out.
+    pub(super) async fn force_shutdown(&self) {
+        self.shutdown_forced.store(true, atomic::Ordering::Release);
+        let (tx, rx) = oneshot::channel();
+        self.send_local(ForceShutdownMsg { response_tx: tx });
+        for task_token in rx.await.unwrap_or_default() {
+            if let Err(e) = self
+                .client
+                .fail_workflow_task(
+                    task_token.clone(),
+                    WorkflowTaskFailedCause::WorkflowWorkerUnhandledFailure,
+                    Some(TemporalFailure::application_failure(
+                        "Worker shut down before the workflow task was completed".to_string(),
+                        true,
+                    )),
+                )
+                .await
+            {
+                warn!(error=%e, task_token=%task_token,
+                      "Failed to fail workflow task during forced shutdown");
+            }
+        }
+    }
+
+    /// Start timing an activation which is about to be handed to lang, if there is a deadline for
+    /// completing it. If lang misses the deadline, the run is evicted and its workflow tasks are
+    /// failed.
+    fn watch_activation_deadline(&self, act: &WorkflowActivation) {
+        let deadline = match self.activation_deadline {
+            Some(d) => d,
+            None => return,
+        };
+        let mut guard = self.activation_deadlines.lock();
+        let seq = guard.next_seq;
+        guard.next_seq += 1;
+        let timer = if act.is_only_eviction() {
+            None
+        } else {
+            let deadlines = self.activation_deadlines.clone();
+            let local_tx = self.local_tx.clone();
+            let client = self.client.clone();
+            let run_id = act.run_id.clone();
+            Some(tokio::spawn(async move {
+                tokio::time::sleep(deadline).await;
+                {
+                    let mut guard = deadlines.lock();
+                    let watched = guard
+                        .outstanding
+                        .get_mut(&run_id)
+                        .and_then(|q| q.iter_mut().find(|wa| wa.seq == seq));
+                    // Lang may have completed the activation just as the deadline elapsed
+                    match watched {
+                        Some(wa) => wa.deadline_exceeded = true,
+                        None => return,
+                    }
+                }
+                let message = format!(
+                    "Workflow activation was not completed within {deadline:?}, the workflow may \
+                     be deadlocked"
+                );
+                error!(run_id, "{message}, evicting it");
+                let (tx, rx) = oneshot::channel();
+                let _ = local_tx.send(LocalInput {
+                    input: ActivationDeadlineMsg {
+                        run_id,
+                        message: message.clone(),
+                        response_tx: tx,
+                    }
+                    .into(),
+                    span: Span::current(),
+                });
+                for task_token in rx.await.unwrap_or_default() {
+                    if let Err(e) = client
+                        .fail_workflow_task(
+                            task_token.clone(),
+                            WorkflowTaskFailedCause::WorkflowWorkerUnhandledFailure,
+                            Some(TemporalFailure::application_failure(message.clone(), false)),
+                        )
+                        .await
+                    {
+                        warn!(error=%e, task_token=%task_token,
+                              "Failed to fail workflow task of deadlocked run");
+                    }
+                }
+            }))
+        };
+        guard
+            .outstanding
+            .entry(act.run_id.clone())
+            .or_default()
+            .push_back(WatchedActivation {
+                seq,
+                timer,
+                deadline_exceeded: false,
+                is_deadlock_eviction: act.eviction_reason()
+                    == Some(EvictionReason::DeadlockDetected),
+            });
+    }
+
+    /// Stop timing the oldest outstanding activation of the run, since it has been completed, and
+    /// return it.
+    fn stop_watching_activation_deadline(&self, run_id: &str) -> Option<WatchedActivation> {
+        if self.activation_deadline.is_none() {
+            return None;
+        }
+        let mut guard = self.activation_deadlines.lock();
+        let queue = guard.outstanding.get_mut(run_id)?;
+        let watched = queue.pop_front();
+        if queue.is_empty() {
+            guard.outstanding.remove(run_id);
+        }
+        if let Some(timer) = watched.as_ref().and_then(|wa| wa.timer.as_ref()) {
+            timer.abort();
+        }
+        watched
+    }
+
     pub(super) fn ever_polled(&self) -> bool {
         self.ever_polled.load(atomic::Ordering::Acquire)
     }
@@ -540,34 +870,82 @@ impl Workflows {
         self.send_local(msg);
     }
 
-    /// Handle server errors from either completing or failing a workflow task. Un-handleable errors
-    /// trigger a workflow eviction and are logged.
-    async fn handle_wft_reporting_errs<T, Fut>(&self, run_id: &str, completer: impl FnOnce() -> Fut)
+    /// Report the outcome of a workflow task to server using `reporter`, which either completes
+    /// (`is_completion`) or fails it. Server errors are handled as described by
+    /// [WFTReportErrAction]. Returns the server's response if reporting eventually succeeded.
+    async fn report_wft<T, Fut>(
+        &self,
+        run_id: &str,
+        task_token: &TaskToken,
+        is_completion: bool,
+        reporter: impl Fn() -> Fut,
+    ) -> Option<T>
     where
         Fut: Future<Output = Result<T, tonic::Status>>,
     {
-        let mut should_evict = None;
-        if let Err(err) = completer().await {
-            match err.code() {
-                // Silence unhandled command errors since the lang SDK cannot do anything
-                // about them besides poll again, which it will do anyway.
-                tonic::Code::InvalidArgument if err.message() == "UnhandledCommand" => {
-                    debug!(error = %err, run_id, "Unhandled command response when completing");
-                    should_evict = Some(EvictionReason::UnhandledCommand);
+        let mut retries = 0;
+        loop {
+            let err = match reporter().await {
+                Ok(resp) => return Some(resp),
+                Err(err) => err,
+            };
+            match WFTReportErrAction::for_error(&err, is_completion) {
+                WFTReportErrAction::Retry if retries < WFT_REPORT_MAX_RETRIES => {
+                    let backoff = WFT_REPORT_RETRY_BACKOFF * 2_u32.pow(retries);
+                    debug!(error=%err, run_id, ?backoff,
+                           "Server is overloaded, will retry reporting workflow task");
+                    retries += 1;
+                    tokio::time::sleep(backoff).await;
                 }
-                tonic::Code::NotFound => {
-                    warn!(error = %err, run_id, "Task not found when completing");
-                    should_evict = Some(EvictionReason::TaskNotFound);
+                WFTReportErrAction::Retry => {
+                    warn!(error=%err, run_id, "Server remained overloaded, giving up on reporting \
+                                               workflow task");
+                    self.request_eviction(
+                        run_id,
+                        "Error reporting WFT to server",
+                        EvictionReason::Fatal,
+                    );
+                    return None;
+                }
+                WFTReportErrAction::FailWFT => {
+                    warn!(error=%err, run_id, "Server rejected workflow task completion as \
+                                               invalid, failing the workflow task instead");
+                    let failure = TemporalFailure::application_failure(
+                        format!(
+                            "Server rejected workflow task completion: {}",
+                            err.message()
+                        ),
+                        false,
+                    );
+                    if let Err(fail_err) = self
+                        .client
+                        .fail_workflow_task(
+                            task_token.clone(),
+                            WorkflowTaskFailedCause::WorkflowWorkerUnhandledFailure,
+                            Some(failure),
+                        )
+                        .await
+                    {
+                        warn!(error=%fail_err, run_id, "Failed to fail rejected workflow task");
+                    }
+                    self.request_eviction(
+                        run_id,
+                        "Server rejected workflow task completion",
+                        EvictionReason::Fatal,
+                    );
+                    return None;
                 }
-                _ => {
-                    warn!(error= %err, "Network error while completing workflow activation");
-                    should_evict = Some(EvictionReason::Fatal);
+                WFTReportErrAction::Evict(reason) => {
+                    if reason == EvictionReason::Fatal {
+                        warn!(error=%err, run_id, "Network error while reporting workflow task");
+                    } else {
+                        debug!(error=%err, run_id, ?reason, "Workflow task could not be reported");
+                    }
+                    self.request_eviction(run_id, "Error reporting WFT to server", reason);
+                    return None;
                 }
             }
         }
-        if let Some(reason) = should_evict {
-            self.request_eviction(run_id, "Error reporting WFT to server", reason);
-        }
     }
 
     /// Sends a message to the workflow processing stream. Returns true if the message was sent
@@ -575,7 +953,10 @@ impl Workflows {
     fn send_local(&self, msg: impl Into<LocalInputs>) -> bool {
         let msg = msg.into();
         let print_err = match &msg {
-            LocalInputs::GetStateInfo(_) => false,
+            LocalInputs::GetStateInfo(_)
+            | LocalInputs::GetRunHistoryInfo(_)
+            | LocalInputs::GetMachinesDump(_)
+            | LocalInputs::ForceShutdown(_) => false,
             LocalInputs::LocalResolution(lr) if lr.res.is_la_cancel_confirmation() => false,
             _ => true,
         };
@@ -651,15 +1032,25 @@ impl Workflows {
             {
                 // If request_eager_execution was already false, that means lang explicitly
                 // told us it didn't want to eagerly execute for some reason. So, we only
-                // ever turn *off* eager execution if a slot is not available or the activity
-                // is scheduled on a different task queue.
+                // ever turn *off* eager execution if a slot is not available, the activity
+                // is scheduled on a different task queue, or it is of a type this worker
+                // would only fail.
                 if attrs.request_eager_execution {
                     let same_task_queue = attrs
                         .task_queue
                         .as_ref()
                         .map(|q| q.name == self.task_queue)
                         .unwrap_or_default();
+                    let type_registered = match self.registered_activity_types.as_ref() {
+                        Some(registered) => attrs
+                            .activity_type
+                            .as_ref()
+                            .map(|at| registered.contains(&at.name))
+                            .unwrap_or_default(),
+                        None => true,
+                    };
                     if same_task_queue
+                        && type_registered
                         && reserved.len() < MAX_EAGER_ACTIVITY_RESERVATIONS_PER_WORKFLOW_TASK
                     {
                         if let Some(p) = self
@@ -694,6 +1085,36 @@ impl Workflows {
     }
 }
 
+/// What to do after server returns an error when reporting the outcome of a workflow task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WFTReportErrAction {
+    /// Server is shedding load. Back off and report again.
+    Retry,
+    /// Server rejected the completion as invalid. Fail the workflow task instead, so the problem
+    /// is recorded in history, and evict the run.
+    FailWFT,
+    /// Nothing more can usefully be reported for this workflow task, just evict the run
+    Evict(EvictionReason),
+}
+
+impl WFTReportErrAction {
+    fn for_error(err: &tonic::Status, is_completion: bool) -> Self {
+        match err.code() {
+            // Unhandled command errors are expected, the lang SDK cannot do anything about them
+            // besides poll again, which it will do anyway.
+            tonic::Code::InvalidArgument if err.message() == "UnhandledCommand" => {
+                Self::Evict(EvictionReason::UnhandledCommand)
+            }
+            tonic::Code::InvalidArgument if is_completion => Self::FailWFT,
+            // The task timed out or the workflow is no longer running, so there is nobody left to
+            // report to.
+            tonic::Code::NotFound => Self::Evict(EvictionReason::TaskNotFound),
+            tonic::Code::ResourceExhausted => Self::Retry,
+            _ => Self::Evict(EvictionReason::Fatal),
+        }
+    }
+}
+
 /// Returned when a cache miss happens and we need to fetch history from the beginning to
 /// replay a run
 #[derive(Debug, derive_more::Display)]
@@ -797,6 +1218,9 @@ pub(crate) struct OutstandingTask {
     pub hit_cache: bool,
     /// Set if the outstanding task has quer(ies) which must be fulfilled upon finishing replay
     pub pending_queries: Vec<QueryWorkflow>,
+    /// Ids of (non-legacy) queries which have been dispatched to lang, all of which must be
+    /// answered by the completion of the activation they were delivered in
+    pub outstanding_queries: HashSet<String>,
     pub start_time: Instant,
     /// The WFT permit owned by this task, ensures we don't exceed max concurrent WFT, and makes
     /// sure the permit is automatically freed when we delete the task.
@@ -908,6 +1332,13 @@ pub(crate) struct WorkflowStateInfo {
     pub outstanding_wft: usize,
 }
 
+/// Identifies the execution a cached run belongs to, and how much of its history has been applied
+#[derive(Debug)]
+pub(crate) struct RunHistoryInfo {
+    pub workflow_id: String,
+    pub last_applied_event_id: i64,
+}
+
 #[derive(Debug)]
 #[cfg_attr(
     feature = "save_wf_inputs",
@@ -915,6 +1346,8 @@ pub(crate) struct WorkflowStateInfo {
 )]
 struct WFActCompleteMsg {
     completion: ValidatedCompletion,
+    /// True if core completed the activation itself, rather than lang
+    is_autocomplete: bool,
     #[cfg_attr(feature = "save_wf_inputs", serde(skip))]
     response_tx: Option<oneshot::Sender<ActivationCompleteResult>>,
 }
@@ -961,6 +1394,28 @@ pub(crate) struct HeartbeatTimeoutMsg {
 struct GetStateInfoMsg {
     response_tx: oneshot::Sender<WorkflowStateInfo>,
 }
+#[derive(Debug)]
+struct GetRunHistoryInfoMsg {
+    run_id: String,
+    response_tx: oneshot::Sender<Option<RunHistoryInfo>>,
+}
+#[derive(Debug)]
+struct GetMachinesDumpMsg {
+    run_id: String,
+    response_tx: oneshot::Sender<Option<MachinesDump>>,
+}
+#[derive(Debug)]
+struct ActivationDeadlineMsg {
+    run_id: String,
+    message: String,
+    /// Sent the task tokens of every workflow task held by the run, which was evicted
+    response_tx: oneshot::Sender<Vec<TaskToken>>,
+}
+#[derive(Debug)]
+struct ForceShutdownMsg {
+    /// Sent the task tokens of every workflow task held by the runs which were evicted
+    response_tx: oneshot::Sender<Vec<TaskToken>>,
+}
 
 /// Each activation completion produces one of these
 #[derive(Debug)]
@@ -968,6 +1423,9 @@ struct ActivationCompleteResult {
     most_recently_processed_event: usize,
     replaying: bool,
     outcome: ActivationCompleteOutcome,
+    /// Set to the run's workflow id if the completed activation evicted a run which has not
+    /// finished, for a reason which makes its sticky queue stale
+    stale_sticky_workflow_id: Option<String>,
 }
 /// What needs to be done after calling [Workflows::activation_completed]
 #[derive(Debug)]
@@ -982,6 +1440,11 @@ enum ActivationCompleteOutcome {
     /// The workflow task failed, but we shouldn't report it. EX: We have failed 2 or more attempts
     /// in a row.
     WFTFailedDontReport,
+    /// A query-only task which was answered while the run had another WFT in flight must be
+    /// responded to using the contained task token & result. The in-flight WFT is unaffected.
+    AnswerConcurrentQuery(TaskToken, Box<QueryResult>),
+    /// The completion was ignored, because no activation of the run was awaiting completion.
+    NoOutstandingActivation,
 }
 /// Did we report, or not, completion of a WFT to server?
 #[derive(Debug, Copy, Clone)]
@@ -997,6 +1460,9 @@ enum WFTReportStatus {
     /// We didn't report, but we want to clear the outstanding workflow task anyway. See
     /// [ActivationCompleteOutcome::WFTFailedDontReport]
     DropWft,
+    /// We responded to a query answered concurrently with the run's in-flight WFT, which must be
+    /// left alone. See [ActivationCompleteOutcome::AnswerConcurrentQuery]
+    ConcurrentQueryAnswered,
 }
 
 fn validate_completion(
@@ -1146,6 +1612,7 @@ pub enum WFCommand {
     CancelSignalWorkflow(CancelSignalWorkflow),
     UpsertSearchAttributes(UpsertWorkflowSearchAttributes),
     ModifyWorkflowProperties(ModifyWorkflowProperties),
+    RecordMarker(RecordMarker),
 }
 
 impl TryFrom<WorkflowCommand> for WFCommand {
@@ -1190,6 +1657,7 @@ impl TryFrom<WorkflowCommand> for WFCommand {
             workflow_command::Variant::ModifyWorkflowProperties(s) => {
                 Ok(Self::ModifyWorkflowProperties(s))
             }
+            workflow_command::Variant::RecordMarker(s) => Ok(Self::RecordMarker(s)),
         }
     }
 }
@@ -1227,9 +1695,6 @@ enum CommandID {
 pub struct WorkflowStartedInfo {
     workflow_task_timeout: Option<Duration>,
     workflow_execution_timeout: Option<Duration>,
-    memo: Option<Memo>,
-    search_attrs: Option<SearchAttributes>,
-    retry_policy: Option<RetryPolicy>,
 }
 
 /// Wraps outgoing activation job protos with some internal details core might care about
@@ -1264,13 +1729,40 @@ pub(crate) enum WFMachinesError {
     Nondeterminism(String),
     #[error("Fatal error in workflow machines: {0}")]
     Fatal(String),
+    #[error(transparent)]
+    MalformedPayload(#[from] MalformedPayload),
+    #[error(
+        "History event {event_id} of type {event_type} was produced by a feature not supported \
+         by this worker build: {feature}"
+    )]
+    UnsupportedFeature {
+        event_id: i64,
+        event_type: String,
+        feature: &'static str,
+    },
+    #[error("Invalid history from server: {0}")]
+    InvalidHistory(#[from] EventIdSequenceError),
+    #[error(
+        "Field `{field}` of {command} command holds {size} bytes of payloads, exceeding the \
+         configured limit of {limit} bytes"
+    )]
+    PayloadTooLarge {
+        command: String,
+        field: &'static str,
+        size: usize,
+        limit: usize,
+    },
 }
 
 impl WFMachinesError {
     pub fn evict_reason(&self) -> EvictionReason {
         match self {
             WFMachinesError::Nondeterminism(_) => EvictionReason::Nondeterminism,
-            WFMachinesError::Fatal(_) => EvictionReason::Fatal,
+            WFMachinesError::Fatal(_)
+            | WFMachinesError::MalformedPayload(_)
+            | WFMachinesError::UnsupportedFeature { .. }
+            | WFMachinesError::InvalidHistory(_)
+            | WFMachinesError::PayloadTooLarge { .. } => EvictionReason::Fatal,
         }
     }
 }
@@ -1288,6 +1780,12 @@ impl From<anyhow::Error> for WFMachinesError {
 }
 
 fn auto_fail_to_complete_status(err: WFMachinesError) -> workflow_activation_completion::Status {
+    if let WFMachinesError::MalformedPayload(mp) = &err {
+        return workflow_activation_completion::Status::Failed(Failure {
+            failure: Some(mp.as_failure()),
+            force_cause: WorkflowTaskFailedCause::from(err.evict_reason()) as i32,
+        });
+    }
     workflow_activation_completion::Status::Failed(Failure {
         failure: Some(
             temporal_sdk_core_protos::temporal::api::failure::v1::Failure {
@@ -1335,7 +1833,8 @@ impl LocalActivityRequestSink for LAReqSink {
 }
 
 /// Sorts jobs in an activation to be in the order lang expects:
-/// `patches -> signals -> other -> queries`
+/// `patches -> signals -> other -> queries -> eviction`. The sort is stable, so jobs of the same
+/// kind keep the order in which they were produced.
 fn sort_act_jobs(wfa: &mut WorkflowActivation) {
     wfa.jobs.sort_by(|j1, j2| {
         // Unwrapping is fine here since we'll never issue empty variants
@@ -1348,7 +1847,9 @@ fn sort_act_jobs(wfa: &mut WorkflowActivation) {
             match v {
                 workflow_activation_job::Variant::NotifyHasPatch(_) => 1,
                 workflow_activation_job::Variant::SignalWorkflow(_) => 2,
-                workflow_activation_job::Variant::QueryWorkflow(_) => 4,
+                workflow_activation_job::Variant::QueryWorkflow(_)
+                | workflow_activation_job::Variant::QueryStackTrace(_) => 4,
+                workflow_activation_job::Variant::RemoveFromCache(_) => 5,
                 _ => 3,
             }
         }
@@ -1365,6 +1866,11 @@ mod tests {
     fn jobs_sort() {
         let mut act = WorkflowActivation {
             jobs: vec![
+                WorkflowActivationJob {
+                    variant: Some(workflow_activation_job::Variant::RemoveFromCache(
+                        Default::default(),
+                    )),
+                },
                 WorkflowActivationJob {
                     variant: Some(workflow_activation_job::Variant::SignalWorkflow(
                         Default::default(),
@@ -1406,8 +1912,40 @@ mod tests {
                 workflow_activation_job::Variant::SignalWorkflow(_),
                 workflow_activation_job::Variant::FireTimer(_),
                 workflow_activation_job::Variant::ResolveActivity(_),
-                workflow_activation_job::Variant::QueryWorkflow(_)
+                workflow_activation_job::Variant::QueryWorkflow(_),
+                workflow_activation_job::Variant::RemoveFromCache(_)
             ]
         )
     }
+
+    #[test]
+    fn wft_report_errors_classified() {
+        let classify = |status: tonic::Status, is_completion| {
+            WFTReportErrAction::for_error(&status, is_completion)
+        };
+        assert_eq!(
+            classify(tonic::Status::not_found("gone"), true),
+            WFTReportErrAction::Evict(EvictionReason::TaskNotFound)
+        );
+        assert_eq!(
+            classify(tonic::Status::invalid_argument("UnhandledCommand"), true),
+            WFTReportErrAction::Evict(EvictionReason::UnhandledCommand)
+        );
+        assert_eq!(
+            classify(tonic::Status::invalid_argument("bad attributes"), true),
+            WFTReportErrAction::FailWFT
+        );
+        assert_eq!(
+            classify(tonic::Status::invalid_argument("bad attributes"), false),
+            WFTReportErrAction::Evict(EvictionReason::Fatal)
+        );
+        assert_eq!(
+            classify(tonic::Status::resource_exhausted("slow down"), false),
+            WFTReportErrAction::Retry
+        );
+        assert_eq!(
+            classify(tonic::Status::unavailable("down"), true),
+            WFTReportErrAction::Evict(EvictionReason::Fatal)
+        );
+    }
 }
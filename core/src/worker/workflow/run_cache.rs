@@ -1,19 +1,25 @@
 use crate::{
-    telemetry::metrics::workflow_type,
+    telemetry::metrics::{eviction_reason, workflow_type},
     worker::workflow::{
         managed_run::{ManagedRun, RunUpdateAct},
-        HistoryUpdate, LocalActivityRequestSink, PermittedWFT, RunBasics,
+        HistoryUpdate, LocalActivityRequestSink, PayloadSizeLimits, PermittedWFT, RunBasics,
+        ServerCapabilitiesFn,
     },
     MetricsContext,
 };
 use lru::LruCache;
 use std::{mem, num::NonZeroUsize, rc::Rc};
-use temporal_sdk_core_protos::temporal::api::workflowservice::v1::get_system_info_response;
+use temporal_sdk_core_api::worker::RunStateDumpTarget;
+use temporal_sdk_core_protos::coresdk::workflow_activation::remove_from_cache::EvictionReason;
 
 pub(super) struct RunCache {
     max: usize,
     namespace: String,
-    server_capabilities: get_system_info_response::Capabilities,
+    server_capabilities: ServerCapabilitiesFn,
+    activation_shard_count: Option<u32>,
+    sdk_name_and_version: (String, String),
+    run_state_dump: Option<RunStateDumpTarget>,
+    payload_size_limits: PayloadSizeLimits,
     /// Run id -> Data
     runs: LruCache<String, ManagedRun>,
     local_activity_request_sink: Rc<dyn LocalActivityRequestSink>,
@@ -22,10 +28,15 @@ pub(super) struct RunCache {
 }
 
 impl RunCache {
+    #[allow(clippy::too_many_arguments)] // Not much worth combining here
     pub fn new(
         max_cache_size: usize,
         namespace: String,
-        server_capabilities: get_system_info_response::Capabilities,
+        server_capabilities: ServerCapabilitiesFn,
+        activation_shard_count: Option<u32>,
+        sdk_name_and_version: (String, String),
+        run_state_dump: Option<RunStateDumpTarget>,
+        payload_size_limits: PayloadSizeLimits,
         local_activity_request_sink: impl LocalActivityRequestSink,
         metrics: MetricsContext,
     ) -> Self {
@@ -40,6 +51,10 @@ impl RunCache {
             max: max_cache_size,
             namespace,
             server_capabilities,
+            activation_shard_count,
+            sdk_name_and_version,
+            run_state_dump,
+            payload_size_limits,
             runs: LruCache::new(
                 NonZeroUsize::new(lru_size).expect("LRU size is guaranteed positive"),
             ),
@@ -66,6 +81,7 @@ impl RunCache {
         // Replace the update in the wft with a dummy one, since we must instantiate the machines
         // with the update.
         let history_update = mem::replace(&mut pwft.work.update, HistoryUpdate::dummy());
+        let capabilities = (self.server_capabilities)();
         let mut mrh = ManagedRun::new(
             RunBasics {
                 namespace: self.namespace.clone(),
@@ -74,7 +90,11 @@ impl RunCache {
                 run_id: pwft.work.execution.run_id.clone(),
                 history: history_update,
                 metrics,
-                capabilities: &self.server_capabilities,
+                capabilities: &capabilities,
+                activation_shard_count: self.activation_shard_count,
+                sdk_name_and_version: self.sdk_name_and_version.clone(),
+                run_state_dump: self.run_state_dump.clone(),
+                payload_size_limits: self.payload_size_limits,
             },
             self.local_activity_request_sink.clone(),
         );
@@ -87,9 +107,26 @@ impl RunCache {
         rur
     }
     pub fn remove(&mut self, k: &str) -> Option<ManagedRun> {
+        self.remove_inner(k, None)
+    }
+    /// Remove a run which is being evicted without first asking lang to evict it
+    pub fn remove_for_reason(&mut self, k: &str, reason: EvictionReason) -> Option<ManagedRun> {
+        self.remove_inner(k, Some(reason))
+    }
+    fn remove_inner(&mut self, k: &str, reason: Option<EvictionReason>) -> Option<ManagedRun> {
         let r = self.runs.pop(k);
         self.metrics.cache_size(self.len() as u64);
-        self.metrics.cache_eviction();
+        // Removing a run which is already gone is not another eviction
+        if let Some(run) = r.as_ref() {
+            // Runs removed without ever requesting eviction (ex: forcibly, during shutdown) have
+            // no reason
+            let reason = reason
+                .or_else(|| run.eviction_reason())
+                .unwrap_or(EvictionReason::Unspecified);
+            self.metrics
+                .with_new_attrs([eviction_reason(reason.as_str_name())])
+                .cache_eviction();
+        }
         r
     }
 
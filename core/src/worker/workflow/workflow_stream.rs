@@ -19,7 +19,9 @@ use crate::{
 use futures::{stream, stream::PollNext, Stream, StreamExt};
 use std::{collections::VecDeque, fmt::Debug, future, sync::Arc};
 use temporal_sdk_core_api::errors::PollWfError;
-use temporal_sdk_core_protos::coresdk::workflow_activation::remove_from_cache::EvictionReason;
+use temporal_sdk_core_protos::coresdk::workflow_activation::{
+    create_evict_activation, remove_from_cache::EvictionReason,
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, Span};
 
@@ -39,6 +41,9 @@ pub(super) struct WFStream {
     history_fetch_refcounter: Arc<HistfetchRC>,
     shutdown_token: CancellationToken,
     ignore_evicts_on_shutdown: bool,
+    /// Set once the force eviction timeout has elapsed, after which we stop without waiting on any
+    /// remaining work
+    shutdown_forced: bool,
 
     metrics: MetricsContext,
 
@@ -92,17 +97,25 @@ impl WFStream {
         basics: WorkflowBasics,
         local_activity_request_sink: impl LocalActivityRequestSink,
     ) -> impl Stream<Item = Result<WFStreamOutput, PollWfError>> {
+        // Inputs are processed on their own thread, so their spans are not children of the
+        // worker's and need to identify the worker themselves
+        let (namespace, task_queue) = (basics.namespace.clone(), basics.task_queue.clone());
         let mut state = WFStream {
             buffered_polls_need_cache_slot: Default::default(),
             runs: RunCache::new(
                 basics.max_cached_workflows,
                 basics.namespace.clone(),
                 basics.server_capabilities.clone(),
+                basics.activation_shard_count,
+                basics.sdk_name_and_version,
+                basics.run_state_dump,
+                basics.payload_size_limits,
                 local_activity_request_sink,
                 basics.metrics.clone(),
             ),
             shutdown_token: basics.shutdown_token,
             ignore_evicts_on_shutdown: basics.ignore_evicts_on_shutdown,
+            shutdown_forced: false,
             metrics: basics.metrics,
             runs_needing_fetching: Default::default(),
             history_fetch_refcounter: Arc::new(HistfetchRC {}),
@@ -112,7 +125,8 @@ impl WFStream {
         };
         all_inputs
             .map(move |action: WFStreamInput| {
-                let span = span!(Level::DEBUG, "new_stream_input", action=?action);
+                let span = span!(Level::DEBUG, "new_stream_input", action=?action,
+                                 namespace=%namespace, task_queue=%task_queue);
                 let _span_g = span.enter();
 
                 #[cfg(feature = "save_wf_inputs")]
@@ -161,6 +175,27 @@ impl WFStream {
                                 });
                                 None
                             }
+                            LocalInputs::GetRunHistoryInfo(ghi) => {
+                                let info = state.runs.peek(&ghi.run_id).map(|rh| rh.history_info());
+                                let _ = ghi.response_tx.send(info);
+                                None
+                            }
+                            LocalInputs::GetMachinesDump(gmd) => {
+                                let dump =
+                                    state.runs.peek(&gmd.run_id).map(|rh| rh.machines_dump());
+                                let _ = gmd.response_tx.send(dump);
+                                None
+                            }
+                            LocalInputs::ActivationDeadline(ad) => {
+                                let (task_tokens, evict_act) =
+                                    state.process_activation_deadline(&ad.run_id, ad.message);
+                                let _ = ad.response_tx.send(task_tokens);
+                                evict_act
+                            }
+                            LocalInputs::ForceShutdown(fs) => {
+                                let _ = fs.response_tx.send(state.force_shutdown());
+                                None
+                            }
                         }
                     }
                     WFStreamInput::FailedFetch {
@@ -242,6 +277,10 @@ impl WFStream {
         // If the run already exists, possibly buffer the work and return early if we can't handle
         // it yet.
         let pwft = if let Some(rh) = self.runs.get_mut(&pwft.work.execution.run_id) {
+            // Queries don't need to wait for the in-flight WFT if lang isn't busy with the run
+            if rh.can_answer_query_concurrently(&pwft) {
+                return Ok(rh.answer_query_concurrently(pwft));
+            }
             if let Some(w) = rh.buffer_wft_if_outstanding_work(pwft) {
                 w
             } else {
@@ -276,7 +315,31 @@ impl WFStream {
         Ok(rur)
     }
 
-    fn process_completion(&mut self, complete: NewOrFetchedComplete) -> Vec<ActivationOrAuto> {
+    fn process_completion(&mut self, mut complete: NewOrFetchedComplete) -> Vec<ActivationOrAuto> {
+        if let NewOrFetchedComplete::New(complete) = &mut complete {
+            let run_id = complete.completion.run_id();
+            let accepted = complete.is_autocomplete
+                || self
+                    .runs
+                    .get_mut(run_id)
+                    .map(|rh| rh.accept_completion())
+                    .unwrap_or_default();
+            if !accepted {
+                warn!(
+                    run_id,
+                    "Rejecting completion for a run with no activation awaiting one"
+                );
+                if let Some(tx) = complete.response_tx.take() {
+                    let _ = tx.send(ActivationCompleteResult {
+                        most_recently_processed_event: 0,
+                        replaying: false,
+                        outcome: ActivationCompleteOutcome::NoOutstandingActivation,
+                        evicted_unfinished_workflow_id: None,
+                    });
+                }
+                return vec![];
+            }
+        }
         let rh = if let Some(rh) = self.runs.get_mut(complete.run_id()) {
             rh
         } else {
@@ -334,6 +397,17 @@ impl WFStream {
             }
         }
 
+        // Answering a concurrent query never touches the run's WFT or outstanding activation
+        if matches!(
+            report.wft_report_status,
+            WFTReportStatus::ConcurrentQueryAnswered
+        ) {
+            return self.runs.get_mut(run_id).and_then(|rh| {
+                rh.concurrent_query_answered();
+                rh.check_more_activations()
+            });
+        }
+
         let mut res = None;
 
         // If we reported to server, we always want to mark it complete.
@@ -529,7 +603,72 @@ impl WFStream {
         acts
     }
 
+    /// Evicts a run whose activation lang did not complete in time, without waiting on lang any
+    /// longer. Returns the task tokens of the workflow tasks it held, which must be failed, and the
+    /// eviction to hand lang so that it drops the run as well.
+    fn process_activation_deadline(
+        &mut self,
+        run_id: &str,
+        message: String,
+    ) -> (Vec<TaskToken>, Option<ActivationOrAuto>) {
+        match self
+            .runs
+            .remove_for_reason(run_id, EvictionReason::DeadlockDetected)
+        {
+            Some(mut rh) => {
+                let task_tokens = rh
+                    .wft()
+                    .map(|wft| wft.info.task_token.clone())
+                    .into_iter()
+                    .chain(rh.take_buffered_wft().map(|pwft| pwft.work.task_token))
+                    .collect();
+                let mut evict_act = create_evict_activation(
+                    run_id.to_string(),
+                    message,
+                    EvictionReason::DeadlockDetected,
+                );
+                evict_act.shard = rh.activation_shard();
+                (
+                    task_tokens,
+                    Some(ActivationOrAuto::LangActivation(evict_act)),
+                )
+            }
+            None => (vec![], None),
+        }
+    }
+
+    /// Evict every run and drop any buffered polls without waiting on lang, returning the task
+    /// tokens of all the workflow tasks they held so that they may be failed. Lang is not sent
+    /// evictions for these runs - it is expected to drop them all once polling reports shutdown.
+    fn force_shutdown(&mut self) -> Vec<TaskToken> {
+        self.shutdown_forced = true;
+        let mut task_tokens: Vec<_> = self
+            .buffered_polls_need_cache_slot
+            .drain(..)
+            .map(|pwft| pwft.work.task_token)
+            .collect();
+        let run_ids: Vec<_> = self
+            .runs
+            .runs_lru_order()
+            .map(|(rid, _)| rid.to_string())
+            .collect();
+        for run_id in run_ids {
+            if let Some(mut rh) = self.runs.remove(&run_id) {
+                warn!(
+                    run_id,
+                    "Forcibly evicting run since the force eviction timeout elapsed"
+                );
+                task_tokens.extend(rh.wft().map(|wft| wft.info.task_token.clone()));
+                task_tokens.extend(rh.take_buffered_wft().map(|pwft| pwft.work.task_token));
+            }
+        }
+        task_tokens
+    }
+
     fn shutdown_done(&self) -> bool {
+        if self.shutdown_forced {
+            return true;
+        }
         if self.shutdown_token.is_cancelled() {
             if Arc::strong_count(&self.history_fetch_refcounter) > 1 {
                 // Don't exit if there are outstanding fetch requests
@@ -633,6 +772,14 @@ pub(super) enum LocalInputs {
     HeartbeatTimeout(String),
     #[cfg_attr(feature = "save_wf_inputs", serde(skip))]
     GetStateInfo(GetStateInfoMsg),
+    #[cfg_attr(feature = "save_wf_inputs", serde(skip))]
+    GetRunHistoryInfo(GetRunHistoryInfoMsg),
+    #[cfg_attr(feature = "save_wf_inputs", serde(skip))]
+    GetMachinesDump(GetMachinesDumpMsg),
+    #[cfg_attr(feature = "save_wf_inputs", serde(skip))]
+    ActivationDeadline(ActivationDeadlineMsg),
+    #[cfg_attr(feature = "save_wf_inputs", serde(skip))]
+    ForceShutdown(ForceShutdownMsg),
 }
 impl LocalInputs {
     fn run_id(&self) -> Option<&str> {
@@ -643,7 +790,11 @@ impl LocalInputs {
             LocalInputs::PostActivation(pa) => &pa.run_id,
             LocalInputs::RequestEviction(re) => &re.run_id,
             LocalInputs::HeartbeatTimeout(hb) => hb,
-            LocalInputs::GetStateInfo(_) => return None,
+            LocalInputs::ActivationDeadline(ad) => &ad.run_id,
+            LocalInputs::GetStateInfo(_)
+            | LocalInputs::GetRunHistoryInfo(_)
+            | LocalInputs::GetMachinesDump(_)
+            | LocalInputs::ForceShutdown(_) => return None,
         })
     }
 }
@@ -46,7 +46,7 @@ pub async fn replay_wf_state_inputs(mut config: WorkerConfig, inputs: impl Strea
         &mut config,
         MetricsContext::no_op(),
         CancellationToken::new(),
-        DEFAULT_TEST_CAPABILITIES.clone(),
+        Arc::new(|| DEFAULT_TEST_CAPABILITIES.clone()),
     );
     let sink = ReadingFromFileLaReqSink {
         resolutions: la_resp_q,
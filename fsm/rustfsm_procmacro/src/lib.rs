@@ -629,13 +629,17 @@ impl StateMachineDefinition {
     }
 
     fn visualize(&self) -> String {
-        let transitions: Vec<String> = self
+        // Machines are conventionally defined starting from their initial state
+        let initial = self
             .transitions
-            .iter()
-            .flat_map(|t| {
+            .first()
+            .map(|t| format!("[*] --> {}", t.from));
+        let transitions: Vec<String> = initial
+            .into_iter()
+            .chain(self.transitions.iter().flat_map(|t| {
                 t.to.iter()
                     .map(move |d| format!("{} --> {}: {}", t.from, d, t.event.ident))
-            })
+            }))
             // Add all final state transitions
             .chain(
                 self.all_states()
@@ -0,0 +1,73 @@
+extern crate rustfsm_trait as rustfsm;
+
+use rustfsm_procmacro::fsm;
+use rustfsm_trait::{StateMachine, TakenTransition, TransitionResult};
+use std::convert::Infallible;
+
+fsm! {
+    name SimpleMachine; command SimpleMachineCommand; error Infallible;
+
+    One --(A)--> Two;
+    Two --(B)--> One;
+    Two --(C)--> Three
+}
+
+#[derive(Default, Clone)]
+pub struct One {}
+impl From<Two> for One {
+    fn from(_: Two) -> Self {
+        One {}
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Two {}
+impl From<One> for Two {
+    fn from(_: One) -> Self {
+        Two {}
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Three {}
+impl From<Two> for Three {
+    fn from(_: Two) -> Self {
+        Three {}
+    }
+}
+
+pub enum SimpleMachineCommand {}
+
+fn taken(from: &str, to: &str, event: &str) -> TakenTransition {
+    TakenTransition {
+        from: from.to_string(),
+        to: to.to_string(),
+        event: event.to_string(),
+    }
+}
+
+fn main() {
+    let path = [
+        taken("One", "Two", "A"),
+        taken("Two", "One", "B"),
+        taken("One", "Two", "A"),
+    ];
+
+    let mermaid = SimpleMachine::mermaid_visualizer(&path);
+    assert!(mermaid.starts_with("stateDiagram-v2\n"));
+    assert!(mermaid.contains("    [*] --> One\n"));
+    assert!(mermaid.contains("    One --> Two: A (#1, #3)\n"));
+    assert!(mermaid.contains("    Two --> One: B (#2)\n"));
+    assert!(mermaid.contains("    Two --> Three: C\n"));
+    assert!(mermaid.contains("    Three --> [*]\n"));
+    assert!(mermaid.contains("    class One,Two taken\n"));
+    assert!(!SimpleMachine::mermaid_visualizer(&[]).contains("classDef"));
+
+    let dot = SimpleMachine::dot_visualizer(&path);
+    assert!(dot.starts_with("digraph {\n"));
+    assert!(dot.contains(r#"    "__start0" -> "One";"#));
+    assert!(dot.contains(r#""One" -> "Two" [label="A (#1, #3)", color=blue, fontcolor=blue];"#));
+    assert!(dot.contains(r#"    "Two" -> "Three" [label="C"];"#));
+    assert!(dot.contains(r#"    "Three" [peripheries=2];"#));
+    assert!(dot.ends_with("}\n"));
+}
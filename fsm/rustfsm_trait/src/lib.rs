@@ -43,6 +43,156 @@ pub trait StateMachine: Sized {
 
     /// Return a PlantUML definition of the fsm that can be used to visualize it
     fn visualizer() -> &'static str;
+
+    /// Return a Mermaid state diagram of the fsm. Any of the `taken` transitions which the fsm
+    /// defines are labeled with their (1-based) positions in that list, and the states they pass
+    /// through are highlighted, so the path an instance of the machine took can be followed.
+    #[cfg(feature = "diagrams")]
+    fn mermaid_visualizer(taken: &[TakenTransition]) -> String {
+        diagrams::mermaid(Self::visualizer(), taken)
+    }
+
+    /// Return a Graphviz DOT definition of the fsm. Any of the `taken` transitions which the fsm
+    /// defines are drawn in blue and labeled with their (1-based) positions in that list, so the
+    /// path an instance of the machine took can be followed.
+    #[cfg(feature = "diagrams")]
+    fn dot_visualizer(taken: &[TakenTransition]) -> String {
+        diagrams::dot(Self::visualizer(), taken)
+    }
+}
+
+#[cfg(feature = "diagrams")]
+pub use diagrams::TakenTransition;
+
+#[cfg(feature = "diagrams")]
+mod diagrams {
+    /// A transition taken by an instance of a [crate::StateMachine], named as in its definition.
+    /// See [crate::StateMachine::mermaid_visualizer] and [crate::StateMachine::dot_visualizer].
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct TakenTransition {
+        pub from: String,
+        pub to: String,
+        pub event: String,
+    }
+
+    enum VizLine<'a> {
+        Initial(&'a str),
+        Transition {
+            from: &'a str,
+            to: &'a str,
+            event: &'a str,
+        },
+        Final(&'a str),
+    }
+
+    /// Parses the lines of a definition produced by [crate::StateMachine::visualizer]
+    fn parse_visualizer(viz: &str) -> impl Iterator<Item = VizLine<'_>> {
+        viz.lines().filter_map(|line| {
+            let (from, rest) = line.split_once(" --> ")?;
+            if from == "[*]" {
+                return Some(VizLine::Initial(rest));
+            }
+            if rest == "[*]" {
+                return Some(VizLine::Final(from));
+            }
+            let (to, event) = rest.split_once(": ")?;
+            Some(VizLine::Transition { from, to, event })
+        })
+    }
+
+    pub(super) fn mermaid(viz: &str, taken: &[TakenTransition]) -> String {
+        let mut out = "stateDiagram-v2\n".to_string();
+        for line in parse_visualizer(viz) {
+            match line {
+                VizLine::Initial(state) => out.push_str(&format!("    [*] --> {state}\n")),
+                VizLine::Transition { from, to, event } => {
+                    let (label, _) = transition_label(from, to, event, taken);
+                    out.push_str(&format!("    {from} --> {to}: {label}\n"));
+                }
+                VizLine::Final(state) => out.push_str(&format!("    {state} --> [*]\n")),
+            }
+        }
+        let mut visited: Vec<&str> = taken
+            .iter()
+            .flat_map(|t| [t.from.as_str(), t.to.as_str()])
+            .collect();
+        visited.sort_unstable();
+        visited.dedup();
+        if !visited.is_empty() {
+            out.push_str("    classDef taken stroke:blue,stroke-width:3px\n");
+            out.push_str(&format!("    class {} taken\n", visited.join(",")));
+        }
+        out
+    }
+
+    pub(super) fn dot(viz: &str, taken: &[TakenTransition]) -> String {
+        let mut out = "digraph {\n".to_string();
+        let mut num_initial = 0;
+        for line in parse_visualizer(viz) {
+            match line {
+                VizLine::Initial(state) => {
+                    // DOT has no notion of an initial state, so draw an entry point leading to it
+                    let entry = format!("__start{num_initial}");
+                    num_initial += 1;
+                    out.push_str(&format!("    \"{entry}\" [shape=point, label=\"\"];\n"));
+                    out.push_str(&format!("    \"{entry}\" -> \"{state}\";\n"));
+                }
+                VizLine::Transition { from, to, event } => {
+                    let (label, was_taken) = transition_label(from, to, event, taken);
+                    let style = if was_taken {
+                        ", color=blue, fontcolor=blue"
+                    } else {
+                        ""
+                    };
+                    out.push_str(&format!(
+                        "    \"{from}\" -> \"{to}\" [label=\"{label}\"{style}];\n"
+                    ));
+                }
+                VizLine::Final(state) => {
+                    out.push_str(&format!("    \"{state}\" [peripheries=2];\n"))
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Returns the label for a transition, and whether it appears among the taken transitions
+    fn transition_label(
+        from: &str,
+        to: &str,
+        event: &str,
+        taken: &[TakenTransition],
+    ) -> (String, bool) {
+        let steps: Vec<String> = taken
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.from == from && t.to == to && t.event == event)
+            .map(|(i, _)| format!("#{}", i + 1))
+            .collect();
+        if steps.is_empty() {
+            (event.to_string(), false)
+        } else {
+            (format!("{event} ({})", steps.join(", ")), true)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const VIZ: &str = "@startuml\n[*] --> One\nOne --> Two: A\nTwo --> [*]\n@enduml";
+
+        #[test]
+        fn initial_state_is_rendered() {
+            let mermaid = mermaid(VIZ, &[]);
+            assert!(mermaid.contains("    [*] --> One\n"));
+            assert!(mermaid.contains("    One --> Two: A\n"));
+            let dot = dot(VIZ, &[]);
+            assert!(dot.contains(r#"    "__start0" [shape=point, label=""];"#));
+            assert!(dot.contains(r#"    "__start0" -> "One";"#));
+        }
+    }
 }
 
 /// The error returned by [StateMachine]s when handling events
@@ -1,2 +1,4 @@
 pub use rustfsm_procmacro::fsm;
+#[cfg(feature = "diagrams")]
+pub use rustfsm_trait::TakenTransition;
 pub use rustfsm_trait::{MachineError, StateMachine, TransitionResult};
@@ -4,6 +4,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed=../protos");
     let out = PathBuf::from(env::var("OUT_DIR").unwrap());
     let descriptor_file = out.join("descriptors.bin");
+    let stand_in_descriptor_file = out.join("stand_in_descriptors.bin");
     tonic_build::configure()
         // We don't actually want to build the grpc definitions - we don't need them (for now).
         // Just build the message structs.
@@ -112,6 +113,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             ".google.protobuf.Value",
             "::prost_wkt_types::Value"
         )
+        // Stands in for the upstream message, which lacks fields newer API versions have
+        .extern_path(
+            ".temporal.api.sdk.v1.WorkflowTaskCompletedMetadata",
+            "crate::coresdk::sdk_metadata::WorkflowTaskCompletedMetadata"
+        )
         .file_descriptor_set_path(#[allow(clippy::needless_borrow)] &descriptor_file)
         .compile(
             &[
@@ -129,6 +135,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             ],
         )?;
 
+    // Compiled separately so that it is not part of the descriptor set above, which would
+    // otherwise give it a second serde registration alongside the upstream message it replaces.
+    tonic_build::configure()
+        .build_server(false)
+        .build_client(false)
+        .type_attribute(
+            ".",
+            "#[cfg_attr(feature = \"serde_serialize\", derive(::serde::Serialize, ::serde::Deserialize))]",
+        )
+        .file_descriptor_set_path(#[allow(clippy::needless_borrow)] &stand_in_descriptor_file)
+        .compile(
+            &["../protos/local/temporal/sdk/core/sdk_metadata/sdk_metadata.proto"],
+            &["../protos/local"],
+        )?;
+
     #[cfg(feature = "serde_serialize")]
     {
         use prost_wkt_build::{FileDescriptorSet, Message};
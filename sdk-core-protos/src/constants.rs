@@ -5,3 +5,14 @@ pub const PATCH_MARKER_NAME: &str = "core_patch";
 
 /// Used as `marker_name` field when recording local activity markers
 pub const LOCAL_ACTIVITY_MARKER_NAME: &str = "core_local_activity";
+
+/// Query type for the built-in stack trace query, delivered to lang as a `QueryStackTrace` job
+pub const STACK_TRACE_QUERY_TYPE: &str = "__stack_trace";
+
+/// Query type for the built-in enhanced stack trace query, delivered to lang as a
+/// `QueryStackTrace` job
+pub const ENHANCED_STACK_TRACE_QUERY_TYPE: &str = "__enhanced_stack_trace";
+
+/// Query type which core answers on its own (without involving lang) with the list of patch IDs the
+/// workflow has encountered so far
+pub const LIST_PATCHES_QUERY_TYPE: &str = "__list_patches";
@@ -0,0 +1,122 @@
+//! A stable, versioned hashing scheme used wherever core derives values from strings which must
+//! be identical across processes, releases, and language SDKs - most notably workflow randomness
+//! seeds. Anything derived this way may end up influencing workflow code, so changing the output
+//! for existing inputs would break replay of existing histories.
+//!
+//! Lang SDKs which need to derive the same values themselves (ex: to seed a random number generator
+//! without waiting on core) should do so by reimplementing the scheme as documented on
+//! [HashVersion], or by calling [deterministic_hash] through their bridge.
+
+use siphasher::sip::SipHasher13;
+use std::hash::Hasher;
+
+/// Identifies a version of the deterministic hashing scheme. The output of a given version for a
+/// given input will never change. If the scheme ever needs to change, a new version will be added
+/// rather than modifying an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum HashVersion {
+    /// SipHash-1-3 with both keys set to zero, fed the UTF-8 bytes of the input followed by a
+    /// single `0xFF` byte, producing a `u64`. This is exactly what hashing a `str` with Rust's
+    /// `SipHasher13` produced when core first started deriving randomness seeds, and is kept
+    /// byte-for-byte compatible with that.
+    V1 = 1,
+}
+
+impl HashVersion {
+    /// The version currently used by core for newly derived values
+    pub const CURRENT: HashVersion = HashVersion::V1;
+
+    /// The version byte, suitable for passing across language boundaries
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Returned when a version byte does not correspond to any known [HashVersion]
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Unknown deterministic hash version {0}")]
+pub struct UnknownHashVersion(pub u8);
+
+impl TryFrom<u8> for HashVersion {
+    type Error = UnknownHashVersion;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            1 => Ok(HashVersion::V1),
+            other => Err(UnknownHashVersion(other)),
+        }
+    }
+}
+
+/// Hash the input using the provided version of the scheme
+pub fn deterministic_hash(version: HashVersion, input: &str) -> u64 {
+    match version {
+        HashVersion::V1 => {
+            let mut s = SipHasher13::new_with_keys(0, 0);
+            s.write(input.as_bytes());
+            s.write_u8(0xff);
+            s.finish()
+        }
+    }
+}
+
+/// Derive the randomness seed lang should use for a workflow run from the provided run id (which
+/// is the original run id for a new execution, or the new run id after a reset).
+///
+/// Seeds are derived again every time a history is replayed, so this is pinned to
+/// [HashVersion::V1] rather than following [HashVersion::CURRENT]. Otherwise bumping the current
+/// version would change the seeds of already recorded histories.
+pub fn randomness_seed_from_run_id(run_id: &str) -> u64 {
+    deterministic_hash(HashVersion::V1, run_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::Hash;
+
+    #[test]
+    fn v1_matches_std_str_hashing() {
+        for input in [
+            "",
+            "a",
+            "some-run-id",
+            "3c7b0f3e-1c1e-4bd2-a0d1-4f1b7e2b6a1f",
+        ] {
+            let mut s = SipHasher13::new();
+            input.hash(&mut s);
+            assert_eq!(deterministic_hash(HashVersion::V1, input), s.finish());
+        }
+    }
+
+    #[test]
+    fn v1_output_is_stable() {
+        // This value must never change. If this test fails, replay of existing histories is
+        // broken.
+        assert_eq!(
+            deterministic_hash(HashVersion::V1, "some-run-id"),
+            2992095395413414811
+        );
+    }
+
+    #[test]
+    fn randomness_seed_is_stable() {
+        // This value must never change, even when the current hash version does. If this test
+        // fails, replay of existing histories is broken.
+        assert_eq!(
+            randomness_seed_from_run_id("some-run-id"),
+            2992095395413414811
+        );
+    }
+
+    #[test]
+    fn version_byte_round_trips() {
+        assert_eq!(
+            HashVersion::try_from(HashVersion::V1.as_u8()),
+            Ok(HashVersion::V1)
+        );
+        assert_eq!(HashVersion::try_from(0), Err(UnknownHashVersion(0)));
+    }
+}
@@ -266,6 +266,16 @@ impl TestHistoryBuilder {
         self.build_and_push_event(EventType::MarkerRecorded, attrs.into());
     }
 
+    pub fn add_marker(&mut self, marker_name: &str, details: HashMap<String, Payloads>) {
+        let attrs = MarkerRecordedEventAttributes {
+            marker_name: marker_name.to_string(),
+            details,
+            workflow_task_completed_event_id: self.previous_task_completed_id,
+            ..Default::default()
+        };
+        self.build_and_push_event(EventType::MarkerRecorded, attrs.into());
+    }
+
     pub fn add_local_activity_marker(
         &mut self,
         seq: u32,
@@ -3,6 +3,7 @@
 //! that will match the generated structs in this module.
 
 pub mod constants;
+pub mod deterministic_hash;
 pub mod utilities;
 
 #[cfg(feature = "history_builders")]
@@ -23,6 +24,16 @@ pub use task_token::TaskToken;
 pub static ENCODING_PAYLOAD_KEY: &str = "encoding";
 pub static JSON_ENCODING_VAL: &str = "json/plain";
 pub static PATCHED_MARKER_DETAILS_KEY: &str = "patch-data";
+/// The encoded `FileDescriptorSet` for every proto compiled into this crate (including their
+/// imports). Useful for working with the protos reflectively, ex: to transcode their JSON form.
+pub static FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/descriptors.bin"));
+/// The encoded `FileDescriptorSet` for local messages which stand in for upstream ones (ex:
+/// [coresdk::sdk_metadata::WorkflowTaskCompletedMetadata]). They are not part of
+/// [FILE_DESCRIPTOR_SET], which only has the upstream definitions, so reflective users must
+/// substitute them in to see every field the generated types have.
+pub static STAND_IN_FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/stand_in_descriptors.bin"));
 
 #[allow(clippy::large_enum_variant, clippy::derive_partial_eq_without_eq)]
 // I'd prefer not to do this, but there are some generated things that just don't need it.
@@ -321,11 +332,24 @@ pub mod coresdk {
         pub fn extract_local_activity_marker_data(
             details: &HashMap<String, Payloads>,
         ) -> Option<LocalActivityMarkerData> {
-            details
+            decode_local_activity_marker_data(details).ok()
+        }
+
+        /// Like [extract_local_activity_marker_data], but if the local activity info is missing
+        /// or malformed, describes what is wrong with it
+        pub fn decode_local_activity_marker_data(
+            details: &HashMap<String, Payloads>,
+        ) -> Result<LocalActivityMarkerData, String> {
+            let payload = details
                 .get("data")
-                .and_then(|p| p.payloads.get(0))
-                .and_then(|p| std::str::from_utf8(&p.data).ok())
-                .and_then(|s| serde_json::from_str(s).ok())
+                .ok_or("missing")?
+                .payloads
+                .first()
+                .ok_or("contains no payloads")?;
+            let as_str = std::str::from_utf8(&payload.data)
+                .map_err(|e| format!("payload is not valid UTF-8: {e}"))?;
+            serde_json::from_str(as_str)
+                .map_err(|e| format!("payload is not valid local activity marker data: {e}"))
         }
 
         /// Given a marker detail map, returns the local activity info and the result payload
@@ -340,6 +364,10 @@ pub mod coresdk {
         }
     }
 
+    pub mod sdk_metadata {
+        tonic::include_proto!("coresdk.sdk_metadata");
+    }
+
     pub mod external_data {
         use prost_wkt_types::{Duration, Timestamp};
         use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -454,6 +482,9 @@ pub mod coresdk {
                     }),
                 )],
                 available_internal_flags: vec![],
+                shard: 0,
+                history_size_bytes: 0,
+                continue_as_new_suggested: false,
             }
         }
 
@@ -574,6 +605,9 @@ pub mod coresdk {
                     workflow_activation_job::Variant::QueryWorkflow(_) => {
                         write!(f, "QueryWorkflow")
                     }
+                    workflow_activation_job::Variant::QueryStackTrace(q) => {
+                        write!(f, "QueryStackTrace(enhanced: {})", q.enhanced)
+                    }
                     workflow_activation_job::Variant::CancelWorkflow(_) => {
                         write!(f, "CancelWorkflow")
                     }
@@ -672,6 +706,8 @@ pub mod coresdk {
                 memo: attrs.memo,
                 search_attributes: attrs.search_attributes,
                 start_time: Some(start_time),
+                task_queue: attrs.task_queue.map(|tq| tq.name).unwrap_or_default(),
+                original_execution_run_id: attrs.original_execution_run_id,
             }
         }
     }
@@ -701,6 +737,19 @@ pub mod coresdk {
 
     pub mod child_workflow {
         tonic::include_proto!("coresdk.child_workflow");
+
+        use crate::temporal::api::enums::v1::ParentClosePolicy as APIParentClosePolicy;
+
+        impl From<ParentClosePolicy> for APIParentClosePolicy {
+            fn from(p: ParentClosePolicy) -> Self {
+                match p {
+                    ParentClosePolicy::Unspecified => APIParentClosePolicy::Unspecified,
+                    ParentClosePolicy::Terminate => APIParentClosePolicy::Terminate,
+                    ParentClosePolicy::Abandon => APIParentClosePolicy::Abandon,
+                    ParentClosePolicy::RequestCancel => APIParentClosePolicy::RequestCancel,
+                }
+            }
+        }
     }
 
     pub mod workflow_commands {
@@ -842,6 +891,12 @@ pub mod coresdk {
             }
         }
 
+        impl Display for RecordMarker {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "RecordMarker({})", self.marker_name)
+            }
+        }
+
         impl Display for ModifyWorkflowProperties {
             fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
                 write!(
@@ -1406,7 +1461,7 @@ pub mod temporal {
                     coresdk::{workflow_commands, IntoPayloadsExt},
                     temporal::api::{
                         common::v1::{ActivityType, WorkflowType},
-                        enums::v1::CommandType,
+                        enums::v1::{CommandType, ParentClosePolicy},
                     },
                 };
                 use command::Attributes;
@@ -1492,6 +1547,17 @@ pub mod temporal {
                     }
                 }
 
+                impl From<workflow_commands::RecordMarker> for command::Attributes {
+                    fn from(s: workflow_commands::RecordMarker) -> Self {
+                        Self::RecordMarkerCommandAttributes(RecordMarkerCommandAttributes {
+                            marker_name: s.marker_name,
+                            details: s.details,
+                            header: None,
+                            failure: None,
+                        })
+                    }
+                }
+
                 impl From<workflow_commands::CancelTimer> for command::Attributes {
                     fn from(s: workflow_commands::CancelTimer) -> Self {
                         Self::CancelTimerCommandAttributes(CancelTimerCommandAttributes {
@@ -1524,6 +1590,7 @@ pub mod temporal {
 
                 impl From<workflow_commands::StartChildWorkflowExecution> for command::Attributes {
                     fn from(s: workflow_commands::StartChildWorkflowExecution) -> Self {
+                        let parent_close_policy: ParentClosePolicy = s.parent_close_policy().into();
                         Self::StartChildWorkflowExecutionCommandAttributes(
                             StartChildWorkflowExecutionCommandAttributes {
                                 workflow_id: s.workflow_id,
@@ -1543,7 +1610,7 @@ pub mod temporal {
                                 workflow_task_timeout: s.workflow_task_timeout,
                                 retry_policy: s.retry_policy.map(Into::into),
                                 cron_schedule: s.cron_schedule.clone(),
-                                parent_close_policy: s.parent_close_policy,
+                                parent_close_policy: parent_close_policy as i32,
                             },
                         )
                     }
@@ -1578,6 +1645,7 @@ pub mod temporal {
                                 input: c.arguments.into_payloads(),
                                 workflow_run_timeout: c.workflow_run_timeout,
                                 workflow_task_timeout: c.workflow_task_timeout,
+                                backoff_start_interval: c.start_delay,
                                 memo: if c.memo.is_empty() {
                                     None
                                 } else {
@@ -1958,6 +2026,7 @@ pub mod temporal {
         }
         pub mod sdk {
             pub mod v1 {
+                pub use crate::coresdk::sdk_metadata::WorkflowTaskCompletedMetadata;
                 tonic::include_proto!("temporal.api.sdk.v1");
             }
         }
@@ -2098,8 +2167,23 @@ pub mod grpc {
 
 #[cfg(test)]
 mod tests {
-    use crate::temporal::api::failure::v1::Failure;
+    use crate::{
+        coresdk::{
+            child_workflow,
+            workflow_activation::start_workflow_from_attribs,
+            workflow_commands::{ContinueAsNewWorkflowExecution, StartChildWorkflowExecution},
+        },
+        temporal::api::{
+            command::v1::command,
+            common::v1::{Header, Memo, Payload, WorkflowExecution},
+            enums::v1::ParentClosePolicy,
+            failure::v1::Failure,
+            history::v1::WorkflowExecutionStartedEventAttributes,
+            taskqueue::v1::TaskQueue,
+        },
+    };
     use anyhow::anyhow;
+    use std::collections::HashMap;
 
     #[test]
     fn anyhow_to_failure_conversion() {
@@ -2114,4 +2198,104 @@ mod tests {
         assert_eq!(as_fail.cause.as_ref().unwrap().message, "fail 2");
         assert_eq!(as_fail.cause.unwrap().cause.unwrap().message, "fail 1");
     }
+
+    #[test]
+    fn start_workflow_carries_execution_info() {
+        let memo = Memo {
+            fields: HashMap::from([("k".to_string(), Payload::default())]),
+        };
+        let attrs = WorkflowExecutionStartedEventAttributes {
+            task_queue: Some(TaskQueue {
+                name: "tq".to_string(),
+                ..Default::default()
+            }),
+            parent_workflow_namespace: "parent-ns".to_string(),
+            parent_workflow_execution: Some(WorkflowExecution {
+                workflow_id: "parent-wf".to_string(),
+                run_id: "parent-run".to_string(),
+            }),
+            memo: Some(memo.clone()),
+            original_execution_run_id: "orig-run".to_string(),
+            ..Default::default()
+        };
+        let sw = start_workflow_from_attribs(attrs, "wfid".to_string(), 1, Default::default());
+        assert_eq!(sw.task_queue, "tq");
+        assert_eq!(sw.original_execution_run_id, "orig-run");
+        assert_eq!(sw.memo, Some(memo));
+        let parent = sw.parent_workflow_info.unwrap();
+        assert_eq!(parent.namespace, "parent-ns");
+        assert_eq!(parent.workflow_id, "parent-wf");
+        assert_eq!(parent.run_id, "parent-run");
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        let headers = HashMap::from([
+            ("tracing".to_string(), Payload::from(b"span")),
+            ("empty".to_string(), Payload::default()),
+        ]);
+        let header: Header = headers.clone().into();
+        assert_eq!(HashMap::from(header.clone()), headers);
+        let attrs = WorkflowExecutionStartedEventAttributes {
+            header: Some(header),
+            ..Default::default()
+        };
+        let sw = start_workflow_from_attribs(attrs, "wfid".to_string(), 1, Default::default());
+        assert_eq!(sw.headers, headers);
+    }
+
+    #[test]
+    fn child_parent_close_policy_mapped_by_name() {
+        for (core_policy, api_policy) in [
+            (
+                child_workflow::ParentClosePolicy::Terminate,
+                ParentClosePolicy::Terminate,
+            ),
+            (
+                child_workflow::ParentClosePolicy::RequestCancel,
+                ParentClosePolicy::RequestCancel,
+            ),
+            (
+                child_workflow::ParentClosePolicy::Abandon,
+                ParentClosePolicy::Abandon,
+            ),
+        ] {
+            assert_eq!(sent_policy(core_policy as i32), api_policy as i32);
+        }
+        // Values lang sends which core does not know are not forwarded to the server as-is
+        assert_eq!(sent_policy(99), ParentClosePolicy::Unspecified as i32);
+    }
+
+    fn sent_policy(parent_close_policy: i32) -> i32 {
+        let attrs: command::Attributes = StartChildWorkflowExecution {
+            parent_close_policy,
+            ..Default::default()
+        }
+        .into();
+        match attrs {
+            command::Attributes::StartChildWorkflowExecutionCommandAttributes(a) => {
+                a.parent_close_policy
+            }
+            _ => panic!("Wrong attributes type"),
+        }
+    }
+
+    #[test]
+    fn continue_as_new_start_delay_sent_as_backoff() {
+        let delay = prost_wkt_types::Duration {
+            seconds: 5,
+            nanos: 0,
+        };
+        let attrs: command::Attributes = ContinueAsNewWorkflowExecution {
+            start_delay: Some(delay.clone()),
+            ..Default::default()
+        }
+        .into();
+        match attrs {
+            command::Attributes::ContinueAsNewWorkflowExecutionCommandAttributes(a) => {
+                assert_eq!(a.backoff_start_interval, Some(delay));
+            }
+            _ => panic!("Wrong attributes type"),
+        }
+    }
 }
@@ -55,16 +55,21 @@ pub use activity_context::ActContext;
 pub use temporal_client::Namespace;
 pub use workflow_context::{
     ActivityOptions, CancellableFuture, ChildWorkflow, ChildWorkflowOptions, LocalActivityOptions,
-    Signal, SignalData, SignalWorkflowOptions, WfContext,
+    Session, SessionOptions, Signal, SignalData, SignalWorkflowOptions, WfContext,
 };
 
 use crate::{
     interceptors::WorkerInterceptor,
-    workflow_context::{ChildWfCommon, PendingChildWorkflow},
+    workflow_context::{
+        ChildWfCommon, PendingChildWorkflow, SESSION_CREATION_ACTIVITY_TYPE,
+        SESSION_HEARTBEAT_ACTIVITY_TYPE,
+    },
 };
 use anyhow::{anyhow, bail, Context};
 use app_data::AppData;
-use futures::{future::BoxFuture, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
+use futures::{
+    future::BoxFuture, stream, stream::BoxStream, FutureExt, StreamExt, TryFutureExt, TryStreamExt,
+};
 use serde::Serialize;
 use std::{
     any::{Any, TypeId},
@@ -74,6 +79,7 @@ use std::{
     future::Future,
     panic::AssertUnwindSafe,
     sync::Arc,
+    time::Duration,
 };
 use temporal_client::ClientOptionsBuilder;
 use temporal_sdk_core::Url;
@@ -135,6 +141,8 @@ struct CommonWorker {
     worker: Arc<dyn CoreWorker>,
     task_queue: String,
     worker_interceptor: Option<Box<dyn WorkerInterceptor>>,
+    /// If sessions are enabled, polls for activities belonging to sessions hosted by this worker
+    session_worker: Option<Arc<dyn CoreWorker>>,
 }
 
 struct WorkflowHalf {
@@ -167,6 +175,7 @@ impl Worker {
                 worker,
                 task_queue: task_queue.into(),
                 worker_interceptor: None,
+                session_worker: None,
             },
             workflow_half: WorkflowHalf {
                 workflows: Default::default(),
@@ -189,7 +198,13 @@ impl Worker {
     /// TODO: Doc better after shutdown changes
     pub fn shutdown_handle(&self) -> impl Fn() {
         let w = self.common.worker.clone();
-        move || w.initiate_shutdown()
+        let session_w = self.common.session_worker.clone();
+        move || {
+            w.initiate_shutdown();
+            if let Some(sw) = session_w.as_ref() {
+                sw.initiate_shutdown();
+            }
+        }
     }
 
     /// Register a Workflow function to invoke when the Worker is asked to run a workflow of
@@ -220,6 +235,23 @@ impl Worker {
         );
     }
 
+    /// Allow this worker to host sessions created by [WfContext::create_session]. Activities
+    /// run within sessions hosted here are polled for by `session_worker`, which must poll a task
+    /// queue that no other worker polls (ex: one named after this host). It is shut down along with
+    /// this worker.
+    pub fn enable_sessions(&mut self, session_worker: Arc<dyn CoreWorker>) {
+        let session_task_queue = session_worker.get_config().task_queue.clone();
+        self.register_activity(
+            SESSION_CREATION_ACTIVITY_TYPE,
+            move |_ctx: ActContext, _: ()| {
+                let session_task_queue = session_task_queue.clone();
+                async move { Ok(session_task_queue) }
+            },
+        );
+        self.register_activity(SESSION_HEARTBEAT_ACTIVITY_TYPE, session_heartbeat);
+        self.common.session_worker = Some(session_worker);
+    }
+
     /// Insert Custom App Context for Workflows and Activities
     pub fn insert_app_data<T: Send + Sync + 'static>(&mut self, data: T) {
         self.app_data.as_mut().map(|a| a.insert(data));
@@ -309,15 +341,22 @@ impl Worker {
             // makes tests which use mocks dramatically more manageable.
             async {
                 if !act_half.activity_fns.is_empty() {
-                    loop {
-                        let activity = common.worker.poll_activity_task().await;
-                        if matches!(activity, Err(PollActivityError::ShutDown)) {
-                            break;
-                        }
-                        act_half.activity_task_handler(
+                    let session_poller = common
+                        .session_worker
+                        .as_ref()
+                        .map(|sw| activity_poller(sw.clone(), sw.get_config().task_queue.clone()));
+                    let mut activities = stream::select_all(
+                        std::iter::once(activity_poller(
                             common.worker.clone(),
-                            safe_app_data.clone(),
                             common.task_queue.clone(),
+                        ))
+                        .chain(session_poller),
+                    );
+                    while let Some((worker, task_queue, activity)) = activities.next().await {
+                        act_half.activity_task_handler(
+                            worker,
+                            safe_app_data.clone(),
+                            task_queue,
                             activity?,
                         )?;
                     }
@@ -333,6 +372,9 @@ impl Worker {
             i.on_shutdown(self);
         }
         self.common.worker.shutdown().await;
+        if let Some(sw) = self.common.session_worker.as_ref() {
+            sw.shutdown().await;
+        }
         debug!("Worker shutdown complete");
         self.app_data = Some(
             Arc::try_unwrap(safe_app_data)
@@ -376,6 +418,54 @@ impl Worker {
     }
 }
 
+/// Activity tasks polled from a core worker, along with the worker and task queue they came from
+type ActivityPollStream = BoxStream<
+    'static,
+    (
+        Arc<dyn CoreWorker>,
+        String,
+        Result<ActivityTask, PollActivityError>,
+    ),
+>;
+
+/// Polls the provided worker for activity tasks until it is shut down, tagging each task with the
+/// worker and task queue it came from so it can be completed by the same worker
+fn activity_poller(worker: Arc<dyn CoreWorker>, task_queue: String) -> ActivityPollStream {
+    stream::unfold((), move |_| {
+        let worker = worker.clone();
+        let task_queue = task_queue.clone();
+        async move {
+            match worker.poll_activity_task().await {
+                Err(PollActivityError::ShutDown) => None,
+                activity => Some(((worker, task_queue, activity), ())),
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Lower bound on how often the session heartbeat activity heartbeats
+const MIN_SESSION_HEARTBEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Implementation of the built-in activity which keeps a session alive on its host until the
+/// workflow completes the session
+async fn session_heartbeat(ctx: ActContext, _: ()) -> Result<(), anyhow::Error> {
+    // A zero (or tiny) heartbeat timeout must not turn this into a busy loop
+    let interval = ctx
+        .get_info()
+        .heartbeat_timeout
+        .map(|t| t / 3)
+        .unwrap_or(Duration::from_secs(1))
+        .max(MIN_SESSION_HEARTBEAT_INTERVAL);
+    loop {
+        ctx.record_heartbeat(vec![]);
+        tokio::select! {
+            _ = ctx.cancelled() => return Err(ActivityCancelledError::default().into()),
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
 impl WorkflowHalf {
     fn workflow_activation_handler(
         &self,
@@ -1,9 +1,12 @@
 mod options;
+mod session;
 
 pub use options::{
     ActivityOptions, ChildWorkflowOptions, LocalActivityOptions, Signal, SignalData,
     SignalWorkflowOptions,
 };
+pub use session::{Session, SessionOptions};
+pub(crate) use session::{SESSION_CREATION_ACTIVITY_TYPE, SESSION_HEARTBEAT_ACTIVITY_TYPE};
 
 use crate::{
     workflow_context::options::IntoWorkflowCommand, CancelExternalWfResult, CancellableID,
@@ -34,12 +37,12 @@ use temporal_sdk_core_protos::{
         workflow_commands::{
             request_cancel_external_workflow_execution as cancel_we,
             signal_external_workflow_execution as sig_we, workflow_command,
-            CancelChildWorkflowExecution, ModifyWorkflowProperties,
+            CancelChildWorkflowExecution, ModifyWorkflowProperties, RecordMarker,
             RequestCancelExternalWorkflowExecution, SetPatchMarker,
             SignalExternalWorkflowExecution, StartTimer, UpsertWorkflowSearchAttributes,
         },
     },
-    temporal::api::common::v1::{Memo, Payload},
+    temporal::api::common::v1::{Memo, Payload, Payloads},
 };
 use tokio::sync::{mpsc, oneshot, watch};
 use tokio_stream::wrappers::UnboundedReceiverStream;
@@ -100,6 +103,8 @@ pub struct WfContextSharedData {
     pub is_replaying: bool,
     pub wf_time: Option<SystemTime>,
     pub history_length: u32,
+    pub history_size_bytes: u64,
+    pub continue_as_new_suggested: bool,
 }
 
 // TODO: Dataconverter type interface to replace Payloads here. Possibly just use serde
@@ -155,6 +160,18 @@ impl WfContext {
         self.shared.read().history_length
     }
 
+    /// Return the size of history in bytes so far at this point in the workflow, as reported by
+    /// the server
+    pub fn history_size_bytes(&self) -> u64 {
+        self.shared.read().history_size_bytes
+    }
+
+    /// Returns true if the server has suggested this workflow continue-as-new soon, because its
+    /// history is getting large
+    pub fn continue_as_new_suggested(&self) -> bool {
+        self.shared.read().continue_as_new_suggested
+    }
+
     pub(crate) fn get_shared_data(&self) -> Arc<RwLock<WfContextSharedData>> {
         self.shared.clone()
     }
@@ -315,6 +332,20 @@ impl WfContext {
         ))
     }
 
+    /// Record a marker with the provided name and details in the workflow's history
+    pub fn record_marker(
+        &self,
+        marker_name: impl Into<String>,
+        details: impl IntoIterator<Item = (String, Payloads)>,
+    ) {
+        self.send(RustWfCmd::NewNonblockingCmd(
+            workflow_command::Variant::RecordMarker(RecordMarker {
+                marker_name: marker_name.into(),
+                details: HashMap::from_iter(details),
+            }),
+        ))
+    }
+
     /// Return a stream that produces values when the named signal is sent to this workflow
     pub fn make_signal_channel(&self, signal_name: impl Into<String>) -> DrainableSignalStream {
         let (tx, rx) = mpsc::unbounded_channel();
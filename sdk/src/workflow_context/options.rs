@@ -185,6 +185,8 @@ pub struct ChildWorkflowOptions {
     pub options: WorkflowOptions,
     /// How to respond to parent workflow ending
     pub parent_close_policy: ParentClosePolicy,
+    /// Headers to attach to the child workflow start
+    pub headers: HashMap<String, Payload>,
 }
 
 impl IntoWorkflowCommand for ChildWorkflowOptions {
@@ -209,6 +211,7 @@ impl IntoWorkflowCommand for ChildWorkflowOptions {
             search_attributes: self.options.search_attributes.unwrap_or_default(),
             cron_schedule: self.options.cron_schedule.unwrap_or_default(),
             parent_close_policy: self.parent_close_policy as i32,
+            headers: self.headers,
             ..Default::default()
         }
     }
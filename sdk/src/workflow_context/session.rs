@@ -0,0 +1,141 @@
+//! Sessions guarantee that a sequence of activities all run on the same host. A session is created
+//! by scheduling a special creation activity, which is picked up by any worker with sessions
+//! enabled and returns the name of a task queue only that host polls. A long-running heartbeat
+//! activity is then started on that queue, and lives for as long as the session does - if the host
+//! goes away, the heartbeat times out and the session is considered failed. Completing the session
+//! cancels the heartbeat activity.
+
+use super::{ActivityOptions, CancellableFuture, WfContext};
+use anyhow::bail;
+use std::{future::Future, pin::Pin, time::Duration};
+use temporal_sdk_core_protos::{
+    coresdk::{
+        activity_result::{activity_resolution, ActivityResolution, Success},
+        workflow_commands::ActivityCancellationType,
+        AsJsonPayloadExt, FromJsonPayloadExt,
+    },
+    temporal::api::common::v1::RetryPolicy,
+};
+
+/// Activity type of the built-in activity which picks a host for a new session, returning the
+/// name of that host's session task queue
+pub(crate) const SESSION_CREATION_ACTIVITY_TYPE: &str = "__temporal_session_creation";
+/// Activity type of the built-in activity which runs on the session host for the lifetime of a
+/// session, heartbeating so that the loss of the host can be detected
+pub(crate) const SESSION_HEARTBEAT_ACTIVITY_TYPE: &str = "__temporal_session_heartbeat";
+
+/// Once a host has picked up the creation activity, it should not take long to respond
+const CREATION_START_TO_CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Options for [WfContext::create_session]
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    /// Task queue on which to look for a worker to host the session. If empty, the workflow's
+    /// task queue is used.
+    pub task_queue: String,
+    /// How long to wait for some worker to accept the session before creation fails
+    pub creation_timeout: Duration,
+    /// The session fails if its host does not heartbeat for this long. Must be non-zero.
+    pub heartbeat_timeout: Duration,
+    /// Maximum lifetime of the session. The session fails once this has elapsed.
+    pub execution_timeout: Duration,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            task_queue: "".to_string(),
+            creation_timeout: Duration::from_secs(60),
+            heartbeat_timeout: Duration::from_secs(20),
+            execution_timeout: Duration::from_secs(60 * 60 * 24),
+        }
+    }
+}
+
+/// A session created by [WfContext::create_session]. Activities scheduled through it are all run
+/// by the host which accepted the session.
+pub struct Session {
+    task_queue: String,
+    heartbeat: Pin<Box<dyn CancellableFuture<ActivityResolution> + Send>>,
+}
+
+impl Session {
+    /// The task queue polled only by the session's host
+    pub fn task_queue(&self) -> &str {
+        &self.task_queue
+    }
+
+    /// Request to run an activity on the session's host. Any task queue set in the options is
+    /// replaced with the session's.
+    pub fn activity(
+        &self,
+        ctx: &WfContext,
+        mut opts: ActivityOptions,
+    ) -> impl CancellableFuture<ActivityResolution> {
+        opts.task_queue = self.task_queue.clone();
+        ctx.activity(opts)
+    }
+
+    /// Resolves if the session fails, ex: because its host stopped heartbeating or the session's
+    /// execution timeout elapsed. Activities already scheduled in the session are not cancelled
+    /// automatically, but will typically time out themselves if the host is gone. Once this has
+    /// resolved, the session must not be completed.
+    pub fn failed(&mut self) -> impl Future<Output = ActivityResolution> + '_ {
+        self.heartbeat.as_mut()
+    }
+
+    /// Ends the session, releasing it on the host
+    pub async fn complete(mut self, ctx: &WfContext) {
+        self.heartbeat.cancel(ctx);
+        self.heartbeat.await;
+    }
+}
+
+impl WfContext {
+    /// Create a session, guaranteeing that all activities scheduled through the returned [Session]
+    /// run on the same host. Fails if no worker with sessions enabled accepts the session within
+    /// [SessionOptions::creation_timeout], or if the options are invalid.
+    pub async fn create_session(&self, opts: SessionOptions) -> anyhow::Result<Session> {
+        if opts.heartbeat_timeout.is_zero() {
+            bail!("Session heartbeat timeout must be non-zero");
+        }
+        let single_attempt = RetryPolicy {
+            maximum_attempts: 1,
+            ..Default::default()
+        };
+        let created = self
+            .activity(ActivityOptions {
+                activity_type: SESSION_CREATION_ACTIVITY_TYPE.to_string(),
+                input: ().as_json_payload()?,
+                task_queue: opts.task_queue,
+                schedule_to_start_timeout: Some(opts.creation_timeout),
+                start_to_close_timeout: Some(CREATION_START_TO_CLOSE_TIMEOUT),
+                retry_policy: Some(single_attempt.clone()),
+                ..Default::default()
+            })
+            .await;
+        let task_queue = match created.status {
+            Some(activity_resolution::Status::Completed(Success {
+                result: Some(ref payload),
+            })) => String::from_json_payload(payload)?,
+            _ => bail!("Session could not be created: {created:?}"),
+        };
+        if task_queue.is_empty() {
+            bail!("Session host returned an empty task queue");
+        }
+        let heartbeat = Box::pin(self.activity(ActivityOptions {
+            activity_type: SESSION_HEARTBEAT_ACTIVITY_TYPE.to_string(),
+            input: ().as_json_payload()?,
+            task_queue: task_queue.clone(),
+            start_to_close_timeout: Some(opts.execution_timeout),
+            heartbeat_timeout: Some(opts.heartbeat_timeout),
+            cancellation_type: ActivityCancellationType::TryCancel,
+            retry_policy: Some(single_attempt),
+            ..Default::default()
+        }));
+        Ok(Session {
+            task_queue,
+            heartbeat,
+        })
+    }
+}
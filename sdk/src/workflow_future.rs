@@ -22,9 +22,9 @@ use temporal_sdk_core_protos::{
             WorkflowActivationJob,
         },
         workflow_commands::{
-            request_cancel_external_workflow_execution as cancel_we, workflow_command,
-            CancelChildWorkflowExecution, CancelSignalWorkflow, CancelTimer,
-            CancelWorkflowExecution, CompleteWorkflowExecution, FailWorkflowExecution,
+            query_result, request_cancel_external_workflow_execution as cancel_we,
+            workflow_command, CancelChildWorkflowExecution, CancelSignalWorkflow, CancelTimer,
+            CancelWorkflowExecution, CompleteWorkflowExecution, FailWorkflowExecution, QueryResult,
             RequestCancelActivity, RequestCancelExternalWorkflowExecution,
             RequestCancelLocalActivity, ScheduleActivity, ScheduleLocalActivity,
             StartChildWorkflowExecution, StartTimer,
@@ -72,6 +72,7 @@ impl WorkflowFunction {
                 cancel_sender: cancel_tx,
                 child_workflow_starts: Default::default(),
                 sig_chans: Default::default(),
+                query_responses: Default::default(),
             },
             tx,
         )
@@ -110,6 +111,8 @@ pub struct WorkflowFuture {
     child_workflow_starts: HashMap<u32, StartChildWorkflowExecution>,
     /// Maps signal IDs to channels to send down when they are signaled
     sig_chans: HashMap<String, SigChanOrBuffer>,
+    /// Responses to queries from the current activation, sent with its completion
+    query_responses: Vec<workflow_command::Variant>,
 }
 
 impl WorkflowFuture {
@@ -187,6 +190,18 @@ impl WorkflowFuture {
                         q.query_id
                     );
                 }
+                Variant::QueryStackTrace(q) => {
+                    // Fail the query rather than leave the caller waiting on a response
+                    self.query_responses
+                        .push(workflow_command::Variant::RespondToQuery(QueryResult {
+                            query_id: q.query_id,
+                            variant: Some(query_result::Variant::Failed(Failure {
+                                message: "Stack trace queries are not supported by the Rust SDK"
+                                    .to_string(),
+                                ..Default::default()
+                            })),
+                        }));
+                }
                 Variant::CancelWorkflow(_) => {
                     // TODO: Cancel pending futures, etc
                     self.cancel_sender
@@ -218,7 +233,8 @@ impl WorkflowFuture {
                     self.unblock(UnblockEvent::CancelExternal(attrs.seq, attrs.failure))?;
                 }
 
-                Variant::RemoveFromCache(_) => {
+                Variant::RemoveFromCache(rfc) => {
+                    debug!(reason=?rfc.reason(), message=%rfc.message, "Workflow evicted");
                     // TODO: Need to abort any spawned tasks, etc. See also cancel WF.
                     //   How best to do this in executor agnostic way? Is that possible?
                     //  -- tokio JoinSet does this in a nice way.
@@ -258,9 +274,12 @@ impl Future for WorkflowFuture {
                 wlock.is_replaying = activation.is_replaying;
                 wlock.wf_time = activation.timestamp.try_into_or_none();
                 wlock.history_length = activation.history_length;
+                wlock.history_size_bytes = activation.history_size_bytes;
+                wlock.continue_as_new_suggested = activation.continue_as_new_suggested;
             }
 
             let mut die_of_eviction_when_done = false;
+            self.query_responses.clear();
             for WorkflowActivationJob { variant } in activation.jobs {
                 match self.handle_job(variant) {
                     Ok(true) => {
@@ -306,7 +325,7 @@ impl Future for WorkflowFuture {
                 Poll::Pending => Poll::Pending,
             };
 
-            let mut activation_cmds = vec![];
+            let mut activation_cmds = std::mem::take(&mut self.query_responses);
             while let Ok(cmd) = self.incoming_commands.try_recv() {
                 match cmd {
                     RustWfCmd::Cancel(cancellable_id) => {
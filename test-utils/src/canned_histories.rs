@@ -6,7 +6,10 @@ use temporal_sdk_core_protos::{
     coresdk::common::NamespacedWorkflowExecution,
     temporal::api::{
         common::v1::{Payload, WorkflowExecution},
-        enums::v1::{EventType, StartChildWorkflowExecutionFailedCause, WorkflowTaskFailedCause},
+        enums::v1::{
+            EventType, ParentClosePolicy, StartChildWorkflowExecutionFailedCause,
+            WorkflowTaskFailedCause,
+        },
         failure::v1::Failure,
         history::v1::*,
     },
@@ -761,6 +764,32 @@ pub fn long_sequential_timers(num_tasks: usize) -> TestHistoryBuilder {
     t
 }
 
+/// Like [long_sequential_timers], but each workflow task also records markers (and their
+/// accompanying search attribute upserts) for `patches_per_task` deprecated patches the workflow
+/// no longer calls
+pub fn long_sequential_timers_with_deprecated_patches(
+    num_tasks: usize,
+    patches_per_task: usize,
+) -> TestHistoryBuilder {
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_full_wf_task();
+
+    for i in 1..=num_tasks {
+        for p in 1..=patches_per_task {
+            let patch_id = format!("patch-{i}-{p}");
+            t.add_has_change_marker(&patch_id, true);
+            t.add_upsert_search_attrs_for_patch(&[patch_id]);
+        }
+        let timer_started_event_id = t.add_by_type(EventType::TimerStarted);
+        t.add_timer_fired(timer_started_event_id, i.to_string());
+        t.add_full_wf_task();
+    }
+
+    t.add_workflow_execution_completed();
+    t
+}
+
 /// Sends 5 large signals per workflow task
 pub fn lots_of_big_signals(num_tasks: usize) -> TestHistoryBuilder {
     let mut t = TestHistoryBuilder::default();
@@ -1299,6 +1328,41 @@ pub fn single_child_workflow_try_cancelled(child_wf_id: &str) -> TestHistoryBuil
     t
 }
 
+///  1: EVENT_TYPE_WORKFLOW_EXECUTION_STARTED
+///  2: EVENT_TYPE_WORKFLOW_TASK_SCHEDULED
+///  3: EVENT_TYPE_WORKFLOW_TASK_STARTED
+///  4: EVENT_TYPE_WORKFLOW_TASK_COMPLETED
+///  5: EVENT_TYPE_START_CHILD_WORKFLOW_EXECUTION_INITIATED (with `parent_close_policy`)
+///  6: EVENT_TYPE_CHILD_WORKFLOW_EXECUTION_STARTED
+///  7: EVENT_TYPE_WORKFLOW_TASK_SCHEDULED
+///  8: EVENT_TYPE_WORKFLOW_TASK_STARTED
+///  9: EVENT_TYPE_WORKFLOW_TASK_COMPLETED
+/// 10: EVENT_TYPE_WORKFLOW_EXECUTION_COMPLETED
+pub fn single_child_workflow_outlives_parent(
+    child_wf_id: &str,
+    parent_close_policy: ParentClosePolicy,
+) -> TestHistoryBuilder {
+    let mut t = TestHistoryBuilder::default();
+    t.add_by_type(EventType::WorkflowExecutionStarted);
+    t.add_full_wf_task();
+    let initiated_event_id = t.add(StartChildWorkflowExecutionInitiatedEventAttributes {
+        workflow_id: child_wf_id.to_owned(),
+        parent_close_policy: parent_close_policy as i32,
+        ..Default::default()
+    });
+    t.add(ChildWorkflowExecutionStartedEventAttributes {
+        initiated_event_id,
+        workflow_execution: Some(WorkflowExecution {
+            workflow_id: child_wf_id.to_owned(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    t.add_full_wf_task();
+    t.add_workflow_execution_completed();
+    t
+}
+
 ///  1: EVENT_TYPE_WORKFLOW_EXECUTION_STARTED
 ///  2: EVENT_TYPE_WORKFLOW_TASK_SCHEDULED
 ///  3: EVENT_TYPE_WORKFLOW_TASK_STARTED
@@ -32,7 +32,7 @@ use temporal_sdk::{
 use temporal_sdk_core::{
     ephemeral_server::{EphemeralExe, EphemeralExeVersion},
     init_replay_worker, init_worker,
-    replay::HistoryForReplay,
+    replay::{fetch_history_for_replay, HistoryForReplay},
     ClientOptions, ClientOptionsBuilder, CoreRuntime, WorkerConfigBuilder,
 };
 use temporal_sdk_core_api::{
@@ -253,16 +253,8 @@ impl CoreWfStarter {
         run_id: impl Into<String>,
         worker: &mut Worker,
     ) -> Result<(), anyhow::Error> {
-        let wf_id = wf_id.into();
-        // Fetch history and replay it
-        let history = self
-            .get_client()
-            .await
-            .get_workflow_execution_history(wf_id.clone(), Some(run_id.into()), vec![])
-            .await?
-            .history
-            .expect("history field must be populated");
-        let with_id = HistoryForReplay::new(history, wf_id);
+        let client = self.get_client().await;
+        let with_id = fetch_history_for_replay(client.as_ref(), wf_id, Some(run_id.into())).await?;
         let replay_worker = init_core_replay_preloaded(worker.task_queue(), [with_id]);
         worker.with_new_core_worker(replay_worker);
         worker.set_worker_interceptor(Box::new(FailOnNondeterminismInterceptor {}));
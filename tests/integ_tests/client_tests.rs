@@ -1,7 +1,17 @@
-use std::time::Duration;
-use temporal_client::{RetryClient, WorkflowClientTrait, WorkflowService};
-use temporal_sdk_core_protos::temporal::api::workflowservice::v1::DescribeNamespaceRequest;
-use temporal_sdk_core_test_utils::{get_integ_server_options, CoreWfStarter, NAMESPACE};
+use std::{sync::Arc, time::Duration};
+use temporal_client::{
+    Client, RetryClient, RetryConfig, WorkflowClientTrait, WorkflowOptions, WorkflowService,
+};
+use temporal_sdk_core::{init_worker, CoreRuntime};
+use temporal_sdk_core_api::{worker::WorkerConfigBuilder, Worker};
+use temporal_sdk_core_protos::temporal::api::{
+    history::v1::history_event::Attributes, workflowservice::v1::DescribeNamespaceRequest,
+};
+use temporal_sdk_core_test_utils::{
+    drain_pollers_and_shutdown, get_integ_server_options, get_integ_telem_options, CoreWfStarter,
+    WorkerTestHelpers, NAMESPACE,
+};
+use tokio::join;
 
 #[tokio::test]
 async fn can_use_retry_client() {
@@ -34,3 +44,94 @@ async fn calls_get_system_info() {
     let raw_client = opts.connect_no_namespace(None, None).await.unwrap();
     assert!(raw_client.get_client().capabilities().is_some());
 }
+
+#[tokio::test]
+async fn workers_can_share_one_connection() {
+    let rt = CoreRuntime::new_assume_tokio(get_integ_telem_options()).unwrap();
+    let opts = get_integ_server_options();
+    let connection = opts
+        .connect_no_namespace(None, None)
+        .await
+        .unwrap()
+        .into_inner();
+    let client = RetryClient::new(
+        Client::new(connection.clone(), NAMESPACE.to_string()),
+        RetryConfig::default(),
+    );
+
+    // Both workers run at the same time, so the connection really is shared between them
+    let (rt, connection, client) = (&rt, &connection, &client);
+    let run_worker = |tq: &'static str| async move {
+        let cfg = WorkerConfigBuilder::default()
+            .namespace(NAMESPACE)
+            .task_queue(tq)
+            .worker_build_id("test_build_id")
+            .client_identity_override(Some(format!("{tq}_identity")))
+            .max_cached_workflows(0_usize)
+            .no_remote_activities(true)
+            .build()
+            .unwrap();
+        let worker: Arc<dyn Worker> = Arc::new(init_worker(&rt, cfg, connection.clone()).unwrap());
+        let run_id = client
+            .start_workflow(
+                vec![],
+                tq.to_string(),
+                tq.to_string(),
+                "whatever".to_string(),
+                None,
+                WorkflowOptions::default(),
+            )
+            .await
+            .unwrap()
+            .run_id;
+        let task = worker.poll_workflow_activation().await.unwrap();
+        worker.complete_execution(&task.run_id).await;
+        drain_pollers_and_shutdown(&worker).await;
+
+        // The task was picked up with the identity of this worker, not the shared connection's
+        let history = client
+            .get_workflow_execution_history(tq.to_string(), Some(run_id), vec![])
+            .await
+            .unwrap()
+            .history
+            .unwrap();
+        let identity = history
+            .events
+            .into_iter()
+            .find_map(|e| match e.attributes {
+                Some(Attributes::WorkflowTaskStartedEventAttributes(a)) => Some(a.identity),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(identity, format!("{tq}_identity"));
+    };
+    join!(
+        run_worker("shared_connection_1"),
+        run_worker("shared_connection_2")
+    );
+}
+
+#[tokio::test]
+async fn lazy_client_connects_on_first_call() {
+    let mut opts = get_integ_server_options();
+    let client = opts
+        .connect_no_namespace_lazy(None, None)
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(client.capabilities().is_none());
+    assert!(client.check_health().await.unwrap());
+    // Capabilities are fetched on first use, then remembered
+    let caps = client.fetch_capabilities().await.unwrap().clone();
+    assert!(caps.sdk_metadata);
+    assert_eq!(client.capabilities(), Some(&caps));
+
+    // Nothing is listening here, but creating the client is still fine
+    opts.target_url = "http://localhost:1".parse().unwrap();
+    let client = opts
+        .connect_no_namespace_lazy(None, None)
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(client.check_health().await.is_err());
+}
@@ -1,5 +1,6 @@
+use opentelemetry::{Context, KeyValue};
 use std::{sync::Arc, time::Duration};
-use temporal_client::{WorkflowClientTrait, WorkflowOptions, WorkflowService};
+use temporal_client::{tonic::Code, WorkflowClientTrait, WorkflowOptions, WorkflowService};
 use temporal_sdk_core::{init_worker, CoreRuntime};
 use temporal_sdk_core_api::{telemetry::MetricsExporter, worker::WorkerConfigBuilder, Worker};
 use temporal_sdk_core_protos::{
@@ -9,7 +10,10 @@ use temporal_sdk_core_protos::{
         workflow_completion::WorkflowActivationCompletion,
         ActivityTaskCompletion,
     },
-    temporal::api::{enums::v1::WorkflowIdReusePolicy, workflowservice::v1::ListNamespacesRequest},
+    temporal::api::{
+        enums::v1::WorkflowIdReusePolicy,
+        workflowservice::v1::{DescribeNamespaceRequest, ListNamespacesRequest},
+    },
 };
 use temporal_sdk_core_test_utils::{get_integ_server_options, get_integ_telem_options, NAMESPACE};
 use tokio::sync::Barrier;
@@ -47,6 +51,96 @@ async fn prometheus_metrics_exported() {
     ));
 }
 
+#[tokio::test]
+async fn custom_prefix_and_buckets() {
+    let mut telemopts = get_integ_telem_options();
+    telemopts.metrics = Some(MetricsExporter::Prometheus(ANY_PORT.parse().unwrap()));
+    telemopts.metric_prefix = Some("myapp_".to_string());
+    telemopts
+        .histogram_bucket_overrides
+        .insert("request_latency".to_string(), vec![1234., 5678.]);
+    let rt = CoreRuntime::new_assume_tokio(telemopts).unwrap();
+    let addr = rt.telemetry().prom_port().unwrap();
+    let opts = get_integ_server_options();
+    let mut raw_client = opts
+        .connect_no_namespace(rt.metric_meter().as_deref(), None)
+        .await
+        .unwrap();
+
+    let _ = raw_client
+        .list_namespaces(ListNamespacesRequest::default())
+        .await
+        .unwrap();
+
+    let body = get_text(format!("http://{addr}/metrics")).await;
+    assert!(body.contains(
+        "myapp_request_latency_count{operation=\"ListNamespaces\",service_name=\"temporal-core-sdk\"} 1"
+    ));
+    assert!(!body.contains("temporal_request_latency"));
+    let bucket_lines: Vec<_> = body
+        .lines()
+        .filter(|l| l.starts_with("myapp_request_latency_bucket{operation=\"ListNamespaces\""))
+        .collect();
+    assert_eq!(bucket_lines.len(), 3);
+    assert!(bucket_lines.iter().any(|l| l.contains("le=\"1234\"")));
+    assert!(bucket_lines.iter().any(|l| l.contains("le=\"5678\"")));
+}
+
+#[tokio::test]
+async fn lang_metrics_share_core_exporter() {
+    let mut telemopts = get_integ_telem_options();
+    telemopts.metrics = Some(MetricsExporter::Prometheus(ANY_PORT.parse().unwrap()));
+    let rt = CoreRuntime::new_assume_tokio(telemopts).unwrap();
+    let addr = rt.telemetry().prom_port().unwrap();
+    let meter = rt.metric_meter().unwrap();
+
+    let counter = meter.custom_counter("lang_counter", "A counter defined by lang");
+    let gauge = meter.custom_gauge("lang_gauge", "A gauge defined by lang");
+    let attrs = [KeyValue::new("lang_label", "hi")];
+    counter.add(&Context::current(), 2, &attrs);
+    gauge.record(5, &attrs);
+    gauge.record(3, &attrs);
+
+    let body = get_text(format!("http://{addr}/metrics")).await;
+    assert!(body
+        .contains("temporal_lang_counter{lang_label=\"hi\",service_name=\"temporal-core-sdk\"} 2"));
+    // Gauges report the last value rather than a distribution
+    assert!(body
+        .contains("temporal_lang_gauge{lang_label=\"hi\",service_name=\"temporal-core-sdk\"} 3"));
+}
+
+#[tokio::test]
+async fn failed_requests_labeled_with_status_code() {
+    let mut telemopts = get_integ_telem_options();
+    telemopts.metrics = Some(MetricsExporter::Prometheus(ANY_PORT.parse().unwrap()));
+    let rt = CoreRuntime::new_assume_tokio(telemopts).unwrap();
+    let addr = rt.telemetry().prom_port().unwrap();
+    let opts = get_integ_server_options();
+    let mut raw_client = opts
+        .connect_no_namespace(rt.metric_meter().as_deref(), None)
+        .await
+        .unwrap();
+
+    let err = raw_client
+        .describe_namespace(DescribeNamespaceRequest {
+            namespace: "this_namespace_does_not_exist".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+
+    let body = get_text(format!("http://{addr}/metrics")).await;
+    let failure_line = body
+        .lines()
+        .find(|l| l.starts_with("temporal_request_failure"))
+        .expect("Failure metric must be recorded");
+    assert!(failure_line.contains("operation=\"DescribeNamespace\""));
+    assert!(failure_line.contains("status_code=\"NotFound\""));
+    // Not found is not retryable
+    assert!(!body.contains("temporal_request_retry"));
+}
+
 #[tokio::test]
 async fn one_slot_worker_reports_available_slot() {
     let mut telemopts = get_integ_telem_options();
@@ -177,6 +271,11 @@ async fn one_slot_worker_reports_available_slot() {
              service_name=\"temporal-core-sdk\",task_queue=\"one_slot_worker_tq\",\
              worker_type=\"WorkflowWorker\"}} 0"
         )));
+        assert!(body.contains(&format!(
+            "temporal_worker_task_slots_used{{namespace=\"{NAMESPACE}\",\
+             service_name=\"temporal-core-sdk\",task_queue=\"one_slot_worker_tq\",\
+             worker_type=\"WorkflowWorker\"}} 1"
+        )));
         assert!(body.contains(&format!(
             "temporal_worker_task_slots_available{{namespace=\"{NAMESPACE}\",\
              service_name=\"temporal-core-sdk\",task_queue=\"one_slot_worker_tq\",\
@@ -226,6 +325,11 @@ async fn one_slot_worker_reports_available_slot() {
              service_name=\"temporal-core-sdk\",task_queue=\"one_slot_worker_tq\",\
              worker_type=\"LocalActivityWorker\"}} 0"
         )));
+        // By now the activity machine has seen its scheduled, started, and completed events
+        assert!(body.lines().any(
+            |l| l.starts_with("temporal_workflow_machine_events_handled{")
+                && l.contains("machine_kind=\"ActivityMachine\"")
+        ));
         // When completion is done, we have 1 again
         act_task_barr.wait().await;
         act_task_barr.wait().await;
@@ -1,8 +1,9 @@
 use std::time::Duration;
 use temporal_client::WorkflowOptions;
 use temporal_sdk::{WfContext, WfExitValue, WorkflowResult};
+use temporal_sdk_core::replay::fetch_continue_as_new_chain;
 use temporal_sdk_core_protos::coresdk::workflow_commands::ContinueAsNewWorkflowExecution;
-use temporal_sdk_core_test_utils::CoreWfStarter;
+use temporal_sdk_core_test_utils::{replay_sdk_worker, CoreWfStarter};
 
 async fn continue_as_new_wf(ctx: WfContext) -> WorkflowResult<()> {
     let run_ct = ctx.get_args()[0].data[0];
@@ -37,6 +38,35 @@ async fn continue_as_new_happy_path() {
     worker.run_until_done().await.unwrap();
 }
 
+#[tokio::test]
+async fn continue_as_new_chain_replays() {
+    let wf_name = "continue_as_new_chain_replays";
+    let mut starter = CoreWfStarter::new(wf_name);
+    starter.no_remote_activities();
+    let mut worker = starter.worker().await;
+    worker.register_wf(wf_name.to_string(), continue_as_new_wf);
+
+    worker
+        .submit_wf(
+            wf_name.to_string(),
+            wf_name.to_string(),
+            vec![[1].into()],
+            WorkflowOptions::default(),
+        )
+        .await
+        .unwrap();
+    worker.run_until_done().await.unwrap();
+
+    let client = starter.get_client().await;
+    let chain = fetch_continue_as_new_chain(client.as_ref(), wf_name, None)
+        .await
+        .unwrap();
+    assert_eq!(chain.len(), 5);
+    let mut worker = replay_sdk_worker(chain);
+    worker.register_wf(wf_name, continue_as_new_wf);
+    worker.run().await.unwrap();
+}
+
 #[tokio::test]
 async fn continue_as_new_multiple_concurrent() {
     let wf_name = "continue_as_new_multiple_concurrent";
@@ -1,9 +1,15 @@
 use crate::integ_tests::workflow_tests::patches::changes_wf;
 use assert_matches::assert_matches;
+use futures::stream;
 use parking_lot::Mutex;
+use prost::Message;
 use std::{collections::HashSet, sync::Arc, time::Duration};
 use temporal_sdk::{interceptors::WorkerInterceptor, WfContext, Worker, WorkflowFunction};
-use temporal_sdk_core::replay::{HistoryFeeder, HistoryForReplay};
+use temporal_sdk_core::{
+    init_batch_replay_worker,
+    replay::{fetch_history_for_replay, HistoryFeeder, HistoryForReplay, ReplayOutcome},
+    WorkerConfigBuilder,
+};
 use temporal_sdk_core_api::errors::{PollActivityError, PollWfError};
 use temporal_sdk_core_protos::{
     coresdk::{
@@ -11,12 +17,12 @@ use temporal_sdk_core_protos::{
         workflow_commands::{ScheduleActivity, StartTimer},
         workflow_completion::WorkflowActivationCompletion,
     },
-    temporal::api::enums::v1::EventType,
+    temporal::api::{enums::v1::EventType, history::v1::History},
     TestHistoryBuilder, DEFAULT_WORKFLOW_TYPE,
 };
 use temporal_sdk_core_test_utils::{
-    canned_histories, history_from_proto_binary, init_core_replay_preloaded, replay_sdk_worker,
-    replay_sdk_worker_stream, WorkerTestHelpers,
+    canned_histories, history_from_proto_binary, init_core_replay_preloaded, init_integ_telem,
+    replay_sdk_worker, replay_sdk_worker_stream, CoreWfStarter, WorkerTestHelpers, NAMESPACE,
 };
 use tokio::join;
 
@@ -131,6 +137,88 @@ async fn replay_using_wf_function() {
     worker.run().await.unwrap();
 }
 
+#[tokio::test]
+async fn replay_from_serialized_history() {
+    let num_timers = 3;
+    let t = canned_histories::long_sequential_timers(num_timers as usize);
+    let hist: History = t.get_full_history_info().unwrap().into();
+    let hist = HistoryForReplay::from_proto_bytes(&hist.encode_to_vec(), "fake").unwrap();
+    let mut worker = replay_sdk_worker([hist]);
+    worker.register_wf(DEFAULT_WORKFLOW_TYPE, timers_wf(num_timers));
+    worker.run().await.unwrap();
+
+    assert!(HistoryForReplay::from_proto_bytes(b"not a history", "fake").is_err());
+}
+
+#[tokio::test]
+async fn batch_replay_reports_each_history() {
+    init_integ_telem();
+    let good_hists = [
+        canned_histories::long_sequential_timers(2),
+        canned_histories::long_sequential_timers(2),
+    ];
+    // Has more timers than the workflow function will start
+    let bad_hist = canned_histories::long_sequential_timers(5);
+    let bad_run_id = bad_hist.get_orig_run_id().to_string();
+    let histories = good_hists
+        .into_iter()
+        .chain([bad_hist])
+        .map(test_hist_to_replay)
+        .collect::<Vec<_>>();
+    let worker_cfg = WorkerConfigBuilder::default()
+        .namespace(NAMESPACE)
+        .task_queue("batch_replay_reports_each_history")
+        .worker_build_id("test_bin_id")
+        .build()
+        .unwrap();
+    let (core, results) = init_batch_replay_worker(worker_cfg, stream::iter(histories), 2).unwrap();
+    let mut worker = Worker::new_from_core(Arc::new(core), "replay_q".to_string());
+    worker.register_wf(DEFAULT_WORKFLOW_TYPE, timers_wf(2));
+    worker.run().await.unwrap();
+
+    let reports = results.reports();
+    assert_eq!(reports.len(), 3);
+    assert!(!results.all_succeeded());
+    for report in reports {
+        if report.run_id == bad_run_id {
+            assert_matches!(report.outcome, ReplayOutcome::Nondeterminism { .. });
+        } else {
+            assert_eq!(report.outcome, ReplayOutcome::Success);
+        }
+    }
+}
+
+#[tokio::test]
+async fn replay_history_fetched_from_server() {
+    let wf_name = "replay_history_fetched_from_server";
+    let mut starter = CoreWfStarter::new(wf_name);
+    starter.no_remote_activities();
+    let mut worker = starter.worker().await;
+    worker.register_wf(wf_name, timers_wf(2));
+    let run_id = starter.start_with_worker(wf_name, &mut worker).await;
+    worker.run_until_done().await.unwrap();
+
+    let client = starter.get_client().await;
+    let history = fetch_history_for_replay(client.as_ref(), starter.get_wf_id(), Some(run_id))
+        .await
+        .unwrap();
+    // Replaying against the same code succeeds, but code which starts fewer timers is caught
+    for (num_timers, should_succeed) in [(2, true), (1, false)] {
+        let worker_cfg = WorkerConfigBuilder::default()
+            .namespace(NAMESPACE)
+            .task_queue(wf_name)
+            .worker_build_id("test_bin_id")
+            .build()
+            .unwrap();
+        let (core, results) =
+            init_batch_replay_worker(worker_cfg, stream::iter([history.clone()]), 1).unwrap();
+        let mut worker = Worker::new_from_core(Arc::new(core), wf_name.to_string());
+        worker.register_wf(wf_name, timers_wf(num_timers));
+        worker.run().await.unwrap();
+        assert_eq!(results.all_succeeded(), should_succeed);
+    }
+}
+
 #[tokio::test]
 async fn replay_ending_wft_complete_with_commands_but_no_scheduled_started() {
     let mut t = TestHistoryBuilder::default();